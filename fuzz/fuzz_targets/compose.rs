@@ -0,0 +1,16 @@
+#![no_main]
+
+use cdm::compose::ComposeFile;
+use cdm::id::InstanceId;
+use libfuzzer_sys::fuzz_target;
+
+// parsing and everything cdm reads off a parsed compose file
+fuzz_target!(|data: &[u8]| {
+    if let Ok(content) = std::str::from_utf8(data)
+        && let Ok(compose) = ComposeFile::parse(content)
+    {
+        if let Some((_, service)) = compose.main_service("challenge-fuzz-1", InstanceId::new(1)) {
+            let _ = service.container_ports();
+        }
+    }
+});
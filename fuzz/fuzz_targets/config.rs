@@ -0,0 +1,13 @@
+#![no_main]
+
+use cdm::ChallengeDockerConfig;
+use libfuzzer_sys::fuzz_target;
+
+// any FloatCTF.toml is either a config within the limits or an error
+fuzz_target!(|data: &[u8]| {
+    if let Ok(content) = std::str::from_utf8(data)
+        && let Ok(config) = ChallengeDockerConfig::parse(content)
+    {
+        assert!(cdm::limits::check_config(&config).is_ok());
+    }
+});
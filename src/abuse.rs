@@ -0,0 +1,386 @@
+use crate::ChallengeDockerManager;
+use crate::id::{InstanceId, TeamId};
+use crate::labels::{self, LabeledContainer};
+use crate::schedule;
+use crate::settings;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use strum::{AsRefStr, Display};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceSample {
+    pub container: String,
+    pub challenge: String,
+    pub instance_id: Option<InstanceId>,
+    pub team: Option<TeamId>,
+    pub timestamp: u64,
+    pub cpu_percent: f64,
+    pub memory_bytes: u64,
+    // received plus sent since the container started
+    pub network_bytes: u64,
+    pub restarts: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, AsRefStr, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AbuseMetric {
+    Cpu,
+    Memory,
+    Network,
+    Restarts,
+}
+
+// an instance, or a team when challenge is None
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Consumer {
+    pub team: Option<TeamId>,
+    pub challenge: Option<String>,
+    pub instance_id: Option<InstanceId>,
+    pub cpu_percent: f64,
+    pub memory_bytes: u64,
+    pub network_bytes: u64,
+    pub restarts: u64,
+}
+
+impl Consumer {
+    fn add(&mut self, other: &Consumer) {
+        self.cpu_percent += other.cpu_percent;
+        self.memory_bytes += other.memory_bytes;
+        self.network_bytes += other.network_bytes;
+        self.restarts += other.restarts;
+    }
+
+    fn metric(&self, metric: AbuseMetric) -> f64 {
+        match metric {
+            AbuseMetric::Cpu => self.cpu_percent,
+            AbuseMetric::Memory => self.memory_bytes as f64,
+            AbuseMetric::Network => self.network_bytes as f64,
+            AbuseMetric::Restarts => self.restarts as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AbuseReport {
+    pub metric: AbuseMetric,
+    pub window: u64,
+    pub samples: usize,
+    // heaviest first
+    pub instances: Vec<Consumer>,
+    pub teams: Vec<Consumer>,
+}
+
+// docker prints sizes like 1.5MiB, 12kB or 0B
+fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier = match unit.trim() {
+        "" | "B" => 1.0,
+        "kB" | "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((number * multiplier) as u64)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StatsLine {
+    name: String,
+    #[serde(rename = "CPUPerc")]
+    cpu_perc: String,
+    mem_usage: String,
+    #[serde(rename = "NetIO")]
+    net_io: String,
+}
+
+// `docker stats --format '{{json .}}'`, one line per container
+fn parse_stats(
+    output: &str,
+    containers: &[LabeledContainer],
+    restarts: &HashMap<String, u64>,
+    timestamp: u64,
+) -> Vec<ResourceSample> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<StatsLine>(line).ok())
+        .filter_map(|stats| {
+            let container = containers.iter().find(|c| c.name == stats.name)?;
+            let (rx, tx) = stats.net_io.split_once('/')?;
+            Some(ResourceSample {
+                container: stats.name.clone(),
+                challenge: container.challenge.clone(),
+                instance_id: container.instance_id,
+                team: container.team,
+                timestamp,
+                cpu_percent: stats.cpu_perc.trim_end_matches('%').parse().unwrap_or(0.0),
+                memory_bytes: parse_size(stats.mem_usage.split('/').next()?).unwrap_or(0),
+                network_bytes: parse_size(rx).unwrap_or(0) + parse_size(tx).unwrap_or(0),
+                restarts: restarts.get(&stats.name).copied().unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+// one look at every running cdm container
+pub fn sample() -> Result<Vec<ResourceSample>, String> {
+    let settings = settings::global()?;
+    let containers = labels::discover(None, None)?;
+    if containers.is_empty() {
+        return Ok(Vec::new());
+    }
+    let names: Vec<&str> = containers.iter().map(|c| c.name.as_str()).collect();
+
+    let mut inspect = vec!["inspect", "--format", "{{.Name}}\t{{.RestartCount}}"];
+    inspect.extend(&names);
+    let output = ChallengeDockerManager::run_command(
+        &settings,
+        "docker",
+        &inspect,
+        settings.docker_env(None),
+    )?;
+    let restarts: HashMap<String, u64> = String::from_utf8_lossy(&output)
+        .lines()
+        .filter_map(|line| {
+            let (name, count) = line.split_once('\t')?;
+            Some((
+                name.trim_start_matches('/').to_string(),
+                count.trim().parse().ok()?,
+            ))
+        })
+        .collect();
+
+    // stopped containers have no stats, docker stats fails on them
+    let mut stats = vec!["stats", "--no-stream", "--format", "{{json .}}"];
+    stats.extend(&names);
+    let output =
+        ChallengeDockerManager::run_command(&settings, "docker", &stats, settings.docker_env(None))
+            .or_else(|_| {
+                ChallengeDockerManager::run_command(
+                    &settings,
+                    "docker",
+                    &stats[..4],
+                    settings.docker_env(None),
+                )
+            })?;
+    Ok(parse_stats(
+        &String::from_utf8_lossy(&output),
+        &containers,
+        &restarts,
+        schedule::now(),
+    ))
+}
+
+// counters that only grow while the container lives, the growth over the window is what counts
+fn growth(first: u64, last: u64, samples: usize) -> u64 {
+    if samples < 2 {
+        last
+    } else {
+        last.saturating_sub(first)
+    }
+}
+
+// samples of the running containers collected over time, e.g. every minute
+#[derive(Debug, Default)]
+pub struct AbuseMonitor {
+    samples: Mutex<Vec<ResourceSample>>,
+}
+
+impl AbuseMonitor {
+    pub fn new() -> Self {
+        AbuseMonitor::default()
+    }
+
+    pub fn record(&self, samples: Vec<ResourceSample>) {
+        self.samples
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend(samples);
+    }
+
+    pub fn sample(&self) -> Result<usize, String> {
+        let samples = sample()?;
+        let count = samples.len();
+        self.record(samples);
+        Ok(count)
+    }
+
+    // forget what is older than the longest window anyone asks for
+    pub fn prune(&self, before: u64) {
+        self.samples
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|sample| sample.timestamp >= before);
+    }
+
+    // the `limit` heaviest instances and teams over the last `window` seconds
+    pub fn abuse_report(
+        &self,
+        window: u64,
+        now: u64,
+        metric: AbuseMetric,
+        limit: usize,
+    ) -> AbuseReport {
+        let samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        let since = now.saturating_sub(window);
+        let mut by_container: BTreeMap<&str, Vec<&ResourceSample>> = BTreeMap::new();
+        for sample in samples.iter().filter(|s| s.timestamp >= since) {
+            by_container
+                .entry(sample.container.as_str())
+                .or_default()
+                .push(sample);
+        }
+
+        let mut instances: BTreeMap<(String, Option<InstanceId>), Consumer> = BTreeMap::new();
+        let mut count = 0;
+        for container in by_container.values_mut() {
+            container.sort_by_key(|sample| sample.timestamp);
+            count += container.len();
+            let (first, last) = (container[0], container[container.len() - 1]);
+            let usage = Consumer {
+                team: last.team,
+                challenge: Some(last.challenge.clone()),
+                instance_id: last.instance_id,
+                cpu_percent: container.iter().map(|s| s.cpu_percent).sum::<f64>()
+                    / container.len() as f64,
+                memory_bytes: container.iter().map(|s| s.memory_bytes).max().unwrap_or(0),
+                network_bytes: growth(first.network_bytes, last.network_bytes, container.len()),
+                restarts: growth(first.restarts, last.restarts, container.len()),
+            };
+            instances
+                .entry((last.challenge.clone(), last.instance_id))
+                .and_modify(|instance| instance.add(&usage))
+                .or_insert(usage);
+        }
+
+        let mut teams: BTreeMap<TeamId, Consumer> = BTreeMap::new();
+        for instance in instances.values() {
+            let Some(team) = instance.team else {
+                continue;
+            };
+            teams
+                .entry(team)
+                .or_insert_with(|| Consumer {
+                    team: Some(team),
+                    challenge: None,
+                    instance_id: None,
+                    cpu_percent: 0.0,
+                    memory_bytes: 0,
+                    network_bytes: 0,
+                    restarts: 0,
+                })
+                .add(instance);
+        }
+
+        let rank = |consumers: Vec<Consumer>| -> Vec<Consumer> {
+            let mut consumers = consumers;
+            consumers.sort_by(|a, b| b.metric(metric).total_cmp(&a.metric(metric)));
+            consumers.truncate(limit);
+            consumers
+        };
+        AbuseReport {
+            metric,
+            window,
+            samples: count,
+            instances: rank(instances.into_values().collect()),
+            teams: rank(teams.into_values().collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_abuse {
+    use super::*;
+
+    fn container(name: &str, challenge: &str, id: u64, team: u64) -> LabeledContainer {
+        LabeledContainer {
+            name: name.to_string(),
+            challenge: challenge.to_string(),
+            instance_id: Some(InstanceId::new(id)),
+            team: Some(TeamId::new(team)),
+            project: String::new(),
+        }
+    }
+
+    #[test]
+    fn check_parse_stats() {
+        assert_eq!(parse_size("1.5MiB"), Some(1572864));
+        assert_eq!(parse_size("12kB"), Some(12000));
+        assert_eq!(parse_size("0B"), Some(0));
+        assert_eq!(parse_size("lots"), None);
+
+        let output = concat!(
+            r#"{"Name":"web-1","CPUPerc":"150.00%","MemUsage":"256MiB / 1GiB","NetIO":"1MB / 1MB"}"#,
+            "\n",
+            r#"{"Name":"someone-else","CPUPerc":"1.00%","MemUsage":"1MiB / 1GiB","NetIO":"0B / 0B"}"#,
+        );
+        let containers = [container("web-1", "comment", 1, 42)];
+        let restarts = HashMap::from([("web-1".to_string(), 3)]);
+        let samples = parse_stats(output, &containers, &restarts, 100);
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].cpu_percent, 150.0);
+        assert_eq!(samples[0].memory_bytes, 256 * 1024 * 1024);
+        assert_eq!(samples[0].network_bytes, 2_000_000);
+        assert_eq!(samples[0].restarts, 3);
+    }
+
+    #[test]
+    fn check_abuse_report() {
+        let sample = |container: &str, id: u64, team: u64, timestamp: u64, cpu: f64, net: u64| {
+            ResourceSample {
+                container: container.to_string(),
+                challenge: "comment".to_string(),
+                instance_id: Some(InstanceId::new(id)),
+                team: Some(TeamId::new(team)),
+                timestamp,
+                cpu_percent: cpu,
+                memory_bytes: 100,
+                network_bytes: net,
+                restarts: 0,
+            }
+        };
+        let monitor = AbuseMonitor::new();
+        monitor.record(vec![
+            sample("a-web", 1, 1, 0, 400.0, 0),
+            sample("a-web", 1, 1, 100, 10.0, 1000),
+            sample("a-web", 1, 1, 200, 20.0, 5000),
+            sample("a-db", 1, 1, 200, 30.0, 10),
+            sample("b-web", 2, 2, 150, 90.0, 100),
+            sample("b-web", 2, 2, 200, 70.0, 300),
+        ]);
+
+        let report = monitor.abuse_report(150, 200, AbuseMetric::Cpu, 10);
+        assert_eq!(report.samples, 5);
+        assert_eq!(report.instances[0].instance_id, Some(InstanceId::new(2)));
+        assert_eq!(report.instances[0].cpu_percent, 80.0);
+        // both containers of instance 1, the 400% is outside the window
+        assert_eq!(report.instances[1].cpu_percent, 45.0);
+        assert_eq!(report.instances[1].memory_bytes, 200);
+
+        let report = monitor.abuse_report(150, 200, AbuseMetric::Network, 1);
+        assert_eq!(report.instances.len(), 1);
+        assert_eq!(report.instances[0].network_bytes, 4000 + 10);
+        assert_eq!(report.teams[0].team, Some(TeamId::new(1)));
+        assert!(report.teams[0].challenge.is_none());
+
+        monitor.prune(150);
+        assert_eq!(
+            monitor
+                .abuse_report(1000, 200, AbuseMetric::Cpu, 10)
+                .samples,
+            4
+        );
+    }
+}
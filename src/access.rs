@@ -0,0 +1,198 @@
+use crate::ChallengeDockerManager;
+use crate::backend::InstanceStatus;
+use crate::error::CdmError;
+use crate::id::{InstanceId, TeamId};
+use crate::schedule;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// what a token grants, the proxy or the port knocking in front of the instance asks validate for it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessGrant {
+    pub challenge: String,
+    pub instance_id: InstanceId,
+    pub team: Option<TeamId>,
+    pub port: u64,
+    pub issued_at: u64,
+    pub expires_at: u64,
+    // who asked for it, e.g. the support staff member
+    pub issued_for: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessToken {
+    // only handed out once, the registry keeps its sha256
+    pub token: String,
+    pub grant: AccessGrant,
+}
+
+impl AccessToken {
+    pub fn url(&self, public_url: &str) -> String {
+        format!(
+            "{}/instances/{}/{}?token={}",
+            public_url.trim_end_matches('/'),
+            self.grant.challenge,
+            self.grant.instance_id,
+            self.token
+        )
+    }
+}
+
+fn token_sha256(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// 128 bits from the os, tokens are secrets
+fn new_token() -> Result<String, String> {
+    let mut token = [0u8; 16];
+    getrandom::fill(&mut token).map_err(|e| format!("Failed to generate a token: {}", e))?;
+    Ok(hex::encode(token))
+}
+
+// time limited tokens granting access to one instance, by the sha256 of the token
+#[derive(Debug, Default)]
+pub struct AccessTokens {
+    grants: Mutex<HashMap<String, AccessGrant>>,
+}
+
+impl AccessTokens {
+    pub fn new() -> Self {
+        AccessTokens::default()
+    }
+
+    pub fn issue(&self, grant: AccessGrant) -> Result<AccessToken, String> {
+        let token = new_token()?;
+        self.grants
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(token_sha256(&token), grant.clone());
+        Ok(AccessToken { token, grant })
+    }
+
+    // the grant of a token that is neither expired nor revoked
+    pub fn validate(&self, token: &str, now: u64) -> Option<AccessGrant> {
+        self.grants
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&token_sha256(token))
+            .filter(|grant| grant.expires_at > now)
+            .cloned()
+    }
+
+    pub fn revoke(&self, token: &str) -> bool {
+        self.grants
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&token_sha256(token))
+            .is_some()
+    }
+
+    // the number of tokens revoked
+    pub fn revoke_instance(&self, challenge: &str, instance_id: InstanceId) -> usize {
+        let mut grants = self.grants.lock().unwrap_or_else(|e| e.into_inner());
+        let before = grants.len();
+        grants.retain(|_, grant| grant.challenge != challenge || grant.instance_id != instance_id);
+        before - grants.len()
+    }
+
+    pub fn prune(&self, now: u64) {
+        self.grants
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|_, grant| grant.expires_at > now);
+    }
+
+    // the grants still valid, e.g. to show which support sessions are open
+    pub fn grants(&self, now: u64) -> Vec<AccessGrant> {
+        let mut grants: Vec<AccessGrant> = self
+            .grants
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .filter(|grant| grant.expires_at > now)
+            .cloned()
+            .collect();
+        grants.sort_by_key(|grant| grant.issued_at);
+        grants
+    }
+}
+
+impl ChallengeDockerManager {
+    // only running instances can be shared, their tokens are revoked when they are taken down
+    pub fn issue_access_token(&self, issued_for: &str, ttl: u64) -> Result<AccessToken, CdmError> {
+        let tokens = self
+            .access_tokens()
+            .ok_or_else(|| "No access token registry configured".to_string())?;
+        let status = self.status()?;
+        if status != InstanceStatus::Running {
+            return Err(CdmError::NotRunning {
+                challenge: self.challenge_docker_config.name.clone(),
+                instance_id: self.id,
+                status,
+            });
+        }
+        let now = schedule::now();
+        Ok(tokens.issue(AccessGrant {
+            challenge: self.challenge_docker_config.name.clone(),
+            instance_id: self.id,
+            team: self.team,
+            port: self.get_port()?,
+            issued_at: now,
+            expires_at: now.saturating_add(ttl),
+            issued_for: issued_for.to_string(),
+        })?)
+    }
+
+    pub(crate) fn revoke_access_tokens(&self) {
+        if let Some(tokens) = self.access_tokens() {
+            tokens.revoke_instance(&self.challenge_docker_config.name, self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_access {
+    use super::*;
+    use crate::backend::MockBackend;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_access_tokens() {
+        let mock = Arc::new(MockBackend::new());
+        let tokens = Arc::new(AccessTokens::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 1)
+            .with_backend(mock.clone())
+            .with_team(TeamId::new(42))
+            .with_access_tokens(tokens.clone());
+        assert!(matches!(
+            cdm.issue_access_token("support", 600),
+            Err(CdmError::NotRunning { .. })
+        ));
+
+        let port = cdm.up(&"flag{access}".into()).unwrap();
+        let first = cdm.issue_access_token("support", 600).unwrap();
+        let second = cdm.issue_access_token("support", 600).unwrap();
+        assert_ne!(first.token, second.token);
+        assert_eq!(first.token.len(), 32);
+        assert!(
+            first
+                .url("https://proxy.ctf.local/")
+                .starts_with("https://proxy.ctf.local/instances/comment/1?token=")
+        );
+
+        let now = first.grant.issued_at;
+        let grant = tokens.validate(&first.token, now).unwrap();
+        assert_eq!(grant.port, port);
+        assert_eq!(grant.team, Some(TeamId::new(42)));
+        assert!(tokens.validate(&first.token, now + 600).is_none());
+        assert!(tokens.validate("guessed", now).is_none());
+
+        assert!(tokens.revoke(&first.token));
+        assert!(tokens.validate(&first.token, now).is_none());
+        assert_eq!(tokens.grants(now).len(), 1);
+        cdm.down().unwrap();
+        assert!(tokens.validate(&second.token, now).is_none());
+    }
+}
@@ -0,0 +1,516 @@
+use crate::{ChallengeDockerConfig, ChallengeDockerManager};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, DateTime, ZipWriter};
+
+pub(crate) fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file =
+        File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+// a file produced by the docker build, copied out of the image into the attachments directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuiltAttachment {
+    pub service: String,
+    // absolute path inside the image
+    pub path: String,
+    // file name below attachments/
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedAttachment {
+    // the name inside archives and download pages
+    pub name: String,
+    pub path: PathBuf,
+}
+
+fn without_cur_dir(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+fn is_glob(attachment: &str) -> bool {
+    attachment.contains(['*', '?', '['])
+}
+
+// plain names live below attachments/, glob patterns are matched from the challenge directory
+pub fn resolve_attachments(
+    challenge_path: &Path,
+    config: &ChallengeDockerConfig,
+) -> Result<Vec<ResolvedAttachment>, String> {
+    let mut resolved: Vec<ResolvedAttachment> = Vec::new();
+    for attachment in &config.attachments {
+        if !is_glob(attachment) {
+            let path = challenge_path.join("attachments").join(attachment);
+            if !path.exists() {
+                return Err(format!(
+                    "The {} has no attachment: {}",
+                    config.name, attachment
+                ));
+            }
+            resolved.push(ResolvedAttachment {
+                name: attachment.clone(),
+                path,
+            });
+            continue;
+        }
+
+        let pattern = format!(
+            "{}/{}",
+            glob::Pattern::escape(&challenge_path.to_string_lossy()),
+            attachment
+        );
+        let paths = glob::glob(&pattern)
+            .map_err(|e| format!("Invalid attachment pattern {}: {}", attachment, e))?;
+        let mut matched = 0;
+        for path in paths {
+            let path = path.map_err(|e| format!("Failed to read {}: {}", attachment, e))?;
+            if !path.is_file() {
+                continue;
+            }
+            matched += 1;
+            // glob drops the leading ./ of relative paths
+            let name = without_cur_dir(&path)
+                .strip_prefix(without_cur_dir(challenge_path))
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string();
+            if !resolved.iter().any(|a| a.path == path) {
+                resolved.push(ResolvedAttachment { name, path });
+            }
+        }
+        if matched == 0 {
+            return Err(format!(
+                "The {} attachment pattern matches nothing: {}",
+                config.name, attachment
+            ));
+        }
+    }
+
+    // extracted by build(), so they may not exist yet
+    for attachment in &config.built_attachments {
+        resolved.push(ResolvedAttachment {
+            name: attachment.name.clone(),
+            path: challenge_path.join("attachments").join(&attachment.name),
+        });
+    }
+    Ok(resolved)
+}
+
+impl ChallengeDockerManager {
+    pub fn attachments_dir(&self) -> PathBuf {
+        self.challenge_path.join("attachments")
+    }
+
+    pub fn attachment_paths(&self) -> Vec<PathBuf> {
+        self.attachment_files
+            .iter()
+            .map(|attachment| attachment.path.clone())
+            .collect()
+    }
+
+    // pick up attachment files that appeared after the manager was created
+    pub fn refresh_attachments(&mut self) -> Result<(), String> {
+        self.attachment_files =
+            resolve_attachments(&self.challenge_path, &self.challenge_docker_config)?;
+        Ok(())
+    }
+
+    // docker cp from a created but never started container of each service
+    pub fn extract_built_attachments(&self) -> Result<(), String> {
+        let built = &self.challenge_docker_config.built_attachments;
+        if built.is_empty() {
+            return Ok(());
+        }
+
+        let attachments_dir = self.attachments_dir();
+        std::fs::create_dir_all(&attachments_dir)
+            .map_err(|e| format!("Failed to create {}: {}", attachments_dir.display(), e))?;
+
+        let compose_yml = self.docker_compose_yml.to_string_lossy();
+        let project = format!("cdm-extract-{}", self.slug());
+        let env_file = self.env_file_args();
+        let mut compose: Vec<&str> = env_file.iter().map(String::as_str).collect();
+        compose.extend(["--file", &compose_yml, "--project-name", &project]);
+        let env_vars = || {
+            let mut env_vars = self.docker_env();
+            env_vars.insert("ID", "0");
+            Some(env_vars)
+        };
+
+        let result = built.iter().try_for_each(|attachment| {
+            ChallengeDockerManager::run_command(
+                self.settings(),
+                "docker-compose",
+                &[&compose[..], &["create", "--no-build", &attachment.service]].concat(),
+                env_vars(),
+            )?;
+            let output = ChallengeDockerManager::run_command(
+                self.settings(),
+                "docker-compose",
+                &[
+                    &compose[..],
+                    &["ps", "--all", "--quiet", &attachment.service],
+                ]
+                .concat(),
+                env_vars(),
+            )?;
+            let container =
+                String::from_utf8(output).map_err(|e| format!("Invalid UTF-8 in output: {}", e))?;
+            let container = container
+                .lines()
+                .next()
+                .ok_or(format!("No container for service {}", attachment.service))?;
+
+            let target = attachments_dir.join(&attachment.name);
+            // docker cp would nest a directory into an existing one
+            if target.is_dir() {
+                std::fs::remove_dir_all(&target)
+                    .map_err(|e| format!("Failed to remove {}: {}", target.display(), e))?;
+            }
+            ChallengeDockerManager::run_command(
+                self.settings(),
+                "docker",
+                &[
+                    "cp",
+                    &format!("{}:{}", container.trim(), attachment.path),
+                    &target.to_string_lossy(),
+                ],
+                Some(self.docker_env()),
+            )?;
+            Ok::<(), String>(())
+        });
+
+        // never leave the extraction containers behind
+        let cleanup = ChallengeDockerManager::run_command(
+            self.settings(),
+            "docker-compose",
+            &[&compose[..], &["down", "--volumes", "--timeout=1"]].concat(),
+            env_vars(),
+        );
+        result?;
+        cleanup?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachmentEntry {
+    pub filename: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+    pub mime: String,
+}
+
+// good enough for download pages, unknown types are served as plain bytes
+pub fn guess_mime(path: &Path) -> &'static str {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        return "application/gzip";
+    }
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .unwrap_or_default();
+    match extension {
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "xz" => "application/x-xz",
+        "bz2" => "application/x-bzip2",
+        "7z" => "application/x-7z-compressed",
+        "tar" => "application/x-tar",
+        "rar" => "application/vnd.rar",
+        "pdf" => "application/pdf",
+        "json" => "application/json",
+        "txt" | "md" | "log" => "text/plain",
+        "py" => "text/x-python",
+        "c" | "h" => "text/x-c",
+        "cpp" | "cc" | "hpp" => "text/x-c++",
+        "rs" => "text/x-rust",
+        "js" => "text/javascript",
+        "html" | "htm" => "text/html",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "bmp" => "image/bmp",
+        "wav" => "audio/wav",
+        "mp3" => "audio/mpeg",
+        "pcap" | "pcapng" => "application/vnd.tcpdump.pcap",
+        _ => "application/octet-stream",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecksumEntry {
+    pub filename: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachmentPackage {
+    pub archive: PathBuf,
+    // derived from the content, so it only changes when an attachment does
+    pub version: String,
+    pub sha256: String,
+    // the password is handed out through a hint, never through the manifest
+    pub encrypted: bool,
+    pub files: Vec<ChecksumEntry>,
+}
+
+impl ChallengeDockerManager {
+    pub fn attachments(&self) -> Result<Vec<AttachmentEntry>, String> {
+        let mut entries = Vec::new();
+        for attachment in &self.attachment_files {
+            let path = &attachment.path;
+            let metadata = std::fs::metadata(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            entries.push(AttachmentEntry {
+                mime: guess_mime(path).to_string(),
+                filename: attachment.name.clone(),
+                size: metadata.len(),
+                sha256: sha256_file(path)?,
+                path: path.clone(),
+            });
+        }
+        entries.sort_by(|a, b| a.filename.cmp(&b.filename));
+        Ok(entries)
+    }
+
+    pub fn attachment_checksums(&self) -> Result<Vec<ChecksumEntry>, String> {
+        Ok(self
+            .attachments()?
+            .into_iter()
+            .map(|entry| ChecksumEntry {
+                filename: entry.filename,
+                size: entry.size,
+                sha256: entry.sha256,
+            })
+            .collect())
+    }
+
+    // zip the attachments into <out_dir>/<name>-v<hash>.zip next to a <name>-v<hash>.json manifest
+    pub fn package_attachments(&self, out_dir: &Path) -> Result<Option<AttachmentPackage>, String> {
+        let entries = self.attachments()?;
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        let files: Vec<ChecksumEntry> = entries
+            .iter()
+            .map(|entry| ChecksumEntry {
+                filename: entry.filename.clone(),
+                size: entry.size,
+                sha256: entry.sha256.clone(),
+            })
+            .collect();
+
+        let sums: String = files
+            .iter()
+            .map(|entry| format!("{}  {}\n", entry.sha256, entry.filename))
+            .collect();
+        let password = self.challenge_docker_config.attachment_password.as_deref();
+        // encrypted and plain archives of the same files must not share a name,
+        // the password itself stays out of the name since it is public
+        let mut hasher = Sha256::new();
+        hasher.update(sums.as_bytes());
+        if password.is_some() {
+            hasher.update(b"aes-256");
+        }
+        let version = hex::encode(hasher.finalize())[..12].to_string();
+        let name = &self.challenge_docker_config.name;
+        let archive = out_dir.join(format!("{}-v{}.zip", name, version));
+
+        std::fs::create_dir_all(out_dir)
+            .map_err(|e| format!("Failed to create {}: {}", out_dir.display(), e))?;
+        // same version, same content
+        if !archive.exists() {
+            let partial = archive.with_extension("zip.partial");
+            write_archive(&partial, &entries, &sums, password)?;
+            std::fs::rename(&partial, &archive)
+                .map_err(|e| format!("Failed to write {}: {}", archive.display(), e))?;
+        }
+
+        let package = AttachmentPackage {
+            sha256: sha256_file(&archive)?,
+            encrypted: password.is_some(),
+            archive,
+            version,
+            files,
+        };
+        let manifest = package.archive.with_extension("json");
+        let json = serde_json::to_vec_pretty(&package)
+            .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+        std::fs::write(&manifest, json)
+            .map_err(|e| format!("Failed to write {}: {}", manifest.display(), e))?;
+        Ok(Some(package))
+    }
+}
+
+fn write_archive(
+    path: &Path,
+    files: &[AttachmentEntry],
+    sums: &str,
+    password: Option<&str>,
+) -> Result<(), String> {
+    let file =
+        File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    // a fixed timestamp keeps the archive byte for byte reproducible
+    let mut options = SimpleFileOptions::default().last_modified_time(DateTime::DEFAULT);
+    if let Some(password) = password {
+        options = options.with_aes_encryption(AesMode::Aes256, password);
+    }
+    let zip_error = |e: zip::result::ZipError| format!("Failed to write {}: {}", path.display(), e);
+
+    for entry in files {
+        zip.start_file(entry.filename.as_str(), options)
+            .map_err(zip_error)?;
+        let mut source = File::open(&entry.path)
+            .map_err(|e| format!("Failed to open {}: {}", entry.path.display(), e))?;
+        std::io::copy(&mut source, &mut zip)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+
+    zip.start_file("SHA256SUMS", options).map_err(zip_error)?;
+    zip.write_all(sums.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_attachments {
+    use super::*;
+    use crate::id::InstanceId;
+    use crate::testing::TestChallenge;
+
+    // the challenge has to outlive the manager, it is removed when dropped
+    fn test_challenge(name: &str) -> (TestChallenge, ChallengeDockerManager) {
+        let challenge = TestChallenge::new(name).unwrap();
+        let dir = &challenge.path;
+        std::fs::write(dir.join("attachments/chall"), b"\x7fELF").unwrap();
+        std::fs::write(dir.join("attachments/libc.so.6"), b"libc").unwrap();
+
+        let mut cdm = ChallengeDockerManager::new(dir.clone(), InstanceId::new(1)).unwrap();
+        cdm.config_mut().attachments = vec!["libc.so.6".into(), "chall".into()];
+        cdm.refresh_attachments().unwrap();
+        (challenge, cdm)
+    }
+
+    #[test]
+    fn check_guess_mime() {
+        assert_eq!(guess_mime(Path::new("dist/src.TAR.GZ")), "application/gzip");
+        assert_eq!(
+            guess_mime(Path::new("capture.pcapng")),
+            "application/vnd.tcpdump.pcap"
+        );
+        assert_eq!(guess_mime(Path::new("chall")), "application/octet-stream");
+    }
+
+    #[test]
+    fn check_listing() {
+        let (_challenge, cdm) = test_challenge("listing");
+        let entries = cdm.attachments().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].filename, "chall");
+        assert_eq!(entries[0].size, 4);
+        assert_eq!(entries[1].mime, "application/octet-stream");
+        assert_eq!(entries[1].sha256, sha256_file(&entries[1].path).unwrap());
+    }
+
+    #[test]
+    fn check_glob() {
+        let (_challenge, mut cdm) = test_challenge("glob");
+        std::fs::create_dir_all(cdm.challenge_path.join("src/app")).unwrap();
+        std::fs::write(cdm.challenge_path.join("src/app/main.py"), b"print()").unwrap();
+        std::fs::write(cdm.challenge_path.join("src/util.py"), b"pass").unwrap();
+        std::fs::write(cdm.challenge_path.join("src/notes.txt"), b"todo").unwrap();
+
+        cdm.config_mut().attachments = vec!["chall".into(), "src/**/*.py".into()];
+        cdm.refresh_attachments().unwrap();
+        let names: Vec<_> = cdm
+            .attachment_files
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(names, ["chall", "src/app/main.py", "src/util.py"]);
+
+        let package = cdm
+            .package_attachments(&cdm.challenge_path.join("dist"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(package.files.len(), 3);
+
+        cdm.config_mut().attachments = vec!["dist/*.tar.gz".into()];
+        let e = cdm.refresh_attachments().unwrap_err();
+        assert!(e.contains("matches nothing"), "{}", e);
+    }
+
+    #[test]
+    fn check_package() {
+        let (_challenge, cdm) = test_challenge("package");
+        let out = cdm.challenge_path.join("dist");
+
+        let package = cdm.package_attachments(&out).unwrap().unwrap();
+        assert_eq!(package.files[0].filename, "chall");
+        assert!(
+            package
+                .archive
+                .ends_with(format!("package-v{}.zip", package.version))
+        );
+        assert!(package.archive.with_extension("json").exists());
+
+        // repackaging is stable, changing an attachment bumps the version
+        let again = cdm.package_attachments(&out).unwrap().unwrap();
+        assert_eq!(again, package);
+        std::fs::write(cdm.attachments_dir().join("chall"), b"\x7fELF2").unwrap();
+        let changed = cdm.package_attachments(&out).unwrap().unwrap();
+        assert_ne!(changed.version, package.version);
+    }
+
+    #[test]
+    fn check_encrypted_package() {
+        let (_challenge, mut cdm) = test_challenge("encrypted");
+        let out = cdm.challenge_path.join("dist");
+        let plain = cdm.package_attachments(&out).unwrap().unwrap();
+
+        cdm.config_mut().attachment_password = Some("hint_password".to_string());
+        cdm.refresh_attachments().unwrap();
+        let package = cdm.package_attachments(&out).unwrap().unwrap();
+        assert!(package.encrypted);
+        assert_ne!(package.version, plain.version);
+        let manifest = std::fs::read_to_string(package.archive.with_extension("json")).unwrap();
+        assert!(!manifest.contains("hint_password"));
+
+        let mut zip = zip::ZipArchive::new(File::open(&package.archive).unwrap()).unwrap();
+        assert!(zip.by_name("chall").is_err());
+        let mut content = Vec::new();
+        zip.by_name_decrypt("chall", b"hint_password")
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, b"\x7fELF");
+    }
+}
@@ -0,0 +1,221 @@
+use crate::ChallengeDockerManager;
+use crate::abuse::ResourceSample;
+use crate::compose::ComposeFile;
+use crate::error::CdmError;
+use crate::events::EventKind;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// [autoscale] of a shared challenge, the scaled service must not publish a fixed host port
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoscaleConfig {
+    // the main service when not set
+    pub service: Option<String>,
+    pub min_replicas: u64,
+    pub max_replicas: u64,
+    // per replica
+    pub target_cpu_percent: f64,
+    pub target_connections: Option<u64>,
+    // seconds between two scalings of the same instance
+    pub cooldown: u64,
+}
+
+impl Default for AutoscaleConfig {
+    fn default() -> Self {
+        AutoscaleConfig {
+            service: None,
+            min_replicas: 1,
+            max_replicas: 4,
+            target_cpu_percent: 70.0,
+            target_connections: None,
+            cooldown: 120,
+        }
+    }
+}
+
+// the load of every replica together
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Load {
+    pub cpu_percent: f64,
+    // as the proxy counts them, None when there is no proxy in front
+    pub connections: Option<u64>,
+}
+
+impl Load {
+    // the cpu of the newest samples of the instance, see AbuseMonitor
+    pub fn from_samples(samples: &[ResourceSample], cdm: &ChallengeDockerManager) -> Self {
+        let samples: Vec<&ResourceSample> = samples
+            .iter()
+            .filter(|sample| {
+                sample.challenge == cdm.challenge_docker_config.name
+                    && sample.instance_id == Some(cdm.id)
+            })
+            .collect();
+        let newest = samples.iter().map(|sample| sample.timestamp).max();
+        Load {
+            cpu_percent: samples
+                .iter()
+                .filter(|sample| Some(sample.timestamp) == newest)
+                .map(|sample| sample.cpu_percent)
+                .sum(),
+            connections: None,
+        }
+    }
+
+    pub fn with_connections(mut self, connections: u64) -> Self {
+        self.connections = Some(connections);
+        self
+    }
+}
+
+impl AutoscaleConfig {
+    // enough replicas to bring every one of them under the targets, within the bounds
+    pub fn desired_replicas(&self, load: Load) -> u64 {
+        let by_cpu = match self.target_cpu_percent > 0.0 {
+            true => (load.cpu_percent / self.target_cpu_percent).ceil() as u64,
+            false => 0,
+        };
+        let by_connections = match (load.connections, self.target_connections) {
+            (Some(connections), Some(target)) if target > 0 => connections.div_ceil(target),
+            _ => 0,
+        };
+        by_cpu
+            .max(by_connections)
+            .clamp(self.min_replicas, self.max_replicas.max(self.min_replicas))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scaling {
+    pub service: String,
+    pub from: u64,
+    pub to: u64,
+}
+
+// the replicas of every shared instance it scaled, by project name
+#[derive(Debug, Default)]
+pub struct Autoscaler {
+    instances: Mutex<HashMap<String, (u64, u64)>>,
+}
+
+impl Autoscaler {
+    pub fn new() -> Self {
+        Autoscaler::default()
+    }
+
+    pub fn replicas(&self, cdm: &ChallengeDockerManager) -> Option<u64> {
+        self.instances
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&cdm.docker_compose_project_name)
+            .map(|(replicas, _)| *replicas)
+    }
+
+    // scale the instance to what the load asks for, None when it stays as it is
+    pub fn evaluate(
+        &self,
+        cdm: &ChallengeDockerManager,
+        load: Load,
+        now: u64,
+    ) -> Result<Option<Scaling>, CdmError> {
+        let Some(config) = &cdm.challenge_docker_config.autoscale else {
+            return Ok(None);
+        };
+        let project = &cdm.docker_compose_project_name;
+        let (from, scaled_at) = self
+            .instances
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(project)
+            .copied()
+            .unwrap_or((config.min_replicas, 0));
+        let to = config.desired_replicas(load);
+        if to == from || now < scaled_at + config.cooldown {
+            return Ok(None);
+        }
+
+        let service = match &config.service {
+            Some(service) => service.clone(),
+            None => ComposeFile::load(&cdm.docker_compose_yml)?
+                .main_service(&cdm.main_container_name, cdm.id)
+                .map(|(name, _)| name.to_string())
+                .ok_or_else(|| format!("{} has no service to scale", project))?,
+        };
+        cdm.backend
+            .scale(cdm, &service, to)
+            .map_err(|e| e.for_instance(cdm, ""))?;
+        self.instances
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(project.clone(), (to, now));
+        cdm.emit(EventKind::Scaled {
+            service: service.clone(),
+            replicas: to,
+        });
+        Ok(Some(Scaling { service, from, to }))
+    }
+
+    // taken down instances start again from the minimum
+    pub fn forget(&self, cdm: &ChallengeDockerManager) {
+        self.instances
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&cdm.docker_compose_project_name);
+    }
+}
+
+#[cfg(test)]
+mod test_autoscale {
+    use super::*;
+    use crate::backend::{MockBackend, MockOperation};
+    use std::sync::Arc;
+
+    #[test]
+    fn check_desired_replicas() {
+        let config = AutoscaleConfig {
+            target_connections: Some(50),
+            ..Default::default()
+        };
+        let load = |cpu_percent| Load {
+            cpu_percent,
+            connections: None,
+        };
+        assert_eq!(config.desired_replicas(load(0.0)), 1);
+        assert_eq!(config.desired_replicas(load(150.0)), 3);
+        assert_eq!(config.desired_replicas(load(1000.0)), 4);
+        assert_eq!(config.desired_replicas(load(10.0).with_connections(101)), 3);
+    }
+
+    #[test]
+    fn check_autoscaler() {
+        let mock = Arc::new(MockBackend::new());
+        let mut cdm = ChallengeDockerManager::test_manager("comment", 1).with_backend(mock.clone());
+        cdm.config_mut().autoscale = Some(AutoscaleConfig {
+            service: Some("web".to_string()),
+            ..Default::default()
+        });
+        let autoscaler = Autoscaler::new();
+        let busy = Load {
+            cpu_percent: 200.0,
+            connections: None,
+        };
+
+        let scaling = autoscaler.evaluate(&cdm, busy, 1000).unwrap().unwrap();
+        assert_eq!((scaling.from, scaling.to), (1, 3));
+        assert_eq!(mock.replicas("challenge-project-1-comment", "web"), Some(3));
+        // still cooling down
+        assert_eq!(
+            autoscaler.evaluate(&cdm, Load::default(), 1060).unwrap(),
+            None
+        );
+        let scaling = autoscaler
+            .evaluate(&cdm, Load::default(), 1200)
+            .unwrap()
+            .unwrap();
+        assert_eq!(scaling.to, 1);
+        assert_eq!(autoscaler.replicas(&cdm), Some(1));
+        assert_eq!(mock.calls_of(MockOperation::Scale).len(), 2);
+    }
+}
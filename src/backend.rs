@@ -0,0 +1,122 @@
+mod compose;
+#[cfg(feature = "kubernetes")]
+mod kubernetes;
+mod mock;
+mod swarm;
+
+pub use compose::ComposeBackend;
+#[cfg(feature = "kubernetes")]
+pub use kubernetes::{KubernetesBackend, KubernetesExposure};
+pub use mock::{MockBackend, MockCall, MockOperation};
+pub use swarm::{SwarmBackend, SwarmConfig};
+
+use crate::ChallengeDockerManager;
+use crate::error::CdmError;
+use crate::flag::Flag;
+use crate::id::InstanceId;
+use crate::plan::{Operation, Plan};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+use std::sync::Arc;
+use strum::{AsRefStr, Display};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, AsRefStr, Display)]
+pub enum InstanceStatus {
+    Running,
+    Starting,
+    Paused,
+    Exited,
+    NotFound,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct PortMapping {
+    pub container_port: u64,
+    pub host_port: u64,
+    pub protocol: String,
+}
+
+// where the instances of a challenge actually run
+pub trait Backend: Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn build(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError>;
+
+    // return the map port
+    fn up(&self, cdm: &ChallengeDockerManager, flag: &str) -> Result<u64, CdmError>;
+
+    fn down(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError>;
+
+    // the last resort when down failed, removes whatever is left without asking nicely
+    fn force_down(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError> {
+        self.down(cdm)
+    }
+
+    fn status(&self, cdm: &ChallengeDockerManager) -> Result<InstanceStatus, CdmError>;
+
+    fn ports(&self, cdm: &ChallengeDockerManager) -> Result<Vec<PortMapping>, CdmError>;
+
+    // whether the instance has live containers, cheaper than status where the backend can tell
+    fn is_running(&self, cdm: &ChallengeDockerManager) -> Result<bool, CdmError> {
+        Ok(self.status(cdm)? == InstanceStatus::Running)
+    }
+
+    // whether anything of the instance is left, running or not
+    fn exists(&self, cdm: &ChallengeDockerManager) -> Result<bool, CdmError> {
+        Ok(self.status(cdm)? != InstanceStatus::NotFound)
+    }
+
+    // the flag the running instance actually got, None when the backend cannot tell
+    fn deployed_flag(&self, _cdm: &ChallengeDockerManager) -> Result<Option<Flag>, CdmError> {
+        Ok(None)
+    }
+
+    // the ids of every instance of the challenge of the manager, running or not
+    fn instances(&self, cdm: &ChallengeDockerManager) -> Result<Vec<InstanceId>, CdmError> {
+        let ids: BTreeSet<InstanceId> = cdm
+            .discover()?
+            .into_iter()
+            .filter_map(|container| container.instance_id)
+            .collect();
+        Ok(ids.into_iter().collect())
+    }
+
+    // run a command in the main container of the instance
+    fn exec(&self, cdm: &ChallengeDockerManager, _command: &[&str]) -> Result<Vec<u8>, CdmError> {
+        Err(format!(
+            "The {} backend cannot exec into {}",
+            self.name(),
+            cdm.main_container_name
+        )
+        .into())
+    }
+
+    // run this many containers of the service of the instance
+    fn scale(
+        &self,
+        cdm: &ChallengeDockerManager,
+        service: &str,
+        _replicas: u64,
+    ) -> Result<(), CdmError> {
+        Err(format!(
+            "The {} backend cannot scale {} of {}",
+            self.name(),
+            service,
+            cdm.docker_compose_project_name
+        )
+        .into())
+    }
+
+    // what the operation would run, without running it
+    fn plan(
+        &self,
+        cdm: &ChallengeDockerManager,
+        operation: Operation,
+        flag: &str,
+    ) -> Result<Plan, String>;
+}
+
+pub(crate) fn default_backend() -> Arc<dyn Backend> {
+    Arc::new(ComposeBackend)
+}
@@ -0,0 +1,795 @@
+use super::{Backend, InstanceStatus, PortMapping};
+use crate::ChallengeDockerManager;
+use crate::compose::{ComposeFile, override_path, render_override, write_override};
+use crate::error::CdmError;
+use crate::flag::{self, Flag, FlagDelivery};
+use crate::plan::{Operation, Plan, PlannedCommand};
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ComposeBackend;
+
+// a container of `compose ps --format json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PsEntry {
+    name: String,
+    #[serde(default)]
+    service: String,
+    state: String,
+    // null for some stopped containers
+    #[serde(default)]
+    publishers: Option<Vec<Publisher>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Publisher {
+    target_port: u64,
+    // 0 for ports that are exposed but not published
+    published_port: u64,
+    protocol: String,
+}
+
+// compose before 2.21 prints one array, later ones a line per container
+fn parse_ps(output: &str) -> Result<Vec<PsEntry>, String> {
+    let output = output.trim();
+    if output.starts_with('[') {
+        return serde_json::from_str(output)
+            .map_err(|e| format!("Failed to parse compose ps: {}", e));
+    }
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line).map_err(|e| format!("Failed to parse compose ps: {}", e))
+        })
+        .collect()
+}
+
+fn main_entry(entries: Vec<PsEntry>, main_container_name: &str) -> Option<PsEntry> {
+    let index = entries
+        .iter()
+        .position(|entry| entry.name == main_container_name)
+        .or_else(|| {
+            entries
+                .iter()
+                .position(|entry| !entry.mappings().is_empty())
+        })
+        .unwrap_or(0);
+    entries.into_iter().nth(index)
+}
+
+impl PsEntry {
+    fn status(&self) -> InstanceStatus {
+        match self.state.as_str() {
+            "running" => InstanceStatus::Running,
+            "created" | "restarting" => InstanceStatus::Starting,
+            "paused" => InstanceStatus::Paused,
+            _ => InstanceStatus::Exited,
+        }
+    }
+
+    // the ipv4 and ipv6 bindings of a port come as two publishers
+    fn mappings(&self) -> Vec<PortMapping> {
+        let mut mappings: Vec<PortMapping> = Vec::new();
+        for publisher in self
+            .publishers
+            .iter()
+            .flatten()
+            .filter(|p| p.published_port != 0)
+        {
+            let mapping = PortMapping {
+                container_port: publisher.target_port,
+                host_port: publisher.published_port,
+                protocol: publisher.protocol.clone(),
+            };
+            if !mappings.contains(&mapping) {
+                mappings.push(mapping);
+            }
+        }
+        mappings
+    }
+}
+
+// compose v1 prints `The FOO variable is not set`, v2 quotes the name
+fn unset_variables(stderr: &str) -> Vec<String> {
+    let mut unset: Vec<String> = stderr
+        .lines()
+        .filter(|line| line.contains("variable is not set"))
+        .filter_map(|line| {
+            let rest = &line[line.find("The ")? + 4..];
+            let name = rest.split_whitespace().next()?;
+            Some(name.trim_matches(|c| c == '"' || c == '\\').to_string())
+        })
+        .collect();
+    unset.sort();
+    unset.dedup();
+    unset
+}
+
+impl ComposeBackend {
+    // every compose call reads the .env of the challenge, not the one of the working directory
+    fn command(cdm: &ChallengeDockerManager, args: &[&str]) -> PlannedCommand {
+        let env_file = cdm.env_file_args();
+        let mut full_args: Vec<&str> = env_file.iter().map(String::as_str).collect();
+        full_args.extend(args);
+        PlannedCommand::new("docker-compose", &full_args).on(cdm.docker_context())
+    }
+
+    fn build_command(cdm: &ChallengeDockerManager, cache_yml: Option<&Path>) -> PlannedCommand {
+        let mut command =
+            ComposeBackend::command(cdm, &["--file", &cdm.docker_compose_yml.to_string_lossy()]);
+        if let Some(cache_yml) = cache_yml {
+            // cache_from and cache_to are only honoured by BuildKit
+            command = command
+                .arg("--file")
+                .arg(&cache_yml.to_string_lossy())
+                .env("DOCKER_BUILDKIT", "1")
+                .env("COMPOSE_DOCKER_CLI_BUILD", "1");
+        }
+        command.arg("build").env("ID", "0")
+    }
+
+    // the BuildKit cache override of the instance, if it has a cache and builds anything
+    fn build_cache_override(cdm: &ChallengeDockerManager) -> Result<Option<Value>, String> {
+        let Some(cache) = cdm.build_cache() else {
+            return Ok(None);
+        };
+        let compose = ComposeFile::load(&cdm.docker_compose_yml)?;
+        Ok(cache.compose_override(&cdm.slug(), &compose))
+    }
+
+    fn config_command(cdm: &ChallengeDockerManager) -> PlannedCommand {
+        ComposeBackend::command(
+            cdm,
+            &[
+                "--file",
+                &cdm.docker_compose_yml.to_string_lossy(),
+                "config",
+            ],
+        )
+        .env("ID", "0")
+        .env("FLAG", "flag{cdm_config}")
+    }
+
+    // every image the instances run, built ones under the name compose gives them
+    pub fn images(cdm: &ChallengeDockerManager) -> Result<Vec<String>, String> {
+        let output = ComposeBackend::config_command(cdm)
+            .arg("--images")
+            .run(cdm.settings())?;
+        let mut images: Vec<String> = String::from_utf8_lossy(&output)
+            .lines()
+            .map(str::trim)
+            .filter(|image| !image.is_empty())
+            .map(str::to_string)
+            .collect();
+        images.sort();
+        images.dedup();
+        Ok(images)
+    }
+
+    // what compose makes of the file after interpolation, unset variables are errors
+    pub fn config(cdm: &ChallengeDockerManager) -> Result<ComposeFile, String> {
+        let name = &cdm.challenge_docker_config.name;
+        let (stdout, stderr) = ComposeBackend::config_command(cdm)
+            .output(cdm.settings())
+            .map_err(|e| format!("The {} compose file is invalid: {}", name, e))?;
+
+        let unset = unset_variables(&stderr);
+        if !unset.is_empty() {
+            return Err(format!(
+                "The {} compose file uses unset variables: {}",
+                name,
+                unset.join(", ")
+            ));
+        }
+
+        let output = String::from_utf8_lossy(&stdout);
+        ComposeFile::parse(&output)
+            .map_err(|e| format!("The {} compose file is invalid: {}", name, e))
+    }
+
+    // validate the compose file first, so a broken one fails with the challenge named
+    pub(crate) fn run_build(cdm: &ChallengeDockerManager, push: bool) -> Result<(), CdmError> {
+        ComposeBackend::config(cdm)?;
+        let cache_yml = match ComposeBackend::build_cache_override(cdm)? {
+            Some(content) => Some(write_override(
+                &cdm.docker_compose_project_name,
+                "build-cache.yml",
+                &content,
+            )?),
+            None => None,
+        };
+        for command in ComposeBackend::build_commands(cdm, push, cache_yml.as_deref()) {
+            command.run(cdm.settings())?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn build_plan(
+        plan: Plan,
+        cdm: &ChallengeDockerManager,
+        push: bool,
+    ) -> Result<Plan, String> {
+        let mut plan = plan.command(ComposeBackend::config_command(cdm));
+        let cache_yml = match ComposeBackend::build_cache_override(cdm)? {
+            Some(content) => {
+                let path = override_path(&cdm.docker_compose_project_name, "build-cache.yml");
+                plan = plan.file(path.clone(), render_override("build-cache.yml", &content)?);
+                Some(path)
+            }
+            None => None,
+        };
+        for command in ComposeBackend::build_commands(cdm, push, cache_yml.as_deref()) {
+            plan = plan.command(command);
+        }
+        Ok(plan)
+    }
+
+    // the container ids of the project, compose labels every container it creates
+    fn ps_command(cdm: &ChallengeDockerManager, all: bool) -> PlannedCommand {
+        let filter = ComposeBackend::project_filter(cdm);
+        let mut command = PlannedCommand::new("docker", &["ps", "--quiet", "--filter", &filter])
+            .on(cdm.docker_context());
+        if all {
+            command = command.arg("--all");
+        }
+        command
+    }
+
+    fn has_containers(cdm: &ChallengeDockerManager, all: bool) -> Result<bool, CdmError> {
+        let output = ComposeBackend::ps_command(cdm, all).run(cdm.settings())?;
+        Ok(!String::from_utf8_lossy(&output).trim().is_empty())
+    }
+
+    fn project_filter(cdm: &ChallengeDockerManager) -> String {
+        format!(
+            "label=com.docker.compose.project={}",
+            cdm.docker_compose_project_name.to_lowercase()
+        )
+    }
+
+    // remove every id the list command prints with the remove command
+    fn remove_listed(
+        cdm: &ChallengeDockerManager,
+        list: &[&str],
+        remove: &[&str],
+    ) -> Result<(), CdmError> {
+        let output = ChallengeDockerManager::run_command(
+            cdm.settings(),
+            "docker",
+            list,
+            Some(cdm.docker_env()),
+        )?;
+        let output = String::from_utf8_lossy(&output);
+        let ids: Vec<&str> = output.split_whitespace().collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let args: Vec<&str> = remove.iter().chain(&ids).copied().collect();
+        ChallengeDockerManager::run_command(
+            cdm.settings(),
+            "docker",
+            &args,
+            Some(cdm.docker_env()),
+        )?;
+        Ok(())
+    }
+
+    // backends running outside this host pull the built images from a registry
+    fn build_commands(
+        cdm: &ChallengeDockerManager,
+        push: bool,
+        cache_yml: Option<&Path>,
+    ) -> Vec<PlannedCommand> {
+        let mut commands = vec![ComposeBackend::build_command(cdm, cache_yml)];
+        if push {
+            commands.push(ComposeBackend::command(
+                cdm,
+                &["--file", &cdm.docker_compose_yml.to_string_lossy(), "push"],
+            ));
+        }
+        commands
+    }
+
+    // the generated files up puts over the compose file of the challenge, by file name
+    pub(crate) fn overrides(
+        cdm: &ChallengeDockerManager,
+    ) -> Result<Vec<(&'static str, Value)>, String> {
+        let mut overrides = vec![("labels.yml", cdm.labels_override()?)];
+        let compose = ComposeFile::load(&cdm.docker_compose_yml)?;
+        let services: Vec<&String> = compose.services.keys().collect();
+        if let Some(content) = cdm.profile().compose_override(&services) {
+            overrides.push(("profile.yml", content));
+        }
+        let main_service = || {
+            compose
+                .main_service(&cdm.main_container_name, cdm.id)
+                .map(|(service, _)| service.to_string())
+                .ok_or_else(|| format!("{} has no main service", cdm.docker_compose_yml.display()))
+        };
+        if let Some(command_override) = cdm.command_override() {
+            let service = main_service()?;
+            let mut services = serde_json::Map::new();
+            services.insert(
+                service.to_string(),
+                serde_json::to_value(command_override)
+                    .map_err(|e| format!("Failed to serialize command.yml: {}", e))?,
+            );
+            overrides.push(("command.yml", serde_json::json!({ "services": services })));
+        }
+        if !cdm.challenge_docker_config.sysctls.is_empty()
+            && let Some(content) = cdm.sysctls_override(&main_service()?)?
+        {
+            overrides.push(("sysctls.yml", content));
+        }
+        if let Some(gpus) = &cdm.challenge_docker_config.gpus {
+            overrides.push(("gpu.yml", gpus.compose_override(&main_service()?)));
+        }
+        if let Some(healthcheck) = cdm.healthcheck() {
+            let service = main_service()?;
+            let container_port = compose.services[&service]
+                .container_ports()
+                .first()
+                .copied();
+            overrides.push((
+                "healthcheck.yml",
+                healthcheck.compose_override(&service, container_port)?,
+            ));
+        }
+        if cdm.challenge_docker_config.is_dynamic_flag
+            && cdm.flag_delivery() == FlagDelivery::Secret
+        {
+            let mut services = serde_json::Map::new();
+            services.insert(main_service()?, serde_json::json!({ "secrets": ["flag"] }));
+            overrides.push((
+                "secret.yml",
+                serde_json::json!({
+                    "services": services,
+                    "secrets": { "flag": { "environment": flag::SECRET_ENV } },
+                }),
+            ));
+        }
+        Ok(overrides)
+    }
+
+    fn write_overrides(cdm: &ChallengeDockerManager) -> Result<Vec<PathBuf>, String> {
+        ComposeBackend::overrides(cdm)?
+            .into_iter()
+            .map(|(name, content)| write_override(&cdm.docker_compose_project_name, name, &content))
+            .collect()
+    }
+
+    // a plan only peeks at the port, reserving it would keep it from the real up for a minute
+    fn up_command(
+        cdm: &ChallengeDockerManager,
+        flag: &str,
+        overrides: &[PathBuf],
+        reserve_port: bool,
+    ) -> PlannedCommand {
+        let mut command =
+            ComposeBackend::command(cdm, &["--file", &cdm.docker_compose_yml.to_string_lossy()]);
+        for path in overrides {
+            command = command.arg("--file").arg(&path.to_string_lossy());
+        }
+        command = command
+            .arg("--project-name")
+            .arg(&cdm.docker_compose_project_name)
+            .arg("up")
+            .arg("--detach");
+        if cdm.challenge_docker_config.is_dynamic_flag {
+            command = match cdm.flag_delivery() {
+                FlagDelivery::Env => command.env("FLAG", flag),
+                FlagDelivery::Secret => command.env(flag::SECRET_ENV, flag),
+            };
+        }
+        if let Some(port) = ComposeBackend::host_port(cdm, reserve_port) {
+            command = command.env("PORT", &port.to_string());
+        }
+        command.env("ID", &cdm.id.to_string())
+    }
+
+    // for compose files publishing ${PORT}, the others get whatever docker picks
+    pub(crate) fn host_port(cdm: &ChallengeDockerManager, reserve_port: bool) -> Option<u16> {
+        cdm.fixed_port().or_else(|| {
+            cdm.port_range().and_then(|range| {
+                let host = &cdm.settings().bind_address;
+                if reserve_port {
+                    range.free_port(host, cdm.id.get())
+                } else {
+                    range.peek_port(host, cdm.id.get())
+                }
+            })
+        })
+    }
+
+    fn ps_json_command(cdm: &ChallengeDockerManager) -> PlannedCommand {
+        ComposeBackend::command(
+            cdm,
+            &[
+                "--file",
+                &cdm.docker_compose_yml.to_string_lossy(),
+                "--project-name",
+                &cdm.docker_compose_project_name,
+                "ps",
+                "--all",
+                "--format",
+                "json",
+            ],
+        )
+    }
+
+    // the main container of the instance, None once it is gone
+    fn main_entry(cdm: &ChallengeDockerManager) -> Result<Option<PsEntry>, CdmError> {
+        let output = ComposeBackend::ps_json_command(cdm).run(cdm.settings())?;
+        let entries = parse_ps(&String::from_utf8_lossy(&output))?;
+        Ok(main_entry(entries, &cdm.main_container_name))
+    }
+
+    fn down_command(cdm: &ChallengeDockerManager) -> PlannedCommand {
+        ComposeBackend::command(
+            cdm,
+            &[
+                "--file",
+                &cdm.docker_compose_yml.to_string_lossy(),
+                "--project-name",
+                &cdm.docker_compose_project_name,
+                "down",
+                "--volumes",
+                &format!("--timeout={}", cdm.settings().down_timeout),
+            ],
+        )
+    }
+}
+
+impl Backend for ComposeBackend {
+    fn name(&self) -> &'static str {
+        "compose"
+    }
+
+    fn build(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError> {
+        ComposeBackend::run_build(cdm, false)
+    }
+
+    fn up(&self, cdm: &ChallengeDockerManager, flag: &str) -> Result<u64, CdmError> {
+        cdm.check_mounts()?;
+        cdm.check_gpus()?;
+        if cdm.profile().is_verbose() {
+            for warning in cdm.capability_warnings()? {
+                eprintln!("{}: {}", cdm.challenge_docker_config.name, warning);
+            }
+        }
+        let overrides = ComposeBackend::write_overrides(cdm)?;
+        ComposeBackend::up_command(cdm, flag, &overrides, true).run(cdm.settings())?;
+
+        let mappings = self.ports(cdm)?;
+        let mapping = mappings.first().ok_or_else(|| {
+            format!(
+                "The {} container publishes no port",
+                cdm.main_container_name
+            )
+        })?;
+        Ok(mapping.host_port)
+    }
+
+    fn down(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError> {
+        ComposeBackend::down_command(cdm).run(cdm.settings())?;
+        Ok(())
+    }
+
+    // compose gives up on containers stuck in removal and leaves their network behind
+    fn force_down(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError> {
+        let filter = ComposeBackend::project_filter(cdm);
+        ComposeBackend::remove_listed(
+            cdm,
+            &["ps", "--all", "--quiet", "--filter", &filter],
+            &["rm", "--force", "--volumes"],
+        )?;
+        ComposeBackend::remove_listed(
+            cdm,
+            &["network", "ls", "--quiet", "--filter", &filter],
+            &["network", "rm"],
+        )
+    }
+
+    fn status(&self, cdm: &ChallengeDockerManager) -> Result<InstanceStatus, CdmError> {
+        Ok(ComposeBackend::main_entry(cdm)?
+            .map(|entry| entry.status())
+            .unwrap_or(InstanceStatus::NotFound))
+    }
+
+    fn is_running(&self, cdm: &ChallengeDockerManager) -> Result<bool, CdmError> {
+        ComposeBackend::has_containers(cdm, false)
+    }
+
+    fn exists(&self, cdm: &ChallengeDockerManager) -> Result<bool, CdmError> {
+        ComposeBackend::has_containers(cdm, true)
+    }
+
+    fn ports(&self, cdm: &ChallengeDockerManager) -> Result<Vec<PortMapping>, CdmError> {
+        Ok(ComposeBackend::main_entry(cdm)?
+            .map(|entry| entry.mappings())
+            .unwrap_or_default())
+    }
+
+    fn exec(&self, cdm: &ChallengeDockerManager, command: &[&str]) -> Result<Vec<u8>, CdmError> {
+        let mut args = vec!["exec", cdm.main_container_name.as_str()];
+        args.extend(command);
+        ChallengeDockerManager::run_command(cdm.settings(), "docker", &args, Some(cdm.docker_env()))
+    }
+
+    // up again, so the new replicas get the flag the running ones have
+    fn scale(
+        &self,
+        cdm: &ChallengeDockerManager,
+        service: &str,
+        replicas: u64,
+    ) -> Result<(), CdmError> {
+        // an empty flag would replace the one of the running replicas
+        let flag = match self.deployed_flag(cdm)? {
+            Some(flag) => flag,
+            None if !cdm.challenge_docker_config.is_dynamic_flag => Flag::from(""),
+            None => {
+                return Err(format!(
+                    "The flag of {} is unknown, it is not scaled",
+                    cdm.docker_compose_project_name
+                )
+                .into());
+            }
+        };
+        let overrides = ComposeBackend::write_overrides(cdm)?;
+        ComposeBackend::up_command(cdm, flag.expose(), &overrides, true)
+            .arg("--no-recreate")
+            .arg("--scale")
+            .arg(&format!("{}={}", service, replicas))
+            .run(cdm.settings())?;
+        Ok(())
+    }
+
+    fn deployed_flag(&self, cdm: &ChallengeDockerManager) -> Result<Option<Flag>, CdmError> {
+        if cdm.flag_delivery() == FlagDelivery::Secret {
+            let output = Zeroizing::new(self.exec(cdm, &["cat", flag::SECRET_PATH])?);
+            let deployed = String::from_utf8_lossy(&output);
+            return Ok(Some(Flag::from(deployed.trim_end_matches(['\r', '\n']))));
+        }
+        let output = ChallengeDockerManager::run_command(
+            cdm.settings(),
+            "docker",
+            &[
+                "inspect",
+                "--format",
+                "{{json .Config.Env}}",
+                &cdm.main_container_name,
+            ],
+            Some(cdm.docker_env()),
+        )?;
+        let output = Zeroizing::new(output);
+        let env: Zeroizing<Vec<String>> = Zeroizing::new(
+            serde_json::from_slice(&output).map_err(|e| format!("Failed to parse env: {}", e))?,
+        );
+        Ok(env
+            .iter()
+            .find_map(|var| var.strip_prefix("FLAG="))
+            .map(Flag::from))
+    }
+
+    fn plan(
+        &self,
+        cdm: &ChallengeDockerManager,
+        operation: Operation,
+        flag: &str,
+    ) -> Result<Plan, String> {
+        let plan = Plan::new(cdm, self.name(), operation);
+        Ok(match operation {
+            Operation::Build => ComposeBackend::build_plan(plan, cdm, false)?,
+            Operation::Up => {
+                let mut paths = Vec::new();
+                let mut plan = plan;
+                for (name, content) in ComposeBackend::overrides(cdm)? {
+                    let path = override_path(&cdm.docker_compose_project_name, name);
+                    plan = plan.file(path.clone(), render_override(name, &content)?);
+                    paths.push(path);
+                }
+                plan.command(ComposeBackend::up_command(cdm, flag, &paths, false))
+            }
+            Operation::Down => plan.command(ComposeBackend::down_command(cdm)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_compose_backend {
+    use super::*;
+    use crate::id::InstanceId;
+    use crate::lifecycle::UpOptions;
+    use crate::settings::CdmSettings;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_ps_command() {
+        let cdm = ChallengeDockerManager::test_manager("Comment", 2);
+        let command = ComposeBackend::ps_command(&cdm, true);
+        assert_eq!(
+            command.args,
+            [
+                "ps",
+                "--quiet",
+                "--filter",
+                "label=com.docker.compose.project=challenge-project-2-comment",
+                "--all"
+            ]
+        );
+    }
+
+    #[test]
+    fn check_env_file() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+        let cdm = ChallengeDockerManager::new(root.clone(), InstanceId::new(1)).unwrap();
+        assert_ne!(ComposeBackend::down_command(&cdm).args[0], "--env-file");
+
+        std::fs::write(root.join(".env"), "IMAGE_TAG=1.2\n").unwrap();
+        let env_file = root.join(".env").to_string_lossy().to_string();
+        let command = ComposeBackend::down_command(&cdm);
+        assert_eq!(command.args[..2], ["--env-file", env_file.as_str()]);
+        let command =
+            ComposeBackend::up_command(&cdm.with_env_file("/etc/cdm/prod.env"), "", &[], false);
+        assert_eq!(command.args[..2], ["--env-file", "/etc/cdm/prod.env"]);
+    }
+
+    #[test]
+    fn check_command_override() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+        let options = UpOptions::default()
+            .entrypoint(&["/crash-handler"])
+            .command(&["./server", "--debug"]);
+        let cdm = ChallengeDockerManager::new(root.clone(), InstanceId::new(1))
+            .unwrap()
+            .with_command_override(options.command_override);
+
+        let overrides = ComposeBackend::overrides(&cdm).unwrap();
+        let (name, content) = overrides.last().unwrap();
+        assert_eq!(*name, "command.yml");
+        assert_eq!(
+            content["services"]["web"],
+            serde_json::json!({
+                "command": ["./server", "--debug"],
+                "entrypoint": ["/crash-handler"],
+            })
+        );
+        let cdm = cdm.with_command_override(None);
+        assert!(
+            ComposeBackend::overrides(&cdm)
+                .unwrap()
+                .iter()
+                .all(|(name, _)| *name != "command.yml")
+        );
+    }
+
+    #[test]
+    fn check_flag_secret() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+        let mut cdm = ChallengeDockerManager::new(root.clone(), InstanceId::new(1)).unwrap();
+        cdm.config_mut().flag_delivery = Some(FlagDelivery::Secret);
+
+        let overrides = ComposeBackend::overrides(&cdm).unwrap();
+        let (name, content) = overrides.last().unwrap();
+        assert_eq!(*name, "secret.yml");
+        assert_eq!(
+            content["services"]["web"]["secrets"],
+            serde_json::json!(["flag"])
+        );
+        assert_eq!(content["secrets"]["flag"]["environment"], "CDM_FLAG");
+        let command = ComposeBackend::up_command(&cdm, "flag{secret}", &[], false);
+        assert!(!command.env.contains_key("FLAG"));
+        assert_eq!(command.env["CDM_FLAG"], "flag{secret}");
+    }
+
+    #[test]
+    fn check_healthcheck_override() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+        let mut cdm = ChallengeDockerManager::new(root.clone(), InstanceId::new(1)).unwrap();
+        cdm.config_mut().healthcheck = Some(toml::from_str("http = \"/\"").unwrap());
+
+        let overrides = ComposeBackend::overrides(&cdm).unwrap();
+        let (name, content) = overrides.last().unwrap();
+        assert_eq!(*name, "healthcheck.yml");
+        assert!(
+            content["services"]["web"]["healthcheck"]["test"][1]
+                .as_str()
+                .unwrap()
+                .contains("http://127.0.0.1:1337/")
+        );
+    }
+
+    #[test]
+    fn check_docker_context() {
+        let cdm = ChallengeDockerManager::test_manager("comment", 1);
+        assert!(ComposeBackend::down_command(&cdm).env.is_empty());
+
+        let cdm = cdm.with_context(Some("pwn-box".to_string()));
+        assert_eq!(
+            ComposeBackend::down_command(&cdm).env["DOCKER_CONTEXT"],
+            "pwn-box"
+        );
+        let cdm = cdm.with_context(Some("ssh://deploy@node1".to_string()));
+        let command = ComposeBackend::ps_command(&cdm, false);
+        assert_eq!(command.env["DOCKER_HOST"], "ssh://deploy@node1");
+        assert!(!command.env.contains_key("DOCKER_CONTEXT"));
+    }
+
+    #[test]
+    fn check_compose_passthrough() {
+        let passed = |cdm: &ChallengeDockerManager| {
+            ComposeBackend::down_command(cdm)
+                .process(cdm.settings())
+                .get_envs()
+                .any(|(key, _)| key == "CARGO_MANIFEST_DIR")
+        };
+        let cdm = ChallengeDockerManager::test_manager("comment", 1);
+        assert!(!passed(&cdm));
+
+        let settings = CdmSettings {
+            env_passthrough: vec!["CARGO_*".to_string()],
+            ..Default::default()
+        };
+        let cdm = cdm.with_settings(Arc::new(settings));
+        assert!(passed(&cdm));
+    }
+
+    #[test]
+    fn check_parse_ps() {
+        let lines = concat!(
+            r#"{"Name":"db-2","Service":"db","State":"running","Publishers":[{"URL":"","TargetPort":3306,"PublishedPort":0,"Protocol":"tcp"}]}"#,
+            "\n",
+            r#"{"Name":"web-2","Service":"web","State":"running","Publishers":[{"URL":"0.0.0.0","TargetPort":80,"PublishedPort":32768,"Protocol":"tcp"},{"URL":"::","TargetPort":80,"PublishedPort":32768,"Protocol":"tcp"},{"URL":"0.0.0.0","TargetPort":9999,"PublishedPort":32769,"Protocol":"udp"}]}"#,
+            "\n",
+        );
+        let entries = parse_ps(lines).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].mappings().is_empty());
+
+        let web = main_entry(entries.clone(), "missing").unwrap();
+        assert_eq!(web.service, "web");
+        assert_eq!(web.status(), InstanceStatus::Running);
+        assert_eq!(
+            web.mappings(),
+            [
+                PortMapping {
+                    container_port: 80,
+                    host_port: 32768,
+                    protocol: "tcp".to_string(),
+                },
+                PortMapping {
+                    container_port: 9999,
+                    host_port: 32769,
+                    protocol: "udp".to_string(),
+                },
+            ]
+        );
+        assert_eq!(main_entry(entries, "db-2").unwrap().service, "db");
+
+        let array = r#"[{"Name":"web-2","State":"exited","Publishers":null}]"#;
+        let entries = parse_ps(array).unwrap();
+        assert_eq!(entries[0].status(), InstanceStatus::Exited);
+        assert!(entries[0].mappings().is_empty());
+        assert!(parse_ps("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_unset_variables() {
+        let stderr = concat!(
+            "time=\"2024-01-01T00:00:00Z\" level=warning msg=\"The \\\"SECRET\\\" variable is not set. Defaulting to a blank string.\"\n",
+            "WARNING: The PORT variable is not set. Defaulting to a blank string.\n",
+            "WARNING: Found orphan containers\n",
+        );
+        assert_eq!(unset_variables(stderr), ["PORT", "SECRET"]);
+    }
+}
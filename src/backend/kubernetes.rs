@@ -0,0 +1,536 @@
+use super::{Backend, ComposeBackend, InstanceStatus, PortMapping};
+use crate::ChallengeDockerManager;
+use crate::compose::{ComposeFile, ComposeService};
+use crate::error::CdmError;
+use crate::flag::{self, FlagDelivery};
+use crate::id::InstanceId;
+use crate::labels;
+use crate::plan::{Operation, Plan, PlannedCommand};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum KubernetesExposure {
+    NodePort,
+    Ingress {
+        domain: String,
+        class: Option<String>,
+        port: u64,
+    },
+}
+
+// drives kubectl rather than kube-rs, so the cluster credentials, contexts and auth plugins are
+// the ones the operator already has configured and the crate keeps no async runtime
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KubernetesBackend {
+    pub namespace: String,
+    pub context: Option<String>,
+    pub exposure: KubernetesExposure,
+    pub network_policy: bool,
+    pub push_images: bool,
+    pub rollout_timeout: u64,
+}
+
+impl KubernetesBackend {
+    pub fn new(namespace: &str) -> Self {
+        KubernetesBackend {
+            namespace: namespace.to_string(),
+            context: None,
+            exposure: KubernetesExposure::NodePort,
+            network_policy: true,
+            push_images: true,
+            rollout_timeout: 120,
+        }
+    }
+
+    fn kubectl_command(&self, args: &[&str]) -> PlannedCommand {
+        let mut command = PlannedCommand::new("kubectl", &["--namespace", &self.namespace]);
+        if let Some(context) = &self.context {
+            command = command.arg("--context").arg(context);
+        }
+        args.iter().fold(command, |command, arg| command.arg(arg))
+    }
+
+    fn kubectl(&self, cdm: &ChallengeDockerManager, args: &[&str]) -> Result<Vec<u8>, CdmError> {
+        self.kubectl_command(args).run(cdm.settings())
+    }
+
+    fn apply_command(
+        &self,
+        cdm: &ChallengeDockerManager,
+        flag: &str,
+    ) -> Result<PlannedCommand, String> {
+        let service = KubernetesBackend::main_service(cdm)?;
+        let manifests = self.manifests(cdm, &service, flag)?;
+        let input = serde_json::to_string_pretty(&manifests).map_err(|e| e.to_string())?;
+        Ok(self
+            .kubectl_command(&["apply", "--filename", "-"])
+            .input(input))
+    }
+
+    fn delete_command(&self, cdm: &ChallengeDockerManager) -> PlannedCommand {
+        let selector = format!(
+            "cdm/project={}",
+            resource_name(&cdm.docker_compose_project_name)
+        );
+        self.kubectl_command(&[
+            "delete",
+            "deployment,service,ingress,networkpolicy,secret",
+            "--selector",
+            &selector,
+            "--ignore-not-found",
+        ])
+    }
+
+    fn main_service(cdm: &ChallengeDockerManager) -> Result<ComposeService, String> {
+        let compose = ComposeFile::load(&cdm.docker_compose_yml)?;
+        let (_, service) = compose
+            .main_service(&cdm.main_container_name, cdm.id)
+            .ok_or(format!(
+                "The {} has no service",
+                cdm.challenge_docker_config.name
+            ))?;
+        Ok(service.clone())
+    }
+
+    pub fn manifests(
+        &self,
+        cdm: &ChallengeDockerManager,
+        service: &ComposeService,
+        flag: &str,
+    ) -> Result<Value, String> {
+        let name = resource_name(&cdm.docker_compose_project_name);
+        let image = service.image.as_ref().ok_or(format!(
+            "The {} main service has no image, which Kubernetes requires",
+            cdm.challenge_docker_config.name
+        ))?;
+        let container_ports = service.container_ports();
+        if container_ports.is_empty() {
+            return Err(format!(
+                "The {} main service publishes no port",
+                cdm.challenge_docker_config.name
+            ));
+        }
+
+        let mut labels: serde_json::Map<String, Value> = cdm
+            .labels()
+            .into_iter()
+            .map(|(key, value)| (key, Value::String(label_value(&value))))
+            .collect();
+        labels.insert("app.kubernetes.io/managed-by".into(), json!("cdm"));
+        labels.insert("cdm/project".into(), json!(name));
+
+        let mut env = vec![json!({"name": "ID", "value": cdm.id.to_string()})];
+        let secret = cdm.challenge_docker_config.is_dynamic_flag
+            && cdm.flag_delivery() == FlagDelivery::Secret;
+        if cdm.challenge_docker_config.is_dynamic_flag && !secret {
+            env.push(json!({"name": "FLAG", "value": flag}));
+        }
+        let secret_name = format!("{}-flag", name);
+
+        let mut items = vec![
+            json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": {"name": name, "labels": labels},
+                "spec": {
+                    "replicas": 1,
+                    "selector": {"matchLabels": {"cdm/project": name}},
+                    "template": {
+                        "metadata": {"labels": labels},
+                        "spec": {
+                            "automountServiceAccountToken": false,
+                            "containers": [{
+                                "name": "main",
+                                "image": image,
+                                "env": env,
+                                "ports": container_ports
+                                    .iter()
+                                    .map(|port| json!({"containerPort": port}))
+                                    .collect::<Vec<_>>(),
+                            }],
+                        },
+                    },
+                },
+            }),
+            json!({
+                "apiVersion": "v1",
+                "kind": "Service",
+                "metadata": {"name": name, "labels": labels},
+                "spec": {
+                    "type": match self.exposure {
+                        KubernetesExposure::NodePort => "NodePort",
+                        KubernetesExposure::Ingress { .. } => "ClusterIP",
+                    },
+                    "selector": {"cdm/project": name},
+                    "ports": container_ports
+                        .iter()
+                        .map(|port| json!({"name": format!("port-{}", port), "port": port, "targetPort": port}))
+                        .collect::<Vec<_>>(),
+                },
+            }),
+        ];
+
+        // mounted where the compose secret would be, it stays out of the pod spec
+        if secret {
+            let pod = &mut items[0]["spec"]["template"]["spec"];
+            pod["volumes"] = json!([{"name": "flag", "secret": {"secretName": secret_name}}]);
+            pod["containers"][0]["volumeMounts"] = json!([{
+                "name": "flag",
+                "mountPath": flag::SECRET_PATH,
+                "subPath": "flag",
+                "readOnly": true,
+            }]);
+            items.insert(
+                0,
+                json!({
+                    "apiVersion": "v1",
+                    "kind": "Secret",
+                    "metadata": {"name": secret_name, "labels": labels},
+                    "type": "Opaque",
+                    "stringData": {"flag": flag},
+                }),
+            );
+        }
+
+        if let KubernetesExposure::Ingress { domain, class, .. } = &self.exposure {
+            let mut spec = json!({
+                "rules": [{
+                    "host": format!("{}.{}", name, domain),
+                    "http": {"paths": [{
+                        "path": "/",
+                        "pathType": "Prefix",
+                        "backend": {"service": {"name": name, "port": {"number": container_ports[0]}}},
+                    }]},
+                }],
+            });
+            if let Some(class) = class {
+                spec["ingressClassName"] = json!(class);
+            }
+            items.push(json!({
+                "apiVersion": "networking.k8s.io/v1",
+                "kind": "Ingress",
+                "metadata": {"name": name, "labels": labels},
+                "spec": spec,
+            }));
+        }
+
+        if self.network_policy {
+            // only the challenge ports are reachable, and the instance only talks to dns
+            items.push(json!({
+                "apiVersion": "networking.k8s.io/v1",
+                "kind": "NetworkPolicy",
+                "metadata": {"name": name, "labels": labels},
+                "spec": {
+                    "podSelector": {"matchLabels": {"cdm/project": name}},
+                    "policyTypes": ["Ingress", "Egress"],
+                    "ingress": [{
+                        "ports": container_ports
+                            .iter()
+                            .map(|port| json!({"port": port}))
+                            .collect::<Vec<_>>(),
+                    }],
+                    "egress": [{
+                        "ports": [{"port": 53, "protocol": "UDP"}, {"port": 53, "protocol": "TCP"}],
+                    }],
+                },
+            }));
+        }
+
+        Ok(json!({"apiVersion": "v1", "kind": "List", "items": items}))
+    }
+
+    // None once it is gone, kubectl prints nothing for a missing one
+    fn get(
+        &self,
+        cdm: &ChallengeDockerManager,
+        kind: &str,
+        name: &str,
+    ) -> Result<Option<Value>, CdmError> {
+        let output = self.kubectl(
+            cdm,
+            &["get", kind, name, "--ignore-not-found", "--output", "json"],
+        )?;
+        if output.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        Ok(serde_json::from_slice(&output)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse kubectl output: {}", e))?)
+    }
+}
+
+// the instance id labels of a kubectl get list
+fn parse_instance_ids(list: &Value) -> Vec<InstanceId> {
+    let ids: BTreeSet<InstanceId> = list["items"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            item["metadata"]["labels"][labels::INSTANCE_ID]
+                .as_str()?
+                .parse()
+                .ok()
+        })
+        .collect();
+    ids.into_iter().collect()
+}
+
+// label values may keep case, dots and underscores but are as short
+pub(crate) fn label_value(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+            _ => '-',
+        })
+        .take(63)
+        .collect();
+    value
+        .trim_matches(|c: char| !c.is_ascii_alphanumeric())
+        .to_string()
+}
+
+// kubernetes names must be lowercase rfc 1123 labels
+fn resource_name(name: &str) -> String {
+    let name: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let name: String = name.trim_matches('-').chars().take(63).collect();
+    // the cut may end on a dash
+    name.trim_end_matches('-').to_string()
+}
+
+impl Backend for KubernetesBackend {
+    fn name(&self) -> &'static str {
+        "kubernetes"
+    }
+
+    fn build(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError> {
+        ComposeBackend::run_build(cdm, self.push_images)
+    }
+
+    fn up(&self, cdm: &ChallengeDockerManager, flag: &str) -> Result<u64, CdmError> {
+        self.apply_command(cdm, flag)?.run(cdm.settings())?;
+
+        let name = resource_name(&cdm.docker_compose_project_name);
+        self.kubectl(
+            cdm,
+            &[
+                "rollout",
+                "status",
+                &format!("deployment/{}", name),
+                &format!("--timeout={}s", self.rollout_timeout),
+            ],
+        )?;
+
+        let ports = self.ports(cdm)?;
+        ports
+            .first()
+            .map(|mapping| mapping.host_port)
+            .ok_or_else(|| format!("The {} exposes no port", name).into())
+    }
+
+    fn down(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError> {
+        self.delete_command(cdm).run(cdm.settings())?;
+        Ok(())
+    }
+
+    fn status(&self, cdm: &ChallengeDockerManager) -> Result<InstanceStatus, CdmError> {
+        let name = resource_name(&cdm.docker_compose_project_name);
+        let Some(deployment) = self.get(cdm, "deployment", &name)? else {
+            return Ok(InstanceStatus::NotFound);
+        };
+
+        let replicas = deployment["spec"]["replicas"].as_u64().unwrap_or(1);
+        let ready = deployment["status"]["readyReplicas"].as_u64().unwrap_or(0);
+        Ok(match (replicas, ready) {
+            (0, _) => InstanceStatus::Exited,
+            (r, n) if n >= r => InstanceStatus::Running,
+            _ => InstanceStatus::Starting,
+        })
+    }
+
+    fn ports(&self, cdm: &ChallengeDockerManager) -> Result<Vec<PortMapping>, CdmError> {
+        let name = resource_name(&cdm.docker_compose_project_name);
+        let Some(service) = self.get(cdm, "service", &name)? else {
+            return Ok(Vec::new());
+        };
+
+        let empty = Vec::new();
+        let ports = service["spec"]["ports"].as_array().unwrap_or(&empty);
+        Ok(ports
+            .iter()
+            .filter_map(|port| {
+                let host_port = match &self.exposure {
+                    KubernetesExposure::NodePort => port["nodePort"].as_u64()?,
+                    KubernetesExposure::Ingress { port, .. } => *port,
+                };
+                Some(PortMapping {
+                    container_port: port["targetPort"].as_u64()?,
+                    host_port,
+                    protocol: port["protocol"].as_str().unwrap_or("TCP").to_lowercase(),
+                })
+            })
+            .collect())
+    }
+
+    fn instances(&self, cdm: &ChallengeDockerManager) -> Result<Vec<InstanceId>, CdmError> {
+        let selector = format!(
+            "{}={}",
+            labels::CHALLENGE,
+            label_value(&cdm.challenge_docker_config.name)
+        );
+        let output = self.kubectl(
+            cdm,
+            &[
+                "get",
+                "deployments",
+                "--selector",
+                &selector,
+                "--output",
+                "json",
+            ],
+        )?;
+        let list: Value = serde_json::from_slice(&output)
+            .map_err(|e| format!("Failed to parse kubectl output: {}", e))?;
+        Ok(parse_instance_ids(&list))
+    }
+
+    fn exec(&self, cdm: &ChallengeDockerManager, command: &[&str]) -> Result<Vec<u8>, CdmError> {
+        let deployment = format!(
+            "deployment/{}",
+            resource_name(&cdm.docker_compose_project_name)
+        );
+        let mut args = vec!["exec", deployment.as_str(), "--container", "main", "--"];
+        args.extend(command);
+        self.kubectl(cdm, &args)
+    }
+
+    fn plan(
+        &self,
+        cdm: &ChallengeDockerManager,
+        operation: Operation,
+        flag: &str,
+    ) -> Result<Plan, String> {
+        let mut plan = Plan::new(cdm, self.name(), operation);
+        match operation {
+            Operation::Build => {
+                plan = ComposeBackend::build_plan(plan, cdm, self.push_images)?;
+            }
+            Operation::Up => plan = plan.command(self.apply_command(cdm, flag)?),
+            Operation::Down => plan = plan.command(self.delete_command(cdm)),
+        }
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod test_kubernetes {
+    use super::*;
+
+    #[test]
+    fn check_resource_name() {
+        assert_eq!(
+            resource_name("challenge-project-1-Web_Chall"),
+            "challenge-project-1-web-chall"
+        );
+        let long = format!("challenge-project-1-{}_{}", "a".repeat(42), "b".repeat(8));
+        let name = resource_name(&long);
+        assert_eq!(name, format!("challenge-project-1-{}", "a".repeat(42)));
+        assert!(name.len() <= 63 && !name.ends_with('-'));
+    }
+
+    #[test]
+    fn check_parse_instance_ids() {
+        let list = json!({"items": [
+            {"metadata": {"labels": {labels::INSTANCE_ID: "4"}}},
+            {"metadata": {"labels": {}}},
+            {"metadata": {"labels": {labels::INSTANCE_ID: "2"}}},
+        ]});
+        assert_eq!(
+            parse_instance_ids(&list),
+            [InstanceId::new(2), InstanceId::new(4)]
+        );
+        assert!(parse_instance_ids(&json!({"items": []})).is_empty());
+    }
+
+    #[test]
+    fn check_label_value() {
+        assert_eq!(label_value("Web Chall"), "Web-Chall");
+        assert_eq!(label_value("0.1.0"), "0.1.0");
+        assert_eq!(label_value(&"a".repeat(70)).len(), 63);
+        assert_eq!(label_value("-héllo_"), "h-llo");
+    }
+
+    #[test]
+    fn check_manifests() {
+        let cdm = ChallengeDockerManager::test_manager("comment", 3);
+        let service = ComposeService {
+            image: Some("registry.local/comment:latest".to_string()),
+            ports: vec![serde_yaml::Value::String("80".into())],
+            ..Default::default()
+        };
+
+        let mut backend = KubernetesBackend::new("ctf");
+        backend.exposure = KubernetesExposure::Ingress {
+            domain: "ctf.local".to_string(),
+            class: None,
+            port: 443,
+        };
+        let manifests = backend.manifests(&cdm, &service, "flag{k8s}").unwrap();
+        let kinds: Vec<_> = manifests["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, ["Deployment", "Service", "Ingress", "NetworkPolicy"]);
+        assert_eq!(
+            manifests["items"][0]["spec"]["template"]["spec"]["containers"][0]["env"][1]["value"],
+            "flag{k8s}"
+        );
+        let labels = &manifests["items"][0]["metadata"]["labels"];
+        assert_eq!(labels[labels::CHALLENGE], "comment");
+        assert_eq!(labels[labels::INSTANCE_ID], "3");
+        assert_eq!(labels[labels::VERSION], env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            manifests["items"][2]["spec"]["rules"][0]["host"],
+            "challenge-project-3-comment.ctf.local"
+        );
+    }
+
+    #[test]
+    fn check_secret_manifests() {
+        let mut cdm = ChallengeDockerManager::test_manager("comment", 3);
+        cdm.config_mut().flag_delivery = Some(FlagDelivery::Secret);
+        let service = ComposeService {
+            image: Some("registry.local/comment:latest".to_string()),
+            ports: vec![serde_yaml::Value::String("80".into())],
+            ..Default::default()
+        };
+        let manifests = KubernetesBackend::new("ctf")
+            .manifests(&cdm, &service, "flag{k8s}")
+            .unwrap();
+        let secret = &manifests["items"][0];
+        assert_eq!(secret["kind"], "Secret");
+        assert_eq!(secret["stringData"]["flag"], "flag{k8s}");
+        let pod = &manifests["items"][1]["spec"]["template"]["spec"];
+        assert!(
+            !pod["containers"][0]["env"]
+                .to_string()
+                .contains("flag{k8s}")
+        );
+        assert_eq!(
+            pod["containers"][0]["volumeMounts"][0]["mountPath"],
+            flag::SECRET_PATH
+        );
+        assert_eq!(
+            pod["volumes"][0]["secret"]["secretName"],
+            secret["metadata"]["name"]
+        );
+    }
+}
@@ -0,0 +1,345 @@
+use super::{Backend, InstanceStatus, PortMapping};
+use crate::ChallengeDockerManager;
+use crate::error::CdmError;
+use crate::flag::{self, Flag, FlagDelivery};
+use crate::id::InstanceId;
+use crate::plan::{Operation, Plan};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MockOperation {
+    Build,
+    Up,
+    Down,
+    Status,
+    Ports,
+    Exec,
+    Scale,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct MockCall {
+    pub operation: MockOperation,
+    pub challenge: String,
+    pub instance_id: InstanceId,
+    // only recorded for up
+    flag: Option<Flag>,
+}
+
+impl MockCall {
+    pub fn flag(&self) -> Option<&Flag> {
+        self.flag.as_ref()
+    }
+}
+
+// by hand, flags are not Clone so copies of them stay deliberate
+impl Clone for MockCall {
+    fn clone(&self) -> Self {
+        MockCall {
+            operation: self.operation,
+            challenge: self.challenge.clone(),
+            instance_id: self.instance_id,
+            flag: self.flag.as_ref().map(|flag| Flag::from(flag.expose())),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    calls: Vec<MockCall>,
+    // project name to host port
+    running: BTreeMap<String, u64>,
+    // project name to the challenge and id it was started for
+    owners: HashMap<String, (String, InstanceId)>,
+    flags: HashMap<String, String>,
+    files: HashMap<String, String>,
+    next_port: u64,
+    failures: HashMap<MockOperation, VecDeque<String>>,
+    ports: VecDeque<u64>,
+    statuses: VecDeque<InstanceStatus>,
+    // by project and service
+    replicas: HashMap<(String, String), u64>,
+}
+
+// a backend that never touches docker, for testing the code driving the manager
+#[derive(Debug)]
+pub struct MockBackend {
+    pub container_port: u64,
+    state: Mutex<MockState>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        MockBackend {
+            container_port: 80,
+            state: Mutex::new(MockState {
+                next_port: 30000,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        MockBackend::default()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        // a panicking test must not poison the other assertions
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // the next call of the operation fails with the error, scripted failures are used in order
+    pub fn fail_next(&self, operation: MockOperation, error: &str) {
+        self.state()
+            .failures
+            .entry(operation)
+            .or_default()
+            .push_back(error.to_string());
+    }
+
+    // the next up maps to this port instead of the next free one
+    pub fn respond_up(&self, port: u64) {
+        self.state().ports.push_back(port);
+    }
+
+    // the next status call reports this instead of the tracked state
+    pub fn respond_status(&self, status: InstanceStatus) {
+        self.state().statuses.push_back(status);
+    }
+
+    // a file every instance has, for exec
+    pub fn with_file(&self, path: &str, content: &str) {
+        self.state()
+            .files
+            .insert(path.to_string(), content.to_string());
+    }
+
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.state().calls.clone()
+    }
+
+    pub fn calls_of(&self, operation: MockOperation) -> Vec<MockCall> {
+        self.state()
+            .calls
+            .iter()
+            .filter(|call| call.operation == operation)
+            .cloned()
+            .collect()
+    }
+
+    // the projects that are up
+    pub fn running(&self) -> Vec<String> {
+        self.state().running.keys().cloned().collect()
+    }
+
+    pub fn replicas(&self, project: &str, service: &str) -> Option<u64> {
+        self.state()
+            .replicas
+            .get(&(project.to_string(), service.to_string()))
+            .copied()
+    }
+
+    pub fn reset(&self) {
+        *self.state() = MockState {
+            next_port: 30000,
+            ..Default::default()
+        };
+    }
+
+    fn record(
+        &self,
+        cdm: &ChallengeDockerManager,
+        operation: MockOperation,
+        flag: Option<&str>,
+    ) -> Result<(), CdmError> {
+        let mut state = self.state();
+        state.calls.push(MockCall {
+            operation,
+            challenge: cdm.challenge_docker_config.name.clone(),
+            instance_id: cdm.id,
+            flag: flag.map(Flag::from),
+        });
+        match state
+            .failures
+            .get_mut(&operation)
+            .and_then(VecDeque::pop_front)
+        {
+            Some(error) => Err(error.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Backend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn build(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError> {
+        self.record(cdm, MockOperation::Build, None)
+    }
+
+    fn up(&self, cdm: &ChallengeDockerManager, flag: &str) -> Result<u64, CdmError> {
+        self.record(cdm, MockOperation::Up, Some(flag))?;
+        let mut state = self.state();
+        let project = &cdm.docker_compose_project_name;
+        // up is idempotent, like docker-compose up
+        if let Some(port) = state.running.get(project) {
+            return Ok(*port);
+        }
+        let port = match cdm
+            .fixed_port()
+            .map(u64::from)
+            .or_else(|| state.ports.pop_front())
+        {
+            Some(port) => port,
+            None => {
+                state.next_port += 1;
+                state.next_port - 1
+            }
+        };
+        state.running.insert(project.clone(), port);
+        state.owners.insert(
+            project.clone(),
+            (cdm.challenge_docker_config.name.clone(), cdm.id),
+        );
+        state.flags.insert(project.clone(), flag.to_string());
+        Ok(port)
+    }
+
+    fn down(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError> {
+        self.record(cdm, MockOperation::Down, None)?;
+        let mut state = self.state();
+        state.running.remove(&cdm.docker_compose_project_name);
+        state.owners.remove(&cdm.docker_compose_project_name);
+        state.flags.remove(&cdm.docker_compose_project_name);
+        Ok(())
+    }
+
+    fn instances(&self, cdm: &ChallengeDockerManager) -> Result<Vec<InstanceId>, CdmError> {
+        let state = self.state();
+        let mut ids: Vec<InstanceId> = state
+            .owners
+            .values()
+            .filter(|(challenge, _)| *challenge == cdm.challenge_docker_config.name)
+            .map(|(_, id)| *id)
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn status(&self, cdm: &ChallengeDockerManager) -> Result<InstanceStatus, CdmError> {
+        self.record(cdm, MockOperation::Status, None)?;
+        let mut state = self.state();
+        if let Some(status) = state.statuses.pop_front() {
+            return Ok(status);
+        }
+        if state.running.contains_key(&cdm.docker_compose_project_name) {
+            Ok(InstanceStatus::Running)
+        } else {
+            Ok(InstanceStatus::NotFound)
+        }
+    }
+
+    fn ports(&self, cdm: &ChallengeDockerManager) -> Result<Vec<PortMapping>, CdmError> {
+        self.record(cdm, MockOperation::Ports, None)?;
+        Ok(self
+            .state()
+            .running
+            .get(&cdm.docker_compose_project_name)
+            .map(|port| PortMapping {
+                container_port: self.container_port,
+                host_port: *port,
+                protocol: "tcp".to_string(),
+            })
+            .into_iter()
+            .collect())
+    }
+
+    // understands printenv and cat, files come from with_file
+    fn exec(&self, cdm: &ChallengeDockerManager, command: &[&str]) -> Result<Vec<u8>, CdmError> {
+        self.record(cdm, MockOperation::Exec, None)?;
+        let state = self.state();
+        let project = &cdm.docker_compose_project_name;
+        if !state.running.contains_key(project) {
+            return Err(format!("No such container: {}", cdm.main_container_name).into());
+        }
+        let delivery = cdm.flag_delivery();
+        let output = match command {
+            ["printenv", "FLAG"] if delivery == FlagDelivery::Env => {
+                state.flags.get(project).map(|flag| format!("{}\n", flag))
+            }
+            ["cat", flag::SECRET_PATH] if delivery == FlagDelivery::Secret => {
+                state.flags.get(project).cloned()
+            }
+            ["cat", path] => state.files.get(*path).cloned(),
+            _ => None,
+        };
+        output
+            .map(String::into_bytes)
+            .ok_or_else(|| format!("exec {:?} failed", command).into())
+    }
+
+    fn scale(
+        &self,
+        cdm: &ChallengeDockerManager,
+        service: &str,
+        replicas: u64,
+    ) -> Result<(), CdmError> {
+        self.record(cdm, MockOperation::Scale, None)?;
+        self.state().replicas.insert(
+            (cdm.docker_compose_project_name.clone(), service.to_string()),
+            replicas,
+        );
+        Ok(())
+    }
+
+    fn deployed_flag(&self, cdm: &ChallengeDockerManager) -> Result<Option<Flag>, CdmError> {
+        Ok(self
+            .state()
+            .flags
+            .get(&cdm.docker_compose_project_name)
+            .map(|flag| Flag::from(flag.as_str())))
+    }
+
+    fn plan(
+        &self,
+        cdm: &ChallengeDockerManager,
+        operation: Operation,
+        _flag: &str,
+    ) -> Result<Plan, String> {
+        Ok(Plan::new(cdm, self.name(), operation))
+    }
+}
+
+#[cfg(test)]
+mod test_mock {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_spawn_and_teardown() {
+        let mock = Arc::new(MockBackend::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 1).with_backend(mock.clone());
+
+        mock.respond_up(31337);
+        assert_eq!(cdm.up(&"flag{mock}".into()).unwrap(), 31337);
+        assert_eq!(cdm.status().unwrap(), InstanceStatus::Running);
+        assert_eq!(mock.running(), ["challenge-project-1-comment"]);
+
+        mock.fail_next(MockOperation::Down, "daemon went away");
+        assert_eq!(cdm.down().unwrap_err(), "daemon went away");
+        cdm.down().unwrap();
+        assert_eq!(cdm.status().unwrap(), InstanceStatus::NotFound);
+
+        let ups = mock.calls_of(MockOperation::Up);
+        assert_eq!(ups.len(), 1);
+        assert_eq!(ups[0].flag().map(Flag::expose), Some("flag{mock}"));
+        assert!(!format!("{:?}", ups).contains("flag{mock}"));
+        assert_eq!(mock.calls_of(MockOperation::Down).len(), 2);
+    }
+}
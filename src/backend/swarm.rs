@@ -0,0 +1,510 @@
+use super::{Backend, ComposeBackend, InstanceStatus, PortMapping};
+use crate::ChallengeDockerManager;
+use crate::compose::{ComposeFile, override_path, render_override, write_override};
+use crate::error::CdmError;
+use crate::flag::FlagDelivery;
+use crate::id::InstanceId;
+use crate::labels;
+use crate::plan::{Operation, Plan, PlannedCommand};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// the [swarm] table of FloatCTF.toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SwarmConfig {
+    pub replicas: Option<u64>,
+    #[serde(default)]
+    pub constraints: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwarmBackend {
+    pub replicas: u64,
+    pub constraints: Vec<String>,
+    pub push_images: bool,
+    pub deploy_timeout: u64,
+}
+
+#[derive(Deserialize)]
+struct EndpointPort {
+    #[serde(rename = "Protocol")]
+    protocol: String,
+    #[serde(rename = "TargetPort")]
+    target_port: u64,
+    #[serde(rename = "PublishedPort")]
+    published_port: Option<u64>,
+}
+
+impl Default for SwarmBackend {
+    fn default() -> Self {
+        SwarmBackend {
+            replicas: 1,
+            constraints: Vec::new(),
+            push_images: true,
+            deploy_timeout: 120,
+        }
+    }
+}
+
+impl SwarmBackend {
+    pub fn new() -> Self {
+        SwarmBackend::default()
+    }
+
+    fn service_name(cdm: &ChallengeDockerManager) -> Result<String, String> {
+        let compose = ComposeFile::load(&cdm.docker_compose_yml)?;
+        let (service, _) = compose
+            .main_service(&cdm.main_container_name, cdm.id)
+            .ok_or(format!(
+                "The {} has no service",
+                cdm.challenge_docker_config.name
+            ))?;
+        Ok(format!("{}_{}", cdm.docker_compose_project_name, service))
+    }
+
+    // the placement of the challenge wins over the defaults of the backend
+    fn deploy_override(&self, cdm: &ChallengeDockerManager) -> Result<serde_json::Value, String> {
+        // stack deploy only creates secrets from files, the flag would have to be written to disk
+        if cdm.challenge_docker_config.is_dynamic_flag
+            && cdm.flag_delivery() == FlagDelivery::Secret
+        {
+            return Err(format!(
+                "The {} wants its flag as a secret, which the swarm backend cannot deliver",
+                cdm.challenge_docker_config.name
+            ));
+        }
+        let compose = ComposeFile::load(&cdm.docker_compose_yml)?;
+        let swarm = cdm
+            .challenge_docker_config
+            .swarm
+            .clone()
+            .unwrap_or_default();
+        let replicas = swarm.replicas.unwrap_or(self.replicas);
+        let constraints = if swarm.constraints.is_empty() {
+            &self.constraints
+        } else {
+            &swarm.constraints
+        };
+
+        // stack deploy ignores mem_limit, cpus and pids_limit, limits go under deploy
+        let profile = cdm.profile();
+        let mut limits = serde_json::Map::new();
+        if let Some(mem_limit) = &profile.mem_limit {
+            limits.insert("memory".to_string(), json!(mem_limit));
+        }
+        if let Some(cpus) = &profile.cpus {
+            limits.insert("cpus".to_string(), json!(cpus));
+        }
+        if let Some(pids_limit) = profile.pids_limit {
+            limits.insert("pids".to_string(), json!(pids_limit));
+        }
+
+        let labels = cdm.labels();
+        let services: serde_json::Map<_, _> = compose
+            .services
+            .keys()
+            .map(|service| {
+                let mut deploy = json!({
+                    "replicas": replicas,
+                    "placement": {"constraints": constraints},
+                    "labels": labels,
+                });
+                if !limits.is_empty() {
+                    deploy["resources"] = json!({ "limits": limits });
+                }
+                (service.clone(), json!({ "deploy": deploy }))
+            })
+            .collect();
+        Ok(json!({ "services": services }))
+    }
+
+    // the compose overrides, which stack deploy reads the same, then the deploy one
+    fn overrides(
+        &self,
+        cdm: &ChallengeDockerManager,
+    ) -> Result<Vec<(&'static str, serde_json::Value)>, String> {
+        // swarm services cannot reserve devices
+        if cdm.challenge_docker_config.gpus.is_some() {
+            return Err(format!(
+                "The {} wants a gpu, which the swarm backend cannot reserve",
+                cdm.challenge_docker_config.name
+            ));
+        }
+        let deploy = self.deploy_override(cdm)?;
+        let mut overrides = ComposeBackend::overrides(cdm)?;
+        overrides.push(("swarm.yml", deploy));
+        Ok(overrides)
+    }
+
+    fn deploy_command(
+        cdm: &ChallengeDockerManager,
+        flag: &str,
+        overrides: &[PathBuf],
+        reserve_port: bool,
+    ) -> PlannedCommand {
+        let mut command = PlannedCommand::new(
+            "docker",
+            &[
+                "stack",
+                "deploy",
+                "--compose-file",
+                &cdm.docker_compose_yml.to_string_lossy(),
+            ],
+        )
+        .on(cdm.docker_context());
+        for path in overrides {
+            command = command.arg("--compose-file").arg(&path.to_string_lossy());
+        }
+        command = command
+            .arg("--with-registry-auth")
+            .arg(&cdm.docker_compose_project_name);
+        if cdm.challenge_docker_config.is_dynamic_flag {
+            command = command.env("FLAG", flag);
+        }
+        if let Some(port) = ComposeBackend::host_port(cdm, reserve_port) {
+            command = command.env("PORT", &port.to_string());
+        }
+        command.env("ID", &cdm.id.to_string())
+    }
+
+    fn remove_command(cdm: &ChallengeDockerManager) -> PlannedCommand {
+        PlannedCommand::new("docker", &["stack", "rm", &cdm.docker_compose_project_name])
+            .on(cdm.docker_context())
+    }
+
+    // the nodes running a task of the main service
+    pub fn nodes(&self, cdm: &ChallengeDockerManager) -> Result<Vec<String>, String> {
+        let service = SwarmBackend::service_name(cdm)?;
+        let output = ChallengeDockerManager::run_command(
+            cdm.settings(),
+            "docker",
+            &[
+                "service",
+                "ps",
+                &service,
+                "--filter",
+                "desired-state=running",
+                "--format",
+                "{{.Node}}",
+            ],
+            Some(cdm.docker_env()),
+        )?;
+
+        let s = String::from_utf8(output).map_err(|e| format!("Invalid UTF-8 in output: {}", e))?;
+        let mut nodes: Vec<String> = s.lines().map(|line| line.trim().to_string()).collect();
+        nodes.sort();
+        nodes.dedup();
+        Ok(nodes)
+    }
+}
+
+// a line of docker service ls per service, the name filter is a prefix match
+fn parse_replicas(output: &str, service: &str) -> InstanceStatus {
+    let Some(replicas) = output.lines().find_map(|line| {
+        let (name, replicas) = line.split_once(' ')?;
+        (name == service).then_some(replicas)
+    }) else {
+        return InstanceStatus::NotFound;
+    };
+
+    // looks like 1/1, or 1/1 (max 1 per node)
+    let replicas = replicas.split_whitespace().next().unwrap_or_default();
+    let (running, desired) = replicas.split_once('/').unwrap_or(("0", "0"));
+    match (running.parse::<u64>(), desired.parse::<u64>()) {
+        (_, Ok(0)) => InstanceStatus::Exited,
+        (Ok(running), Ok(desired)) if running >= desired => InstanceStatus::Running,
+        _ => InstanceStatus::Starting,
+    }
+}
+
+// a label value per line, services without one are not instances
+fn parse_instance_ids(output: &str) -> Vec<InstanceId> {
+    let ids: BTreeSet<InstanceId> = output
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect();
+    ids.into_iter().collect()
+}
+
+fn parse_ports(output: &[u8]) -> Result<Vec<PortMapping>, String> {
+    let ports: Option<Vec<EndpointPort>> =
+        serde_json::from_slice(output).map_err(|e| format!("Failed to parse ports: {}", e))?;
+    Ok(ports
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|port| {
+            Some(PortMapping {
+                container_port: port.target_port,
+                host_port: port.published_port?,
+                protocol: port.protocol,
+            })
+        })
+        .collect())
+}
+
+impl Backend for SwarmBackend {
+    fn name(&self) -> &'static str {
+        "swarm"
+    }
+
+    fn build(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError> {
+        ComposeBackend::run_build(cdm, self.push_images)
+    }
+
+    fn up(&self, cdm: &ChallengeDockerManager, flag: &str) -> Result<u64, CdmError> {
+        cdm.check_mounts()?;
+        let overrides = self
+            .overrides(cdm)?
+            .into_iter()
+            .map(|(name, content)| write_override(&cdm.docker_compose_project_name, name, &content))
+            .collect::<Result<Vec<_>, _>>()?;
+        SwarmBackend::deploy_command(cdm, flag, &overrides, true).run(cdm.settings())?;
+
+        let deadline = Instant::now() + Duration::from_secs(self.deploy_timeout);
+        while self.status(cdm)? != InstanceStatus::Running {
+            if Instant::now() > deadline {
+                return Err(format!(
+                    "The stack {} is not running after {}s",
+                    cdm.docker_compose_project_name, self.deploy_timeout
+                )
+                .into());
+            }
+            std::thread::sleep(Duration::from_secs(1));
+        }
+
+        let ports = self.ports(cdm)?;
+        ports
+            .first()
+            .map(|mapping| mapping.host_port)
+            .ok_or_else(|| {
+                format!(
+                    "The stack {} publishes no port",
+                    cdm.docker_compose_project_name
+                )
+                .into()
+            })
+    }
+
+    fn down(&self, cdm: &ChallengeDockerManager) -> Result<(), CdmError> {
+        SwarmBackend::remove_command(cdm).run(cdm.settings())?;
+        Ok(())
+    }
+
+    fn status(&self, cdm: &ChallengeDockerManager) -> Result<InstanceStatus, CdmError> {
+        let service = SwarmBackend::service_name(cdm)?;
+        let output = ChallengeDockerManager::run_command(
+            cdm.settings(),
+            "docker",
+            &[
+                "service",
+                "ls",
+                "--filter",
+                &format!("name={}", service),
+                "--format",
+                "{{.Name}} {{.Replicas}}",
+            ],
+            Some(cdm.docker_env()),
+        )?;
+
+        let s = String::from_utf8(output).map_err(|e| format!("Invalid UTF-8 in output: {}", e))?;
+        Ok(parse_replicas(&s, &service))
+    }
+
+    fn ports(&self, cdm: &ChallengeDockerManager) -> Result<Vec<PortMapping>, CdmError> {
+        let service = SwarmBackend::service_name(cdm)?;
+        let output = ChallengeDockerManager::run_command(
+            cdm.settings(),
+            "docker",
+            &[
+                "service",
+                "inspect",
+                "--format",
+                "{{json .Endpoint.Ports}}",
+                &service,
+            ],
+            Some(cdm.docker_env()),
+        )?;
+
+        Ok(parse_ports(&output)?)
+    }
+
+    // service ls cannot print labels, inspect the services it found instead
+    fn instances(&self, cdm: &ChallengeDockerManager) -> Result<Vec<InstanceId>, CdmError> {
+        let output = ChallengeDockerManager::run_command(
+            cdm.settings(),
+            "docker",
+            &[
+                "service",
+                "ls",
+                "--quiet",
+                "--filter",
+                &labels::filter(labels::CHALLENGE, Some(&cdm.challenge_docker_config.name)),
+            ],
+            Some(cdm.docker_env()),
+        )?;
+        let s = String::from_utf8(output).map_err(|e| format!("Invalid UTF-8 in output: {}", e))?;
+        let services: Vec<&str> = s.split_whitespace().collect();
+        if services.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let format = format!("{{{{index .Spec.Labels \"{}\"}}}}", labels::INSTANCE_ID);
+        let mut args = vec!["service", "inspect", "--format", format.as_str()];
+        args.extend(services);
+        let output = ChallengeDockerManager::run_command(
+            cdm.settings(),
+            "docker",
+            &args,
+            Some(cdm.docker_env()),
+        )?;
+        Ok(parse_instance_ids(&String::from_utf8_lossy(&output)))
+    }
+
+    fn plan(
+        &self,
+        cdm: &ChallengeDockerManager,
+        operation: Operation,
+        flag: &str,
+    ) -> Result<Plan, String> {
+        let mut plan = Plan::new(cdm, self.name(), operation);
+        match operation {
+            Operation::Build => {
+                plan = ComposeBackend::build_plan(plan, cdm, self.push_images)?;
+            }
+            Operation::Up => {
+                let mut paths = Vec::new();
+                for (name, content) in self.overrides(cdm)? {
+                    let path = override_path(&cdm.docker_compose_project_name, name);
+                    plan = plan.file(path.clone(), render_override(name, &content)?);
+                    paths.push(path);
+                }
+                plan = plan.command(SwarmBackend::deploy_command(cdm, flag, &paths, false));
+            }
+            Operation::Down => plan = plan.command(SwarmBackend::remove_command(cdm)),
+        }
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod test_swarm {
+    use super::*;
+    use crate::gpu::GpuConfig;
+    use crate::profile::Profile;
+    use crate::testing::TestChallenge;
+
+    #[test]
+    fn check_deploy_override() {
+        let challenge = TestChallenge::new("comment").unwrap();
+        let mut cdm = challenge.manager().unwrap();
+        let backend = SwarmBackend {
+            replicas: 2,
+            constraints: vec!["node.role==worker".to_string()],
+            ..SwarmBackend::new()
+        };
+        let content = backend.deploy_override(&cdm).unwrap();
+        let deploy = &content["services"]["web"]["deploy"];
+        assert_eq!(deploy["replicas"], 2);
+        assert_eq!(deploy["placement"]["constraints"][0], "node.role==worker");
+        assert_eq!(deploy["labels"]["cdm.challenge"], "comment");
+
+        cdm.config_mut().swarm = Some(SwarmConfig {
+            replicas: Some(3),
+            constraints: vec!["node.labels.gpu==true".to_string()],
+        });
+        let content = backend.deploy_override(&cdm).unwrap();
+        let deploy = &content["services"]["web"]["deploy"];
+        assert_eq!(deploy["replicas"], 3);
+        assert_eq!(
+            deploy["placement"]["constraints"][0],
+            "node.labels.gpu==true"
+        );
+
+        cdm.config_mut().flag_delivery = Some(FlagDelivery::Secret);
+        assert!(backend.deploy_override(&cdm).is_err());
+    }
+
+    #[test]
+    fn check_up_plan() {
+        let challenge = TestChallenge::new("comment").unwrap();
+        let mut cdm = challenge
+            .manager()
+            .unwrap()
+            .with_port(Some(31337))
+            .with_profile("small");
+        cdm.config_mut().profiles.insert(
+            "small".to_string(),
+            Profile {
+                mem_limit: Some("256m".to_string()),
+                ..Default::default()
+            },
+        );
+        let backend = SwarmBackend::new();
+        let plan = backend.plan(&cdm, Operation::Up, "flag{swarm}").unwrap();
+        let files: Vec<String> = plan
+            .files
+            .iter()
+            .map(|file| file.path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert!(files.iter().any(|file| file.ends_with("labels.yml")));
+        assert!(files.last().unwrap().ends_with("swarm.yml"));
+        let deploy = &plan.commands[0];
+        assert_eq!(deploy.env["PORT"], "31337");
+        let compose_files = deploy.args.iter().filter(|arg| *arg == "--compose-file");
+        assert_eq!(compose_files.count(), files.len() + 1);
+        let content = backend.deploy_override(&cdm).unwrap();
+        assert_eq!(
+            content["services"]["web"]["deploy"]["resources"]["limits"]["memory"],
+            "256m"
+        );
+
+        cdm.config_mut().gpus = Some(GpuConfig::default());
+        assert!(backend.plan(&cdm, Operation::Up, "flag{swarm}").is_err());
+    }
+
+    #[test]
+    fn check_parse_replicas() {
+        let service = "challenge-project-1-comment_web";
+        let output = "challenge-project-1-comment_web2 0/1\nchallenge-project-1-comment_web 1/1 (max 1 per node)\n";
+        assert_eq!(parse_replicas(output, service), InstanceStatus::Running);
+        assert_eq!(
+            parse_replicas("challenge-project-1-comment_web 0/2\n", service),
+            InstanceStatus::Starting
+        );
+        assert_eq!(
+            parse_replicas("challenge-project-1-comment_web 0/0\n", service),
+            InstanceStatus::Exited
+        );
+        assert_eq!(
+            parse_replicas("challenge-project-1-comment_web2 1/1\n", service),
+            InstanceStatus::NotFound
+        );
+    }
+
+    #[test]
+    fn check_parse_ports() {
+        let output = br#"[{"Protocol":"tcp","TargetPort":1337,"PublishedPort":30001},{"Protocol":"udp","TargetPort":53}]"#;
+        assert_eq!(
+            parse_ports(output).unwrap(),
+            [PortMapping {
+                container_port: 1337,
+                host_port: 30001,
+                protocol: "tcp".to_string(),
+            }]
+        );
+        assert!(parse_ports(b"null").unwrap().is_empty());
+        assert!(parse_ports(b"not json").is_err());
+    }
+
+    #[test]
+    fn check_parse_instance_ids() {
+        assert_eq!(
+            parse_instance_ids("2\n1\n\n2\nnot-an-id\n"),
+            [InstanceId::new(1), InstanceId::new(2)]
+        );
+        assert!(parse_instance_ids("").is_empty());
+    }
+}
@@ -0,0 +1,310 @@
+use crate::Category;
+use crate::backend::{Backend, InstanceStatus};
+use crate::dto::DTO_VERSION;
+use crate::events::Event;
+use crate::freeze::{self, Freeze};
+use crate::maintenance::{self, MaintenanceInfo, MaintenanceRegistry};
+use crate::schedule::{self, Schedule};
+use crate::state::InstanceState;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use strum::{AsRefStr, Display};
+use zip::write::SimpleFileOptions;
+use zip::{DateTime, ZipArchive, ZipWriter};
+
+fn dto_version() -> u32 {
+    DTO_VERSION
+}
+
+// everything the platform would need to pick up where it was, one file per part in the archive
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    #[serde(default = "dto_version")]
+    pub version: u32,
+    pub created_at: u64,
+    // the ports of every instance are its port reservations
+    pub instances: Vec<InstanceState>,
+    pub schedule: Schedule,
+    pub maintenance: BTreeMap<String, MaintenanceInfo>,
+    // the exceptions, while frozen
+    pub freeze: Option<Vec<Category>>,
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, AsRefStr, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Reconciliation {
+    // live and as recorded
+    Unchanged,
+    // live, but the status or ports moved on since the backup
+    Updated,
+    // not there anymore, dropped from the restored registry
+    Gone,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconciledInstance {
+    // the live state, or the recorded one for instances that are gone
+    pub state: InstanceState,
+    pub reconciliation: Reconciliation,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub snapshot: Snapshot,
+    pub instances: Vec<ReconciledInstance>,
+}
+
+impl RestoreReport {
+    // what the registry holds after the restore
+    pub fn live(&self) -> Vec<&InstanceState> {
+        self.instances
+            .iter()
+            .filter(|instance| instance.reconciliation != Reconciliation::Gone)
+            .map(|instance| &instance.state)
+            .collect()
+    }
+}
+
+const PARTS: [&str; 5] = [
+    "snapshot.json",
+    "instances.json",
+    "schedule.json",
+    "maintenance.json",
+    "events.json",
+];
+
+fn read_part<T: DeserializeOwned>(
+    archive: &mut ZipArchive<File>,
+    path: &Path,
+    name: &str,
+) -> Result<T, String> {
+    let mut content = String::new();
+    archive
+        .by_name(name)
+        .map_err(|e| format!("Failed to read {} from {}: {}", name, path.display(), e))?
+        .read_to_string(&mut content)
+        .map_err(|e| format!("Failed to read {} from {}: {}", name, path.display(), e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse {} from {}: {}", name, path.display(), e))
+}
+
+impl Snapshot {
+    pub fn capture(
+        instances: Vec<InstanceState>,
+        schedule: &Schedule,
+        maintenance: &MaintenanceRegistry,
+        freeze: &Freeze,
+        events: Vec<Event>,
+    ) -> Self {
+        Snapshot {
+            version: DTO_VERSION,
+            created_at: schedule::now(),
+            instances,
+            schedule: schedule.clone(),
+            maintenance: maintenance.list(),
+            freeze: freeze.is_frozen().then(|| freeze.exceptions()),
+            events,
+        }
+    }
+
+    pub fn backup(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let mut zip = ZipWriter::new(file);
+        let options = SimpleFileOptions::default().last_modified_time(DateTime::DEFAULT);
+        let zip_error =
+            |e: zip::result::ZipError| format!("Failed to write {}: {}", path.display(), e);
+
+        let header = serde_json::json!({
+            "version": self.version,
+            "created_at": self.created_at,
+            "freeze": self.freeze,
+        });
+        let parts = [
+            serde_json::to_vec_pretty(&header),
+            serde_json::to_vec_pretty(&self.instances),
+            serde_json::to_vec_pretty(&self.schedule),
+            serde_json::to_vec_pretty(&self.maintenance),
+            serde_json::to_vec_pretty(&self.events),
+        ];
+        for (name, content) in PARTS.iter().zip(parts) {
+            let content = content.map_err(|e| format!("Failed to serialize {}: {}", name, e))?;
+            zip.start_file(*name, options).map_err(zip_error)?;
+            zip.write_all(&content)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        zip.finish().map_err(zip_error)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let mut archive = ZipArchive::new(file)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        #[derive(Deserialize)]
+        struct Header {
+            #[serde(default = "dto_version")]
+            version: u32,
+            created_at: u64,
+            freeze: Option<Vec<Category>>,
+        }
+        let header: Header = read_part(&mut archive, path, PARTS[0])?;
+        Ok(Snapshot {
+            version: header.version,
+            created_at: header.created_at,
+            instances: read_part(&mut archive, path, PARTS[1])?,
+            schedule: read_part(&mut archive, path, PARTS[2])?,
+            maintenance: read_part(&mut archive, path, PARTS[3])?,
+            freeze: header.freeze,
+            events: read_part(&mut archive, path, PARTS[4])?,
+        })
+    }
+
+    // put the maintenance entries and the freeze back the way they were
+    pub fn apply(&self, maintenance: &MaintenanceRegistry, freeze: &Freeze) {
+        for (challenge, info) in &self.maintenance {
+            maintenance.insert(challenge, info.clone());
+        }
+        match &self.freeze {
+            Some(exceptions) => freeze.freeze(exceptions),
+            None => freeze.thaw(),
+        }
+    }
+
+    // ask docker about every recorded instance, the backup may be older than what is running
+    pub fn reconcile(&self, backend: Arc<dyn Backend>) -> Vec<ReconciledInstance> {
+        self.instances
+            .iter()
+            .map(|recorded| {
+                let live = recorded
+                    .restore(backend.clone())
+                    .and_then(|cdm| cdm.state());
+                match live {
+                    Ok(live) if live.status == InstanceStatus::NotFound => ReconciledInstance {
+                        state: recorded.clone(),
+                        reconciliation: Reconciliation::Gone,
+                        error: None,
+                    },
+                    Ok(live) => {
+                        let reconciliation =
+                            if live.status == recorded.status && live.ports == recorded.ports {
+                                Reconciliation::Unchanged
+                            } else {
+                                Reconciliation::Updated
+                            };
+                        ReconciledInstance {
+                            state: InstanceState {
+                                created_at: recorded.created_at,
+                                flag_sha256: recorded.flag_sha256.clone(),
+                                ..live
+                            },
+                            reconciliation,
+                            error: None,
+                        }
+                    }
+                    // keep it, a daemon that does not answer is no proof the instance is gone
+                    Err(e) => ReconciledInstance {
+                        state: recorded.clone(),
+                        reconciliation: Reconciliation::Unchanged,
+                        error: Some(e),
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+// load the archive, apply it to the process-wide maintenance and freeze, and reconcile the instances
+pub fn restore(path: &Path, backend: Arc<dyn Backend>) -> Result<RestoreReport, String> {
+    let snapshot = Snapshot::load(path)?;
+    snapshot.apply(&maintenance::global(), &freeze::global());
+    Ok(RestoreReport {
+        instances: snapshot.reconcile(backend),
+        snapshot,
+    })
+}
+
+#[cfg(test)]
+mod test_backup {
+    use super::*;
+    use crate::ChallengeDockerManager;
+    use crate::backend::MockBackend;
+    use crate::events::{EventKind, EventLog, EventSink};
+    use crate::id::InstanceId;
+    use crate::schedule::ChallengeWindow;
+
+    #[test]
+    fn check_backup_and_restore() {
+        let dir = crate::testing::TestDir::new("backup").unwrap();
+        let path = dir.path.join("backup.zip");
+        let mock = Arc::new(MockBackend::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 1).with_backend(mock.clone());
+        let other = cdm.instance(InstanceId::new(2));
+        cdm.up(&"flag{kept}".into()).unwrap();
+        other.up(&"flag{gone}".into()).unwrap();
+        let kept = cdm.up_state(&"flag{kept}".into()).unwrap();
+        let gone = other.up_state(&"flag{gone}".into()).unwrap();
+
+        let maintenance = MaintenanceRegistry::new();
+        maintenance.set_maintenance("stack", "rebuilding");
+        let freeze = Freeze::new();
+        freeze.freeze(&[Category::Pwn]);
+        let mut schedule = Schedule::default();
+        schedule.challenges.insert(
+            "comment".to_string(),
+            ChallengeWindow {
+                opens_at: Some(100),
+                closes_at: None,
+            },
+        );
+        let log = EventLog::new();
+        log.emit(&Event::new(EventKind::Down, "comment", InstanceId::new(3)))
+            .unwrap();
+        Snapshot::capture(
+            vec![kept, gone],
+            &schedule,
+            &maintenance,
+            &freeze,
+            log.events(),
+        )
+        .backup(&path)
+        .unwrap();
+
+        other.down().unwrap();
+        let snapshot = Snapshot::load(&path).unwrap();
+        assert_eq!(snapshot.schedule, schedule);
+        assert_eq!(snapshot.events.len(), 1);
+        assert_eq!(
+            snapshot.instances[0].flag_sha256,
+            Some(crate::state::flag_sha256("flag{kept}"))
+        );
+
+        let restored_maintenance = MaintenanceRegistry::new();
+        let restored_freeze = Freeze::new();
+        snapshot.apply(&restored_maintenance, &restored_freeze);
+        assert_eq!(
+            restored_maintenance.get("stack").unwrap().reason,
+            "rebuilding"
+        );
+        assert!(restored_freeze.is_frozen_for(Category::Web));
+        assert!(!restored_freeze.is_frozen_for(Category::Pwn));
+
+        let reconciled = snapshot.reconcile(mock.clone());
+        let outcomes: Vec<Reconciliation> = reconciled
+            .iter()
+            .map(|instance| instance.reconciliation)
+            .collect();
+        assert_eq!(outcomes, [Reconciliation::Unchanged, Reconciliation::Gone]);
+        assert!(reconciled[0].state.created_at.is_some());
+    }
+}
@@ -0,0 +1,329 @@
+use crate::ChallengeDockerManager;
+use crate::flag::Flag;
+use crate::id::{InstanceId, TeamId};
+use crate::lifecycle::DownOptions;
+use crate::plan::Operation;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Instant;
+
+// one operation of a batch, the manager carries the instance and the team
+#[derive(Debug)]
+pub struct BatchJob {
+    pub manager: ChallengeDockerManager,
+    pub operation: Operation,
+    // only used for up
+    pub flag: Flag,
+    pub down_options: DownOptions,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub challenge: String,
+    pub instance_id: InstanceId,
+    pub team: Option<TeamId>,
+    pub operation: Operation,
+    // the host port, for up
+    pub port: Option<u64>,
+    pub error: Option<String>,
+    // from the batch starting, so time spent waiting for a slot shows
+    pub queued_ms: u64,
+    pub duration_ms: u64,
+}
+
+// what is left after tearing many instances down, the failed ones are worth a forced retry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TeardownSummary {
+    pub total: usize,
+    pub stopped: usize,
+    pub failed: Vec<BatchResult>,
+    pub duration_ms: u64,
+}
+
+impl TeardownSummary {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Semaphore {
+    in_use: Mutex<usize>,
+    released: Condvar,
+}
+
+// runs compose for many instances without letting all of them hit the host at once
+#[derive(Debug, Clone)]
+pub struct BatchSpawner {
+    pub concurrency: usize,
+    // shared by clones, so concurrent batches stay under one limit together
+    permits: Arc<Semaphore>,
+}
+
+impl Default for BatchSpawner {
+    fn default() -> Self {
+        BatchSpawner::new(8)
+    }
+}
+
+impl Semaphore {
+    fn acquire(&self, max: usize) {
+        let mut in_use = self.in_use.lock().unwrap_or_else(|e| e.into_inner());
+        while *in_use >= max {
+            in_use = self
+                .released
+                .wait(in_use)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        *in_use += 1;
+    }
+
+    fn release(&self) {
+        *self.in_use.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.released.notify_one();
+    }
+}
+
+impl BatchJob {
+    pub fn up(manager: ChallengeDockerManager, flag: Flag) -> Self {
+        BatchJob {
+            manager,
+            operation: Operation::Up,
+            flag,
+            down_options: DownOptions::default(),
+        }
+    }
+
+    pub fn down(manager: ChallengeDockerManager) -> Self {
+        BatchJob::down_with(manager, DownOptions::default())
+    }
+
+    pub fn down_with(manager: ChallengeDockerManager, options: DownOptions) -> Self {
+        BatchJob {
+            manager,
+            operation: Operation::Down,
+            flag: Flag::from(""),
+            down_options: options,
+        }
+    }
+
+    fn run(&self) -> Result<Option<u64>, String> {
+        match self.operation {
+            Operation::Build => self.manager.build().map(|_| None),
+            Operation::Up => self.manager.up(&self.flag).map(Some),
+            Operation::Down => self
+                .manager
+                .down_with(&self.down_options)
+                .map(|_| None)
+                .map_err(String::from),
+        }
+    }
+}
+
+// the first job of every team, then the second of every team and so on,
+// so a team clicking spawn ten times does not push everyone else back
+fn fair_order(jobs: &[BatchJob]) -> Vec<usize> {
+    let mut teams: Vec<Option<TeamId>> = Vec::new();
+    let mut queues: BTreeMap<usize, VecDeque<usize>> = BTreeMap::new();
+    for (index, job) in jobs.iter().enumerate() {
+        let team = job.manager.team;
+        let slot = match teams.iter().position(|t| *t == team) {
+            Some(slot) => slot,
+            None => {
+                teams.push(team);
+                teams.len() - 1
+            }
+        };
+        queues.entry(slot).or_default().push_back(index);
+    }
+
+    let mut order = Vec::with_capacity(jobs.len());
+    while order.len() < jobs.len() {
+        for queue in queues.values_mut() {
+            order.extend(queue.pop_front());
+        }
+    }
+    order
+}
+
+impl BatchSpawner {
+    pub fn new(concurrency: usize) -> Self {
+        BatchSpawner {
+            concurrency: concurrency.max(1),
+            permits: Arc::new(Semaphore::default()),
+        }
+    }
+
+    // results come back in the order the jobs were given, whatever order they ran in
+    pub fn run(&self, jobs: &[BatchJob]) -> Vec<BatchResult> {
+        self.run_with_progress(jobs, &|_, _, _| {})
+    }
+
+    // progress gets the number of finished jobs, the total and the result that just came in
+    pub fn run_with_progress(
+        &self,
+        jobs: &[BatchJob],
+        progress: &(dyn Fn(usize, usize, &BatchResult) + Sync),
+    ) -> Vec<BatchResult> {
+        let order = fair_order(jobs);
+        let started = Instant::now();
+        let next = Mutex::new(order.into_iter());
+        let results: Mutex<BTreeMap<usize, BatchResult>> = Mutex::new(BTreeMap::new());
+        std::thread::scope(|scope| {
+            for _ in 0..self.concurrency.min(jobs.len()) {
+                scope.spawn(|| {
+                    loop {
+                        let Some(index) = next.lock().unwrap_or_else(|e| e.into_inner()).next()
+                        else {
+                            break;
+                        };
+                        let job = &jobs[index];
+                        self.permits.acquire(self.concurrency);
+                        let queued_ms = started.elapsed().as_millis() as u64;
+                        let result = job.run();
+                        self.permits.release();
+
+                        let mut results = results.lock().unwrap_or_else(|e| e.into_inner());
+                        let result = BatchResult {
+                            challenge: job.manager.challenge_docker_config.name.clone(),
+                            instance_id: job.manager.id,
+                            team: job.manager.team,
+                            operation: job.operation,
+                            port: result.as_ref().ok().copied().flatten(),
+                            error: result.err(),
+                            queued_ms,
+                            duration_ms: started.elapsed().as_millis() as u64 - queued_ms,
+                        };
+                        progress(results.len() + 1, jobs.len(), &result);
+                        results.insert(index, result);
+                    }
+                });
+            }
+        });
+        results
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .into_values()
+            .collect()
+    }
+
+    // tear down every instance in parallel, e.g. when the event ends
+    pub fn down_all(
+        &self,
+        managers: Vec<ChallengeDockerManager>,
+        options: &DownOptions,
+        progress: &(dyn Fn(usize, usize, &BatchResult) + Sync),
+    ) -> TeardownSummary {
+        let started = Instant::now();
+        let jobs: Vec<BatchJob> = managers
+            .into_iter()
+            .map(|manager| BatchJob::down_with(manager, options.clone()))
+            .collect();
+        let results = self.run_with_progress(&jobs, progress);
+        let failed: Vec<BatchResult> = results
+            .into_iter()
+            .filter(|result| result.error.is_some())
+            .collect();
+        TeardownSummary {
+            total: jobs.len(),
+            stopped: jobs.len() - failed.len(),
+            failed,
+            duration_ms: started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_batch {
+    use super::*;
+    use crate::backend::{MockBackend, MockOperation};
+
+    #[test]
+    fn check_fair_order() {
+        let mock = Arc::new(MockBackend::new());
+        let base = ChallengeDockerManager::test_manager("comment", 0).with_backend(mock.clone());
+        // team 1 asks four times before team 2 and team 3 get their turn
+        let teams = [1, 1, 1, 1, 2, 3, 2];
+        let jobs: Vec<BatchJob> = teams
+            .iter()
+            .enumerate()
+            .map(|(id, team)| {
+                let cdm = base
+                    .instance(InstanceId::new(id as u64))
+                    .with_team(TeamId::new(*team));
+                BatchJob::up(cdm, Flag::new(format!("flag{{{}}}", id)))
+            })
+            .collect();
+        assert_eq!(fair_order(&jobs), [0, 4, 5, 1, 6, 2, 3]);
+
+        mock.fail_next(MockOperation::Up, "compose exploded");
+        let results = BatchSpawner::new(1).run(&jobs);
+        let ids: Vec<u64> = mock
+            .calls_of(MockOperation::Up)
+            .iter()
+            .map(|call| call.instance_id.get())
+            .collect();
+        assert_eq!(ids, [0, 4, 5, 1, 6, 2, 3]);
+
+        assert_eq!(results.len(), jobs.len());
+        assert_eq!(results[0].error.as_deref(), Some("compose exploded"));
+        assert!(results[1..].iter().all(|result| result.port.is_some()));
+        assert_eq!(results[4].team, Some(TeamId::new(2)));
+    }
+
+    #[test]
+    fn check_concurrency() {
+        let mock = Arc::new(MockBackend::new());
+        let base = ChallengeDockerManager::test_manager("comment", 0).with_backend(mock.clone());
+        let jobs: Vec<BatchJob> = (0..12)
+            .map(|id| BatchJob::up(base.instance(InstanceId::new(id)), "flag{x}".into()))
+            .collect();
+        let spawner = BatchSpawner::new(3);
+        let results = spawner.run(&jobs);
+        assert!(results.iter().all(|result| result.error.is_none()));
+        assert_eq!(mock.running().len(), 12);
+        assert_eq!(*spawner.permits.in_use.lock().unwrap(), 0);
+
+        let results = spawner.run(
+            &jobs
+                .into_iter()
+                .map(|job| BatchJob::down(job.manager))
+                .collect::<Vec<_>>(),
+        );
+        assert!(
+            results
+                .iter()
+                .all(|result| result.operation == Operation::Down)
+        );
+        assert!(mock.running().is_empty());
+    }
+
+    #[test]
+    fn check_down_all() {
+        let mock = Arc::new(MockBackend::new());
+        let base = ChallengeDockerManager::test_manager("comment", 0).with_backend(mock.clone());
+        let managers: Vec<ChallengeDockerManager> = (0..5)
+            .map(|id| base.instance(InstanceId::new(id)))
+            .collect();
+        for cdm in &managers {
+            cdm.up(&"flag{x}".into()).unwrap();
+        }
+
+        mock.fail_next(MockOperation::Down, "stuck in removal");
+        let done = Mutex::new(Vec::new());
+        let summary = BatchSpawner::new(2).down_all(
+            managers,
+            &DownOptions::default(),
+            &|finished, total, _| done.lock().unwrap().push((finished, total)),
+        );
+        assert_eq!((summary.total, summary.stopped), (5, 4));
+        assert_eq!(summary.failed[0].error.as_deref(), Some("stuck in removal"));
+        assert!(!summary.is_ok());
+
+        let mut done = done.into_inner().unwrap();
+        done.sort();
+        assert_eq!(done, (1..=5).map(|n| (n, 5)).collect::<Vec<_>>());
+        assert_eq!(mock.running().len(), 1);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::compose::ComposeFile;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::path::PathBuf;
+
+// where BuildKit keeps layers between CI runs, set per repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BuildCache {
+    // e.g. registry.example.com/ctf/cache, every built service gets its own tag there
+    Registry { repository: String },
+    // a directory kept by the CI runner, exporting to it needs a docker-container buildx builder
+    Local { dir: PathBuf },
+}
+
+fn cache_name(challenge: &str, service: &str) -> String {
+    format!("{}-{}", challenge, service).to_lowercase()
+}
+
+impl BuildCache {
+    pub fn cache_from(&self, challenge: &str, service: &str) -> String {
+        match self {
+            BuildCache::Registry { repository } => format!(
+                "type=registry,ref={}:{}",
+                repository,
+                cache_name(challenge, service)
+            ),
+            BuildCache::Local { dir } => format!(
+                "type=local,src={}",
+                dir.join(cache_name(challenge, service)).display()
+            ),
+        }
+    }
+
+    // mode=max keeps the intermediate layers too, which is most of what a rebuild reuses
+    pub fn cache_to(&self, challenge: &str, service: &str) -> String {
+        match self {
+            BuildCache::Registry { repository } => format!(
+                "type=registry,ref={}:{},mode=max",
+                repository,
+                cache_name(challenge, service)
+            ),
+            BuildCache::Local { dir } => format!(
+                "type=local,dest={},mode=max",
+                dir.join(cache_name(challenge, service)).display()
+            ),
+        }
+    }
+
+    // a compose override adding the cache to every service that is built, None when nothing is
+    pub fn compose_override(&self, challenge: &str, compose: &ComposeFile) -> Option<Value> {
+        let services: serde_json::Map<String, Value> = compose
+            .services
+            .iter()
+            .filter(|(_, service)| service.build.is_some())
+            .map(|(name, _)| {
+                (
+                    name.clone(),
+                    json!({"build": {
+                        "cache_from": [self.cache_from(challenge, name)],
+                        "cache_to": [self.cache_to(challenge, name)],
+                    }}),
+                )
+            })
+            .collect();
+        if services.is_empty() {
+            return None;
+        }
+        Some(json!({ "services": services }))
+    }
+}
+
+#[cfg(test)]
+mod test_build_cache {
+    use super::*;
+
+    #[test]
+    fn check_compose_override() {
+        let compose =
+            ComposeFile::parse("services:\n  db:\n    image: mysql:8\n  Web:\n    build: .\n")
+                .unwrap();
+
+        let registry = BuildCache::Registry {
+            repository: "registry.example.com/cache".to_string(),
+        };
+        let content = registry.compose_override("comment", &compose).unwrap();
+        assert!(content["services"].get("db").is_none());
+        assert_eq!(
+            content["services"]["Web"]["build"]["cache_to"][0],
+            "type=registry,ref=registry.example.com/cache:comment-web,mode=max"
+        );
+
+        let local = BuildCache::Local {
+            dir: PathBuf::from("/var/cache/cdm"),
+        };
+        assert_eq!(
+            local.cache_from("comment", "web"),
+            "type=local,src=/var/cache/cdm/comment-web"
+        );
+
+        let pulled = ComposeFile::parse("services:\n  db:\n    image: mysql:8\n").unwrap();
+        assert!(local.compose_override("comment", &pulled).is_none());
+    }
+}
@@ -0,0 +1,289 @@
+use crate::ChallengeDockerManager;
+use crate::attachments::{resolve_attachments, sha256_file};
+use crate::compose::ComposeFile;
+use crate::repository::{Challenge, ChallengeRepository};
+use crate::settings;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleOptions {
+    pub include_images: bool,
+    // also pack the bundle directory into <dir>.tar.gz
+    pub tarball: bool,
+}
+
+impl Default for BundleOptions {
+    fn default() -> Self {
+        BundleOptions {
+            include_images: true,
+            tarball: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleFile {
+    pub path: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleChallenge {
+    pub name: String,
+    pub category: String,
+    pub points: i32,
+    pub directory: String,
+    pub is_dockerd: bool,
+    pub is_dynamic_flag: bool,
+    pub images: Vec<String>,
+    pub attachments: Vec<BundleFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub created_at: u64,
+    pub commit: Option<String>,
+    pub challenges: Vec<BundleChallenge>,
+}
+
+fn copy_dir(src: &Path, dst: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dst)
+        .map_err(|e| format!("Failed to create {}: {}", dst.display(), e))?;
+    let entries =
+        std::fs::read_dir(src).map_err(|e| format!("Failed to read {}: {}", src.display(), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {}", src.display(), e))?;
+        let path = entry.path();
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let target = dst.join(entry.file_name());
+        if path.is_dir() {
+            copy_dir(&path, &target)?;
+        } else {
+            std::fs::copy(&path, &target)
+                .map_err(|e| format!("Failed to copy {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+// a plain `docker compose up` picks up the .env and docker-compose.override.yml next to the compose file
+fn write_compose_defaults(
+    challenge: &Challenge,
+    dir: &Path,
+    images: &HashMap<String, String>,
+) -> Result<(), String> {
+    let services: serde_json::Map<_, _> = images
+        .iter()
+        .map(|(service, image)| (service.clone(), json!({ "image": image })))
+        .collect();
+    let yaml = serde_yaml::to_string(&json!({ "services": services }))
+        .map_err(|e| format!("Failed to serialize override: {}", e))?;
+    let override_yml = dir.join("docker-compose.override.yml");
+    std::fs::write(&override_yml, yaml)
+        .map_err(|e| format!("Failed to write {}: {}", override_yml.display(), e))?;
+
+    let dot_env = dir.join(".env");
+    let mut content = std::fs::read_to_string(&dot_env).unwrap_or_default();
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    if !content.lines().any(|line| line.starts_with("ID=")) {
+        content.push_str("ID=0\n");
+    }
+    if challenge.config.is_dynamic_flag && !content.lines().any(|line| line.starts_with("FLAG=")) {
+        content.push_str("FLAG=flag{change_me}\n");
+    }
+    std::fs::write(&dot_env, content)
+        .map_err(|e| format!("Failed to write {}: {}", dot_env.display(), e))
+}
+
+impl ChallengeRepository {
+    // everything needed to run the challenges with plain docker compose
+    pub fn export_bundle(
+        &self,
+        out: &Path,
+        options: &BundleOptions,
+    ) -> Result<BundleManifest, String> {
+        let settings = settings::global()?;
+        std::fs::create_dir_all(out)
+            .map_err(|e| format!("Failed to create {}: {}", out.display(), e))?;
+
+        let mut challenges = Vec::new();
+        for challenge in &self.challenges {
+            challenges.push(self.export_challenge(challenge, out, options)?);
+        }
+
+        let manifest = BundleManifest {
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            commit: self.git.as_ref().map(|git| git.commit.clone()),
+            challenges,
+        };
+        let json = serde_json::to_vec_pretty(&manifest)
+            .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+        std::fs::write(out.join("manifest.json"), json)
+            .map_err(|e| format!("Failed to write manifest.json: {}", e))?;
+
+        if options.tarball {
+            let parent = out.parent().unwrap_or(Path::new("."));
+            let name = out
+                .file_name()
+                .ok_or(format!("{} is not a directory", out.display()))?;
+            let tarball = PathBuf::from(format!("{}.tar.gz", out.display()));
+            ChallengeDockerManager::run_command(
+                &settings,
+                "tar",
+                &[
+                    "-czf",
+                    &tarball.to_string_lossy(),
+                    "-C",
+                    &parent.to_string_lossy(),
+                    &name.to_string_lossy(),
+                ],
+                None,
+            )?;
+        }
+
+        Ok(manifest)
+    }
+
+    fn export_challenge(
+        &self,
+        challenge: &Challenge,
+        out: &Path,
+        options: &BundleOptions,
+    ) -> Result<BundleChallenge, String> {
+        let settings = settings::global()?;
+        let name = &challenge.config.name;
+        let dir = out.join(name);
+        copy_dir(&challenge.path, &dir)?;
+
+        let mut images = Vec::new();
+        if challenge.config.is_dockerd {
+            let compose_yml = dir.join("docker-compose.yml");
+            let compose = ComposeFile::load(&compose_yml)?;
+
+            // built services get a stable tag so the saved images are found again
+            let built: HashMap<String, String> = compose
+                .services
+                .iter()
+                .filter(|(_, service)| service.image.is_none())
+                .map(|(service, _)| {
+                    (
+                        service.clone(),
+                        format!("cdm-bundle/{}-{}:latest", name, service),
+                    )
+                })
+                .collect();
+            write_compose_defaults(challenge, &dir, &built)?;
+
+            images = compose
+                .services
+                .iter()
+                .map(|(service, s)| s.image.clone().unwrap_or_else(|| built[service].clone()))
+                .collect();
+            images.sort();
+            images.dedup();
+
+            if options.include_images {
+                let mut env_vars = HashMap::new();
+                env_vars.insert("ID", "0");
+                let compose_yml = compose_yml.to_string_lossy();
+                let override_yml = dir.join("docker-compose.override.yml");
+                let override_yml = override_yml.to_string_lossy();
+                // the .env was copied along with the challenge
+                let env_file = dir.join(".env");
+                let env_file = env_file.to_string_lossy();
+                let mut files = vec!["--file", &compose_yml, "--file", &override_yml];
+                if dir.join(".env").is_file() {
+                    files.splice(0..0, ["--env-file", &env_file]);
+                }
+
+                ChallengeDockerManager::run_command(
+                    &settings,
+                    "docker-compose",
+                    &[&files[..], &["build"]].concat(),
+                    Some(env_vars.clone()),
+                )?;
+                ChallengeDockerManager::run_command(
+                    &settings,
+                    "docker-compose",
+                    &[&files[..], &["pull", "--ignore-pull-failures"]].concat(),
+                    Some(env_vars),
+                )?;
+
+                let images_tar = dir.join("images.tar");
+                let images_tar = images_tar.to_string_lossy();
+                let mut args = vec!["save", "--output", &images_tar];
+                args.extend(images.iter().map(String::as_str));
+                ChallengeDockerManager::run_command(&settings, "docker", &args, None)?;
+            }
+        }
+
+        let mut attachments = Vec::new();
+        for attachment in resolve_attachments(&dir, &challenge.config)? {
+            let relative = attachment
+                .path
+                .strip_prefix(out)
+                .unwrap_or(&attachment.path);
+            attachments.push(BundleFile {
+                path: relative.to_string_lossy().to_string(),
+                sha256: sha256_file(&attachment.path)?,
+            });
+        }
+
+        Ok(BundleChallenge {
+            name: name.clone(),
+            category: challenge.config.category.to_string(),
+            points: challenge.config.points,
+            directory: name.clone(),
+            is_dockerd: challenge.config.is_dockerd,
+            is_dynamic_flag: challenge.config.is_dynamic_flag,
+            images,
+            attachments,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_bundle {
+    use super::*;
+
+    #[test]
+    fn check_export_without_images() {
+        let dir = crate::testing::TestDir::new("bundle").unwrap();
+        let root = dir.path.clone();
+        crate::testing::write_challenge(&root.join("challenges/comment"), "comment").unwrap();
+
+        let repository = ChallengeRepository::open(root.join("challenges")).unwrap();
+        let options = BundleOptions {
+            include_images: false,
+            tarball: false,
+        };
+        let manifest = repository
+            .export_bundle(&root.join("bundle"), &options)
+            .unwrap();
+
+        assert_eq!(
+            manifest.challenges[0].images,
+            ["cdm-bundle/comment-web:latest"]
+        );
+        let dot_env = std::fs::read_to_string(root.join("bundle/comment/.env")).unwrap();
+        assert!(dot_env.contains("ID=0\n") && dot_env.contains("FLAG="));
+        let override_yml = root.join("bundle/comment/docker-compose.override.yml");
+        let compose = ComposeFile::load(&override_yml).unwrap();
+        assert_eq!(
+            compose.services["web"].image.as_deref(),
+            Some("cdm-bundle/comment-web:latest")
+        );
+        assert!(root.join("bundle/manifest.json").exists());
+    }
+}
@@ -0,0 +1,292 @@
+use crate::ChallengeDockerManager;
+use crate::compose::ComposeFile;
+use crate::settings::{CdmSettings, PortRange};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+// what the docker daemon on this host can do, told apart from what it claims
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DockerCapabilities {
+    pub server_version: String,
+    pub rootless: bool,
+    pub cgroup_version: Option<u8>,
+    pub cgroup_driver: String,
+    // ports below this cannot be published, 1024 on a rootless daemon unless the sysctl says otherwise
+    pub min_host_port: u16,
+    // the daemon enforces mem_limit, cpus and pids_limit
+    pub resource_limits: bool,
+    // the nvidia runtime is installed or CDI found gpus
+    #[serde(default)]
+    pub gpus: bool,
+    pub warnings: Vec<String>,
+}
+
+fn unprivileged_port_start() -> u16 {
+    std::fs::read_to_string("/proc/sys/net/ipv4/ip_unprivileged_port_start")
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(1024)
+}
+
+// the host side of a published port, None when docker picks it
+fn host_port(port: &serde_yaml::Value) -> Option<u64> {
+    match port {
+        serde_yaml::Value::String(s) => {
+            let parts: Vec<&str> = s.split('/').next()?.split(':').collect();
+            if parts.len() < 2 {
+                return None;
+            }
+            parts[parts.len() - 2].split('-').next()?.parse().ok()
+        }
+        serde_yaml::Value::Mapping(m) => match m.get("published")? {
+            serde_yaml::Value::Number(n) => n.as_u64(),
+            serde_yaml::Value::String(s) => s.split('-').next()?.parse().ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl DockerCapabilities {
+    // from `docker info --format {{json .}}`
+    pub fn from_info(info: &Value, unprivileged_port_start: u16) -> Self {
+        let rootless = info["SecurityOptions"].as_array().is_some_and(|options| {
+            options
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|option| option.contains("name=rootless"))
+        });
+        let cgroup_version = info["CgroupVersion"]
+            .as_str()
+            .and_then(|version| version.parse().ok());
+        let cgroup_driver = info["CgroupDriver"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        // rootless only gets limits through systemd delegating cgroup v2 controllers
+        let resource_limits = cgroup_driver != "none" && (!rootless || cgroup_version == Some(2));
+
+        let gpus = info["Runtimes"].get("nvidia").is_some()
+            || info["DiscoveredDevices"].as_array().is_some_and(|devices| {
+                devices
+                    .iter()
+                    .filter_map(|device| device["ID"].as_str())
+                    .any(|id| id.contains("gpu"))
+            });
+
+        let mut warnings = Vec::new();
+        if rootless {
+            warnings.push(format!(
+                "docker runs rootless, host ports below {} cannot be published",
+                unprivileged_port_start
+            ));
+        }
+        if !resource_limits {
+            warnings.push(
+                "the daemon cannot enforce mem_limit, cpus or pids_limit on this host".to_string(),
+            );
+        }
+
+        DockerCapabilities {
+            server_version: info["ServerVersion"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            rootless,
+            cgroup_version,
+            cgroup_driver,
+            min_host_port: if rootless { unprivileged_port_start } else { 1 },
+            resource_limits,
+            gpus,
+            warnings,
+        }
+    }
+
+    // of the daemon the context or host points at, the default one without
+    pub fn detect(settings: &CdmSettings, target: Option<&str>) -> Result<Self, String> {
+        let output = ChallengeDockerManager::run_command(
+            settings,
+            "docker",
+            &["info", "--format", "{{json .}}"],
+            settings.docker_env(target),
+        )?;
+        let info: Value = serde_json::from_slice(&output)
+            .map_err(|e| format!("Failed to parse docker info: {}", e))?;
+        Ok(DockerCapabilities::from_info(
+            &info,
+            unprivileged_port_start(),
+        ))
+    }
+
+    pub(crate) fn adapt_range(&self, range: PortRange) -> Result<PortRange, String> {
+        if range.start >= self.min_host_port {
+            return Ok(range);
+        }
+        if range.end < self.min_host_port {
+            return Err(format!(
+                "The port range {}-{} lies below {}, which rootless docker cannot publish",
+                range.start, range.end, self.min_host_port
+            ));
+        }
+        Ok(PortRange {
+            start: self.min_host_port,
+            end: range.end,
+        })
+    }
+
+    // keeps the ${PORT} ranges above what the daemon can publish
+    pub fn adapt(&self, settings: &mut CdmSettings) -> Result<(), String> {
+        if let Some(range) = settings.port_range {
+            settings.port_range = Some(self.adapt_range(range)?);
+        }
+        for range in settings.port_ranges.values_mut() {
+            *range = self.adapt_range(*range)?;
+        }
+        Ok(())
+    }
+
+    // what of a compose file will not work on this daemon
+    pub fn check_compose(&self, name: &str, compose: &ComposeFile) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (service_name, service) in &compose.services {
+            for port in service.ports.iter().filter_map(host_port) {
+                if port < self.min_host_port as u64 {
+                    warnings.push(format!(
+                        "The {} service {} publishes port {}, which rootless docker cannot bind",
+                        name, service_name, port
+                    ));
+                }
+            }
+            let limited = service.mem_limit.is_some()
+                || service.cpus.is_some()
+                || service.pids_limit.is_some();
+            if limited && !self.resource_limits {
+                warnings.push(format!(
+                    "The {} service {} sets resource limits the daemon will not enforce",
+                    name, service_name
+                ));
+            }
+        }
+        warnings
+    }
+}
+
+impl ChallengeDockerManager {
+    // the port ranges of the settings move above what the daemon cannot publish
+    pub fn with_capabilities(mut self, capabilities: DockerCapabilities) -> Result<Self, String> {
+        let mut settings = self.settings().clone();
+        capabilities.adapt(&mut settings)?;
+        self.settings = Arc::new(settings);
+        self.capabilities = Some(capabilities);
+        Ok(self)
+    }
+
+    pub fn capabilities(&self) -> Option<&DockerCapabilities> {
+        self.capabilities.as_ref()
+    }
+
+    // of the daemon and of the compose file on it, none while the daemon is not known
+    pub fn capability_warnings(&self) -> Result<Vec<String>, String> {
+        let Some(capabilities) = &self.capabilities else {
+            return Ok(Vec::new());
+        };
+        let compose = ComposeFile::load(&self.docker_compose_yml)?;
+        let mut warnings = capabilities.warnings.clone();
+        warnings.extend(capabilities.check_compose(&self.challenge_docker_config.name, &compose));
+        Ok(warnings)
+    }
+}
+
+#[cfg(test)]
+mod test_capabilities {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn check_rootless() {
+        let info = json!({
+            "ServerVersion": "27.3.1",
+            "SecurityOptions": ["name=seccomp,profile=builtin", "name=rootless", "name=cgroupns"],
+            "CgroupVersion": "1",
+            "CgroupDriver": "cgroupfs",
+        });
+        let capabilities = DockerCapabilities::from_info(&info, 1024);
+        assert!(capabilities.rootless);
+        assert!(!capabilities.gpus);
+        assert_eq!(capabilities.min_host_port, 1024);
+        assert!(!capabilities.resource_limits);
+        assert_eq!(capabilities.warnings.len(), 2);
+
+        let mut settings = CdmSettings {
+            port_range: Some(PortRange {
+                start: 80,
+                end: 2000,
+            }),
+            ..Default::default()
+        };
+        capabilities.adapt(&mut settings).unwrap();
+        assert_eq!(settings.port_range.unwrap().start, 1024);
+        settings.port_range = Some(PortRange {
+            start: 80,
+            end: 443,
+        });
+        assert!(capabilities.adapt(&mut settings).is_err());
+
+        let compose = ComposeFile::parse(
+            "services:\n  web:\n    ports:\n      - \"80:80\"\n      - \"1337\"\n      - \"127.0.0.1:8080:80\"\n    mem_limit: 256m\n",
+        )
+        .unwrap();
+        let warnings = capabilities.check_compose("comment", &compose);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("port 80,"));
+
+        let root = DockerCapabilities::from_info(
+            &json!({"CgroupVersion": "2", "CgroupDriver": "systemd"}),
+            1024,
+        );
+        assert!(!root.rootless && root.resource_limits && root.warnings.is_empty());
+        assert!(root.check_compose("comment", &compose).is_empty());
+        let gpu = DockerCapabilities::from_info(
+            &json!({"Runtimes": {"runc": {}, "nvidia": {"path": "nvidia-container-runtime"}}}),
+            1024,
+        );
+        assert!(gpu.gpus);
+    }
+
+    #[test]
+    fn check_rootless_manager() {
+        let rootless = DockerCapabilities::from_info(
+            &json!({"SecurityOptions": ["name=rootless"], "CgroupVersion": "2", "CgroupDriver": "systemd"}),
+            1024,
+        );
+        let challenge = crate::testing::TestChallenge::new("rootless").unwrap();
+        let settings = CdmSettings {
+            port_range: Some(PortRange {
+                start: 80,
+                end: 2000,
+            }),
+            ..Default::default()
+        };
+        let cdm = challenge
+            .manager()
+            .unwrap()
+            .with_settings(Arc::new(settings));
+        assert!(cdm.capability_warnings().unwrap().is_empty());
+        let mut cdm = cdm.with_capabilities(rootless).unwrap();
+        assert_eq!(cdm.port_range().unwrap().start, 1024);
+
+        let policy = crate::validate::ValidationPolicy {
+            check_staleness: false,
+            ..Default::default()
+        };
+        let report = cdm.validate(&policy);
+        assert!(report.is_ok(), "{:?}", report);
+        assert!(report.warnings.iter().any(|w| w.contains("rootless")));
+        cdm.config_mut().port_range = Some(PortRange {
+            start: 80,
+            end: 443,
+        });
+        assert!(!cdm.validate(&policy).is_ok());
+    }
+}
@@ -0,0 +1,339 @@
+use crate::ChallengeDockerManager;
+use crate::labels;
+use crate::limits;
+use crate::settings::{self, CdmSettings};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use strum::{AsRefStr, Display};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, AsRefStr, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ChaosAction {
+    Kill,
+    Pause,
+    Restart,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChaosStrike {
+    pub container: String,
+    pub action: ChaosAction,
+    pub timestamp: u64,
+}
+
+// opt in only, it really kills containers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawChaosMonkey")]
+pub struct ChaosMonkey {
+    // average strikes per minute
+    pub rate: f64,
+    pub actions: Vec<ChaosAction>,
+    // paused containers are resumed after this many seconds
+    pub pause_duration: u64,
+    // only containers of this challenge are touched, any cdm container otherwise
+    pub challenge: Option<String>,
+    pub seed: u64,
+    // the docker context or host of the targets, the one of the settings unless given
+    #[serde(default)]
+    pub context: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawChaosMonkey {
+    rate: f64,
+    actions: Vec<ChaosAction>,
+    pause_duration: u64,
+    challenge: Option<String>,
+    seed: u64,
+    #[serde(default)]
+    context: Option<String>,
+}
+
+impl TryFrom<RawChaosMonkey> for ChaosMonkey {
+    type Error = String;
+
+    fn try_from(raw: RawChaosMonkey) -> Result<Self, Self::Error> {
+        let monkey = ChaosMonkey {
+            rate: raw.rate,
+            actions: raw.actions,
+            pause_duration: raw.pause_duration,
+            challenge: raw.challenge,
+            seed: raw.seed,
+            context: raw.context,
+        };
+        limits::check_chaos(&monkey)?;
+        Ok(monkey)
+    }
+}
+
+// the longest wait between strikes, whatever the rate says
+const MAX_INTERVAL: Duration = Duration::from_secs(3600);
+
+// xorshift64*, chaos does not need more than that
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // uniform in (0, 1]
+    fn unit(&mut self) -> f64 {
+        ((self.next() >> 11) as f64 + 1.0) / (1u64 << 53) as f64
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        items.get((self.next() % items.len() as u64) as usize)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl Default for ChaosMonkey {
+    fn default() -> Self {
+        ChaosMonkey {
+            rate: 1.0,
+            actions: vec![ChaosAction::Kill, ChaosAction::Pause, ChaosAction::Restart],
+            pause_duration: 30,
+            challenge: None,
+            seed: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(1),
+            context: None,
+        }
+    }
+}
+
+// a running chaos monkey, stopping it resumes everything it paused
+#[derive(Debug)]
+pub struct ChaosHandle {
+    stop: Arc<AtomicBool>,
+    strikes: Arc<Mutex<Vec<ChaosStrike>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ChaosHandle {
+    pub fn strikes(&self) -> Vec<ChaosStrike> {
+        self.strikes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn stop(mut self) -> Vec<ChaosStrike> {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        self.strikes()
+    }
+}
+
+impl Drop for ChaosHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+impl ChaosMonkey {
+    pub fn new(rate: f64) -> Self {
+        ChaosMonkey {
+            rate,
+            ..Default::default()
+        }
+    }
+
+    fn target_args(&self) -> Vec<String> {
+        [
+            "ps",
+            "--filter",
+            "status=running",
+            "--filter",
+            &labels::filter(labels::CHALLENGE, self.challenge.as_deref()),
+            "--format",
+            "{{.Names}}",
+        ]
+        .map(str::to_string)
+        .to_vec()
+    }
+
+    fn docker(&self, settings: &CdmSettings, args: &[&str]) -> Result<Vec<u8>, String> {
+        let env_vars = settings.docker_env(self.context.as_deref());
+        Ok(ChallengeDockerManager::run_command(
+            settings, "docker", args, env_vars,
+        )?)
+    }
+
+    // the running containers chaos may pick from, found by label so nothing else is ever hit
+    pub fn targets(&self) -> Result<Vec<String>, String> {
+        let settings = settings::global()?;
+        let args = self.target_args();
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let output = self.docker(&settings, &args)?;
+        let s = String::from_utf8(output).map_err(|e| format!("Invalid UTF-8 in output: {}", e))?;
+        Ok(s.lines()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn apply(
+        &self,
+        settings: &CdmSettings,
+        container: &str,
+        action: ChaosAction,
+    ) -> Result<(), String> {
+        let args: &[&str] = match action {
+            ChaosAction::Kill => &["kill", container],
+            ChaosAction::Pause => &["pause", container],
+            ChaosAction::Restart => &["restart", "--time", "0", container],
+        };
+        self.docker(settings, args)?;
+        Ok(())
+    }
+
+    fn choose(&self, rng: &mut Rng, targets: &[String]) -> Option<ChaosStrike> {
+        Some(ChaosStrike {
+            container: rng.pick(targets)?.clone(),
+            action: *rng.pick(&self.actions)?,
+            timestamp: now(),
+        })
+    }
+
+    // seconds until the next strike, exponentially distributed around the rate
+    fn interval(&self, rng: &mut Rng) -> Duration {
+        // a rate built by hand may still be zero, negative or NaN
+        Duration::try_from_secs_f64(-rng.unit().ln() * 60.0 / self.rate)
+            .map_or(MAX_INTERVAL, |interval| interval.min(MAX_INTERVAL))
+    }
+
+    pub fn spawn(&self) -> ChaosHandle {
+        let stop = Arc::new(AtomicBool::new(false));
+        let strikes = Arc::new(Mutex::new(Vec::new()));
+        let monkey = self.clone();
+        let thread = {
+            let stop = stop.clone();
+            let strikes = strikes.clone();
+            std::thread::spawn(move || monkey.run(&stop, &strikes))
+        };
+        ChaosHandle {
+            stop,
+            strikes,
+            thread: Some(thread),
+        }
+    }
+
+    fn run(&self, stop: &AtomicBool, strikes: &Mutex<Vec<ChaosStrike>>) {
+        let Ok(settings) = settings::global() else {
+            return;
+        };
+        let mut rng = Rng::new(self.seed);
+        let mut paused: Vec<(String, Instant)> = Vec::new();
+        let enabled = self.rate > 0.0;
+        let mut next_strike = Instant::now() + self.interval(&mut rng);
+
+        while !stop.load(Ordering::SeqCst) {
+            let pause_duration = Duration::from_secs(self.pause_duration);
+            paused.retain(|(container, since)| {
+                if since.elapsed() < pause_duration {
+                    return true;
+                }
+                let _ = self.docker(&settings, &["unpause", container]);
+                false
+            });
+
+            // no rate above zero means the monkey only waits to be stopped
+            if enabled && Instant::now() >= next_strike {
+                next_strike = Instant::now() + self.interval(&mut rng);
+                let targets = self.targets().unwrap_or_default();
+                if let Some(strike) = self.choose(&mut rng, &targets)
+                    && self
+                        .apply(&settings, &strike.container, strike.action)
+                        .is_ok()
+                {
+                    if strike.action == ChaosAction::Pause {
+                        paused.push((strike.container.clone(), Instant::now()));
+                    }
+                    strikes
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push(strike);
+                }
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        }
+
+        // nothing stays frozen after the game day drill
+        for (container, _) in paused {
+            let _ = self.docker(&settings, &["unpause", &container]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_chaos {
+    use super::*;
+
+    #[test]
+    fn check_choose() {
+        let monkey = ChaosMonkey {
+            seed: 42,
+            ..ChaosMonkey::new(6.0)
+        };
+        assert!(
+            monkey
+                .target_args()
+                .contains(&"label=cdm.challenge".to_string())
+        );
+        let targets = ["challenge-web-1".to_string(), "challenge-pwn-2".to_string()];
+
+        let mut rng = Rng::new(monkey.seed);
+        let strike = monkey.choose(&mut rng, &targets).unwrap();
+        assert!(targets.contains(&strike.container));
+        assert!(monkey.choose(&mut rng, &[]).is_none());
+
+        // six a minute means ten seconds apart on average
+        let total: f64 = (0..10_000)
+            .map(|_| monkey.interval(&mut rng).as_secs_f64())
+            .sum();
+        assert!((total / 10_000.0 - 10.0).abs() < 1.0);
+        for rate in [0.0, -1.0, f64::NAN, f64::MIN_POSITIVE] {
+            let monkey = ChaosMonkey::new(rate);
+            assert_eq!(monkey.interval(&mut rng), MAX_INTERVAL, "{}", rate);
+        }
+
+        let json = serde_json::to_value(&monkey).unwrap();
+        serde_json::from_value::<ChaosMonkey>(json.clone()).unwrap();
+        for rate in [0.0, -1.0, 1e9] {
+            let mut hostile = json.clone();
+            hostile["rate"] = rate.into();
+            assert!(
+                serde_json::from_value::<ChaosMonkey>(hostile).is_err(),
+                "{}",
+                rate
+            );
+        }
+    }
+}
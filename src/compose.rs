@@ -0,0 +1,159 @@
+use crate::id::InstanceId;
+use crate::limits;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct ComposeFile {
+    #[serde(default)]
+    pub services: BTreeMap<String, ComposeService>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub networks: BTreeMap<String, serde_yaml::Value>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub volumes: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct ComposeService {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<serde_yaml::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<serde_yaml::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<serde_yaml::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mem_limit: Option<serde_yaml::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<serde_yaml::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pids_limit: Option<serde_yaml::Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<serde_yaml::Value>,
+}
+
+impl ComposeFile {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = limits::read_limited(path, limits::MAX_COMPOSE_SIZE)?;
+        ComposeFile::parse(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    // serde_yaml bounds nesting and alias expansion itself, the rest is on us
+    pub fn parse(content: &str) -> Result<Self, String> {
+        limits::check_size("the compose file", content.len(), limits::MAX_COMPOSE_SIZE)?;
+        let compose: ComposeFile = serde_yaml::from_str(content).map_err(|e| e.to_string())?;
+        if compose.services.len() > limits::MAX_SERVICES {
+            return Err(format!(
+                "The compose file has {} services, at most {} are allowed",
+                compose.services.len(),
+                limits::MAX_SERVICES
+            ));
+        }
+        Ok(compose)
+    }
+
+    // the main service is the one named by `container_name`, otherwise the first one publishing ports
+    pub fn main_service(
+        &self,
+        main_container_name: &str,
+        id: InstanceId,
+    ) -> Option<(&str, &ComposeService)> {
+        let id = id.to_string();
+        self.services
+            .iter()
+            .find(|(_, service)| {
+                service.container_name.as_deref().is_some_and(|name| {
+                    name.replace("${ID}", &id).replace("$ID", &id) == main_container_name
+                })
+            })
+            .or_else(|| self.services.iter().find(|(_, s)| !s.ports.is_empty()))
+            .or_else(|| self.services.iter().next())
+            .map(|(name, service)| (name.as_str(), service))
+    }
+}
+
+impl ComposeService {
+    // container side of every published port, for both the short and the long syntax
+    pub fn container_ports(&self) -> Vec<u64> {
+        self.ports
+            .iter()
+            .filter_map(|port| match port {
+                serde_yaml::Value::Number(n) => n.as_u64(),
+                serde_yaml::Value::String(s) => {
+                    let target = s.rsplit(':').next()?;
+                    let target = target.split('/').next()?;
+                    // a range like 8000-8001 only contributes its first port
+                    target.split('-').next()?.parse().ok()
+                }
+                serde_yaml::Value::Mapping(m) => m.get("target")?.as_u64(),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+// generated compose files live outside the challenge directory so instances never share them
+pub fn override_path(project: &str, name: &str) -> PathBuf {
+    std::env::temp_dir().join("cdm").join(project).join(name)
+}
+
+pub fn render_override(name: &str, content: &Value) -> Result<String, String> {
+    serde_yaml::to_string(content).map_err(|e| format!("Failed to serialize {}: {}", name, e))
+}
+
+pub fn write_override(project: &str, name: &str, content: &Value) -> Result<PathBuf, String> {
+    let path = override_path(project, name);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+
+    let yaml = render_override(name, content)?;
+    std::fs::write(&path, yaml)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test_compose {
+    use super::*;
+
+    const COMPOSE: &str = r#"
+services:
+  db:
+    image: mysql:8
+  web:
+    build: .
+    container_name: challenge-comment-${ID}
+    ports:
+      - "80"
+      - "127.0.0.1:${PORT}:8080/tcp"
+      - target: 9000
+        published: 0
+"#;
+
+    #[test]
+    fn check_main_service() {
+        let compose = ComposeFile::parse(COMPOSE).unwrap();
+        let (name, service) = compose
+            .main_service("challenge-comment-7", InstanceId::new(7))
+            .unwrap();
+        assert_eq!(name, "web");
+        assert_eq!(service.container_ports(), vec![80, 8080, 9000]);
+    }
+
+    #[test]
+    fn check_main_service_fallback() {
+        let compose = ComposeFile::parse(COMPOSE).unwrap();
+        let (name, _) = compose
+            .main_service("challenge-other-1", InstanceId::new(1))
+            .unwrap();
+        assert_eq!(name, "web");
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{ChallengeDockerConfig, limits};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone)]
+struct CachedConfig {
+    modified: Option<SystemTime>,
+    len: u64,
+    sha256: Vec<u8>,
+    config: Arc<ChallengeDockerConfig>,
+}
+
+// parsed FloatCTF.toml files by challenge directory, so hundreds of instances parse it once
+#[derive(Debug, Default)]
+pub struct ConfigCache {
+    entries: Mutex<HashMap<PathBuf, CachedConfig>>,
+}
+
+impl ConfigCache {
+    pub fn new() -> Self {
+        ConfigCache::default()
+    }
+
+    // an unchanged mtime and size is a hit, otherwise the content hash decides whether to parse again
+    pub fn get(&self, challenge_path: &Path) -> Result<Arc<ChallengeDockerConfig>, String> {
+        let path = challenge_path.join("FloatCTF.toml");
+        let metadata =
+            std::fs::metadata(&path).map_err(|e| format!("Failed to read FloatCTF.toml: {}", e))?;
+        let modified = metadata.modified().ok();
+
+        let cached = self
+            .entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(challenge_path)
+            .cloned();
+        if let Some(cached) = &cached
+            && cached.modified.is_some()
+            && cached.modified == modified
+            && cached.len == metadata.len()
+        {
+            return Ok(cached.config.clone());
+        }
+
+        let content = limits::read_limited(&path, limits::MAX_CONFIG_SIZE)
+            .map_err(|e| format!("Failed to read FloatCTF.toml: {}", e))?;
+        let sha256 = Sha256::digest(content.as_bytes()).to_vec();
+        let config = match cached {
+            // touched but not changed
+            Some(cached) if cached.sha256 == sha256 => cached.config,
+            _ => Arc::new(ChallengeDockerConfig::parse(&content)?),
+        };
+
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                challenge_path.to_path_buf(),
+                CachedConfig {
+                    modified,
+                    len: content.len() as u64,
+                    sha256,
+                    config: config.clone(),
+                },
+            );
+        Ok(config)
+    }
+
+    pub fn invalidate(&self, challenge_path: &Path) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(challenge_path);
+    }
+
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// the cache ChallengeDockerManager::new and the repository go through
+pub fn global() -> Arc<ConfigCache> {
+    static CACHE: OnceLock<Arc<ConfigCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Arc::new(ConfigCache::new())).clone()
+}
+
+#[cfg(test)]
+mod test_config_cache {
+    use super::*;
+    use std::time::Duration;
+
+    fn touch(path: &Path, seconds: u64) {
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn check_invalidation() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+        let toml = root.join("FloatCTF.toml");
+        touch(&toml, 1_000_000);
+
+        let cache = ConfigCache::new();
+        let first = cache.get(&root).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.get(&root).unwrap()));
+
+        // the same content with a new mtime is still the same config
+        touch(&toml, 1_000_001);
+        assert!(Arc::ptr_eq(&first, &cache.get(&root).unwrap()));
+
+        let content = std::fs::read_to_string(&toml).unwrap();
+        std::fs::write(&toml, content.replace("points = 100", "points = 500")).unwrap();
+        touch(&toml, 1_000_002);
+        let changed = cache.get(&root).unwrap();
+        assert_eq!(changed.points, 500);
+        assert_eq!(first.points, 100);
+
+        cache.invalidate(&root);
+        assert!(cache.is_empty());
+    }
+}
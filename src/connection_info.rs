@@ -0,0 +1,111 @@
+use crate::error::CdmError;
+use crate::{Category, ChallengeDockerManager};
+
+// what players are shown when the FloatCTF.toml and the settings have no template
+pub fn default_template(category: Category) -> &'static str {
+    match category {
+        Category::Web => "{url}",
+        _ => "nc {host} {port}",
+    }
+}
+
+// placeholders that are not known are left as they are, so a typo shows up on the platform
+pub fn render(template: &str, host: &str, port: u64, domain: &str) -> String {
+    let url = format!("http://{}:{}", host, port);
+    template
+        .replace("{host}", host)
+        .replace("{port}", &port.to_string())
+        .replace("{url}", &url)
+        .replace("{domain}", domain)
+}
+
+impl ChallengeDockerManager {
+    // the FloatCTF.toml, then the settings of the category, then the default of the category
+    pub fn connection_template(&self) -> String {
+        let config = &self.challenge_docker_config;
+        config
+            .connection_info
+            .clone()
+            .or_else(|| {
+                self.settings()
+                    .connection_info
+                    .iter()
+                    .find(|(name, _)| {
+                        Category::try_from(name.to_string()).ok() == Some(config.category)
+                    })
+                    .map(|(_, template)| template.clone())
+            })
+            .unwrap_or_else(|| default_template(config.category).to_string())
+    }
+
+    pub fn connection_info(&self, port: u64) -> String {
+        let settings = self.settings();
+        let host = settings
+            .public_host
+            .as_deref()
+            .unwrap_or(&settings.bind_address);
+        render(
+            &self.connection_template(),
+            host,
+            port,
+            settings.domain.as_deref().unwrap_or(host),
+        )
+    }
+
+    // from the port the running instance has
+    pub fn render_connection_info(&self) -> Result<String, CdmError> {
+        match self.get_port()? {
+            0 => Err(CdmError::NotRunning {
+                challenge: self.challenge_docker_config.name.clone(),
+                instance_id: self.id,
+                status: self.backend.status(self)?,
+            }),
+            port => Ok(self.connection_info(port)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_connection_info {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::settings::CdmSettings;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_render_connection_info() {
+        let mut settings = CdmSettings {
+            public_host: Some("chall.floatctf.org".to_string()),
+            ..Default::default()
+        };
+        settings
+            .connection_info
+            .insert("pwn".to_string(), "ncat --ssl {host} {port}".to_string());
+        let mock = Arc::new(MockBackend::new());
+        let mut cdm = ChallengeDockerManager::test_manager("comment", 1)
+            .with_backend(mock.clone())
+            .with_settings(Arc::new(settings));
+        assert!(matches!(
+            cdm.render_connection_info(),
+            Err(CdmError::NotRunning { .. })
+        ));
+
+        mock.respond_up(31234);
+        cdm.up(&"flag{info}".into()).unwrap();
+        assert_eq!(
+            cdm.render_connection_info().unwrap(),
+            "http://chall.floatctf.org:31234"
+        );
+        cdm.config_mut().category = Category::Pwn;
+        assert_eq!(
+            cdm.render_connection_info().unwrap(),
+            "ncat --ssl chall.floatctf.org 31234"
+        );
+        cdm.config_mut().connection_info =
+            Some("https://{domain}/ on {port}, {unknown}".to_string());
+        assert_eq!(
+            cdm.render_connection_info().unwrap(),
+            "https://chall.floatctf.org/ on 31234, {unknown}"
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use crate::ChallengeDockerManager;
+use crate::flag::Flag;
+use crate::settings::{self, CdmSettings};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Shutdown, TcpListener, TcpStream};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+fn default_max_per_ip() -> usize {
+    4
+}
+
+fn default_max_total() -> usize {
+    64
+}
+
+fn default_mem_limit() -> String {
+    "64m".to_string()
+}
+
+fn default_cpus() -> String {
+    "0.5".to_string()
+}
+
+fn default_pids_limit() -> u64 {
+    64
+}
+
+fn default_timeout() -> u64 {
+    300
+}
+
+// xinetd style, a fresh container per tcp connection with the connection as its stdio
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PerConnectionConfig {
+    // e.g. the image compose builds for the main service
+    pub image: String,
+    // what the image runs unless given
+    #[serde(default)]
+    pub command: Vec<String>,
+    #[serde(default = "default_max_per_ip")]
+    pub max_per_ip: usize,
+    #[serde(default = "default_max_total")]
+    pub max_total: usize,
+    #[serde(default = "default_mem_limit")]
+    pub mem_limit: String,
+    #[serde(default = "default_cpus")]
+    pub cpus: String,
+    #[serde(default = "default_pids_limit")]
+    pub pids_limit: u64,
+    // seconds a connection may last
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    // the containers get no network unless the challenge needs one
+    #[serde(default)]
+    pub network: bool,
+}
+
+// how many connections every address holds, a permit gives its slot back on drop
+#[derive(Debug, Default)]
+pub struct ConnectionLimiter {
+    max_per_ip: usize,
+    max_total: usize,
+    open: Mutex<HashMap<IpAddr, usize>>,
+}
+
+pub struct ConnectionPermit {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_per_ip: usize, max_total: usize) -> Self {
+        ConnectionLimiter {
+            max_per_ip,
+            max_total,
+            open: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn acquire(self: &Arc<Self>, ip: IpAddr) -> Option<ConnectionPermit> {
+        let mut open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        // a refused address leaves no entry behind, spoofed sources must not grow the map
+        let total: usize = open.values().sum();
+        if total >= self.max_total || open.get(&ip).copied().unwrap_or(0) >= self.max_per_ip {
+            return None;
+        }
+        *open.entry(ip).or_default() += 1;
+        Some(ConnectionPermit {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+
+    pub fn open(&self, ip: IpAddr) -> usize {
+        let open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        open.get(&ip).copied().unwrap_or(0)
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut open = self.limiter.open.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = open.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&self.ip);
+            }
+        }
+    }
+}
+
+// the flag comes through the env of docker run, so it is not in the arguments
+fn run_args(config: &PerConnectionConfig, name: &str, dynamic_flag: bool) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "run",
+        "--rm",
+        "--interactive",
+        "--name",
+        name,
+        "--memory",
+        &config.mem_limit,
+        "--cpus",
+        &config.cpus,
+        "--pids-limit",
+        &config.pids_limit.to_string(),
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+    if !config.network {
+        args.extend(["--network".to_string(), "none".to_string()]);
+    }
+    if dynamic_flag {
+        args.extend(["--env".to_string(), "FLAG".to_string()]);
+    }
+    args.push(config.image.clone());
+    args.extend(config.command.iter().cloned());
+    args
+}
+
+impl ChallengeDockerManager {
+    // the published port of the listener, like ${PORT} of an instance
+    pub fn bind_connections(&self) -> Result<TcpListener, String> {
+        let bind_address = &self.settings().bind_address;
+        let port = self
+            .fixed_port()
+            .or_else(|| {
+                self.port_range()
+                    .and_then(|range| range.free_port(bind_address, self.id.get()))
+            })
+            .unwrap_or(0);
+        TcpListener::bind((bind_address.as_str(), port))
+            .map_err(|e| format!("Failed to listen on {}:{}: {}", bind_address, port, e))
+    }
+
+    // blocks, every accepted connection gets its own container until the listener fails
+    pub fn serve_connections(&self, listener: TcpListener, flag: Flag) -> Result<(), String> {
+        let config = self
+            .challenge_docker_config
+            .per_connection
+            .clone()
+            .ok_or_else(|| {
+                format!(
+                    "The {} has no per_connection section",
+                    self.challenge_docker_config.name
+                )
+            })?;
+        let limiter = Arc::new(ConnectionLimiter::new(config.max_per_ip, config.max_total));
+        let flag = Arc::new(flag);
+        let dynamic_flag = self.challenge_docker_config.is_dynamic_flag;
+        let context = self.docker_context().map(str::to_string);
+        let settings = self.settings.clone();
+        let counter = AtomicU64::new(0);
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => return Err(format!("Failed to accept a connection: {}", e)),
+            };
+            let Ok(peer) = stream.peer_addr() else {
+                continue;
+            };
+            let Some(permit) = limiter.acquire(peer.ip()) else {
+                let _ = stream.shutdown(Shutdown::Both);
+                continue;
+            };
+            let name = format!(
+                "{}-conn-{}",
+                self.main_container_name,
+                counter.fetch_add(1, Ordering::SeqCst)
+            );
+            let (config, flag, context, settings) = (
+                config.clone(),
+                flag.clone(),
+                context.clone(),
+                settings.clone(),
+            );
+            std::thread::spawn(move || {
+                let _permit = permit;
+                let context = context.as_deref();
+                if let Err(e) = serve_connection(
+                    stream,
+                    &settings,
+                    &config,
+                    &name,
+                    context,
+                    dynamic_flag,
+                    &flag,
+                ) {
+                    eprintln!("{}: {}", name, e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+fn serve_connection(
+    stream: TcpStream,
+    settings: &CdmSettings,
+    config: &PerConnectionConfig,
+    name: &str,
+    context: Option<&str>,
+    dynamic_flag: bool,
+    flag: &Flag,
+) -> Result<(), String> {
+    let env_vars = Some(context.map(settings::target_env).into_iter().collect());
+    let mut command = ChallengeDockerManager::command(settings, "docker", &env_vars);
+    command
+        .args(run_args(config, name, dynamic_flag))
+        .envs(env_vars.iter().flatten())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    if dynamic_flag {
+        command.env("FLAG", flag.expose());
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("Failed to execute docker run: {}", e))?;
+
+    let mut stdin = child.stdin.take().ok_or("docker run has no stdin")?;
+    let mut stdout = child.stdout.take().ok_or("docker run has no stdout")?;
+    let mut input = stream
+        .try_clone()
+        .map_err(|e| format!("Failed to clone the connection: {}", e))?;
+    let mut output = stream
+        .try_clone()
+        .map_err(|e| format!("Failed to clone the connection: {}", e))?;
+    // closing stdin is how the program learns the player hung up
+    let upstream = std::thread::spawn(move || {
+        let _ = std::io::copy(&mut input, &mut stdin);
+    });
+    let downstream = std::thread::spawn(move || {
+        let _ = std::io::copy(&mut stdout, &mut output);
+        let _ = output.shutdown(Shutdown::Write);
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(config.timeout);
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            // killing docker run leaves the container, it has to be removed
+            Ok(None) if Instant::now() > deadline => {
+                let _ = ChallengeDockerManager::run_command(
+                    settings,
+                    "docker",
+                    &["rm", "--force", name],
+                    env_vars.clone(),
+                );
+                let _ = child.kill();
+                let _ = child.wait();
+                break;
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(100)),
+            Err(e) => return Err(format!("Failed to wait for docker run: {}", e)),
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+    let _ = downstream.join();
+    let _ = upstream.join();
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_connections {
+    use super::*;
+
+    #[test]
+    fn check_limiter() {
+        let limiter = Arc::new(ConnectionLimiter::new(2, 3));
+        let player: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let first = limiter.acquire(player).unwrap();
+        let _second = limiter.acquire(player).unwrap();
+        assert!(limiter.acquire(player).is_none());
+        let _third = limiter.acquire(other).unwrap();
+        assert!(limiter.acquire(other).is_none());
+        // full, a new address is turned away without taking a slot in the map
+        let stranger: IpAddr = "10.0.0.3".parse().unwrap();
+        assert!(limiter.acquire(stranger).is_none());
+        assert_eq!(limiter.open.lock().unwrap().len(), 2);
+
+        drop(first);
+        assert_eq!(limiter.open(player), 1);
+        assert!(limiter.acquire(player).is_some());
+    }
+
+    #[test]
+    fn check_run_args() {
+        let config: PerConnectionConfig =
+            toml::from_str("image = \"challenge-heap\"\ncommand = [\"/chall\"]\n").unwrap();
+        let args = run_args(&config, "challenge-heap-1-conn-0", true);
+        assert_eq!(
+            args,
+            [
+                "run",
+                "--rm",
+                "--interactive",
+                "--name",
+                "challenge-heap-1-conn-0",
+                "--memory",
+                "64m",
+                "--cpus",
+                "0.5",
+                "--pids-limit",
+                "64",
+                "--network",
+                "none",
+                "--env",
+                "FLAG",
+                "challenge-heap",
+                "/chall"
+            ]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use crate::backend::InstanceStatus;
+use crate::id::InstanceId;
+use crate::maintenance::MaintenanceRegistry;
+use crate::{ChallengeDockerConfig, ChallengeDockerManager};
+use serde::{Deserialize, Serialize};
+
+// bumped whenever a field changes meaning or is removed, new fields are added with defaults
+pub const DTO_VERSION: u32 = 1;
+
+fn dto_version() -> u32 {
+    DTO_VERSION
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceRecordStatus {
+    Running,
+    Starting,
+    Paused,
+    Exited,
+    NotFound,
+}
+
+impl From<InstanceStatus> for InstanceRecordStatus {
+    fn from(status: InstanceStatus) -> Self {
+        match status {
+            InstanceStatus::Running => InstanceRecordStatus::Running,
+            InstanceStatus::Starting => InstanceRecordStatus::Starting,
+            InstanceStatus::Paused => InstanceRecordStatus::Paused,
+            InstanceStatus::Exited => InstanceRecordStatus::Exited,
+            InstanceStatus::NotFound => InstanceRecordStatus::NotFound,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChallengeSummary {
+    #[serde(default = "dto_version")]
+    pub version: u32,
+    pub name: String,
+    pub author: String,
+    pub category: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    pub points: i32,
+    pub is_dynamic_flag: bool,
+    pub is_dockerd: bool,
+    // the reason, while the challenge cannot be spawned
+    #[serde(default)]
+    pub maintenance: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceRecord {
+    #[serde(default = "dto_version")]
+    pub version: u32,
+    pub instance_id: InstanceId,
+    pub challenge: String,
+    pub project: String,
+    pub container: String,
+    pub status: InstanceRecordStatus,
+    #[serde(default)]
+    pub port: Option<u64>,
+    #[serde(default)]
+    pub created_at: Option<u64>,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    #[serde(default)]
+    pub maintenance: Option<String>,
+    // e.g. nc host 31234, only while there is a port
+    #[serde(default)]
+    pub connection_info: Option<String>,
+}
+
+impl ChallengeSummary {
+    pub fn new(config: &ChallengeDockerConfig, maintenance: &MaintenanceRegistry) -> Self {
+        ChallengeSummary {
+            version: DTO_VERSION,
+            name: config.name.clone(),
+            author: config.author.clone(),
+            category: config.category.to_string(),
+            tags: config.tags.clone(),
+            description: config.description.clone(),
+            attachments: config.attachments.clone(),
+            points: config.points,
+            is_dynamic_flag: config.is_dynamic_flag,
+            is_dockerd: config.is_dockerd,
+            maintenance: maintenance.get(&config.name).map(|info| info.reason),
+        }
+    }
+}
+
+impl From<&ChallengeDockerManager> for ChallengeSummary {
+    fn from(cdm: &ChallengeDockerManager) -> Self {
+        ChallengeSummary::new(&cdm.challenge_docker_config, &cdm.maintenance)
+    }
+}
+
+impl InstanceRecord {
+    pub fn new(cdm: &ChallengeDockerManager, status: InstanceStatus, port: Option<u64>) -> Self {
+        InstanceRecord {
+            version: DTO_VERSION,
+            instance_id: cdm.id,
+            challenge: cdm.challenge_docker_config.name.clone(),
+            project: cdm.docker_compose_project_name.clone(),
+            container: cdm.main_container_name.clone(),
+            status: status.into(),
+            port,
+            created_at: None,
+            expires_at: None,
+            maintenance: cdm.maintenance().map(|info| info.reason),
+            connection_info: port.map(|port| cdm.connection_info(port)),
+        }
+    }
+
+    pub fn with_created_at(mut self, created_at: u64) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    pub fn with_expires_at(mut self, expires_at: u64) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+}
+
+// a record of an instance that has not been queried yet
+impl From<&ChallengeDockerManager> for InstanceRecord {
+    fn from(cdm: &ChallengeDockerManager) -> Self {
+        InstanceRecord::new(cdm, InstanceStatus::NotFound, None)
+    }
+}
+
+#[cfg(test)]
+mod test_dto {
+    use super::*;
+
+    #[test]
+    fn check_instance_record() {
+        let cdm = ChallengeDockerManager::test_manager("comment", 2);
+        let record = InstanceRecord::new(&cdm, InstanceStatus::Running, Some(31337))
+            .with_expires_at(1700000000);
+
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["version"], DTO_VERSION);
+        assert_eq!(json["status"], "running");
+        assert_eq!(json["port"], 31337);
+        assert_eq!(json["connection_info"], "http://127.0.0.1:31337");
+
+        let back: InstanceRecord = serde_json::from_value(json).unwrap();
+        assert_eq!(back, record);
+    }
+
+    #[test]
+    fn check_challenge_summary() {
+        let cdm = ChallengeDockerManager::test_manager("comment", 3);
+        let maintenance = MaintenanceRegistry::new();
+        let summary = ChallengeSummary::new(&cdm.challenge_docker_config, &maintenance);
+        assert_eq!(summary.name, "comment");
+        assert!(summary.maintenance.is_none());
+        maintenance.set_maintenance("comment", "hotfix");
+        let summary = ChallengeSummary::new(&cdm.challenge_docker_config, &maintenance);
+        assert_eq!(summary.maintenance.as_deref(), Some("hotfix"));
+    }
+
+    #[test]
+    fn check_older_record() {
+        // records written before optional fields existed still load
+        let record: InstanceRecord = serde_json::from_str(
+            r#"{"instance_id":1,"challenge":"comment","project":"p","container":"c","status":"exited"}"#,
+        )
+        .unwrap();
+        assert_eq!(record.version, DTO_VERSION);
+        assert_eq!(record.status, InstanceRecordStatus::Exited);
+        assert_eq!(record.port, None);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::backend::InstanceStatus;
+use crate::id::{InstanceId, TeamId};
+use crate::plan::REDACTED;
+use crate::{Category, ChallengeDockerManager};
+use std::fmt;
+use std::path::PathBuf;
+
+// a command that did not succeed, with enough around it to run it again by hand
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandError {
+    pub program: String,
+    // the full command line, the flag redacted
+    pub command: String,
+    pub working_dir: PathBuf,
+    pub challenge: Option<String>,
+    pub project: Option<String>,
+    // None when it could not be started or was killed by a signal
+    pub status: Option<i32>,
+    pub stderr: String,
+}
+
+// most of cdm reports errors as strings, this is for the ones callers need to tell apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CdmError {
+    NotRunning {
+        challenge: String,
+        instance_id: InstanceId,
+        status: InstanceStatus,
+    },
+    AlreadyRunning {
+        challenge: String,
+        instance_id: InstanceId,
+        port: u64,
+    },
+    NotOpen {
+        challenge: String,
+        opens_at: u64,
+    },
+    Closed {
+        challenge: String,
+        closed_at: u64,
+    },
+    Frozen {
+        challenge: String,
+        category: Category,
+    },
+    Maintenance {
+        challenge: String,
+        reason: String,
+    },
+    BudgetExhausted {
+        team: TeamId,
+        used_seconds: u64,
+        budget_seconds: u64,
+    },
+    ShellDenied {
+        challenge: String,
+        user: String,
+    },
+    NoGpu {
+        challenge: String,
+    },
+    MountDenied {
+        challenge: String,
+        mount: String,
+    },
+    HookDenied {
+        challenge: String,
+        reason: String,
+    },
+    RateLimited {
+        team: TeamId,
+        // seconds until the team may spawn again
+        retry_after: u64,
+    },
+    Command(Box<CommandError>),
+    Other(String),
+}
+
+impl fmt::Display for CdmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CdmError::NotRunning {
+                challenge,
+                instance_id,
+                status,
+            } => write!(
+                f,
+                "The {} instance {} is not running ({})",
+                challenge, instance_id, status
+            ),
+            CdmError::AlreadyRunning {
+                challenge,
+                instance_id,
+                port,
+            } => write!(
+                f,
+                "The {} instance {} is already running on port {}",
+                challenge, instance_id, port
+            ),
+            CdmError::NotOpen {
+                challenge,
+                opens_at,
+            } => write!(f, "The {} is not open until {}", challenge, opens_at),
+            CdmError::Closed {
+                challenge,
+                closed_at,
+            } => write!(f, "The {} closed at {}", challenge, closed_at),
+            CdmError::Frozen {
+                challenge,
+                category,
+            } => write!(
+                f,
+                "No new {} instances during the freeze, {} is frozen",
+                challenge, category
+            ),
+            CdmError::Maintenance { challenge, reason } => {
+                write!(f, "The {} is under maintenance: {}", challenge, reason)
+            }
+            CdmError::BudgetExhausted {
+                team,
+                used_seconds,
+                budget_seconds,
+            } => write!(
+                f,
+                "Team {} used {}s of its {}s instance budget",
+                team, used_seconds, budget_seconds
+            ),
+            CdmError::ShellDenied { challenge, user } => {
+                write!(f, "{} may not open a shell in the {}", user, challenge)
+            }
+            CdmError::NoGpu { challenge } => {
+                write!(
+                    f,
+                    "The {} needs a gpu, this docker host has none",
+                    challenge
+                )
+            }
+            CdmError::MountDenied { challenge, mount } => {
+                write!(f, "The {} may not be started, {}", challenge, mount)
+            }
+            CdmError::HookDenied { challenge, reason } => {
+                write!(f, "The hook of {} may not run, {}", challenge, reason)
+            }
+            CdmError::RateLimited { team, retry_after } => write!(
+                f,
+                "Team {} spawns too often, retry in {}s",
+                team, retry_after
+            ),
+            CdmError::Command(e) => write!(f, "{}", e),
+            CdmError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            Some(status) => write!(f, "{} failed with exit status {}", self.program, status)?,
+            None => write!(f, "Failed to execute {}", self.program)?,
+        }
+        if let Some(challenge) = &self.challenge {
+            write!(f, " for {}", challenge)?;
+        }
+        if let Some(project) = &self.project {
+            write!(f, " (project {})", project)?;
+        }
+        write!(
+            f,
+            " in {}: `{}`: {}",
+            self.working_dir.display(),
+            self.command,
+            self.stderr.trim()
+        )
+    }
+}
+
+impl CommandError {
+    fn redact(&mut self, secret: &str) {
+        self.command = self.command.replace(secret, REDACTED);
+        self.stderr = self.stderr.replace(secret, REDACTED);
+    }
+}
+
+impl CdmError {
+    // names the instance a failed command belonged to, and makes sure its flag is not in the message
+    pub(crate) fn for_instance(self, cdm: &ChallengeDockerManager, flag: &str) -> Self {
+        match self {
+            CdmError::Command(mut e) => {
+                e.challenge
+                    .get_or_insert_with(|| cdm.challenge_docker_config.name.clone());
+                e.project
+                    .get_or_insert_with(|| cdm.docker_compose_project_name.clone());
+                if !flag.is_empty() {
+                    e.redact(flag);
+                }
+                CdmError::Command(e)
+            }
+            CdmError::Other(e) if !flag.is_empty() => CdmError::Other(e.replace(flag, REDACTED)),
+            e => e,
+        }
+    }
+}
+
+impl std::error::Error for CdmError {}
+
+impl From<String> for CdmError {
+    fn from(e: String) -> Self {
+        CdmError::Other(e)
+    }
+}
+
+impl From<CommandError> for CdmError {
+    fn from(e: CommandError) -> Self {
+        CdmError::Command(Box::new(e))
+    }
+}
+
+impl From<CdmError> for String {
+    fn from(e: CdmError) -> Self {
+        e.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test_error {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn check_command_error() {
+        let cdm = ChallengeDockerManager::test_manager("comment", 5);
+        let env = HashMap::from([("FLAG", "flag{leak}")]);
+        let e = ChallengeDockerManager::run_command(
+            cdm.settings(),
+            "sh",
+            &["-c", "echo $FLAG >&2; exit 3"],
+            Some(env),
+        )
+        .unwrap_err()
+        .for_instance(&cdm, "flag{leak}");
+
+        let CdmError::Command(command) = &e else {
+            panic!("{:?}", e);
+        };
+        assert_eq!(command.status, Some(3));
+        assert_eq!(command.challenge.as_deref(), Some("comment"));
+        assert_eq!(
+            command.project.as_deref(),
+            Some(cdm.docker_compose_project_name.as_str())
+        );
+        assert_eq!(
+            command.command,
+            "FLAG='<redacted>' sh -c 'echo $FLAG >&2; exit 3'"
+        );
+        let message = e.to_string();
+        assert!(message.starts_with("sh failed with exit status 3 for comment"));
+        assert!(!message.contains("flag{leak}"), "{}", message);
+
+        // the secret delivery hands compose the flag under another name
+        let env = HashMap::from([(crate::flag::SECRET_ENV, "flag{leak}")]);
+        let e = ChallengeDockerManager::run_command(cdm.settings(), "false", &[], Some(env))
+            .unwrap_err();
+        assert!(!e.to_string().contains("flag{leak}"), "{}", e);
+        assert!(e.to_string().contains("CDM_FLAG='<redacted>'"), "{}", e);
+    }
+}
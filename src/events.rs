@@ -0,0 +1,120 @@
+use crate::id::InstanceId;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    Built,
+    Up {
+        port: u64,
+    },
+    Down,
+    Failed {
+        operation: String,
+        error: String,
+    },
+    Crashed {
+        exit_code: Option<i64>,
+    },
+    QuotaExhausted {
+        scope: String,
+        limit: u64,
+    },
+    Reaped {
+        reason: String,
+    },
+    Opened,
+    Closed,
+    Reset {
+        previous_port: Option<u64>,
+        port: u64,
+    },
+    Scaled {
+        service: String,
+        replicas: u64,
+    },
+    // exec or attach, by an author debugging the instance
+    ShellOpened {
+        user: String,
+        command: String,
+    },
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::Built => "built",
+            EventKind::Up { .. } => "up",
+            EventKind::Down => "down",
+            EventKind::Failed { .. } => "failed",
+            EventKind::Crashed { .. } => "crashed",
+            EventKind::QuotaExhausted { .. } => "quota_exhausted",
+            EventKind::Reaped { .. } => "reaped",
+            EventKind::Opened => "opened",
+            EventKind::Closed => "closed",
+            EventKind::Reset { .. } => "reset",
+            EventKind::Scaled { .. } => "scaled",
+            EventKind::ShellOpened { .. } => "shell_opened",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    #[serde(flatten)]
+    pub kind: EventKind,
+    pub challenge: String,
+    pub instance_id: InstanceId,
+    pub timestamp: u64,
+}
+
+impl Event {
+    pub fn new(kind: EventKind, challenge: &str, instance_id: InstanceId) -> Self {
+        Event {
+            kind,
+            challenge: challenge.to_string(),
+            instance_id,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+// anything that wants to react to what happens to instances
+pub trait EventSink: Debug + Send + Sync {
+    fn emit(&self, event: &Event) -> Result<(), String>;
+}
+
+// every event in memory, the audit log that goes into backups
+#[derive(Debug, Default)]
+pub struct EventLog {
+    events: Mutex<Vec<Event>>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        EventLog::default()
+    }
+
+    pub fn events(&self) -> Vec<Event> {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl EventSink for EventLog {
+    fn emit(&self, event: &Event) -> Result<(), String> {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(event.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+use crate::ChallengeDockerManager;
+use crate::backend::Backend;
+use crate::events::{Event, EventKind};
+use crate::id::InstanceId;
+use crate::labels::{self, LabeledContainer};
+use crate::lifecycle::DownOptions;
+use crate::repository::ChallengeRepository;
+use crate::schedule;
+use crate::settings;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FinalizeOptions {
+    // logs, snapshots and the report end up here
+    pub archive_dir: PathBuf,
+    // the instances to keep a docker save of, e.g. the ones the abuse report pointed at
+    #[serde(default)]
+    pub flagged: Vec<(String, InstanceId)>,
+    // remove the images, volumes and networks of the challenges afterwards
+    #[serde(default)]
+    pub prune: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChallengeStats {
+    pub spawns: u64,
+    pub failures: u64,
+    pub crashes: u64,
+    pub uptime_seconds: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FinalReport {
+    pub finished_at: u64,
+    pub challenges: BTreeMap<String, ChallengeStats>,
+    pub stopped: usize,
+    pub logs: Vec<PathBuf>,
+    pub snapshots: Vec<PathBuf>,
+    // whatever went wrong on the way, finalizing goes on regardless
+    pub errors: Vec<String>,
+    // no cdm container is left
+    pub clean: bool,
+}
+
+// spawns, failures and instance time per challenge, instances still up count until now
+pub fn challenge_stats(events: &[Event], now: u64) -> BTreeMap<String, ChallengeStats> {
+    let mut stats: BTreeMap<String, ChallengeStats> = BTreeMap::new();
+    let mut started: HashMap<(&str, InstanceId), u64> = HashMap::new();
+    for event in events {
+        let challenge = stats.entry(event.challenge.clone()).or_default();
+        let instance = (event.challenge.as_str(), event.instance_id);
+        match &event.kind {
+            EventKind::Up { .. } | EventKind::Reset { .. } => {
+                challenge.spawns += 1;
+                started.entry(instance).or_insert(event.timestamp);
+            }
+            EventKind::Down | EventKind::Reaped { .. } => {
+                if let Some(at) = started.remove(&instance) {
+                    challenge.uptime_seconds += event.timestamp.saturating_sub(at);
+                }
+            }
+            EventKind::Failed { .. } => challenge.failures += 1,
+            EventKind::Crashed { .. } => challenge.crashes += 1,
+            _ => {}
+        }
+    }
+    for ((challenge, _), at) in started {
+        if let Some(stats) = stats.get_mut(challenge) {
+            stats.uptime_seconds += now.saturating_sub(at);
+        }
+    }
+    stats
+}
+
+fn docker(args: &[&str]) -> Result<Vec<u8>, String> {
+    let settings = settings::global()?;
+    Ok(ChallengeDockerManager::run_command(
+        &settings,
+        "docker",
+        args,
+        settings.docker_env(None),
+    )?)
+}
+
+fn create_dir(dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))
+}
+
+fn export_logs(container: &LabeledContainer, dir: &Path) -> Result<PathBuf, String> {
+    let output = docker(&["logs", "--timestamps", &container.name])?;
+    let path = dir.join(format!("{}.log", container.name));
+    std::fs::write(&path, output)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+// committed and saved, so the image does not outlive the snapshot on the host
+fn snapshot(container: &LabeledContainer, dir: &Path) -> Result<PathBuf, String> {
+    let image = format!("cdm-snapshot/{}:final", container.name.to_lowercase());
+    let path = dir.join(format!("{}.tar", container.name));
+    docker(&["commit", &container.name, &image])?;
+    let saved = docker(&["save", "--output", &path.to_string_lossy(), &image]);
+    docker(&["rmi", &image])?;
+    saved?;
+    Ok(path)
+}
+
+impl ChallengeRepository {
+    // the end of the event: archive what is worth keeping, take everything down and clean the host
+    pub fn finalize_event(
+        &self,
+        backend: Arc<dyn Backend>,
+        events: &[Event],
+        options: &FinalizeOptions,
+    ) -> Result<FinalReport, String> {
+        let logs_dir = options.archive_dir.join("logs");
+        let snapshots_dir = options.archive_dir.join("snapshots");
+        create_dir(&logs_dir)?;
+        create_dir(&snapshots_dir)?;
+        let mut report = FinalReport::default();
+
+        let containers = labels::discover(None, None)?;
+        for container in &containers {
+            match export_logs(container, &logs_dir) {
+                Ok(path) => report.logs.push(path),
+                Err(e) => report.errors.push(e),
+            }
+            let flagged = container.instance_id.is_some_and(|id| {
+                options
+                    .flagged
+                    .iter()
+                    .any(|(challenge, flagged)| *challenge == container.challenge && *flagged == id)
+            });
+            if flagged {
+                match snapshot(container, &snapshots_dir) {
+                    Ok(path) => report.snapshots.push(path),
+                    Err(e) => report.errors.push(e),
+                }
+            }
+        }
+
+        let instances: BTreeSet<(&str, InstanceId)> = containers
+            .iter()
+            .filter_map(|container| Some((container.challenge.as_str(), container.instance_id?)))
+            .collect();
+        for (challenge, id) in instances {
+            let down = self.manager(challenge, id).and_then(|cdm| {
+                cdm.with_backend(backend.clone())
+                    .down_with(&DownOptions::force())
+                    .map_err(String::from)
+            });
+            match down {
+                Ok(_) => report.stopped += 1,
+                Err(e) => report.errors.push(format!("{} #{}: {}", challenge, id, e)),
+            }
+        }
+        // whatever the repository does not know anymore
+        let left: Vec<String> = labels::discover(None, None)?
+            .into_iter()
+            .map(|container| container.name)
+            .collect();
+        if !left.is_empty() {
+            let mut args = vec!["rm", "--force", "--volumes"];
+            args.extend(left.iter().map(String::as_str));
+            if let Err(e) = docker(&args) {
+                report.errors.push(e);
+            }
+        }
+
+        if options.prune {
+            let filter = labels::filter(labels::CHALLENGE, None);
+            for args in [
+                vec!["volume", "prune", "--force", "--filter", &filter],
+                vec!["network", "prune", "--force", "--filter", &filter],
+                vec![
+                    "image",
+                    "prune",
+                    "--all",
+                    "--force",
+                    "--filter",
+                    "label=com.docker.compose.project",
+                ],
+                vec!["image", "prune", "--force"],
+            ] {
+                if let Err(e) = docker(&args) {
+                    report.errors.push(e);
+                }
+            }
+        }
+
+        report.finished_at = schedule::now();
+        report.challenges = challenge_stats(events, report.finished_at);
+        report.clean = labels::discover(None, None)?.is_empty();
+        let path = options.archive_dir.join("report.json");
+        let content = serde_json::to_vec_pretty(&report)
+            .map_err(|e| format!("Failed to serialize {}: {}", path.display(), e))?;
+        std::fs::write(&path, content)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test_finalize {
+    use super::*;
+
+    #[test]
+    fn check_challenge_stats() {
+        let event = |kind, challenge: &str, id, timestamp| Event {
+            kind,
+            challenge: challenge.to_string(),
+            instance_id: InstanceId::new(id),
+            timestamp,
+        };
+        let events = [
+            event(EventKind::Up { port: 30000 }, "comment", 1, 100),
+            event(EventKind::Up { port: 30001 }, "comment", 2, 150),
+            event(EventKind::Down, "comment", 1, 400),
+            event(
+                EventKind::Failed {
+                    operation: "up".to_string(),
+                    error: "no space left".to_string(),
+                },
+                "stack",
+                1,
+                200,
+            ),
+            event(
+                EventKind::Crashed {
+                    exit_code: Some(139),
+                },
+                "comment",
+                2,
+                300,
+            ),
+        ];
+
+        let stats = challenge_stats(&events, 1000);
+        assert_eq!(
+            stats["comment"],
+            ChallengeStats {
+                spawns: 2,
+                failures: 0,
+                crashes: 1,
+                uptime_seconds: 300 + 850,
+            }
+        );
+        assert_eq!(stats["stack"].failures, 1);
+        assert_eq!(stats["stack"].uptime_seconds, 0);
+    }
+}
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use strum::{AsRefStr, Display};
+use zeroize::Zeroizing;
+
+// where the main container reads its flag from
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, AsRefStr, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum FlagDelivery {
+    // FLAG=${FLAG} in the compose file, docker inspect shows it to anyone on the host
+    #[default]
+    Env,
+    // a compose secret at SECRET_PATH, compose copies it in and it stays out of the env
+    Secret,
+}
+
+pub const SECRET_PATH: &str = "/run/secrets/flag";
+// only compose sees this one, the secret is sourced from it
+pub(crate) const SECRET_ENV: &str = "CDM_FLAG";
+
+// the flag of an instance, wiped from memory on drop and never printed,
+// there is no Display or Serialize on purpose
+#[derive(PartialEq, Eq)]
+pub struct Flag(Zeroizing<String>);
+
+impl Flag {
+    pub fn new(flag: String) -> Self {
+        Flag(Zeroizing::new(flag))
+    }
+
+    // only for what has to hand the flag to the instance or compare it
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Flag(<redacted>)")
+    }
+}
+
+impl From<String> for Flag {
+    fn from(flag: String) -> Self {
+        Flag::new(flag)
+    }
+}
+
+impl From<&str> for Flag {
+    fn from(flag: &str) -> Self {
+        Flag::new(flag.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test_flag {
+    use super::*;
+
+    #[test]
+    fn check_redacted() {
+        let flag = Flag::from("flag{secret}");
+        assert_eq!(flag.expose(), "flag{secret}");
+        assert_eq!(format!("{:?}", flag), "Flag(<redacted>)");
+        assert_eq!(flag, Flag::from("flag{secret}".to_string()));
+    }
+}
@@ -0,0 +1,100 @@
+use crate::error::CdmError;
+use crate::{Category, ChallengeDockerManager};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+// no new instances during the scoreboard freeze, the running ones stay up
+#[derive(Debug, Default)]
+pub struct Freeze {
+    frozen: AtomicBool,
+    // categories that keep spawning while frozen
+    exceptions: Mutex<Vec<Category>>,
+}
+
+impl Freeze {
+    pub fn new() -> Self {
+        Freeze::default()
+    }
+
+    pub fn freeze(&self, exceptions: &[Category]) {
+        *self.exceptions.lock().unwrap_or_else(|e| e.into_inner()) = exceptions.to_vec();
+        self.frozen.store(true, Ordering::SeqCst);
+    }
+
+    pub fn thaw(&self) {
+        self.frozen.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::SeqCst)
+    }
+
+    pub fn exceptions(&self) -> Vec<Category> {
+        self.exceptions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn is_frozen_for(&self, category: Category) -> bool {
+        self.is_frozen()
+            && !self
+                .exceptions
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .contains(&category)
+    }
+}
+
+// the freeze every manager respects unless given another one
+pub fn global() -> Arc<Freeze> {
+    static FREEZE: OnceLock<Arc<Freeze>> = OnceLock::new();
+    FREEZE.get_or_init(|| Arc::new(Freeze::new())).clone()
+}
+
+impl ChallengeDockerManager {
+    pub(crate) fn check_frozen(&self) -> Result<(), CdmError> {
+        let category = self.challenge_docker_config.category;
+        if self.freeze().is_frozen_for(category) {
+            return Err(CdmError::Frozen {
+                challenge: self.challenge_docker_config.name.clone(),
+                category,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_freeze {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::lifecycle::UpOptions;
+
+    #[test]
+    fn check_freeze() {
+        let mock = Arc::new(MockBackend::new());
+        let freeze = Arc::new(Freeze::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 1)
+            .with_backend(mock.clone())
+            .with_freeze(freeze.clone());
+        let port = cdm.up(&"flag{before}".into()).unwrap();
+
+        freeze.freeze(&[Category::Pwn]);
+        // the running instance is kept and handed out again
+        assert_eq!(cdm.up(&"flag{before}".into()).unwrap(), port);
+        let other = cdm.instance(crate::id::InstanceId::new(2));
+        assert_eq!(
+            other.up_with(&"flag{frozen}".into(), &UpOptions::default()),
+            Err(CdmError::Frozen {
+                challenge: "comment".to_string(),
+                category: Category::Web,
+            })
+        );
+
+        freeze.freeze(&[Category::Web]);
+        other.up(&"flag{excepted}".into()).unwrap();
+        freeze.thaw();
+        assert!(!freeze.is_frozen_for(Category::Crypto));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::ChallengeDockerManager;
+use crate::capabilities::DockerCapabilities;
+use crate::error::CdmError;
+use crate::settings::CdmSettings;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+fn default_driver() -> String {
+    "nvidia".to_string()
+}
+
+fn default_capabilities() -> Vec<String> {
+    vec!["gpu".to_string()]
+}
+
+// a device reservation of the main service, `gpus = { count = 1 }` in the FloatCTF.toml
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GpuConfig {
+    #[serde(default = "default_driver")]
+    pub driver: String,
+    // every gpu of the host unless a count or the device ids are given
+    #[serde(default)]
+    pub count: Option<u64>,
+    #[serde(default)]
+    pub device_ids: Vec<String>,
+    #[serde(default = "default_capabilities")]
+    pub capabilities: Vec<String>,
+}
+
+impl Default for GpuConfig {
+    fn default() -> Self {
+        GpuConfig {
+            driver: default_driver(),
+            count: None,
+            device_ids: Vec::new(),
+            capabilities: default_capabilities(),
+        }
+    }
+}
+
+impl GpuConfig {
+    // compose takes either a count or the device ids
+    pub(crate) fn compose_override(&self, service: &str) -> Value {
+        let mut device = json!({
+            "driver": self.driver,
+            "capabilities": self.capabilities,
+        });
+        if !self.device_ids.is_empty() {
+            device["device_ids"] = json!(self.device_ids);
+        } else {
+            device["count"] = match self.count {
+                Some(count) => json!(count),
+                None => json!("all"),
+            };
+        }
+        let mut services = serde_json::Map::new();
+        services.insert(
+            service.to_string(),
+            json!({ "deploy": { "resources": { "reservations": { "devices": [device] } } } }),
+        );
+        json!({ "services": services })
+    }
+}
+
+// docker info takes a while, the gpus of a host do not change while cdm runs, by context or host
+static GPUS: OnceLock<Mutex<HashMap<Option<String>, bool>>> = OnceLock::new();
+
+fn has_gpus(settings: &CdmSettings, target: Option<&str>) -> Result<bool, String> {
+    let gpus = GPUS.get_or_init(Default::default);
+    let key = target.map(str::to_string);
+    if let Some(found) = gpus.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        return Ok(*found);
+    }
+    let found = DockerCapabilities::detect(settings, target)?.gpus;
+    gpus.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(key, found);
+    Ok(found)
+}
+
+impl ChallengeDockerManager {
+    // before compose gets to pull or create anything
+    pub(crate) fn check_gpus(&self) -> Result<(), CdmError> {
+        if self.challenge_docker_config.gpus.is_none()
+            || has_gpus(self.settings(), self.docker_context())?
+        {
+            return Ok(());
+        }
+        Err(CdmError::NoGpu {
+            challenge: self.challenge_docker_config.name.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_gpu {
+    use super::*;
+
+    fn devices(gpus: &GpuConfig) -> Value {
+        let content = gpus.compose_override("model");
+        content["services"]["model"]["deploy"]["resources"]["reservations"]["devices"].clone()
+    }
+
+    #[test]
+    fn check_compose_override() {
+        let gpus: GpuConfig = toml::from_str("count = 1").unwrap();
+        assert_eq!(
+            devices(&gpus),
+            json!([{ "driver": "nvidia", "count": 1, "capabilities": ["gpu"] }])
+        );
+
+        let gpus = GpuConfig {
+            device_ids: vec!["0".to_string(), "2".to_string()],
+            ..Default::default()
+        };
+        let device = &devices(&gpus)[0];
+        assert_eq!(device["device_ids"], json!(["0", "2"]));
+        assert!(device.get("count").is_none());
+        assert_eq!(devices(&GpuConfig::default())[0]["count"], "all");
+    }
+
+    #[test]
+    fn check_gpus_by_target() {
+        {
+            let mut gpus = GPUS.get_or_init(Default::default).lock().unwrap();
+            gpus.insert(Some("gpu-box".to_string()), true);
+            gpus.insert(Some("cpu-box".to_string()), false);
+        }
+        let mut cdm = ChallengeDockerManager::test_manager("model", 1);
+        cdm.config_mut().gpus = Some(GpuConfig::default());
+        let gpu = cdm.clone().with_context(Some("gpu-box".to_string()));
+        assert!(gpu.check_gpus().is_ok());
+        let cpu = cdm.with_context(Some("cpu-box".to_string()));
+        assert!(matches!(cpu.check_gpus(), Err(CdmError::NoGpu { .. })));
+    }
+}
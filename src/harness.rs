@@ -0,0 +1,233 @@
+use crate::ChallengeDockerManager;
+use crate::backend::Backend;
+use crate::flag::Flag;
+use crate::id::InstanceId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// spawns many instances of one challenge at once to shake out races between them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConcurrencyHarness {
+    pub instances: u64,
+    // instance ids are first_id..first_id + instances
+    pub first_id: InstanceId,
+    pub concurrency: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceCheck {
+    pub instance_id: InstanceId,
+    pub project: String,
+    pub port: Option<u64>,
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HarnessReport {
+    pub challenge: String,
+    pub instances: Vec<InstanceCheck>,
+}
+
+impl HarnessReport {
+    pub fn is_ok(&self) -> bool {
+        self.instances.iter().all(|check| check.problems.is_empty())
+    }
+
+    pub fn mismatches(&self) -> Vec<&InstanceCheck> {
+        self.instances
+            .iter()
+            .filter(|check| !check.problems.is_empty())
+            .collect()
+    }
+}
+
+impl Default for ConcurrencyHarness {
+    fn default() -> Self {
+        ConcurrencyHarness {
+            instances: 16,
+            first_id: InstanceId::new(1000),
+            concurrency: 16,
+        }
+    }
+}
+
+fn harness_flag(id: InstanceId) -> Flag {
+    Flag::new(format!("flag{{cdm_harness_{}}}", id))
+}
+
+// which instance a deployed harness flag was meant for, the flag itself is never reported
+fn flag_owner(flag: &Flag) -> Option<InstanceId> {
+    flag.expose()
+        .strip_prefix("flag{cdm_harness_")?
+        .strip_suffix('}')?
+        .parse()
+        .ok()
+}
+
+impl ConcurrencyHarness {
+    pub fn new(instances: u64) -> Self {
+        ConcurrencyHarness {
+            instances,
+            concurrency: instances as usize,
+            ..Default::default()
+        }
+    }
+
+    fn check_instance(cdm: &ChallengeDockerManager) -> InstanceCheck {
+        let flag = harness_flag(cdm.id);
+        let mut check = InstanceCheck {
+            instance_id: cdm.id,
+            project: cdm.docker_compose_project_name.clone(),
+            port: None,
+            problems: Vec::new(),
+        };
+
+        let port = match cdm.up(&flag) {
+            Ok(port) => port,
+            Err(e) => {
+                check.problems.push(format!("up failed: {}", e));
+                return check;
+            }
+        };
+        check.port = Some(port);
+
+        match cdm.backend().ports(cdm) {
+            Ok(ports) if ports.iter().any(|mapping| mapping.host_port == port) => {}
+            Ok(ports) => check.problems.push(format!(
+                "up reported port {} but the instance publishes {:?}",
+                port,
+                ports.iter().map(|m| m.host_port).collect::<Vec<_>>()
+            )),
+            Err(e) => check.problems.push(format!("ports failed: {}", e)),
+        }
+
+        if cdm.challenge_docker_config.is_dynamic_flag {
+            match cdm.backend().deployed_flag(cdm) {
+                Ok(Some(deployed)) if deployed == flag => {}
+                Ok(Some(deployed)) => check.problems.push(match flag_owner(&deployed) {
+                    Some(owner) => format!(
+                        "expected the flag of instance {} but got the one of instance {}",
+                        cdm.id, owner
+                    ),
+                    None => format!("expected the flag of instance {} but got another", cdm.id),
+                }),
+                Ok(None) => {}
+                Err(e) => check
+                    .problems
+                    .push(format!("reading the flag failed: {}", e)),
+            }
+        }
+        check
+    }
+
+    pub fn run(
+        &self,
+        challenge_path: &Path,
+        backend: Arc<dyn Backend>,
+    ) -> Result<HarnessReport, String> {
+        let base = ChallengeDockerManager::new(challenge_path.to_path_buf(), self.first_id)?
+            .with_backend(backend);
+        let managers: Vec<ChallengeDockerManager> = (self.first_id.get()
+            ..self.first_id.get() + self.instances)
+            .map(|id| base.instance(InstanceId::new(id)))
+            .collect();
+        let challenge = base.challenge_docker_config.name.clone();
+
+        // nothing is torn down before every instance has been checked, so they all overlap
+        let next = AtomicU64::new(0);
+        let mut checks: Vec<InstanceCheck> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.concurrency.max(1))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut checks = Vec::new();
+                        while let Some(cdm) =
+                            managers.get(next.fetch_add(1, Ordering::SeqCst) as usize)
+                        {
+                            checks.push(ConcurrencyHarness::check_instance(cdm));
+                        }
+                        checks
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap_or_default())
+                .collect()
+        });
+        checks.sort_by_key(|check| check.instance_id);
+
+        let mut by_port: HashMap<u64, Vec<u64>> = HashMap::new();
+        let mut by_project: HashMap<String, Vec<u64>> = HashMap::new();
+        for check in &checks {
+            if let Some(port) = check.port.filter(|port| *port != 0) {
+                by_port
+                    .entry(port)
+                    .or_default()
+                    .push(check.instance_id.get());
+            }
+            by_project
+                .entry(check.project.clone())
+                .or_default()
+                .push(check.instance_id.get());
+        }
+        for check in &mut checks {
+            if let Some(port) = check.port
+                && let Some(ids) = by_port.get(&port)
+                && ids.len() > 1
+            {
+                check
+                    .problems
+                    .push(format!("port {} is shared by instances {:?}", port, ids));
+            }
+            if let Some(ids) = by_project.get(&check.project)
+                && ids.len() > 1
+            {
+                check.problems.push(format!(
+                    "project {} is shared by instances {:?}",
+                    check.project, ids
+                ));
+            }
+        }
+
+        for cdm in &managers {
+            if let Err(e) = cdm.down()
+                && let Some(check) = checks.iter_mut().find(|c| c.instance_id == cdm.id)
+            {
+                check.problems.push(format!("down failed: {}", e));
+            }
+        }
+
+        Ok(HarnessReport {
+            challenge,
+            instances: checks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_harness {
+    use super::*;
+    use crate::backend::MockBackend;
+
+    #[test]
+    fn check_collisions() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+
+        let harness = ConcurrencyHarness::new(8);
+        let mock = Arc::new(MockBackend::new());
+        let report = harness.run(&root, mock.clone()).unwrap();
+        assert!(report.is_ok(), "{:?}", report.mismatches());
+        assert!(mock.running().is_empty());
+
+        // two instances handed the same port is exactly what the harness is for
+        mock.respond_up(31337);
+        mock.respond_up(31337);
+        let report = ConcurrencyHarness::new(2).run(&root, mock).unwrap();
+        assert_eq!(report.mismatches().len(), 2);
+        assert!(report.instances[0].problems[0].contains("shared by instances"));
+    }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+fn default_interval() -> u64 {
+    10
+}
+
+fn default_timeout() -> u64 {
+    5
+}
+
+fn default_retries() -> u64 {
+    3
+}
+
+// one of tcp, http or command, put over the main service as a compose healthcheck
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthcheckConfig {
+    // the container port that has to accept connections
+    #[serde(default)]
+    pub tcp: Option<u64>,
+    // a path that has to answer 2xx, e.g. /health
+    #[serde(default)]
+    pub http: Option<String>,
+    // of http, the first container port of the main service unless given
+    #[serde(default)]
+    pub port: Option<u64>,
+    // run in the container, exit code 0 is healthy
+    #[serde(default)]
+    pub command: Vec<String>,
+    // seconds
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    #[serde(default = "default_retries")]
+    pub retries: u64,
+    #[serde(default)]
+    pub start_period: u64,
+}
+
+impl HealthcheckConfig {
+    // images rarely have both nc and wget, so either one will do
+    pub fn test(&self, container_port: Option<u64>) -> Result<Vec<String>, String> {
+        let probes = self.tcp.is_some() as usize
+            + self.http.is_some() as usize
+            + !self.command.is_empty() as usize;
+        if probes != 1 {
+            return Err("The healthcheck needs exactly one of tcp, http or command".to_string());
+        }
+        if let Some(port) = self.tcp {
+            return Ok(vec![
+                "CMD-SHELL".to_string(),
+                format!(
+                    "nc -z 127.0.0.1 {0} || bash -c ': > /dev/tcp/127.0.0.1/{0}'",
+                    port
+                ),
+            ]);
+        }
+        if let Some(path) = &self.http {
+            let port = self
+                .port
+                .or(container_port)
+                .ok_or("The http healthcheck needs a port, the main service publishes none")?;
+            let url = format!("http://127.0.0.1:{}/{}", port, path.trim_start_matches('/'));
+            return Ok(vec![
+                "CMD-SHELL".to_string(),
+                format!(
+                    "wget -q -O /dev/null {0} || curl -fsS -o /dev/null {0}",
+                    url
+                ),
+            ]);
+        }
+        let mut test = vec!["CMD".to_string()];
+        test.extend(self.command.iter().cloned());
+        Ok(test)
+    }
+
+    pub(crate) fn compose_override(
+        &self,
+        service: &str,
+        container_port: Option<u64>,
+    ) -> Result<Value, String> {
+        let mut services = serde_json::Map::new();
+        services.insert(
+            service.to_string(),
+            json!({
+                "healthcheck": {
+                    "test": self.test(container_port)?,
+                    "interval": format!("{}s", self.interval),
+                    "timeout": format!("{}s", self.timeout),
+                    "retries": self.retries,
+                    "start_period": format!("{}s", self.start_period),
+                }
+            }),
+        );
+        Ok(json!({ "services": services }))
+    }
+}
+
+#[cfg(test)]
+mod test_healthcheck {
+    use super::*;
+
+    #[test]
+    fn check_healthcheck() {
+        let http: HealthcheckConfig = toml::from_str("http = \"/health\"\nretries = 5\n").unwrap();
+        let content = http.compose_override("web", Some(1337)).unwrap();
+        let healthcheck = &content["services"]["web"]["healthcheck"];
+        assert_eq!(
+            healthcheck["test"][1],
+            "wget -q -O /dev/null http://127.0.0.1:1337/health || curl -fsS -o /dev/null http://127.0.0.1:1337/health"
+        );
+        assert_eq!(healthcheck["interval"], "10s");
+        assert_eq!(healthcheck["retries"], 5);
+        assert!(http.test(None).is_err());
+
+        let tcp: HealthcheckConfig = toml::from_str("tcp = 9999").unwrap();
+        assert!(tcp.test(None).unwrap()[1].starts_with("nc -z 127.0.0.1 9999"));
+        let command: HealthcheckConfig =
+            toml::from_str("command = [\"pgrep\", \"socat\"]").unwrap();
+        assert_eq!(command.test(None).unwrap(), ["CMD", "pgrep", "socat"]);
+        let both = HealthcheckConfig {
+            tcp: Some(1),
+            ..command
+        };
+        assert!(both.test(None).is_err());
+    }
+}
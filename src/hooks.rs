@@ -0,0 +1,336 @@
+use crate::ChallengeDockerManager;
+use crate::error::CdmError;
+use crate::events::EventKind;
+use crate::flag::Flag;
+use crate::plan::PlannedCommand;
+use crate::state;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path};
+
+// a step of the instance lifecycle, a script of the challenge or a one-off compose service
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hook {
+    // run in the challenge directory, e.g. ["./init.sh", "--keys"], see allow_host_hooks
+    #[serde(default)]
+    pub command: Vec<String>,
+    // like an init container, `compose run --rm` of this service, command replaces its own
+    #[serde(default)]
+    pub service: Option<String>,
+    // post_up only, run the command in the main container instead of on the host
+    #[serde(default)]
+    pub exec: bool,
+    // post_up only, a failing hook takes the instance down again, pre_up hooks always are
+    #[serde(default)]
+    pub fatal: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hooks {
+    // before up, the spawn fails with it
+    #[serde(default)]
+    pub pre_up: Option<Hook>,
+    // once the instance accepts connections, in order
+    #[serde(default)]
+    pub post_up: Vec<Hook>,
+}
+
+impl ChallengeDockerManager {
+    // what every hook learns about the instance it runs for
+    fn hook_env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("ID", self.id.to_string()),
+            ("CDM_CHALLENGE", self.challenge_docker_config.name.clone()),
+            ("CDM_INSTANCE_ID", self.id.to_string()),
+            ("CDM_PROJECT", self.docker_compose_project_name.clone()),
+            (
+                "CDM_CHALLENGE_PATH",
+                self.challenge_path.to_string_lossy().to_string(),
+            ),
+        ];
+        if let Some(team) = self.team {
+            env.push(("CDM_TEAM", team.to_string()));
+        }
+        env
+    }
+
+    pub(crate) fn hook_command(
+        &self,
+        hook: &Hook,
+        env: &[(&str, String)],
+    ) -> Result<PlannedCommand, CdmError> {
+        let command = match &hook.service {
+            Some(service) => {
+                let env_file = self.env_file_args();
+                let mut args: Vec<&str> = env_file.iter().map(String::as_str).collect();
+                let compose_yml = self.docker_compose_yml.to_string_lossy();
+                args.extend([
+                    "--file",
+                    &compose_yml,
+                    "--project-name",
+                    &self.docker_compose_project_name,
+                    "run",
+                    "--rm",
+                    "--no-deps",
+                    service,
+                ]);
+                args.extend(hook.command.iter().map(String::as_str));
+                PlannedCommand::new("docker-compose", &args).on(self.docker_context())
+            }
+            None => {
+                let (program, args) = hook.command.split_first().ok_or_else(|| {
+                    format!(
+                        "The hook of {} has neither a command nor a service",
+                        self.challenge_docker_config.name
+                    )
+                })?;
+                let denied = |reason: &str| CdmError::HookDenied {
+                    challenge: self.challenge_docker_config.name.clone(),
+                    reason: reason.to_string(),
+                };
+                // the flag is in its env, it is the host the challenge author gets to run code on
+                if !self.settings().allow_host_hooks {
+                    return Err(denied(
+                        "host commands need allow_host_hooks, use a service or exec instead",
+                    ));
+                }
+                let program = match program.strip_prefix("./") {
+                    Some(script) => {
+                        let escapes = Path::new(script)
+                            .components()
+                            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+                        if escapes {
+                            return Err(denied(&format!(
+                                "{} is outside the challenge directory",
+                                program
+                            )));
+                        }
+                        self.challenge_path
+                            .join(script)
+                            .to_string_lossy()
+                            .to_string()
+                    }
+                    None => program.clone(),
+                };
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                PlannedCommand::new(&program, &args).dir(&self.challenge_path)
+            }
+        };
+        Ok(env
+            .iter()
+            .fold(command, |command, (key, value)| command.env(key, value)))
+    }
+
+    pub(crate) fn run_pre_up(&self, flag: &Flag) -> Result<(), CdmError> {
+        let Some(hook) = &self.challenge_docker_config.hooks.pre_up else {
+            return Ok(());
+        };
+        let mut env = self.hook_env();
+        if self.challenge_docker_config.is_dynamic_flag {
+            env.push(("FLAG", flag.expose().to_string()));
+        }
+        self.hook_command(hook, &env)?.run(self.settings())?;
+        Ok(())
+    }
+
+    // the hooks only get the sha256 of the flag, to tell instances apart or check a submission
+    fn run_post_up_hook(&self, hook: &Hook, env: &[(&str, String)]) -> Result<(), CdmError> {
+        if !hook.exec {
+            self.hook_command(hook, env)?.run(self.settings())?;
+            return Ok(());
+        }
+        let assignments: Vec<String> = env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        let mut command = vec!["env"];
+        command.extend(assignments.iter().map(String::as_str));
+        command.extend(hook.command.iter().map(String::as_str));
+        self.backend.exec(self, &command)?;
+        Ok(())
+    }
+
+    // waits for the port first, failures of hooks that are not fatal are only reported
+    pub(crate) fn run_post_up(&self, port: u64, flag: &Flag) -> Result<(), CdmError> {
+        let hooks = &self.challenge_docker_config.hooks.post_up;
+        if hooks.is_empty() {
+            return Ok(());
+        }
+        self.wait_ready(port, self.settings().readiness_timeout)?;
+        let mut env = self.hook_env();
+        env.push(("CDM_PORT", port.to_string()));
+        if self.challenge_docker_config.is_dynamic_flag {
+            env.push(("CDM_FLAG_SHA256", state::flag_sha256(flag.expose())));
+        }
+        for hook in hooks {
+            match self.run_post_up_hook(hook, &env) {
+                // a hook the settings forbid is never tried, whatever fatal says
+                Err(e) if hook.fatal || matches!(e, CdmError::HookDenied { .. }) => return Err(e),
+                Err(e) => self.emit(EventKind::Failed {
+                    operation: "post_up".to_string(),
+                    error: e.to_string(),
+                }),
+                Ok(()) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_hooks {
+    use super::*;
+    use crate::backend::{MockBackend, MockOperation};
+    use crate::id::{InstanceId, TeamId};
+    use crate::lifecycle::UpOptions;
+    use crate::settings::CdmSettings;
+    use std::sync::Arc;
+
+    fn allow_host_hooks() -> Arc<CdmSettings> {
+        Arc::new(CdmSettings {
+            allow_host_hooks: true,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn check_pre_up() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+        let mock = Arc::new(MockBackend::new());
+        let mut cdm = ChallengeDockerManager::new(root.clone(), InstanceId::new(3))
+            .unwrap()
+            .with_backend(mock.clone())
+            .with_settings(Arc::new(CdmSettings::default()));
+        // run in the challenge directory
+        cdm.config_mut().hooks.pre_up = Some(Hook {
+            command: vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                r#"echo "$CDM_INSTANCE_ID $FLAG" > instance"#.to_string(),
+            ],
+            ..Default::default()
+        });
+        let err = cdm
+            .up_with(&"flag{hooked}".into(), &UpOptions::default())
+            .unwrap_err();
+        assert!(matches!(err, CdmError::HookDenied { .. }), "{}", err);
+        assert!(mock.calls_of(MockOperation::Up).is_empty());
+
+        let mut cdm = cdm.with_settings(allow_host_hooks());
+        cdm.up_with(&"flag{hooked}".into(), &UpOptions::default())
+            .unwrap();
+        let written = std::fs::read_to_string(root.join("instance")).unwrap();
+        assert_eq!(written, "3 flag{hooked}\n");
+        cdm.down().unwrap();
+
+        cdm.config_mut().hooks.pre_up = Some(Hook {
+            command: vec!["sh".to_string(), "-c".to_string(), "exit 3".to_string()],
+            ..Default::default()
+        });
+        let err = cdm
+            .up_with(&"flag{hooked}".into(), &UpOptions::default())
+            .unwrap_err();
+        assert!(matches!(err, CdmError::Command(e) if e.status == Some(3)));
+        assert_eq!(mock.calls_of(MockOperation::Up).len(), 1);
+
+        let escaping = Hook {
+            command: vec!["./../init.sh".to_string()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            cdm.hook_command(&escaping, &[]),
+            Err(CdmError::HookDenied { .. })
+        ));
+        let script = Hook {
+            command: vec!["./scripts/init.sh".to_string()],
+            ..Default::default()
+        };
+        let command = cdm.hook_command(&script, &[]).unwrap();
+        assert_eq!(
+            command.program,
+            root.join("scripts/init.sh").to_string_lossy()
+        );
+        assert_eq!(command.dir.as_deref(), Some(root.as_path()));
+
+        let hook = Hook {
+            command: vec!["pg_restore".to_string()],
+            service: Some("seed".to_string()),
+            ..Default::default()
+        };
+        let command = cdm.hook_command(&hook, &cdm.hook_env()).unwrap();
+        assert!(command.args.ends_with(&[
+            "run".to_string(),
+            "--rm".to_string(),
+            "--no-deps".to_string(),
+            "seed".to_string(),
+            "pg_restore".to_string()
+        ]));
+    }
+
+    #[test]
+    fn check_post_up() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = u64::from(listener.local_addr().unwrap().port());
+        let mock = Arc::new(MockBackend::new());
+        let mut cdm = ChallengeDockerManager::new(root.clone(), InstanceId::new(4))
+            .unwrap()
+            .with_backend(mock.clone())
+            .with_team(TeamId::new(9))
+            .with_settings(allow_host_hooks());
+        let register = Hook {
+            command: vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                r#"echo "$CDM_TEAM $CDM_PORT $CDM_FLAG_SHA256 $FLAG" > "$CDM_CHALLENGE_PATH/registered""#
+                    .to_string(),
+            ],
+            ..Default::default()
+        };
+        let failing = Hook {
+            command: vec!["false".to_string()],
+            ..Default::default()
+        };
+        cdm.config_mut().hooks.post_up = vec![failing.clone(), register];
+
+        mock.respond_up(port);
+        assert_eq!(cdm.up(&"flag{posted}".into()).unwrap(), port);
+        let registered = std::fs::read_to_string(root.join("registered")).unwrap();
+        assert_eq!(
+            registered,
+            format!("9 {} {} \n", port, state::flag_sha256("flag{posted}"))
+        );
+        cdm.down().unwrap();
+
+        cdm.config_mut().hooks.post_up = vec![Hook {
+            fatal: true,
+            ..failing
+        }];
+        mock.respond_up(port);
+        assert!(cdm.up(&"flag{posted}".into()).is_err());
+        assert!(mock.running().is_empty());
+
+        // only exec hooks without allow_host_hooks, a host one takes the instance down
+        let mut cdm = cdm.with_settings(Arc::new(CdmSettings::default()));
+        cdm.config_mut().hooks.post_up = vec![Hook {
+            command: vec!["true".to_string()],
+            exec: true,
+            ..Default::default()
+        }];
+        mock.respond_up(port);
+        cdm.up(&"flag{posted}".into()).unwrap();
+        assert_eq!(mock.calls_of(MockOperation::Exec).len(), 1);
+        cdm.down().unwrap();
+        cdm.config_mut().hooks.post_up = vec![Hook {
+            command: vec!["true".to_string()],
+            ..Default::default()
+        }];
+        mock.respond_up(port);
+        let err = cdm
+            .up_with(&"flag{posted}".into(), &UpOptions::default())
+            .unwrap_err();
+        assert!(matches!(err, CdmError::HookDenied { .. }), "{}", err);
+        assert!(mock.running().is_empty());
+    }
+}
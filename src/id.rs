@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// instance ids end up in project and container names, team ids in attachment paths,
+// so both only ever parse from plain decimal digits
+macro_rules! id_type {
+    ($name:ident, $what:literal) => {
+        #[derive(
+            Debug,
+            Clone,
+            Copy,
+            Default,
+            PartialEq,
+            Eq,
+            Hash,
+            PartialOrd,
+            Ord,
+            Serialize,
+            Deserialize,
+        )]
+        #[serde(transparent)]
+        pub struct $name(u64);
+
+        impl $name {
+            pub const fn new(id: u64) -> Self {
+                $name(id)
+            }
+
+            pub const fn get(self) -> u64 {
+                self.0
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(id: u64) -> Self {
+                $name(id)
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(format!("Invalid {} {:?}, expected a number", $what, s));
+                }
+                s.parse()
+                    .map($name)
+                    .map_err(|e| format!("Invalid {} {:?}: {}", $what, s, e))
+            }
+        }
+    };
+}
+
+id_type!(InstanceId, "instance id");
+id_type!(TeamId, "team id");
+
+#[cfg(test)]
+mod test_id {
+    use super::*;
+
+    #[test]
+    fn check_parse() {
+        assert_eq!("42".parse::<InstanceId>().unwrap(), InstanceId::new(42));
+        assert_eq!(TeamId::new(7).to_string(), "7");
+        assert!("".parse::<InstanceId>().is_err());
+        assert!("+1".parse::<InstanceId>().is_err());
+        assert!("-1".parse::<TeamId>().is_err());
+        assert!("1; rm -rf /".parse::<InstanceId>().is_err());
+        assert!("99999999999999999999".parse::<TeamId>().is_err());
+
+        // plain numbers on the wire
+        assert_eq!(serde_json::to_string(&InstanceId::new(3)).unwrap(), "3");
+        let team: TeamId = serde_json::from_str("12").unwrap();
+        assert_eq!(team.get(), 12);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::ChallengeDockerManager;
+use crate::flag::{self, Flag, FlagDelivery};
+use zeroize::Zeroizing;
+
+impl ChallengeDockerManager {
+    // check the running instance really got the flag, a compose file without FLAG=${FLAG} deploys fine
+    pub fn verify_injection(&self, flag: &Flag) -> Result<(), String> {
+        let config = &self.challenge_docker_config;
+        if !config.is_dockerd || !config.is_dynamic_flag {
+            return Ok(());
+        }
+
+        let output = match self.flag_delivery() {
+            FlagDelivery::Env => self.backend().exec(self, &["printenv", "FLAG"]).map_err(|e| {
+                format!(
+                    "The {} container has no FLAG in its environment, is FLAG=${{FLAG}} missing from the compose file? {}",
+                    config.name, e
+                )
+            })?,
+            FlagDelivery::Secret => self
+                .backend()
+                .exec(self, &["cat", flag::SECRET_PATH])
+                .map_err(|e| {
+                    format!(
+                        "The {} container has no flag secret at {}: {}",
+                        config.name,
+                        flag::SECRET_PATH,
+                        e
+                    )
+                })?,
+        };
+        let output = Zeroizing::new(output);
+        let deployed = String::from_utf8_lossy(&output);
+        if deployed.trim_end_matches(['\r', '\n']) != flag.expose() {
+            return Err(format!(
+                "The FLAG in the {} container is not the flag of instance {}",
+                config.name, self.id
+            ));
+        }
+
+        if let Some(flag_file) = &config.flag_file {
+            let output = self
+                .backend()
+                .exec(self, &["cat", flag_file])
+                .map_err(|e| format!("Failed to read {} in {}: {}", flag_file, config.name, e))?;
+            if !String::from_utf8_lossy(&output).contains(flag.expose()) {
+                return Err(format!(
+                    "The {} in the {} container does not contain the flag of instance {}",
+                    flag_file, config.name, self.id
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_injection {
+    use super::*;
+    use crate::backend::{Backend, MockBackend};
+    use std::sync::Arc;
+
+    #[test]
+    fn check_verify_injection() {
+        let mock = Arc::new(MockBackend::new());
+        let mut cdm = ChallengeDockerManager::test_manager("comment", 1).with_backend(mock.clone());
+        cdm.up(&"flag{injected}".into()).unwrap();
+        cdm.verify_injection(&"flag{injected}".into()).unwrap();
+        assert!(cdm.verify_injection(&"flag{other}".into()).is_err());
+
+        cdm.config_mut().flag_file = Some("/flag".to_string());
+        assert!(cdm.verify_injection(&"flag{injected}".into()).is_err());
+        mock.with_file("/flag", "flag{injected}\n");
+        cdm.verify_injection(&"flag{injected}".into()).unwrap();
+
+        cdm.config_mut().flag_file = None;
+        cdm.config_mut().flag_delivery = Some(FlagDelivery::Secret);
+        cdm.verify_injection(&"flag{injected}".into()).unwrap();
+        assert!(mock.exec(&cdm, &["printenv", "FLAG"]).is_err());
+    }
+}
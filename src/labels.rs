@@ -0,0 +1,199 @@
+use crate::ChallengeDockerManager;
+use crate::compose::ComposeFile;
+use crate::id::{InstanceId, TeamId};
+use crate::settings;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+// every container, network and volume cdm creates carries these, names are not to be trusted
+pub const CHALLENGE: &str = "cdm.challenge";
+pub const INSTANCE_ID: &str = "cdm.instance_id";
+pub const TEAM: &str = "cdm.team";
+pub const VERSION: &str = "cdm.version";
+const PROJECT: &str = "com.docker.compose.project";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabeledContainer {
+    pub name: String,
+    pub challenge: String,
+    pub instance_id: Option<InstanceId>,
+    pub team: Option<TeamId>,
+    // empty for containers compose did not create
+    pub project: String,
+}
+
+// a docker --filter matching the label, or only the given value of it
+pub fn filter(key: &str, value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("label={}={}", key, value),
+        None => format!("label={}", key),
+    }
+}
+
+pub fn label_args(labels: &BTreeMap<String, String>) -> Vec<String> {
+    labels
+        .iter()
+        .flat_map(|(key, value)| ["--label".to_string(), format!("{}={}", key, value)])
+        .collect()
+}
+
+fn parse_discovered(output: &str) -> Vec<LabeledContainer> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [name, challenge, instance_id, team, project] = fields[..] else {
+                return None;
+            };
+            Some(LabeledContainer {
+                name: name.to_string(),
+                challenge: challenge.to_string(),
+                instance_id: instance_id.parse().ok(),
+                team: team.parse().ok(),
+                project: project.to_string(),
+            })
+        })
+        .collect()
+}
+
+// every container cdm knows about, running or not, optionally of one challenge only, on the
+// docker context or host given or the one of the settings
+pub fn discover(
+    challenge: Option<&str>,
+    target: Option<&str>,
+) -> Result<Vec<LabeledContainer>, String> {
+    let settings = settings::global()?;
+    let format = [CHALLENGE, INSTANCE_ID, TEAM, PROJECT]
+        .iter()
+        .fold("{{.Names}}".to_string(), |format, key| {
+            format!("{}\t{{{{.Label \"{}\"}}}}", format, key)
+        });
+    let output = ChallengeDockerManager::run_command(
+        &settings,
+        "docker",
+        &[
+            "ps",
+            "--all",
+            "--filter",
+            &filter(CHALLENGE, challenge),
+            "--format",
+            &format,
+        ],
+        settings.docker_env(target),
+    )?;
+    Ok(parse_discovered(&String::from_utf8_lossy(&output)))
+}
+
+impl ChallengeDockerManager {
+    // the containers of the challenge on the daemon of the instance
+    pub fn discover(&self) -> Result<Vec<LabeledContainer>, String> {
+        discover(
+            Some(&self.challenge_docker_config.name),
+            self.docker_context(),
+        )
+    }
+
+    pub fn labels(&self) -> BTreeMap<String, String> {
+        let mut labels = BTreeMap::from([
+            (
+                CHALLENGE.to_string(),
+                self.challenge_docker_config.name.clone(),
+            ),
+            (INSTANCE_ID.to_string(), self.id.to_string()),
+            (VERSION.to_string(), env!("CARGO_PKG_VERSION").to_string()),
+        ]);
+        if let Some(team) = self.team {
+            labels.insert(TEAM.to_string(), team.to_string());
+        }
+        labels
+    }
+
+    // a compose override putting the labels on every service, network and volume of the file
+    pub(crate) fn labels_override(&self) -> Result<Value, String> {
+        let compose = ComposeFile::load(&self.docker_compose_yml)?;
+        let labels = self.labels();
+        let labeled = |names: Vec<&String>| -> serde_json::Map<String, Value> {
+            names
+                .into_iter()
+                .map(|name| (name.clone(), json!({ "labels": labels })))
+                .collect()
+        };
+        // external ones belong to someone else
+        let owned = |entries: &BTreeMap<String, serde_yaml::Value>| -> Vec<String> {
+            entries
+                .iter()
+                .filter(|(_, entry)| entry.get("external").is_none())
+                .map(|(name, _)| name.clone())
+                .collect()
+        };
+
+        let mut networks = owned(&compose.networks);
+        if !compose.networks.contains_key("default") {
+            networks.push("default".to_string());
+        }
+        let volumes = owned(&compose.volumes);
+        let mut content = json!({
+            "services": labeled(compose.services.keys().collect()),
+            "networks": labeled(networks.iter().collect()),
+        });
+        if !volumes.is_empty() {
+            content["volumes"] = Value::Object(labeled(volumes.iter().collect()));
+        }
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod test_labels {
+    use super::*;
+
+    #[test]
+    fn check_labels_override() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+        let compose = root.join("docker-compose.yml");
+        let content = std::fs::read_to_string(&compose).unwrap();
+        std::fs::write(
+            &compose,
+            format!(
+                "{}volumes:\n  data: {{}}\nnetworks:\n  shared:\n    external: true\n",
+                content
+            ),
+        )
+        .unwrap();
+
+        let cdm = ChallengeDockerManager::new(root.clone(), InstanceId::new(3))
+            .unwrap()
+            .with_team(TeamId::new(12));
+        let labels = cdm.labels();
+        assert_eq!(labels[CHALLENGE], "comment");
+        assert_eq!(labels[INSTANCE_ID], "3");
+        assert_eq!(labels[TEAM], "12");
+
+        let content = cdm.labels_override().unwrap();
+        assert_eq!(content["services"]["web"]["labels"][TEAM], "12");
+        assert_eq!(
+            content["networks"]["default"]["labels"][CHALLENGE],
+            "comment"
+        );
+        assert!(content["networks"].get("shared").is_none());
+        assert_eq!(content["volumes"]["data"]["labels"][INSTANCE_ID], "3");
+    }
+
+    #[test]
+    fn check_discover() {
+        let output = "challenge-comment-3\tcomment\t3\t12\tchallenge-project-3-comment\nbroken\n";
+        assert_eq!(
+            parse_discovered(output),
+            [LabeledContainer {
+                name: "challenge-comment-3".to_string(),
+                challenge: "comment".to_string(),
+                instance_id: Some(InstanceId::new(3)),
+                team: Some(TeamId::new(12)),
+                project: "challenge-project-3-comment".to_string(),
+            }]
+        );
+        assert_eq!(filter(CHALLENGE, Some("web")), "label=cdm.challenge=web");
+    }
+}
@@ -1,17 +1,184 @@
+pub mod abuse;
+pub mod access;
+pub mod attachments;
+pub mod autoscale;
+pub mod backend;
+pub mod backup;
+pub mod batch;
+pub mod build_cache;
+pub mod bundle;
+pub mod capabilities;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod compose;
+pub mod config_cache;
+pub mod connection_info;
+pub mod connections;
+pub mod dto;
+pub mod error;
+pub mod events;
+pub mod finalize;
+pub mod flag;
+pub mod freeze;
+pub mod gpu;
+#[cfg(feature = "loadtest")]
+pub mod harness;
+pub mod healthcheck;
+pub mod hooks;
+pub mod id;
+pub mod injection;
+pub mod labels;
+pub mod lifecycle;
+pub mod limits;
+#[cfg(feature = "loadtest")]
+pub mod loadtest;
+pub mod maintenance;
+pub mod mounts;
+#[cfg(feature = "webhook")]
+pub mod notify;
+pub mod plan;
+pub mod preload;
+pub mod profile;
+pub mod ratelimit;
+pub mod redeploy;
+pub mod repository;
+pub mod reset;
+pub mod rolling;
+pub mod schedule;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod settings;
+pub mod shell;
+pub mod slug;
+pub mod smoke;
+pub mod solve;
+pub mod staleness;
+pub mod state;
+#[cfg(feature = "store")]
+pub mod store;
+pub mod summary;
+pub mod sysctls;
+pub mod team_attachments;
+pub mod template;
+pub mod testing;
+pub mod timing;
+pub mod usage;
+pub mod validate;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+use access::AccessTokens;
+use attachments::{BuiltAttachment, ResolvedAttachment};
+use autoscale::AutoscaleConfig;
+use backend::{Backend, InstanceStatus, PortMapping, SwarmConfig};
+use build_cache::BuildCache;
+use capabilities::DockerCapabilities;
+use connections::PerConnectionConfig;
+use error::{CdmError, CommandError};
+use events::{Event, EventKind, EventSink};
+use flag::{Flag, FlagDelivery};
+use freeze::Freeze;
+use gpu::GpuConfig;
+use healthcheck::HealthcheckConfig;
+use hooks::Hooks;
+use id::{InstanceId, TeamId};
+use lifecycle::{CommandOverride, DownOptions, UpOptions};
+use maintenance::MaintenanceRegistry;
+use plan::{Operation, PlannedCommand, REDACTED};
+use profile::Profile;
+use ratelimit::RateLimiter;
+use schedule::ChallengeWindow;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::{path::PathBuf, process::Command};
-use strum::{AsRefStr, Display, EnumIter, EnumString};
+use settings::{CdmSettings, PortRange};
+use solve::SolveConfig;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::process::{Command, Output};
+use std::sync::Arc;
+use strum::{AsRefStr, Display, EnumIter, EnumString, IntoEnumIterator};
+use team_attachments::TeamAttachmentGenerator;
+use timing::{SpawnStage, TimingLog};
+use usage::UsageLedger;
+use zeroize::Zeroizing;
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy, EnumIter, EnumString, AsRefStr, Display)]
+// players and older FloatCTF.toml files spell categories in many ways, parsing takes all of them
+#[derive(
+    Debug,
+    Deserialize,
+    Serialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    EnumIter,
+    EnumString,
+    AsRefStr,
+    Display,
+)]
+#[serde(try_from = "String")]
+#[strum(ascii_case_insensitive)]
 pub enum Category {
+    #[strum(to_string = "Web", serialize = "web exploitation", serialize = "webex")]
     Web,
+    #[strum(
+        to_string = "Pwn",
+        serialize = "pwnable",
+        serialize = "binary exploitation",
+        serialize = "exploit"
+    )]
     Pwn,
+    #[strum(to_string = "Crypto", serialize = "cryptography")]
     Crypto,
+    #[strum(to_string = "Misc", serialize = "miscellaneous", serialize = "other")]
     Misc,
+    #[strum(
+        to_string = "Reverse",
+        serialize = "rev",
+        serialize = "re",
+        serialize = "reversing",
+        serialize = "reverse engineering",
+        serialize = "reverse-engineering"
+    )]
     Reverse,
 }
 
+impl Category {
+    pub fn all() -> Vec<Category> {
+        Category::iter().collect()
+    }
+
+    // for the UI, Display stays the short name that FloatCTF.toml uses
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Category::Web => "Web Exploitation",
+            Category::Pwn => "Binary Exploitation",
+            Category::Crypto => "Cryptography",
+            Category::Misc => "Miscellaneous",
+            Category::Reverse => "Reverse Engineering",
+        }
+    }
+}
+
+impl TryFrom<String> for Category {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.trim().parse().map_err(|_| {
+            format!(
+                "Unknown category {}, expected one of {}",
+                value,
+                Category::all()
+                    .iter()
+                    .map(Category::as_ref)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChallengeDockerConfig {
     pub name: String,
@@ -23,68 +190,312 @@ pub struct ChallengeDockerConfig {
     pub is_dynamic_flag: bool,
     pub is_dockerd: bool,
     pub points: i32,
+    #[serde(default)]
+    pub swarm: Option<SwarmConfig>,
+    #[serde(default)]
+    pub built_attachments: Vec<BuiltAttachment>,
+    #[serde(default)]
+    pub team_attachments: Option<TeamAttachmentGenerator>,
+    // packs the attachments into an AES-256 encrypted zip
+    #[serde(default)]
+    pub attachment_password: Option<String>,
+    #[serde(default)]
+    pub solve: Option<SolveConfig>,
+    // where the challenge puts the flag inside the main container, besides $FLAG
+    #[serde(default)]
+    pub flag_file: Option<String>,
+    // the one of the category template, else env, see ChallengeDockerManager::flag_delivery
+    #[serde(default)]
+    pub flag_delivery: Option<FlagDelivery>,
+    // scripts or one-off services run around the lifecycle of an instance
+    #[serde(default)]
+    pub hooks: Hooks,
+    // reserved for the main service, up fails right away on hosts without
+    #[serde(default)]
+    pub gpus: Option<GpuConfig>,
+    // of the main service, e.g. net.ipv4.ip_unprivileged_port_start, see CdmSettings::allowed_sysctls
+    #[serde(default)]
+    pub sysctls: BTreeMap<String, String>,
+    // the firewall window of the challenge, before the one of its category in the settings
+    #[serde(default)]
+    pub port_range: Option<PortRange>,
+    // served by serve_connections instead of a long-lived instance
+    #[serde(default)]
+    pub per_connection: Option<PerConnectionConfig>,
+    // what the platform shows, e.g. "nc {host} {port}", see connection_info
+    #[serde(default)]
+    pub connection_info: Option<String>,
+    // the compose healthcheck of the main service, see healthcheck.yml
+    #[serde(default)]
+    pub healthcheck: Option<HealthcheckConfig>,
+    // when the challenge opens and closes, see ScheduleWatcher
+    #[serde(default)]
+    pub schedule: Option<ChallengeWindow>,
+    // a shared challenge the Autoscaler adds replicas to under load
+    #[serde(default)]
+    pub autoscale: Option<AutoscaleConfig>,
+    // overrides of the settings profiles of the same name
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl ChallengeDockerConfig {
+    pub fn load(challenge_path: &Path) -> Result<Self, String> {
+        let content = limits::read_limited(
+            &challenge_path.join("FloatCTF.toml"),
+            limits::MAX_CONFIG_SIZE,
+        )
+        .map_err(|e| format!("Failed to read FloatCTF.toml: {}", e))?;
+
+        ChallengeDockerConfig::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        limits::check_size("FloatCTF.toml", content.len(), limits::MAX_CONFIG_SIZE)?;
+        let config: ChallengeDockerConfig = toml::from_str(content)
+            .map_err(|e| format!("Failed to parse the FloatCTF.toml: {}", e))?;
+        limits::check_config(&config).map_err(|e| format!("Invalid FloatCTF.toml: {}", e))?;
+        Ok(config)
+    }
+}
+
+// docker only takes some names, the display name stays in the config and the cdm.challenge label
+fn project_name(name: &str, id: InstanceId) -> String {
+    format!("challenge-project-{}-{}", id, slug::slugify(name))
+}
+
+fn container_name(name: &str, id: InstanceId) -> String {
+    format!("challenge-{}-{}", slug::container_slug(name), id)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChallengeDockerManager {
-    pub challenge_docker_config: ChallengeDockerConfig,
+    // shared between clones, config_mut copies it on write
+    pub challenge_docker_config: Arc<ChallengeDockerConfig>,
     pub challenge_path: PathBuf,
     pub docker_compose_yml: PathBuf,
     pub docker_compose_project_name: String,
     pub main_container_name: String,
-    pub id: u64,
+    pub id: InstanceId,
+    // the team the instance belongs to, only used to label it
+    #[serde(default)]
+    pub team: Option<TeamId>,
+    #[serde(default)]
+    pub attachment_files: Vec<ResolvedAttachment>,
+    #[serde(skip, default = "backend::default_backend")]
+    backend: Arc<dyn Backend>,
+    #[serde(skip)]
+    event_sinks: Vec<Arc<dyn EventSink>>,
+    // print what would run instead of running it
+    #[serde(skip)]
+    dry_run: bool,
+    #[serde(skip, default = "settings::unloaded")]
+    settings: Arc<CdmSettings>,
+    #[serde(default)]
+    build_cache: Option<BuildCache>,
+    #[serde(skip, default = "timing::global")]
+    timings: Arc<TimingLog>,
+    // the window of the repository schedule, which wins over the one in the config
+    #[serde(default)]
+    window: Option<ChallengeWindow>,
+    #[serde(skip, default = "freeze::global")]
+    freeze: Arc<Freeze>,
+    #[serde(skip, default = "maintenance::global")]
+    maintenance: Arc<MaintenanceRegistry>,
+    // instance time of the team, only accounted when set
+    #[serde(skip)]
+    usage: Option<Arc<UsageLedger>>,
+    #[serde(skip)]
+    rate_limiter: Option<Arc<RateLimiter>>,
+    #[serde(skip)]
+    access_tokens: Option<Arc<AccessTokens>>,
+    // of the daemon the instance runs on, once detected
+    #[serde(skip)]
+    capabilities: Option<DockerCapabilities>,
+    // the command and entrypoint of the main service, as up was asked for
+    #[serde(default)]
+    command_override: Option<CommandOverride>,
+    // instead of the .env of the challenge directory
+    #[serde(default)]
+    env_file: Option<PathBuf>,
+    // the settings profile when not set
+    #[serde(default)]
+    profile: Option<String>,
+    // the host port ${PORT} is set to, instead of one picked from the port range
+    #[serde(default)]
+    port: Option<u16>,
+    // the docker context or DOCKER_HOST the instance lives on, instead of the one of the settings
+    #[serde(default)]
+    context: Option<String>,
 }
 
 impl ChallengeDockerManager {
     fn run_command(
+        settings: &CdmSettings,
         command: &str,
         args: &[&str],
         env_vars: Option<HashMap<&str, &str>>,
-    ) -> Result<Vec<u8>, String> {
-        let mut cmd = Command::new(command);
-        cmd.args(args);
+    ) -> Result<Vec<u8>, CdmError> {
+        ChallengeDockerManager::execute(settings, command, args, env_vars, None, None)
+            .map(|o| o.stdout)
+    }
 
-        // 设置环境变量
-        if let Some(envs) = env_vars {
-            for (key, value) in envs {
+    // what goes into the error when the command fails, as the shell would have to run it
+    fn command_error(
+        settings: &CdmSettings,
+        command: &str,
+        args: &[&str],
+        env_vars: &Option<HashMap<&str, &str>>,
+        input: bool,
+        status: Option<i32>,
+        stderr: String,
+    ) -> CdmError {
+        let (program, prefix) = settings.resolve(command);
+        let mut line = PlannedCommand::new(&program, &[]);
+        line.args = prefix;
+        line.args.extend(args.iter().map(|arg| arg.to_string()));
+        for (key, value) in env_vars.iter().flatten() {
+            let secret = matches!(*key, "FLAG" | flag::SECRET_ENV);
+            line = line.env(key, if secret { REDACTED } else { value });
+        }
+        if input {
+            line = line.input(String::new());
+        }
+        CommandError {
+            program: command.to_string(),
+            command: line.to_string(),
+            working_dir: std::env::current_dir().unwrap_or_default(),
+            challenge: None,
+            project: None,
+            status,
+            stderr,
+        }
+        .into()
+    }
+
+    // compose interpolates the host env into the challenge, so it only gets the allowed part
+    fn command(
+        settings: &CdmSettings,
+        command: &str,
+        env_vars: &Option<HashMap<&str, &str>>,
+    ) -> Command {
+        let (program, prefix) = settings.resolve(command);
+        let mut cmd = Command::new(program);
+        cmd.args(prefix);
+        if command == "docker-compose" {
+            cmd.env_clear().envs(settings.compose_env(std::env::vars()));
+        }
+        if matches!(command, "docker" | "docker-compose") {
+            // DOCKER_HOST beats DOCKER_CONTEXT, an inherited one must not win over the chosen context
+            let targeted = env_vars
+                .iter()
+                .flatten()
+                .any(|(key, _)| matches!(*key, "DOCKER_HOST" | "DOCKER_CONTEXT"));
+            if targeted || settings.context.is_some() {
+                cmd.env_remove("DOCKER_HOST").env_remove("DOCKER_CONTEXT");
+            }
+            if let (false, Some(context)) = (targeted, &settings.context) {
+                let (key, value) = settings::target_env(context);
                 cmd.env(key, value);
             }
         }
+        if let Some(envs) = env_vars {
+            cmd.envs(envs);
+        }
+        cmd
+    }
+
+    // every command cdm runs ends up here, in dir instead of the working directory when given
+    fn execute(
+        settings: &CdmSettings,
+        command: &str,
+        args: &[&str],
+        env_vars: Option<HashMap<&str, &str>>,
+        input: Option<&[u8]>,
+        dir: Option<&Path>,
+    ) -> Result<Output, CdmError> {
+        let mut cmd = ChallengeDockerManager::command(settings, command, &env_vars);
+        cmd.args(args);
+        if let Some(dir) = dir {
+            cmd.current_dir(dir);
+        }
+        let error = |status: Option<i32>, stderr: String| {
+            ChallengeDockerManager::command_error(
+                settings,
+                command,
+                args,
+                &env_vars,
+                input.is_some(),
+                status,
+                stderr,
+            )
+        };
 
-        let output = cmd
-            .output()
-            .map_err(|e| format!("Failed to execute {}: {}", command, e))?;
+        let output = match input {
+            Some(input) => {
+                let mut child = cmd
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()
+                    .map_err(|e| error(None, e.to_string()))?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin
+                        .write_all(input)
+                        .map_err(|e| error(None, format!("Failed to write to stdin: {}", e)))?;
+                }
+                child
+                    .wait_with_output()
+                    .map_err(|e| error(None, e.to_string()))?
+            }
+            None => cmd.output().map_err(|e| error(None, e.to_string()))?,
+        };
 
         if !output.status.success() {
-            return Err(format!(
-                "{} failed: {}",
-                command,
-                String::from_utf8_lossy(&output.stderr)
+            return Err(error(
+                output.status.code(),
+                String::from_utf8_lossy(&output.stderr).to_string(),
             ));
         }
-
-        Ok(output.stdout)
+        Ok(output)
     }
 
-    pub fn check_docker_env() -> Result<(), String> {
+    pub fn check_docker_env(settings: &CdmSettings) -> Result<DockerCapabilities, String> {
         // docker installed?
-        ChallengeDockerManager::run_command("docker", &["--version"], None)?;
+        ChallengeDockerManager::run_command(settings, "docker", &["--version"], None)?;
 
         // docker-compose
-        ChallengeDockerManager::run_command("docker-compose", &["--version"], None)?;
+        ChallengeDockerManager::run_command(settings, "docker-compose", &["--version"], None)?;
 
-        // docker permission
-        ChallengeDockerManager::run_command("docker", &["ps"], None)?;
+        // every configured context exists and its daemon answers
+        for target in settings.context.iter().chain(&settings.hosts) {
+            let (key, value) = settings::target_env(target);
+            if key == "DOCKER_CONTEXT" {
+                ChallengeDockerManager::run_command(
+                    settings,
+                    "docker",
+                    &["context", "inspect", value],
+                    None,
+                )
+                .map_err(|e| format!("No docker context {}: {}", value, e))?;
+            }
+            let env_vars = HashMap::from([(key, value)]);
+            ChallengeDockerManager::run_command(
+                settings,
+                "docker",
+                &["info", "--format", "{{.ServerVersion}}"],
+                Some(env_vars),
+            )
+            .map_err(|e| format!("The docker daemon of {} does not answer: {}", target, e))?;
+        }
 
-        Ok(())
+        // docker permission, and whether it runs rootless
+        DockerCapabilities::detect(settings, settings.context.as_deref())
     }
 
-    pub fn new(challenge_path: PathBuf, id: u64) -> Result<Self, String> {
-        let content = std::fs::read_to_string(challenge_path.join("FloatCTF.toml"))
-            .map_err(|e| format!("Failed to read FloatCTF.toml: {}", e))?;
-
-        let config: ChallengeDockerConfig = toml::from_str(&content)
-            .map_err(|e| format!("Failed to parse the FloatCTF.toml: {}", e))?;
+    pub fn new(challenge_path: PathBuf, id: InstanceId) -> Result<Self, String> {
+        let config = config_cache::global().get(&challenge_path)?;
+        slug::global().register(&config.name)?;
 
         let docker_compose_yml = challenge_path.join("docker-compose.yml");
         if !docker_compose_yml.exists() {
@@ -92,142 +503,509 @@ impl ChallengeDockerManager {
         }
 
         // check attachments is exist
-        for attachment in &config.attachments {
-            let attachment_path = challenge_path.join("attachments").join(attachment);
-            if !attachment_path.exists() {
-                return Err(format!(
-                    "The {} has no attachment: {}",
-                    config.name, attachment
-                ));
-            }
-        }
+        let attachment_files = attachments::resolve_attachments(&challenge_path, &config)?;
 
         Ok(ChallengeDockerManager {
             id,
+            team: None,
             docker_compose_yml,
-            docker_compose_project_name: format!("challenge-project-{}-{}", id, config.name),
-            main_container_name: format!("challenge-{}-{}", config.name, id),
+            docker_compose_project_name: project_name(&config.name, id),
+            main_container_name: container_name(&config.name, id),
+            attachment_files,
             challenge_path,
             challenge_docker_config: config,
+            backend: backend::default_backend(),
+            event_sinks: Vec::new(),
+            dry_run: false,
+            settings: settings::global()?,
+            build_cache: None,
+            timings: timing::global(),
+            window: None,
+            freeze: freeze::global(),
+            maintenance: maintenance::global(),
+            usage: None,
+            rate_limiter: None,
+            access_tokens: None,
+            capabilities: None,
+            command_override: None,
+            env_file: None,
+            profile: None,
+            port: None,
+            context: None,
+        })
+    }
+
+    // another instance of the same challenge, sharing the parsed config instead of loading it again
+    pub fn instance(&self, id: InstanceId) -> Self {
+        let name = &self.challenge_docker_config.name;
+        ChallengeDockerManager {
+            id,
+            docker_compose_project_name: project_name(name, id),
+            main_container_name: container_name(name, id),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_backend(mut self, backend: Arc<dyn Backend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn with_team(mut self, team: TeamId) -> Self {
+        self.team = Some(team);
+        self
+    }
+
+    pub fn with_settings(mut self, settings: Arc<CdmSettings>) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn settings(&self) -> &CdmSettings {
+        &self.settings
+    }
+
+    pub fn with_build_cache(mut self, build_cache: Option<BuildCache>) -> Self {
+        self.build_cache = build_cache;
+        self
+    }
+
+    pub fn with_window(mut self, window: Option<ChallengeWindow>) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_freeze(mut self, freeze: Arc<Freeze>) -> Self {
+        self.freeze = freeze;
+        self
+    }
+
+    pub fn freeze(&self) -> &Freeze {
+        &self.freeze
+    }
+
+    pub fn with_maintenance(mut self, maintenance: Arc<MaintenanceRegistry>) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    pub fn with_usage(mut self, usage: Arc<UsageLedger>) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    pub fn usage(&self) -> Option<&UsageLedger> {
+        self.usage.as_deref()
+    }
+
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_deref()
+    }
+
+    pub fn with_access_tokens(mut self, access_tokens: Arc<AccessTokens>) -> Self {
+        self.access_tokens = Some(access_tokens);
+        self
+    }
+
+    pub fn access_tokens(&self) -> Option<&AccessTokens> {
+        self.access_tokens.as_deref()
+    }
+
+    pub fn with_command_override(mut self, command_override: Option<CommandOverride>) -> Self {
+        self.command_override = command_override;
+        self
+    }
+
+    pub fn command_override(&self) -> Option<&CommandOverride> {
+        self.command_override.as_ref()
+    }
+
+    pub fn with_env_file(mut self, env_file: impl Into<PathBuf>) -> Self {
+        self.env_file = Some(env_file.into());
+        self
+    }
+
+    // compose would read the .env of whatever directory cdm runs in
+    pub fn env_file(&self) -> Option<PathBuf> {
+        match &self.env_file {
+            Some(env_file) => Some(env_file.clone()),
+            None => Some(self.challenge_path.join(".env")).filter(|path| path.is_file()),
+        }
+    }
+
+    pub(crate) fn env_file_args(&self) -> Vec<String> {
+        self.env_file()
+            .map(|path| vec!["--env-file".to_string(), path.to_string_lossy().to_string()])
+            .unwrap_or_default()
+    }
+
+    pub fn with_profile(mut self, profile: &str) -> Self {
+        self.profile = Some(profile.to_string());
+        self
+    }
+
+    // the name in docker, see slug::slugify
+    pub fn slug(&self) -> String {
+        slug::slugify(&self.challenge_docker_config.name)
+    }
+
+    pub fn profile_name(&self) -> Option<&str> {
+        self.profile.as_deref().or(self.settings.profile.as_deref())
+    }
+
+    pub fn with_context(mut self, context: Option<String>) -> Self {
+        self.context = context;
+        self
+    }
+
+    pub fn docker_context(&self) -> Option<&str> {
+        self.context.as_deref().or(self.settings.context.as_deref())
+    }
+
+    // for the docker and compose calls of the instance, empty for the default daemon
+    pub(crate) fn docker_env(&self) -> HashMap<&str, &str> {
+        self.docker_context()
+            .map(settings::target_env)
+            .into_iter()
+            .collect()
+    }
+
+    pub fn with_port(mut self, port: Option<u16>) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn fixed_port(&self) -> Option<u16> {
+        self.port
+    }
+
+    // what ${PORT} is picked from unless the port is fixed
+    pub fn port_range(&self) -> Option<PortRange> {
+        let config_range = self.challenge_docker_config.port_range.map(|range| {
+            // the error is reported by validate, up fails to publish the port either way
+            self.capabilities
+                .as_ref()
+                .and_then(|capabilities| capabilities.adapt_range(range).ok())
+                .unwrap_or(range)
+        });
+        config_range.or_else(|| {
+            self.settings()
+                .port_range_of(self.challenge_docker_config.category)
         })
     }
 
+    pub fn with_timings(mut self, timings: Arc<TimingLog>) -> Self {
+        self.timings = timings;
+        self
+    }
+
+    pub fn timings(&self) -> &TimingLog {
+        &self.timings
+    }
+
+    pub fn build_cache(&self) -> Option<&BuildCache> {
+        self.build_cache.as_ref()
+    }
+
+    pub fn config_mut(&mut self) -> &mut ChallengeDockerConfig {
+        Arc::make_mut(&mut self.challenge_docker_config)
+    }
+
+    pub fn backend(&self) -> &Arc<dyn Backend> {
+        &self.backend
+    }
+
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.event_sinks.push(sink);
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    fn print_plan(&self, operation: Operation, flag: &str) -> Result<(), String> {
+        print!("{}", self.plan(operation, flag)?);
+        Ok(())
+    }
+
+    fn emit(&self, kind: EventKind) {
+        let event = Event::new(kind, &self.challenge_docker_config.name, self.id);
+        if self.profile().is_verbose() {
+            eprintln!(
+                "{} #{}: {:?}",
+                event.challenge, event.instance_id, event.kind
+            );
+        }
+        for sink in &self.event_sinks {
+            // a broken sink must not break the instance
+            let _ = sink.emit(&event);
+        }
+    }
+
+    fn emit_result<T, E: std::fmt::Display>(
+        &self,
+        operation: &str,
+        result: &Result<T, E>,
+        kind: EventKind,
+    ) {
+        match result {
+            Ok(_) => self.emit(kind),
+            Err(e) => self.emit(EventKind::Failed {
+                operation: operation.to_string(),
+                error: e.to_string(),
+            }),
+        }
+    }
+
     pub fn build(&self) -> Result<(), String> {
         if !self.challenge_docker_config.is_dockerd {
             return Ok(());
         }
-        ChallengeDockerManager::run_command(
-            "docker-compose",
-            &[
-                "--file",
-                &self.docker_compose_yml.to_string_lossy(),
-                "build",
-            ],
-            Some({
-                let mut env_vars = HashMap::new();
-                env_vars.insert("ID", "0".into());
-                env_vars
-            }),
-        )?;
-        Ok(())
+        if self.dry_run {
+            return self.print_plan(Operation::Build, "");
+        }
+        let result = self
+            .timings
+            .measure(
+                &self.challenge_docker_config.name,
+                SpawnStage::Build,
+                || self.backend.build(self),
+            )
+            .map_err(|e| e.for_instance(self, "").to_string())
+            .and_then(|_| self.extract_built_attachments())
+            .and_then(|_| self.record_build());
+        self.emit_result("build", &result, EventKind::Built);
+        result
     }
 
     // return the map port
-    pub fn up(&self, flag: String) -> Result<u64, String> {
-        // handle the misc crypto reverse
+    // an instance that is already up is reused, see up_with
+    pub fn up(&self, flag: &Flag) -> Result<u64, String> {
+        Ok(self.up_with(flag, &UpOptions::default())?)
+    }
+
+    // nothing to take down is not an error, see down_with
+    pub fn down(&self) -> Result<(), String> {
+        self.down_with(&DownOptions::default())?;
+        Ok(())
+    }
+
+    pub fn status(&self) -> Result<InstanceStatus, String> {
         if !self.challenge_docker_config.is_dockerd {
-            return Ok(0);
+            return Ok(InstanceStatus::NotFound);
         }
+        Ok(self.backend.status(self)?)
+    }
 
-        let mut env_vars = HashMap::new();
-        if self.challenge_docker_config.is_dynamic_flag {
-            env_vars.insert("FLAG", flag.as_str());
-        }
-
-        let binding = self.id.to_string();
-        env_vars.insert("ID", &binding);
-
-        ChallengeDockerManager::run_command(
-            "docker-compose",
-            &[
-                "--file",
-                &self.docker_compose_yml.to_string_lossy(),
-                "--project-name",
-                &self.docker_compose_project_name,
-                "up",
-                "--detach",
-            ],
-            Some(env_vars),
-        )?;
-
-        let output = ChallengeDockerManager::run_command(
-            "docker",
-            &[
-                "inspect",
-                "--format",
-                "{{range $p, $conf := .NetworkSettings.Ports}}{{(index $conf 0).HostPort}}{{end}}",
-                &self.main_container_name,
-            ],
-            None,
-        )?;
-
-        let s = String::from_utf8(output).map_err(|e| format!("Invalid UTF-8 in output: {}", e))?;
-        let port = s.trim();
-        port.parse::<u64>()
-            .map_err(|e| format!("Failed to parse port: {}", e))
+    pub fn is_running(&self) -> Result<bool, String> {
+        if !self.challenge_docker_config.is_dockerd {
+            return Ok(false);
+        }
+        Ok(self.backend.is_running(self)?)
     }
 
-    pub fn down(&self) -> Result<(), String> {
+    pub fn exists(&self) -> Result<bool, String> {
         if !self.challenge_docker_config.is_dockerd {
-            return Ok(());
+            return Ok(false);
         }
+        Ok(self.backend.exists(self)?)
+    }
 
-        ChallengeDockerManager::run_command(
-            "docker-compose",
-            &[
-                "--file",
-                &self.docker_compose_yml.to_string_lossy(),
-                "--project-name",
-                &self.docker_compose_project_name,
-                "down",
-                "--volumes",
-                "--timeout=1",
-            ],
-            None,
-        )?;
-        Ok(())
+    // the mappings of an instance that is already up, e.g. after the platform restarted
+    pub fn get_ports(&self) -> Result<Vec<PortMapping>, CdmError> {
+        if !self.challenge_docker_config.is_dockerd {
+            return Ok(Vec::new());
+        }
+        let status = self.status()?;
+        if status != InstanceStatus::Running {
+            return Err(CdmError::NotRunning {
+                challenge: self.challenge_docker_config.name.clone(),
+                instance_id: self.id,
+                status,
+            });
+        }
+        self.backend.ports(self)
+    }
+
+    // the host port up returned, without running up again
+    pub fn get_port(&self) -> Result<u64, CdmError> {
+        Ok(self
+            .get_ports()?
+            .first()
+            .map(|mapping| mapping.host_port)
+            .unwrap_or(0))
     }
 
-    pub fn get_static_flag(&self) -> Result<String, String> {
+    pub fn get_static_flag(&self) -> Result<Flag, String> {
         if self.challenge_docker_config.is_dynamic_flag {
             return Err("The challenge is not static flag".to_string());
         }
         let dot_env = self.challenge_path.join(".env");
-        let content =
-            std::fs::read_to_string(dot_env).map_err(|e| format!("Failed to read .env: {}", e))?;
+        let content = Zeroizing::new(
+            std::fs::read_to_string(dot_env).map_err(|e| format!("Failed to read .env: {}", e))?,
+        );
         for line in content.lines() {
             if let Some(rest) = line.strip_prefix("FLAG=") {
-                return Ok(rest.trim().to_string());
+                return Ok(Flag::from(rest.trim()));
             }
         }
         Err("FLAG not found in .env".to_string())
     }
 }
 
+#[cfg(test)]
+impl ChallengeDockerManager {
+    pub(crate) fn test_manager(name: &str, id: u64) -> Self {
+        let challenge_path = PathBuf::from("./challenges").join(name);
+        let id = InstanceId::new(id);
+        ChallengeDockerManager {
+            challenge_docker_config: Arc::new(ChallengeDockerConfig {
+                name: name.to_string(),
+                author: "FloatCTF".to_string(),
+                category: Category::Web,
+                tags: Vec::new(),
+                description: String::new(),
+                attachments: Vec::new(),
+                is_dynamic_flag: true,
+                is_dockerd: true,
+                points: 100,
+                swarm: None,
+                built_attachments: Vec::new(),
+                team_attachments: None,
+                attachment_password: None,
+                solve: None,
+                flag_file: None,
+                flag_delivery: None,
+                hooks: Hooks::default(),
+                gpus: None,
+                sysctls: BTreeMap::new(),
+                port_range: None,
+                per_connection: None,
+                connection_info: None,
+                healthcheck: None,
+                schedule: None,
+                autoscale: None,
+                profiles: BTreeMap::new(),
+            }),
+            docker_compose_yml: challenge_path.join("docker-compose.yml"),
+            docker_compose_project_name: project_name(name, id),
+            main_container_name: container_name(name, id),
+            challenge_path,
+            id,
+            team: None,
+            attachment_files: Vec::new(),
+            backend: backend::default_backend(),
+            event_sinks: Vec::new(),
+            dry_run: false,
+            settings: Arc::new(CdmSettings::default()),
+            build_cache: None,
+            timings: Arc::new(TimingLog::new()),
+            window: None,
+            freeze: Arc::new(Freeze::new()),
+            maintenance: Arc::new(MaintenanceRegistry::new()),
+            usage: None,
+            rate_limiter: None,
+            access_tokens: None,
+            capabilities: None,
+            command_override: None,
+            env_file: None,
+            profile: None,
+            port: None,
+            context: None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_cdm {
     use super::*;
+    use testing::{InstanceGuard, TestChallenge};
 
     #[test]
     fn check_docker() {
-        if let Err(e) = ChallengeDockerManager::check_docker_env() {
+        if let Err(e) = ChallengeDockerManager::check_docker_env(&settings::global().unwrap()) {
             panic!("Docker environment check failed: {}", e);
         }
     }
 
+    #[test]
+    fn check_category() {
+        use std::str::FromStr;
+        assert_eq!(Category::from_str("rev").unwrap(), Category::Reverse);
+        assert_eq!(
+            Category::from_str("Reverse Engineering").unwrap(),
+            Category::Reverse
+        );
+        assert_eq!(Category::from_str("PWN").unwrap(), Category::Pwn);
+        assert!(Category::from_str("forensics").is_err());
+        assert_eq!(Category::Reverse.to_string(), "Reverse");
+        assert_eq!(Category::all().len(), 5);
+
+        let config: ChallengeDockerConfig = toml::from_str(
+            "name = \"c\"\nauthor = \"a\"\ncategory = \"cryptography\"\ntags = []\ndescription = \"\"\nattachments = []\nis_dynamic_flag = true\nis_dockerd = true\npoints = 100\n",
+        )
+        .unwrap();
+        assert_eq!(config.category, Category::Crypto);
+        assert_eq!(config.category.display_name(), "Cryptography");
+    }
+
+    #[test]
+    fn check_get_ports() {
+        let cdm = ChallengeDockerManager::test_manager("comment", 5)
+            .with_backend(Arc::new(backend::MockBackend::new()));
+        assert!(matches!(
+            cdm.get_ports(),
+            Err(CdmError::NotRunning {
+                status: InstanceStatus::NotFound,
+                ..
+            })
+        ));
+
+        assert!(!cdm.exists().unwrap());
+
+        let port = cdm.up(&"flag{ports}".into()).unwrap();
+        assert!(cdm.is_running().unwrap() && cdm.exists().unwrap());
+        assert_eq!(cdm.get_port().unwrap(), port);
+        assert_eq!(cdm.get_ports().unwrap()[0].container_port, 80);
+        cdm.down().unwrap();
+        assert!(cdm.get_port().is_err());
+    }
+
+    #[test]
+    fn check_instance() {
+        let cdm = ChallengeDockerManager::test_manager("comment", 1);
+        let other = cdm.instance(InstanceId::new(2));
+        assert!(Arc::ptr_eq(
+            &cdm.challenge_docker_config,
+            &other.challenge_docker_config
+        ));
+        assert_eq!(
+            other.docker_compose_project_name,
+            "challenge-project-2-comment"
+        );
+        assert_eq!(other.main_container_name, "challenge-comment-2");
+
+        // writing to the config of one leaves the other alone
+        let mut other = other;
+        other.config_mut().points = 500;
+        assert_eq!(cdm.challenge_docker_config.points, 100);
+    }
+
     #[test]
     fn check_init() {
-        let cdm = ChallengeDockerManager::new("./challenges/comment".into(), 1);
+        let challenge = TestChallenge::new("comment").unwrap();
+        let cdm = challenge.manager();
 
         if let Err(e) = cdm {
             panic!("Failed to  init the ChallengeDockerManager:{}", e);
@@ -239,7 +1017,8 @@ mod test_cdm {
 
     #[test]
     fn check_build() {
-        let cdm = ChallengeDockerManager::new("./challenges/comment".into(), 1);
+        let challenge = TestChallenge::new("comment").unwrap();
+        let cdm = challenge.manager();
 
         if let Err(e) = cdm {
             panic!("Failed to  init the ChallengeDockerManager:{}", e);
@@ -251,7 +1030,8 @@ mod test_cdm {
 
     #[test]
     fn check_up() {
-        let cdm = ChallengeDockerManager::new("./challenges/comment".into(), 1);
+        let challenge = TestChallenge::new("comment").unwrap();
+        let cdm = challenge.manager();
 
         if let Err(e) = cdm {
             panic!("Failed to  init the ChallengeDockerManager:{}", e);
@@ -260,19 +1040,20 @@ mod test_cdm {
         let cdm = cdm.unwrap();
         let flag = {
             if cdm.challenge_docker_config.is_dynamic_flag {
-                "flag{this_is_a_test_flag}".to_string()
+                Flag::from("flag{this_is_a_test_flag}")
             } else {
-                cdm.clone().get_static_flag().unwrap()
+                cdm.get_static_flag().unwrap()
             }
         };
 
-        let port = cdm.up(flag).unwrap();
-        println!("{}", port);
+        let instance = InstanceGuard::up(cdm, &flag).unwrap();
+        println!("{}", instance.port);
     }
 
     #[test]
     fn check_down() {
-        let cdm = ChallengeDockerManager::new("./challenges/comment".into(), 1);
+        let challenge = TestChallenge::new("comment").unwrap();
+        let cdm = challenge.manager();
 
         if let Err(e) = cdm {
             panic!("Failed to  init the ChallengeDockerManager:{}", e);
@@ -284,7 +1065,8 @@ mod test_cdm {
 
     #[test]
     fn check_static_flag() {
-        let cdm = ChallengeDockerManager::new("./challenges/comment".into(), 1);
+        let challenge = TestChallenge::with_static_flag("comment", "flag{static}").unwrap();
+        let cdm = challenge.manager();
 
         if let Err(e) = cdm {
             panic!("Failed to  init the ChallengeDockerManager:{}", e);
@@ -292,6 +1074,6 @@ mod test_cdm {
 
         let cdm = cdm.unwrap();
         let flag = cdm.get_static_flag().unwrap();
-        println!("{}", flag)
+        assert_eq!(flag.expose(), "flag{static}");
     }
 }
@@ -0,0 +1,276 @@
+use crate::ChallengeDockerManager;
+use crate::backend::InstanceStatus;
+use crate::error::CdmError;
+use crate::events::EventKind;
+use crate::flag::Flag;
+use crate::plan::Operation;
+use crate::schedule;
+use crate::timing::SpawnStage;
+use serde::{Deserialize, Serialize};
+use strum::{AsRefStr, Display};
+
+// what up does when the instance is already running
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, AsRefStr, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum IfRunning {
+    // hand out the port it already has, it keeps the flag it was started with
+    #[default]
+    Reuse,
+    Error,
+}
+
+// what the main service runs instead, e.g. the same image behind a crash handler
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpOptions {
+    #[serde(default)]
+    pub if_running: IfRunning,
+    // admins still spawn challenges in maintenance
+    #[serde(default)]
+    pub admin: bool,
+    // put over the compose file, the challenge files are left alone
+    #[serde(default)]
+    pub command_override: Option<CommandOverride>,
+}
+
+impl UpOptions {
+    pub fn if_running(mut self, if_running: IfRunning) -> Self {
+        self.if_running = if_running;
+        self
+    }
+
+    pub fn admin(mut self, admin: bool) -> Self {
+        self.admin = admin;
+        self
+    }
+
+    pub fn command(mut self, command: &[&str]) -> Self {
+        self.command_override.get_or_insert_default().command =
+            Some(command.iter().map(|arg| arg.to_string()).collect());
+        self
+    }
+
+    pub fn entrypoint(mut self, entrypoint: &[&str]) -> Self {
+        self.command_override.get_or_insert_default().entrypoint =
+            Some(entrypoint.iter().map(|arg| arg.to_string()).collect());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DownOptions {
+    // remove containers and networks by hand when compose down fails
+    #[serde(default)]
+    pub force: bool,
+    // taken down by cdm rather than asked for, reported as reaped with the reason
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl DownOptions {
+    pub fn force() -> Self {
+        DownOptions {
+            force: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn reaped(reason: &str) -> Self {
+        DownOptions {
+            reason: Some(reason.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, AsRefStr, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum DownOutcome {
+    Removed,
+    NothingToDo,
+}
+
+impl ChallengeDockerManager {
+    pub fn up_with(&self, flag: &Flag, options: &UpOptions) -> Result<u64, CdmError> {
+        let static_flag = self.profile().static_flag.map(Flag::from);
+        let flag = static_flag.as_ref().unwrap_or(flag);
+        let overridden = options
+            .command_override
+            .clone()
+            .map(|command_override| self.clone().with_command_override(Some(command_override)));
+        let cdm = overridden.as_ref().unwrap_or(self);
+        // handle the misc crypto reverse
+        if !self.challenge_docker_config.is_dockerd {
+            return Ok(0);
+        }
+        if self.dry_run {
+            return Ok(cdm.print_plan(Operation::Up, flag.expose()).map(|_| 0)?);
+        }
+        self.check_window(schedule::now())?;
+        if self.backend.is_running(self)? {
+            let port = self.get_port()?;
+            return match options.if_running {
+                IfRunning::Reuse => Ok(port),
+                IfRunning::Error => Err(CdmError::AlreadyRunning {
+                    challenge: self.challenge_docker_config.name.clone(),
+                    instance_id: self.id,
+                    port,
+                }),
+            };
+        }
+        // nobody took it down, whatever is left stopped on its own
+        if self.backend.status(self)? == InstanceStatus::Exited {
+            self.emit(EventKind::Crashed { exit_code: None });
+        }
+        self.check_maintenance(options.admin)?;
+        self.check_frozen()?;
+        self.check_budget()?;
+        self.check_rate_limit(options.admin)?;
+        if let Err(e) = cdm.run_pre_up(flag) {
+            let e = e.for_instance(self, flag.expose());
+            self.emit(EventKind::Failed {
+                operation: "pre_up".to_string(),
+                error: e.to_string(),
+            });
+            return Err(e);
+        }
+        let result = self
+            .timings()
+            .measure(
+                &self.challenge_docker_config.name,
+                SpawnStage::Start,
+                || self.backend.up(cdm, flag.expose()),
+            )
+            .and_then(|port| match cdm.run_post_up(port, flag) {
+                Ok(()) => Ok(port),
+                // a fatal hook leaves nothing half set up behind
+                Err(e) => {
+                    let _ = self.backend.down(cdm);
+                    Err(e)
+                }
+            })
+            .map_err(|e| e.for_instance(self, flag.expose()));
+        let port = *result.as_ref().unwrap_or(&0);
+        if result.is_ok() {
+            self.record_usage(true);
+            self.record_spawn(true);
+        }
+        self.emit_result("up", &result, EventKind::Up { port });
+        result
+    }
+
+    pub fn down_with(&self, options: &DownOptions) -> Result<DownOutcome, CdmError> {
+        if !self.challenge_docker_config.is_dockerd {
+            return Ok(DownOutcome::NothingToDo);
+        }
+        if self.dry_run {
+            self.print_plan(Operation::Down, "")?;
+            return Ok(DownOutcome::Removed);
+        }
+        // forcing also sweeps networks left behind by instances without containers
+        if !options.force && !self.backend.exists(self)? {
+            return Ok(DownOutcome::NothingToDo);
+        }
+        let result = match self.backend.down(self) {
+            Err(_) if options.force => self.backend.force_down(self),
+            result => result,
+        }
+        .map_err(|e| e.for_instance(self, ""));
+        let kind = match &options.reason {
+            Some(reason) => EventKind::Reaped {
+                reason: reason.clone(),
+            },
+            None => EventKind::Down,
+        };
+        self.emit_result("down", &result, kind);
+        result?;
+        self.record_usage(false);
+        self.record_spawn(false);
+        self.revoke_access_tokens();
+        Ok(DownOutcome::Removed)
+    }
+}
+
+#[cfg(test)]
+mod test_lifecycle {
+    use super::*;
+    use crate::backend::{MockBackend, MockOperation};
+    use crate::events::EventLog;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_up_twice() {
+        let mock = Arc::new(MockBackend::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 6).with_backend(mock.clone());
+        let port = cdm.up(&"flag{first}".into()).unwrap();
+        assert_eq!(cdm.up(&"flag{second}".into()).unwrap(), port);
+        assert_eq!(mock.calls_of(MockOperation::Up).len(), 1);
+
+        let options = UpOptions::default().if_running(IfRunning::Error);
+        assert_eq!(
+            cdm.up_with(&"flag{third}".into(), &options),
+            Err(CdmError::AlreadyRunning {
+                challenge: "comment".to_string(),
+                instance_id: cdm.id,
+                port,
+            })
+        );
+    }
+
+    #[test]
+    fn check_down_twice() {
+        let mock = Arc::new(MockBackend::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 7).with_backend(mock.clone());
+        let options = DownOptions::default();
+        assert_eq!(cdm.down_with(&options).unwrap(), DownOutcome::NothingToDo);
+        assert!(mock.calls_of(MockOperation::Down).is_empty());
+
+        cdm.up(&"flag{down}".into()).unwrap();
+        mock.fail_next(MockOperation::Down, "device or resource busy");
+        assert!(cdm.down_with(&options).is_err());
+        mock.fail_next(MockOperation::Down, "device or resource busy");
+        assert_eq!(
+            cdm.down_with(&DownOptions::force()).unwrap(),
+            DownOutcome::Removed
+        );
+        assert!(mock.running().is_empty());
+        cdm.down().unwrap();
+    }
+
+    #[test]
+    fn check_crashed_and_reaped() {
+        let mock = Arc::new(MockBackend::new());
+        let log = Arc::new(EventLog::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 8)
+            .with_backend(mock.clone())
+            .with_event_sink(log.clone());
+        cdm.up(&"flag{up}".into()).unwrap();
+        // is_running and the status after it both see the exited containers
+        mock.respond_status(InstanceStatus::Exited);
+        mock.respond_status(InstanceStatus::Exited);
+        cdm.up(&"flag{again}".into()).unwrap();
+        cdm.down_with(&DownOptions::reaped("the challenge closed"))
+            .unwrap();
+        let kinds: Vec<EventKind> = log.events().into_iter().map(|event| event.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                EventKind::Up { port: 30000 },
+                EventKind::Crashed { exit_code: None },
+                EventKind::Up { port: 30000 },
+                EventKind::Reaped {
+                    reason: "the challenge closed".to_string()
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,447 @@
+use crate::ChallengeDockerConfig;
+#[cfg(feature = "chaos")]
+use crate::chaos::ChaosMonkey;
+use crate::ratelimit::RateLimit;
+use std::io::Read;
+use std::path::{Component, Path};
+
+// community challenges are not trusted, nothing in them gets to be arbitrarily large
+pub const MAX_CONFIG_SIZE: u64 = 64 * 1024;
+pub const MAX_COMPOSE_SIZE: u64 = 1024 * 1024;
+pub const MAX_NAME_LEN: usize = 64;
+pub const MAX_FIELD_LEN: usize = 256;
+pub const MAX_DESCRIPTION_LEN: usize = 32 * 1024;
+pub const MAX_TAGS: usize = 32;
+pub const MAX_ATTACHMENTS: usize = 256;
+pub const MAX_SERVICES: usize = 64;
+pub const MAX_SOLVE_TIMEOUT: u64 = 3600;
+// of per_connection, every connection of every player gets a container
+pub const MAX_CONNECTIONS: usize = 256;
+pub const MAX_CONNECTION_MEMORY: u64 = 1024 * 1024 * 1024;
+pub const MAX_CONNECTION_PIDS: u64 = 1024;
+pub const MAX_HOOKS: usize = 16;
+pub const MAX_COMMAND_ARGS: usize = 64;
+pub const MAX_ARG_LEN: usize = 4096;
+pub const MAX_SYSCTLS: usize = 32;
+pub const MAX_PROFILES: usize = 16;
+// timeouts and intervals of healthchecks and connections
+pub const MAX_SECONDS: u64 = 3600;
+pub const MAX_RETRIES: u64 = 100;
+// operator files, larger than a FloatCTF.toml but still not unbounded
+pub const MAX_SETTINGS_SIZE: u64 = 1024 * 1024;
+pub const MAX_RATE_PERIOD: u64 = 7 * 24 * 3600;
+// strikes per minute
+pub const MAX_CHAOS_RATE: f64 = 600.0;
+
+// read at most max bytes, a FloatCTF.toml pointing at /dev/zero must not hang the deployer
+pub fn read_limited(path: &Path, max: u64) -> Result<String, String> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut content = String::new();
+    file.take(max + 1)
+        .read_to_string(&mut content)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    check_size(path, content.len(), max)?;
+    Ok(content)
+}
+
+pub fn check_size(what: impl AsRef<Path>, size: usize, max: u64) -> Result<(), String> {
+    if size as u64 > max {
+        return Err(format!(
+            "{} is larger than {} bytes",
+            what.as_ref().display(),
+            max
+        ));
+    }
+    Ok(())
+}
+
+fn check_len(field: &str, value: &str, max: usize) -> Result<(), String> {
+    if value.len() > max {
+        return Err(format!(
+            "The {} is {} bytes long, at most {} are allowed",
+            field,
+            value.len(),
+            max
+        ));
+    }
+    if value.contains('\0') {
+        return Err(format!("The {} contains a NUL byte", field));
+    }
+    Ok(())
+}
+
+// attachments are joined onto the challenge directory, they must stay inside it
+fn check_relative(field: &str, value: &str) -> Result<(), String> {
+    check_len(field, value, MAX_FIELD_LEN)?;
+    let escapes = Path::new(value)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if value.is_empty() || escapes {
+        return Err(format!(
+            "The {} {:?} must be a relative path inside the challenge",
+            field, value
+        ));
+    }
+    Ok(())
+}
+
+fn check_count(
+    config: &ChallengeDockerConfig,
+    what: &str,
+    count: usize,
+    max: usize,
+) -> Result<(), String> {
+    if count > max {
+        return Err(format!(
+            "The {} has {} {}, at most {} are allowed",
+            config.name, count, what, max
+        ));
+    }
+    Ok(())
+}
+
+fn check_seconds(config: &ChallengeDockerConfig, what: &str, seconds: u64) -> Result<(), String> {
+    if seconds > MAX_SECONDS {
+        return Err(format!(
+            "The {} of {} is {}s, at most {}s are allowed",
+            what, config.name, seconds, MAX_SECONDS
+        ));
+    }
+    Ok(())
+}
+
+fn check_command(
+    config: &ChallengeDockerConfig,
+    field: &str,
+    command: &[String],
+) -> Result<(), String> {
+    check_count(
+        config,
+        &format!("{} arguments", field),
+        command.len(),
+        MAX_COMMAND_ARGS,
+    )?;
+    for arg in command {
+        check_len(&format!("{} argument", field), arg, MAX_ARG_LEN)?;
+    }
+    Ok(())
+}
+
+fn check_hooks(config: &ChallengeDockerConfig) -> Result<(), String> {
+    let hooks = &config.hooks;
+    check_count(config, "post_up hooks", hooks.post_up.len(), MAX_HOOKS)?;
+    for hook in hooks.pre_up.iter().chain(&hooks.post_up) {
+        check_command(config, "hook command", &hook.command)?;
+        if let Some(service) = &hook.service {
+            check_len("hook service", service, MAX_NAME_LEN)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_healthcheck(config: &ChallengeDockerConfig) -> Result<(), String> {
+    let Some(healthcheck) = &config.healthcheck else {
+        return Ok(());
+    };
+    if let Some(http) = &healthcheck.http {
+        check_len("healthcheck http path", http, MAX_FIELD_LEN)?;
+    }
+    check_command(config, "healthcheck command", &healthcheck.command)?;
+    check_seconds(config, "healthcheck interval", healthcheck.interval)?;
+    check_seconds(config, "healthcheck timeout", healthcheck.timeout)?;
+    check_seconds(config, "healthcheck start_period", healthcheck.start_period)?;
+    if healthcheck.retries > MAX_RETRIES {
+        return Err(format!(
+            "The healthcheck of {} retries {} times, at most {} are allowed",
+            config.name, healthcheck.retries, MAX_RETRIES
+        ));
+    }
+    Ok(())
+}
+
+fn check_profiles(config: &ChallengeDockerConfig) -> Result<(), String> {
+    check_count(config, "profiles", config.profiles.len(), MAX_PROFILES)?;
+    for (name, profile) in &config.profiles {
+        check_len("profile name", name, MAX_NAME_LEN)?;
+        let fields = [
+            ("mem_limit", &profile.mem_limit),
+            ("cpus", &profile.cpus),
+            ("network", &profile.network),
+            ("static_flag", &profile.static_flag),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                check_len(&format!("profile {}", field), value, MAX_FIELD_LEN)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn check_rate_limit(limit: &RateLimit) -> Result<(), String> {
+    if limit.period == 0 || limit.period > MAX_RATE_PERIOD {
+        return Err(format!(
+            "The rate limit period is {}s, between 1s and {}s are allowed",
+            limit.period, MAX_RATE_PERIOD
+        ));
+    }
+    if limit.cooldown > MAX_RATE_PERIOD {
+        return Err(format!(
+            "The rate limit cooldown is {}s, at most {}s are allowed",
+            limit.cooldown, MAX_RATE_PERIOD
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "chaos")]
+pub fn check_chaos(monkey: &ChaosMonkey) -> Result<(), String> {
+    // NaN fails both comparisons, so it is spelled out
+    if monkey.rate.is_nan() || monkey.rate <= 0.0 || monkey.rate > MAX_CHAOS_RATE {
+        return Err(format!(
+            "The chaos rate is {} strikes a minute, above 0 and at most {} are allowed",
+            monkey.rate, MAX_CHAOS_RATE
+        ));
+    }
+    if monkey.pause_duration > MAX_SECONDS {
+        return Err(format!(
+            "The chaos pause duration is {}s, at most {}s are allowed",
+            monkey.pause_duration, MAX_SECONDS
+        ));
+    }
+    Ok(())
+}
+
+// docker --memory takes a number with an optional b, k, m or g
+fn memory_bytes(value: &str) -> Option<u64> {
+    let value = value.trim().to_ascii_lowercase();
+    let (number, multiplier) = match value.char_indices().last()? {
+        (i, 'b') => (&value[..i], 1),
+        (i, 'k') => (&value[..i], 1024),
+        (i, 'm') => (&value[..i], 1024 * 1024),
+        (i, 'g') => (&value[..i], 1024 * 1024 * 1024),
+        _ => (value.as_str(), 1),
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+fn check_per_connection(config: &ChallengeDockerConfig) -> Result<(), String> {
+    let Some(per_connection) = &config.per_connection else {
+        return Ok(());
+    };
+    check_len("per_connection image", &per_connection.image, MAX_FIELD_LEN)?;
+    check_command(config, "per_connection command", &per_connection.command)?;
+    check_len("per_connection cpus", &per_connection.cpus, MAX_NAME_LEN)?;
+    check_seconds(config, "per_connection timeout", per_connection.timeout)?;
+    if per_connection.max_total == 0 || per_connection.max_total > MAX_CONNECTIONS {
+        return Err(format!(
+            "The {} allows {} connections, between 1 and {} are allowed",
+            config.name, per_connection.max_total, MAX_CONNECTIONS
+        ));
+    }
+    check_len(
+        "per_connection mem_limit",
+        &per_connection.mem_limit,
+        MAX_NAME_LEN,
+    )?;
+    match memory_bytes(&per_connection.mem_limit) {
+        Some(bytes) if bytes > 0 && bytes <= MAX_CONNECTION_MEMORY => {}
+        _ => {
+            return Err(format!(
+                "The per_connection mem_limit {:?} of {} must be between 1b and {}m",
+                per_connection.mem_limit,
+                config.name,
+                MAX_CONNECTION_MEMORY / 1024 / 1024
+            ));
+        }
+    }
+    // docker takes 0 as no limit at all
+    if per_connection.pids_limit == 0 || per_connection.pids_limit > MAX_CONNECTION_PIDS {
+        return Err(format!(
+            "The per_connection pids_limit of {} is {}, between 1 and {} are allowed",
+            config.name, per_connection.pids_limit, MAX_CONNECTION_PIDS
+        ));
+    }
+    Ok(())
+}
+
+pub fn check_config(config: &ChallengeDockerConfig) -> Result<(), String> {
+    check_len("name", &config.name, MAX_NAME_LEN)?;
+    if config.name.trim().is_empty() {
+        return Err("The name is empty".to_string());
+    }
+    check_len("author", &config.author, MAX_FIELD_LEN)?;
+    check_len("description", &config.description, MAX_DESCRIPTION_LEN)?;
+
+    if config.tags.len() > MAX_TAGS {
+        return Err(format!(
+            "The {} has {} tags, at most {} are allowed",
+            config.name,
+            config.tags.len(),
+            MAX_TAGS
+        ));
+    }
+    for tag in &config.tags {
+        check_len("tag", tag, MAX_NAME_LEN)?;
+    }
+
+    let attachments = config.attachments.len() + config.built_attachments.len();
+    if attachments > MAX_ATTACHMENTS {
+        return Err(format!(
+            "The {} has {} attachments, at most {} are allowed",
+            config.name, attachments, MAX_ATTACHMENTS
+        ));
+    }
+    for attachment in &config.attachments {
+        check_relative("attachment", attachment)?;
+    }
+    for attachment in &config.built_attachments {
+        check_relative("built attachment", &attachment.name)?;
+        check_len(
+            "built attachment service",
+            &attachment.service,
+            MAX_NAME_LEN,
+        )?;
+        check_len("built attachment path", &attachment.path, MAX_FIELD_LEN)?;
+    }
+
+    if let Some(solve) = &config.solve
+        && solve.timeout > MAX_SOLVE_TIMEOUT
+    {
+        return Err(format!(
+            "The solve timeout of {} is {}s, at most {}s are allowed",
+            config.name, solve.timeout, MAX_SOLVE_TIMEOUT
+        ));
+    }
+    check_per_connection(config)?;
+    check_hooks(config)?;
+    check_healthcheck(config)?;
+    check_profiles(config)?;
+
+    check_count(config, "sysctls", config.sysctls.len(), MAX_SYSCTLS)?;
+    for (sysctl, value) in &config.sysctls {
+        check_len("sysctl", sysctl, MAX_NAME_LEN)?;
+        check_len("sysctl value", value, MAX_FIELD_LEN)?;
+    }
+    if let Some(connection_info) = &config.connection_info {
+        check_len("connection info", connection_info, MAX_FIELD_LEN)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_limits {
+    use super::*;
+
+    const CONFIG: &str = r#"
+name = "comment"
+author = "FloatCTF"
+category = "Misc"
+tags = []
+description = ""
+attachments = ["chall"]
+is_dynamic_flag = true
+is_dockerd = true
+points = 100
+"#;
+
+    #[test]
+    fn check_hostile_configs() {
+        ChallengeDockerConfig::parse(CONFIG).unwrap();
+
+        let long_name = CONFIG.replace("\"comment\"", &format!("\"{}\"", "a".repeat(65)));
+        assert!(ChallengeDockerConfig::parse(&long_name).is_err());
+
+        for attachment in ["../../etc/passwd", "/etc/passwd", "", "a/../../b"] {
+            let config = CONFIG.replace("[\"chall\"]", &format!("[{:?}]", attachment));
+            assert!(
+                ChallengeDockerConfig::parse(&config).is_err(),
+                "{}",
+                attachment
+            );
+        }
+
+        let many = vec!["\"a\""; MAX_ATTACHMENTS + 1].join(",");
+        let config = CONFIG.replace("[\"chall\"]", &format!("[{}]", many));
+        assert!(ChallengeDockerConfig::parse(&config).is_err());
+
+        let huge = format!("{}# {}\n", CONFIG, "x".repeat(MAX_CONFIG_SIZE as usize));
+        assert!(ChallengeDockerConfig::parse(&huge).is_err());
+
+        let per_connection = format!("{}[per_connection]\nimage = \"comment\"\n", CONFIG);
+        ChallengeDockerConfig::parse(&per_connection).unwrap();
+        for limit in [
+            "max_total = 0",
+            "max_total = 100000",
+            "mem_limit = \"64t\"",
+            "mem_limit = \"2g\"",
+            "mem_limit = \"0\"",
+            "pids_limit = 0",
+            "pids_limit = 1000000",
+        ] {
+            let config = format!("{}{}\n", per_connection, limit);
+            assert!(ChallengeDockerConfig::parse(&config).is_err(), "{}", limit);
+        }
+        let args = vec!["\"a\""; MAX_COMMAND_ARGS + 1].join(",");
+        let long = "a".repeat(MAX_FIELD_LEN + 1);
+        for hostile in [
+            "[[hooks.post_up]]\ncommand = [\"true\"]\n".repeat(MAX_HOOKS + 1),
+            format!("[hooks.pre_up]\ncommand = [{}]\n", args),
+            format!(
+                "[hooks.pre_up]\ncommand = [\"{}\"]\n",
+                "a".repeat(MAX_ARG_LEN + 1)
+            ),
+            (0..=MAX_SYSCTLS)
+                .map(|i| format!("sysctls.\"net.{}\" = \"1\"\n", i))
+                .collect(),
+            format!("sysctls.\"net.core.somaxconn\" = \"{}\"\n", long),
+            format!("connection_info = \"{}\"\n", long),
+            "[healthcheck]\ntcp = 1337\ninterval = 100000\n".to_string(),
+            "[healthcheck]\ntcp = 1337\nretries = 100000\n".to_string(),
+            format!("[healthcheck]\ncommand = [{}]\n", args),
+            (0..=MAX_PROFILES)
+                .map(|i| format!("[profiles.p{}]\n", i))
+                .collect(),
+            format!("[profiles.p]\nnetwork = \"{}\"\n", long),
+            format!("[per_connection]\nimage = \"{}\"\n", long),
+            format!(
+                "[per_connection]\nimage = \"comment\"\ncommand = [{}]\n",
+                args
+            ),
+            "[per_connection]\nimage = \"comment\"\ntimeout = 100000\n".to_string(),
+        ] {
+            let config = format!("{}{}", CONFIG, hostile);
+            assert!(
+                ChallengeDockerConfig::parse(&config).is_err(),
+                "{}",
+                hostile
+            );
+        }
+        let bounded = format!(
+            "{}sysctls.\"net.core.somaxconn\" = \"1024\"\n[[hooks.post_up]]\ncommand = [\"true\"]\n[healthcheck]\ntcp = 1337\n[profiles.p]\nnetwork = \"ctf\"\n",
+            CONFIG
+        );
+        ChallengeDockerConfig::parse(&bounded).unwrap();
+        assert_eq!(memory_bytes("512M"), Some(512 * 1024 * 1024));
+        assert_eq!(memory_bytes("1048576"), Some(1048576));
+
+        let inverted = format!("{}port_range = {{ start = 40000, end = 30000 }}\n", CONFIG);
+        assert!(ChallengeDockerConfig::parse(&inverted).is_err());
+
+        // garbage is an error, never a panic
+        for garbage in ["", "\0", "name = [[[[", "points = 99999999999999999999"] {
+            assert!(ChallengeDockerConfig::parse(garbage).is_err());
+        }
+    }
+
+    #[test]
+    fn check_read_limited() {
+        let tmp = crate::testing::TestDir::new("limits").unwrap();
+        let dir = tmp.path.clone();
+        let path = dir.join("big.yml");
+        std::fs::write(&path, "x".repeat(2048)).unwrap();
+        assert!(read_limited(&path, 1024).is_err());
+        assert_eq!(read_limited(&path, 4096).unwrap().len(), 2048);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::ChallengeDockerManager;
+use crate::backend::Backend;
+use crate::flag::Flag;
+use crate::id::InstanceId;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadTest {
+    pub instances: u64,
+    // how many spawns are in flight at once
+    pub concurrency: usize,
+    pub first_id: InstanceId,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct HostResources {
+    pub cpus: u64,
+    pub load_1m: f64,
+    // bytes
+    pub memory_total: u64,
+    pub memory_available: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyStats {
+    pub min_ms: u64,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoadTestReport {
+    pub challenge: String,
+    pub requested: u64,
+    pub spawned: u64,
+    pub errors: Vec<String>,
+    pub latency: LatencyStats,
+    // None where /proc is not available
+    pub before: Option<HostResources>,
+    pub peak: Option<HostResources>,
+    pub memory_per_instance: Option<u64>,
+    // how many more instances fit into the available memory at that rate
+    pub estimated_headroom: Option<u64>,
+    pub duration_ms: u64,
+}
+
+impl Default for LoadTest {
+    fn default() -> Self {
+        LoadTest {
+            instances: 50,
+            concurrency: 8,
+            first_id: InstanceId::new(2000),
+        }
+    }
+}
+
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    // nearest rank
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl LatencyStats {
+    pub fn from_samples(samples: &[u64]) -> Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        LatencyStats {
+            min_ms: sorted.first().copied().unwrap_or_default(),
+            p50_ms: percentile(&sorted, 50.0),
+            p90_ms: percentile(&sorted, 90.0),
+            p99_ms: percentile(&sorted, 99.0),
+            max_ms: sorted.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+fn meminfo_bytes(meminfo: &str, key: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|line| line.starts_with(key))?;
+    let kib: u64 = line[key.len()..]
+        .trim_start_matches(':')
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+impl HostResources {
+    pub fn sample() -> Result<Self, String> {
+        let meminfo = std::fs::read_to_string("/proc/meminfo")
+            .map_err(|e| format!("Failed to read /proc/meminfo: {}", e))?;
+        let loadavg = std::fs::read_to_string("/proc/loadavg")
+            .map_err(|e| format!("Failed to read /proc/loadavg: {}", e))?;
+        Ok(HostResources {
+            cpus: std::thread::available_parallelism()
+                .map(|n| n.get() as u64)
+                .unwrap_or(1),
+            load_1m: loadavg
+                .split_whitespace()
+                .next()
+                .and_then(|load| load.parse().ok())
+                .unwrap_or_default(),
+            memory_total: meminfo_bytes(&meminfo, "MemTotal").unwrap_or_default(),
+            memory_available: meminfo_bytes(&meminfo, "MemAvailable").unwrap_or_default(),
+        })
+    }
+}
+
+impl LoadTest {
+    pub fn new(instances: u64) -> Self {
+        LoadTest {
+            instances,
+            ..Default::default()
+        }
+    }
+
+    // spawn everything, sample the host while it all runs, then tear it all down
+    pub fn run(
+        &self,
+        challenge_path: &Path,
+        backend: Arc<dyn Backend>,
+    ) -> Result<LoadTestReport, String> {
+        let base = ChallengeDockerManager::new(challenge_path.to_path_buf(), self.first_id)?
+            .with_backend(backend);
+        let managers: Vec<ChallengeDockerManager> = (self.first_id.get()
+            ..self.first_id.get() + self.instances)
+            .map(|id| base.instance(InstanceId::new(id)))
+            .collect();
+        let challenge = base.challenge_docker_config.name.clone();
+
+        let before = HostResources::sample().ok();
+        let started = Instant::now();
+        let next = AtomicU64::new(0);
+        let latencies = Mutex::new(Vec::new());
+        let errors = Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            for _ in 0..self.concurrency.max(1) {
+                scope.spawn(|| {
+                    while let Some(cdm) = managers.get(next.fetch_add(1, Ordering::SeqCst) as usize)
+                    {
+                        let spawned = Instant::now();
+                        match cdm.up(&Flag::new(format!("flag{{cdm_loadtest_{}}}", cdm.id))) {
+                            Ok(_) => latencies
+                                .lock()
+                                .unwrap_or_else(|e| e.into_inner())
+                                .push(spawned.elapsed().as_millis() as u64),
+                            Err(e) => errors
+                                .lock()
+                                .unwrap_or_else(|e| e.into_inner())
+                                .push(format!("instance {}: {}", cdm.id, e)),
+                        }
+                    }
+                });
+            }
+        });
+        let peak = HostResources::sample().ok();
+
+        let mut errors = errors.into_inner().unwrap_or_else(|e| e.into_inner());
+        for cdm in &managers {
+            if let Err(e) = cdm.down() {
+                errors.push(format!("instance {} down: {}", cdm.id, e));
+            }
+        }
+
+        let latencies = latencies.into_inner().unwrap_or_else(|e| e.into_inner());
+        let spawned = latencies.len() as u64;
+        let memory_per_instance = match (before, peak) {
+            (Some(before), Some(peak)) if spawned > 0 => Some(
+                before
+                    .memory_available
+                    .saturating_sub(peak.memory_available)
+                    / spawned,
+            ),
+            _ => None,
+        };
+        let estimated_headroom = match (peak, memory_per_instance) {
+            (Some(peak), Some(per_instance)) if per_instance > 0 => {
+                Some(peak.memory_available / per_instance)
+            }
+            _ => None,
+        };
+
+        Ok(LoadTestReport {
+            challenge,
+            requested: self.instances,
+            spawned,
+            errors,
+            latency: LatencyStats::from_samples(&latencies),
+            before,
+            peak,
+            memory_per_instance,
+            estimated_headroom,
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_loadtest {
+    use super::*;
+    use crate::backend::{MockBackend, MockOperation};
+
+    #[test]
+    fn check_latency_stats() {
+        let samples: Vec<u64> = (1..=100).rev().collect();
+        let stats = LatencyStats::from_samples(&samples);
+        assert_eq!((stats.min_ms, stats.p50_ms, stats.p90_ms), (1, 50, 90));
+        assert_eq!((stats.p99_ms, stats.max_ms), (99, 100));
+        assert_eq!(LatencyStats::from_samples(&[]), LatencyStats::default());
+    }
+
+    #[test]
+    fn check_run() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+
+        let mock = Arc::new(MockBackend::new());
+        mock.fail_next(MockOperation::Up, "out of memory");
+        let report = LoadTest::new(10).run(&root, mock.clone()).unwrap();
+        assert_eq!(report.spawned, 9);
+        assert_eq!(report.errors.len(), 1);
+        assert!(mock.running().is_empty());
+    }
+}
@@ -1,3 +1,63 @@
+use cdm::id::InstanceId;
+use cdm::repository::ChallengeRepository;
+use cdm::{Category, ChallengeDockerManager};
+
+fn list(root: &str) -> Result<(), String> {
+    let repository = ChallengeRepository::open(root)?;
+    for challenge in &repository.challenges {
+        let cdm = ChallengeDockerManager::new(challenge.path.clone(), InstanceId::new(0))?;
+        println!("{}", cdm);
+    }
+    Ok(())
+}
+
+// a shell in a running instance, for the authors the shell policy of the settings lets in
+fn shell(challenge: Option<&String>, id: Option<&String>, attach: bool) -> Result<(), String> {
+    let (Some(challenge), Some(id)) = (challenge, id) else {
+        return Err("usage: cdm shell|attach <challenge dir> <instance id>".to_string());
+    };
+    let id: InstanceId = id
+        .parse()
+        .map_err(|e| format!("Invalid instance id {}: {}", id, e))?;
+    let cdm = ChallengeDockerManager::new(challenge.into(), id)?;
+    let user = std::env::var("USER").unwrap_or_default();
+    let code = if attach {
+        cdm.attach(&user)?
+    } else {
+        cdm.open_shell(&user)?
+    };
+    std::process::exit(code)
+}
+
+// a new challenge to fill in, with the template of its category
+fn scaffold(
+    root: Option<&String>,
+    name: Option<&String>,
+    category: Option<&String>,
+) -> Result<(), String> {
+    let (Some(root), Some(name), Some(category)) = (root, name, category) else {
+        return Err("usage: cdm scaffold <challenges dir> <name> <category>".to_string());
+    };
+    let category = Category::try_from(category.clone())?;
+    let dir = ChallengeRepository::open(root)?.scaffold(name, category)?;
+    println!("{}", dir.display());
+    Ok(())
+}
+
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = std::env::args().collect();
+    let result = match args.get(1).map(String::as_str) {
+        Some("list") => list(args.get(2).map(String::as_str).unwrap_or("./challenges")),
+        Some("shell") => shell(args.get(2), args.get(3), false),
+        Some("attach") => shell(args.get(2), args.get(3), true),
+        Some("scaffold") => scaffold(args.get(2), args.get(3), args.get(4)),
+        _ => Err(
+            "usage: cdm list [challenges dir] | shell|attach <challenge dir> <instance id> | scaffold <challenges dir> <name> <category>"
+                .to_string(),
+        ),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
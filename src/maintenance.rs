@@ -0,0 +1,143 @@
+use crate::ChallengeDockerManager;
+use crate::error::CdmError;
+use crate::schedule;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, OnceLock};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceInfo {
+    pub reason: String,
+    pub since: u64,
+}
+
+// challenges being hotfixed, players cannot spawn them but admins still can
+#[derive(Debug, Default)]
+pub struct MaintenanceRegistry {
+    challenges: Mutex<HashMap<String, MaintenanceInfo>>,
+}
+
+impl MaintenanceRegistry {
+    pub fn new() -> Self {
+        MaintenanceRegistry::default()
+    }
+
+    pub fn set_maintenance(&self, challenge: &str, reason: &str) {
+        self.challenges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                challenge.to_string(),
+                MaintenanceInfo {
+                    reason: reason.to_string(),
+                    since: schedule::now(),
+                },
+            );
+    }
+
+    // keeps the original since, for restoring backups
+    pub(crate) fn insert(&self, challenge: &str, info: MaintenanceInfo) {
+        self.challenges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(challenge.to_string(), info);
+    }
+
+    pub fn clear_maintenance(&self, challenge: &str) -> Option<MaintenanceInfo> {
+        self.challenges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(challenge)
+    }
+
+    pub fn get(&self, challenge: &str) -> Option<MaintenanceInfo> {
+        self.challenges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(challenge)
+            .cloned()
+    }
+
+    pub fn list(&self) -> BTreeMap<String, MaintenanceInfo> {
+        self.challenges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, info)| (name.clone(), info.clone()))
+            .collect()
+    }
+}
+
+// the registry every manager consults unless given another one
+pub fn global() -> Arc<MaintenanceRegistry> {
+    static REGISTRY: OnceLock<Arc<MaintenanceRegistry>> = OnceLock::new();
+    REGISTRY
+        .get_or_init(|| Arc::new(MaintenanceRegistry::new()))
+        .clone()
+}
+
+pub fn set_maintenance(challenge: &str, reason: &str) {
+    global().set_maintenance(challenge, reason);
+}
+
+pub fn clear_maintenance(challenge: &str) -> Option<MaintenanceInfo> {
+    global().clear_maintenance(challenge)
+}
+
+impl ChallengeDockerManager {
+    pub fn maintenance(&self) -> Option<MaintenanceInfo> {
+        self.maintenance.get(&self.challenge_docker_config.name)
+    }
+
+    pub(crate) fn check_maintenance(&self, admin: bool) -> Result<(), CdmError> {
+        match self.maintenance() {
+            Some(info) if !admin => Err(CdmError::Maintenance {
+                challenge: self.challenge_docker_config.name.clone(),
+                reason: info.reason,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_maintenance {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::dto::ChallengeSummary;
+    use crate::lifecycle::UpOptions;
+
+    #[test]
+    fn check_maintenance() {
+        let mock = Arc::new(MockBackend::new());
+        let registry = Arc::new(MaintenanceRegistry::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 1)
+            .with_backend(mock.clone())
+            .with_maintenance(registry.clone());
+        registry.set_maintenance("comment", "hotfixing the upload");
+
+        assert_eq!(
+            cdm.up_with(&"flag{player}".into(), &UpOptions::default()),
+            Err(CdmError::Maintenance {
+                challenge: "comment".to_string(),
+                reason: "hotfixing the upload".to_string(),
+            })
+        );
+        let admin = UpOptions::default().admin(true);
+        cdm.up_with(&"flag{admin}".into(), &admin).unwrap();
+
+        assert!(
+            cdm.to_string()
+                .ends_with("maintenance: hotfixing the upload)")
+        );
+        assert_eq!(
+            ChallengeSummary::from(&cdm).maintenance.as_deref(),
+            Some("hotfixing the upload")
+        );
+        assert_eq!(registry.list().len(), 1);
+
+        registry.clear_maintenance("comment");
+        assert!(cdm.maintenance().is_none());
+        assert!(ChallengeSummary::from(&cdm).maintenance.is_none());
+    }
+}
@@ -0,0 +1,301 @@
+use crate::ChallengeDockerManager;
+use crate::compose::ComposeFile;
+use crate::error::CdmError;
+use crate::id::InstanceId;
+use crate::labels::{self, LabeledContainer};
+use crate::settings;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+
+// what the compose files may mount, binds inside the challenge directory travel with it and are always fine
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MountPolicy {
+    pub allow_host_binds: bool,
+    // host paths that may be bound anyway, e.g. /srv/ctf/shared
+    pub allowed_prefixes: Vec<PathBuf>,
+    // every named volume is one cdm creates and labels, so gc_volumes finds it, external ones are denied
+    pub managed_volumes: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mount {
+    Bind { source: PathBuf, target: String },
+    Volume { name: String, target: String },
+    // anonymous volumes and tmpfs
+    Other { target: String },
+}
+
+fn string(value: &serde_yaml::Value, key: &str) -> Option<String> {
+    value.get(key)?.as_str().map(str::to_string)
+}
+
+// the short `source:target[:mode]` and the long syntax of a service volume
+pub fn parse_mount(value: &serde_yaml::Value) -> Option<Mount> {
+    if let Some(short) = value.as_str() {
+        let mut parts = short.splitn(3, ':');
+        let first = parts.next()?.to_string();
+        let Some(target) = parts.next() else {
+            return Some(Mount::Other { target: first });
+        };
+        let target = target.to_string();
+        // compose interpolates ${HOME} or $PWD into a host path, never into a volume name
+        if first.starts_with(['/', '.', '~']) || first.contains('$') {
+            return Some(Mount::Bind {
+                source: PathBuf::from(first),
+                target,
+            });
+        }
+        return Some(Mount::Volume {
+            name: first,
+            target,
+        });
+    }
+    let target = string(value, "target")?;
+    match (string(value, "type").as_deref(), string(value, "source")) {
+        (Some("bind"), Some(source)) => Some(Mount::Bind {
+            source: PathBuf::from(source),
+            target,
+        }),
+        (Some("volume"), Some(name)) => Some(Mount::Volume { name, target }),
+        _ => Some(Mount::Other { target }),
+    }
+}
+
+// without touching the filesystem, the source may not exist on this host
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+impl MountPolicy {
+    // what the compose file mounts against the policy, one message per mount that is not allowed
+    pub fn violations(&self, compose: &ComposeFile, challenge_path: &Path) -> Vec<String> {
+        let challenge_path = normalize(challenge_path);
+        let mut violations = Vec::new();
+        for (service, definition) in &compose.services {
+            for mount in definition.volumes.iter().filter_map(parse_mount) {
+                match mount {
+                    Mount::Bind { source, target } => {
+                        let resolved = normalize(&challenge_path.join(&source));
+                        // whatever the variable holds on the deploying host is unknown here
+                        let interpolated = source.to_string_lossy().contains('$');
+                        let allowed = (resolved.starts_with(&challenge_path)
+                            && !source.starts_with("~")
+                            && !interpolated)
+                            || self.allow_host_binds
+                            || self
+                                .allowed_prefixes
+                                .iter()
+                                .any(|prefix| resolved.starts_with(prefix));
+                        if !allowed {
+                            violations.push(format!(
+                                "service {} binds the host path {} to {}",
+                                service,
+                                source.display(),
+                                target
+                            ));
+                        }
+                    }
+                    Mount::Volume { name, .. } if self.managed_volumes => {
+                        let external = compose
+                            .volumes
+                            .get(&name)
+                            .is_some_and(|volume| volume.get("external").is_some());
+                        if external {
+                            violations.push(format!(
+                                "service {} mounts the external volume {}",
+                                service, name
+                            ));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        violations
+    }
+}
+
+impl ChallengeDockerManager {
+    pub(crate) fn check_mounts(&self) -> Result<(), CdmError> {
+        let compose = ComposeFile::load(&self.docker_compose_yml)?;
+        match self
+            .settings()
+            .mounts
+            .violations(&compose, &self.challenge_path)
+            .into_iter()
+            .next()
+        {
+            Some(mount) => Err(CdmError::MountDenied {
+                challenge: self.challenge_docker_config.name.clone(),
+                mount,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabeledVolume {
+    pub name: String,
+    pub challenge: String,
+    pub instance_id: Option<InstanceId>,
+}
+
+fn parse_volumes(output: &str) -> Vec<LabeledVolume> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            Some(LabeledVolume {
+                name: fields.next()?.to_string(),
+                challenge: fields.next()?.to_string(),
+                instance_id: fields.next().and_then(|id| id.parse().ok()),
+            })
+        })
+        .collect()
+}
+
+// the volumes of instances that have no container left
+pub fn orphaned_volumes(
+    volumes: Vec<LabeledVolume>,
+    containers: &[LabeledContainer],
+) -> Vec<LabeledVolume> {
+    let live: BTreeSet<(&str, Option<InstanceId>)> = containers
+        .iter()
+        .map(|container| (container.challenge.as_str(), container.instance_id))
+        .collect();
+    volumes
+        .into_iter()
+        .filter(|volume| !live.contains(&(volume.challenge.as_str(), volume.instance_id)))
+        .collect()
+}
+
+// remove the managed volumes a failed down left behind on the docker context or host, returns
+// their names
+pub fn gc_volumes(target: Option<&str>) -> Result<Vec<String>, String> {
+    let settings = settings::global()?;
+    let format = format!(
+        "{{{{.Name}}}}\t{{{{.Label \"{}\"}}}}\t{{{{.Label \"{}\"}}}}",
+        labels::CHALLENGE,
+        labels::INSTANCE_ID
+    );
+    let output = ChallengeDockerManager::run_command(
+        &settings,
+        "docker",
+        &[
+            "volume",
+            "ls",
+            "--filter",
+            &labels::filter(labels::CHALLENGE, None),
+            "--format",
+            &format,
+        ],
+        settings.docker_env(target),
+    )?;
+    let volumes = parse_volumes(&String::from_utf8_lossy(&output));
+    let names: Vec<String> = orphaned_volumes(volumes, &labels::discover(None, target)?)
+        .into_iter()
+        .map(|volume| volume.name)
+        .collect();
+    if !names.is_empty() {
+        let mut args = vec!["volume", "rm", "--force"];
+        args.extend(names.iter().map(String::as_str));
+        ChallengeDockerManager::run_command(
+            &settings,
+            "docker",
+            &args,
+            settings.docker_env(target),
+        )?;
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod test_mounts {
+    use super::*;
+
+    const COMPOSE: &str = r#"
+services:
+  web:
+    image: nginx
+    volumes:
+      - ./html:/usr/share/nginx/html:ro
+      - ../../etc:/host-etc
+      - /var/run/docker.sock:/var/run/docker.sock
+      - data:/data
+      - shared:/shared
+      - /cache
+      - type: bind
+        source: /srv/ctf/shared
+        target: /ctf
+volumes:
+  data: {}
+  shared:
+    external: true
+"#;
+
+    #[test]
+    fn check_mount_policy() {
+        let compose = ComposeFile::parse(COMPOSE).unwrap();
+        let root = Path::new("/challenges/web/comment");
+        let violations = MountPolicy::default().violations(&compose, root);
+        assert_eq!(violations.len(), 3, "{:?}", violations);
+        assert!(violations[0].contains("../../etc"));
+        assert!(violations[1].contains("docker.sock"));
+
+        let policy = MountPolicy {
+            allowed_prefixes: vec![PathBuf::from("/srv/ctf")],
+            managed_volumes: true,
+            ..Default::default()
+        };
+        let violations = policy.violations(&compose, root);
+        assert_eq!(violations.len(), 3, "{:?}", violations);
+        assert!(violations[2].contains("external volume shared"));
+
+        let open = MountPolicy {
+            allow_host_binds: true,
+            ..Default::default()
+        };
+        assert!(open.violations(&compose, root).is_empty());
+
+        for source in ["${HOME}", "$PWD/..", "./${DIR}", "data$X"] {
+            let compose = ComposeFile::parse(&format!(
+                "services:\n  web:\n    volumes:\n      - {}:/x\n",
+                source
+            ))
+            .unwrap();
+            let violations = MountPolicy::default().violations(&compose, root);
+            assert_eq!(violations.len(), 1, "{}", source);
+        }
+    }
+
+    #[test]
+    fn check_orphaned_volumes() {
+        let volumes = parse_volumes(concat!(
+            "challenge-project-1-comment_data\tcomment\t1\n",
+            "challenge-project-2-comment_data\tcomment\t2\n",
+        ));
+        let containers = [LabeledContainer {
+            name: "challenge-comment-1".to_string(),
+            challenge: "comment".to_string(),
+            instance_id: Some(InstanceId::new(1)),
+            team: None,
+            project: "challenge-project-1-comment".to_string(),
+        }];
+        let orphaned = orphaned_volumes(volumes, &containers);
+        assert_eq!(orphaned.len(), 1);
+        assert_eq!(orphaned[0].name, "challenge-project-2-comment_data");
+    }
+}
@@ -0,0 +1,181 @@
+use crate::events::{Event, EventKind, EventSink};
+use crate::webhook::{self, Outbox};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatPlatform {
+    Discord,
+    Slack,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatNotifier {
+    pub platform: ChatPlatform,
+    // operational events go here unless routed elsewhere
+    pub default_webhook: Option<String>,
+    // event name (see EventKind::name) to webhook url
+    pub routes: HashMap<String, String>,
+    #[serde(skip, default = "webhook::agent")]
+    agent: ureq::Agent,
+    #[serde(skip)]
+    outbox: Outbox,
+}
+
+impl ChatNotifier {
+    pub fn new(platform: ChatPlatform, webhook: &str) -> Self {
+        ChatNotifier {
+            platform,
+            default_webhook: Some(webhook.to_string()),
+            routes: HashMap::new(),
+            agent: webhook::agent(),
+            outbox: Outbox::default(),
+        }
+    }
+
+    pub fn route(mut self, event: &str, webhook: &str) -> Self {
+        self.routes.insert(event.to_string(), webhook.to_string());
+        self
+    }
+
+    // routine lifecycle events are only sent when explicitly routed
+    fn webhook(&self, kind: &EventKind) -> Option<&str> {
+        if let Some(webhook) = self.routes.get(kind.name()) {
+            return Some(webhook);
+        }
+        match kind {
+            EventKind::Built
+            | EventKind::Up { .. }
+            | EventKind::Down
+            | EventKind::Scaled { .. } => None,
+            _ => self.default_webhook.as_deref(),
+        }
+    }
+
+    pub fn format(event: &Event) -> String {
+        let instance = format!("{} #{}", event.challenge, event.instance_id);
+        match &event.kind {
+            EventKind::Built => format!(":hammer: {} built", event.challenge),
+            EventKind::Up { port } => format!(":green_circle: {} is up on port {}", instance, port),
+            EventKind::Down => format!(":white_circle: {} is down", instance),
+            EventKind::Failed { operation, error } => {
+                format!(
+                    ":warning: {} failed to {}: {}",
+                    instance,
+                    operation,
+                    error.trim()
+                )
+            }
+            EventKind::Crashed { exit_code } => match exit_code {
+                Some(code) => format!(":boom: {} crashed with exit code {}", instance, code),
+                None => format!(":boom: {} crashed", instance),
+            },
+            EventKind::QuotaExhausted { scope, limit } => format!(
+                ":no_entry: quota exhausted for {} ({} reached) while spawning {}",
+                scope, limit, instance
+            ),
+            EventKind::Reaped { reason } => format!(":skull: {} reaped: {}", instance, reason),
+            EventKind::Opened => format!(":unlock: {} is open", event.challenge),
+            EventKind::Closed => format!(":lock: {} is closed", event.challenge),
+            EventKind::Reset { port, .. } => {
+                format!(
+                    ":arrows_counterclockwise: {} was reset, now on port {}",
+                    instance, port
+                )
+            }
+            EventKind::Scaled { service, replicas } => {
+                format!(
+                    ":chart_with_upwards_trend: {} scaled {} to {} replicas",
+                    instance, service, replicas
+                )
+            }
+            EventKind::ShellOpened { user, command } => {
+                format!(
+                    ":computer: {} opened a shell ({}) in {}",
+                    user, command, instance
+                )
+            }
+        }
+    }
+
+    pub fn payload(&self, event: &Event) -> serde_json::Value {
+        let text = ChatNotifier::format(event);
+        match self.platform {
+            ChatPlatform::Discord => json!({ "content": text }),
+            ChatPlatform::Slack => json!({ "text": text }),
+        }
+    }
+
+    pub fn send(&self, event: &Event) -> Result<(), String> {
+        let Some(webhook) = self.webhook(&event.kind) else {
+            return Ok(());
+        };
+        let body = serde_json::to_vec(&self.payload(event))
+            .map_err(|e| format!("Failed to serialize notification: {}", e))?;
+        self.agent
+            .post(webhook)
+            .header("Content-Type", "application/json")
+            .send(&body[..])
+            .map_err(|e| format!("Failed to notify {}: {}", webhook, e))?;
+        Ok(())
+    }
+}
+
+impl EventSink for ChatNotifier {
+    fn emit(&self, event: &Event) -> Result<(), String> {
+        if self.webhook(&event.kind).is_none() {
+            return Ok(());
+        }
+        let notifier = ChatNotifier {
+            outbox: Outbox::default(),
+            ..self.clone()
+        };
+        self.outbox.push("cdm-notify", event, move |event| {
+            let _ = notifier.send(event);
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_notify {
+    use super::*;
+    use crate::id::InstanceId;
+
+    #[test]
+    fn check_routing() {
+        let notifier = ChatNotifier::new(ChatPlatform::Discord, "https://discord/ops")
+            .route("quota_exhausted", "https://discord/quota");
+
+        assert_eq!(notifier.webhook(&EventKind::Down), None);
+        assert_eq!(
+            notifier.webhook(&EventKind::Crashed {
+                exit_code: Some(139)
+            }),
+            Some("https://discord/ops")
+        );
+        assert_eq!(
+            notifier.webhook(&EventKind::QuotaExhausted {
+                scope: "team 42".to_string(),
+                limit: 3
+            }),
+            Some("https://discord/quota")
+        );
+    }
+
+    #[test]
+    fn check_payload() {
+        let event = Event::new(
+            EventKind::Crashed {
+                exit_code: Some(139),
+            },
+            "stack",
+            InstanceId::new(4),
+        );
+        let slack = ChatNotifier::new(ChatPlatform::Slack, "https://slack/ops");
+        assert_eq!(
+            slack.payload(&event)["text"],
+            ":boom: stack #4 crashed with exit code 139"
+        );
+    }
+}
@@ -0,0 +1,300 @@
+use crate::ChallengeDockerManager;
+use crate::compose::ComposeFile;
+use crate::error::CdmError;
+use crate::id::InstanceId;
+use crate::settings::{self, CdmSettings};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use strum::{AsRefStr, Display};
+use zeroize::Zeroize;
+
+pub(crate) const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, AsRefStr, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Operation {
+    Build,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    // piped to stdin
+    pub input: Option<String>,
+    // the working directory, the one of cdm unless set
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+// everything an operation would do, resolved without touching docker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    pub challenge: String,
+    pub instance_id: InstanceId,
+    pub backend: String,
+    pub operation: Operation,
+    pub commands: Vec<PlannedCommand>,
+    pub files: Vec<PlannedFile>,
+    // container side, the host ports are only known once the instance runs
+    pub ports: Vec<u64>,
+}
+
+impl PlannedCommand {
+    pub fn new(program: &str, args: &[&str]) -> Self {
+        PlannedCommand {
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            env: BTreeMap::new(),
+            input: None,
+            dir: None,
+        }
+    }
+
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    // the daemon a docker or compose command talks to, the one of the settings when None
+    pub fn on(self, target: Option<&str>) -> Self {
+        match target {
+            Some(target) => {
+                let (key, value) = settings::target_env(target);
+                self.env(key, value)
+            }
+            None => self,
+        }
+    }
+
+    pub fn input(mut self, input: String) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    pub fn dir(mut self, dir: &Path) -> Self {
+        self.dir = Some(dir.to_path_buf());
+        self
+    }
+
+    fn env_vars(&self) -> HashMap<&str, &str> {
+        self.env
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect()
+    }
+
+    // with the docker and docker-compose of the given settings, usually the ones of the manager
+    pub fn run(&self, settings: &CdmSettings) -> Result<Vec<u8>, CdmError> {
+        self.output(settings).map(|(stdout, _)| stdout)
+    }
+
+    // stdout and stderr, for commands reporting warnings on stderr
+    pub fn output(&self, settings: &CdmSettings) -> Result<(Vec<u8>, String), CdmError> {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        let output = ChallengeDockerManager::execute(
+            settings,
+            &self.program,
+            &args,
+            Some(self.env_vars()),
+            self.input.as_deref().map(str::as_bytes),
+            self.dir.as_deref(),
+        )?;
+        Ok((
+            output.stdout,
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ))
+    }
+
+    // the process run would start, for commands that keep the terminal
+    pub(crate) fn process(&self, settings: &CdmSettings) -> Command {
+        let mut command =
+            ChallengeDockerManager::command(settings, &self.program, &Some(self.env_vars()));
+        command.args(&self.args);
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+
+    fn redact(&mut self, secret: &str) {
+        for value in self.env.values_mut().chain(self.args.iter_mut()) {
+            *value = value.replace(secret, REDACTED);
+        }
+        if let Some(input) = &mut self.input {
+            *input = input.replace(secret, REDACTED);
+        }
+    }
+}
+
+// the flag travels in the env or the input, it must not outlive the command
+impl Drop for PlannedCommand {
+    fn drop(&mut self) {
+        self.env.values_mut().for_each(Zeroize::zeroize);
+        self.args.zeroize();
+        self.input.zeroize();
+    }
+}
+
+fn quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+impl fmt::Display for PlannedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(dir) = &self.dir {
+            write!(f, "cd {} && ", quote(&dir.to_string_lossy()))?;
+        }
+        for (key, value) in &self.env {
+            write!(f, "{}={} ", key, quote(value))?;
+        }
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", quote(arg))?;
+        }
+        if self.input.is_some() {
+            write!(f, " < stdin")?;
+        }
+        Ok(())
+    }
+}
+
+impl Plan {
+    pub fn new(cdm: &ChallengeDockerManager, backend: &str, operation: Operation) -> Self {
+        Plan {
+            challenge: cdm.challenge_docker_config.name.clone(),
+            instance_id: cdm.id,
+            backend: backend.to_string(),
+            operation,
+            commands: Vec::new(),
+            files: Vec::new(),
+            ports: Vec::new(),
+        }
+    }
+
+    pub fn command(mut self, command: PlannedCommand) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    pub fn file(mut self, path: PathBuf, content: String) -> Self {
+        self.files.push(PlannedFile { path, content });
+        self
+    }
+
+    // the flag must never end up in a plan that is printed or stored
+    pub fn redact(&mut self, flag: &str) {
+        if flag.is_empty() {
+            return;
+        }
+        for command in &mut self.commands {
+            command.redact(flag);
+        }
+        for file in &mut self.files {
+            file.content = file.content.replace(flag, REDACTED);
+        }
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} {} #{} on {}",
+            self.operation, self.challenge, self.instance_id, self.backend
+        )?;
+        for file in &self.files {
+            writeln!(f, "  write {}", file.path.display())?;
+            for line in file.content.lines() {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        for command in &self.commands {
+            writeln!(f, "  $ {}", command)?;
+            if let Some(input) = &command.input {
+                for line in input.lines() {
+                    writeln!(f, "    {}", line)?;
+                }
+            }
+        }
+        if !self.ports.is_empty() {
+            let ports: Vec<String> = self.ports.iter().map(u64::to_string).collect();
+            writeln!(f, "  ports {}", ports.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl ChallengeDockerManager {
+    pub fn plan(&self, operation: Operation, flag: &str) -> Result<Plan, String> {
+        if !self.challenge_docker_config.is_dockerd {
+            return Ok(Plan::new(self, self.backend().name(), operation));
+        }
+
+        let mut plan = self.backend().plan(self, operation, flag)?;
+        if operation == Operation::Up {
+            let compose = ComposeFile::load(&self.docker_compose_yml)?;
+            if let Some((_, service)) = compose.main_service(&self.main_container_name, self.id) {
+                plan.ports = service.container_ports();
+            }
+        }
+        plan.redact(flag);
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod test_plan {
+    use super::*;
+
+    #[test]
+    fn check_display() {
+        let command = PlannedCommand::new("docker", &["inspect", "--format", "{{.State}}"])
+            .env("ID", "1")
+            .env("FLAG", "flag{it's}");
+        assert_eq!(
+            command.to_string(),
+            r"FLAG='flag{it'\''s}' ID=1 docker inspect --format '{{.State}}'"
+        );
+    }
+
+    #[test]
+    fn check_redact() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+
+        let cdm = ChallengeDockerManager::new(root.clone(), InstanceId::new(4)).unwrap();
+        let plan = cdm.plan(Operation::Up, "flag{secret}").unwrap();
+        let printed = plan.to_string();
+        assert!(!printed.contains("flag{secret}"));
+        assert!(printed.contains("FLAG='<redacted>'"));
+        assert!(printed.contains("--project-name challenge-project-4-comment up --detach"));
+        assert_eq!(plan.ports, [1337]);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::ChallengeDockerManager;
+use crate::backend::ComposeBackend;
+use crate::id::InstanceId;
+use crate::plan::PlannedCommand;
+use crate::repository::ChallengeRepository;
+use crate::settings::{self, CdmSettings};
+use crate::timing::{self, SpawnStage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Instant;
+use strum::{AsRefStr, Display};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, AsRefStr, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum PreloadAction {
+    Present,
+    Pulled,
+    // built here and copied over with docker save and docker load
+    Loaded,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImagePreload {
+    pub challenge: String,
+    pub image: String,
+    // the DOCKER_HOST or docker context, empty for the local daemon
+    pub host: String,
+    pub action: PreloadAction,
+    pub error: Option<String>,
+}
+
+impl ImagePreload {
+    pub fn is_ok(&self) -> bool {
+        self.action != PreloadAction::Failed
+    }
+}
+
+fn docker_on(host: &str, args: &[&str]) -> PlannedCommand {
+    PlannedCommand::new("docker", args).on(Some(host).filter(|host| !host.is_empty()))
+}
+
+fn is_present(settings: &CdmSettings, host: &str, image: &str) -> bool {
+    docker_on(host, &["image", "inspect", "--format", "{{.Id}}", image])
+        .run(settings)
+        .is_ok()
+}
+
+fn archive_path(image: &str) -> PathBuf {
+    let name: String = image
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    std::env::temp_dir()
+        .join("cdm")
+        .join("preload")
+        .join(format!("{}.tar", name))
+}
+
+// images only built here have no registry to pull from, they are saved once and loaded on every host
+fn copy_image(
+    settings: &CdmSettings,
+    host: &str,
+    image: &str,
+    archives: &mut HashMap<String, PathBuf>,
+) -> Result<(), String> {
+    let archive = match archives.get(image) {
+        Some(archive) => archive.clone(),
+        None => {
+            let archive = archive_path(image);
+            if let Some(dir) = archive.parent() {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+            }
+            docker_on("", &["save", "--output", &archive.to_string_lossy(), image])
+                .run(settings)?;
+            archives.insert(image.to_string(), archive.clone());
+            archive
+        }
+    };
+    docker_on(host, &["load", "--input", &archive.to_string_lossy()]).run(settings)?;
+    Ok(())
+}
+
+fn preload_image(
+    settings: &CdmSettings,
+    host: &str,
+    image: &str,
+    archives: &mut HashMap<String, PathBuf>,
+) -> Result<PreloadAction, String> {
+    if is_present(settings, host, image) {
+        return Ok(PreloadAction::Present);
+    }
+    let pulled = docker_on(host, &["pull", "--quiet", image]).run(settings);
+    let action = match pulled {
+        Ok(_) => PreloadAction::Pulled,
+        Err(_) if !host.is_empty() && is_present(settings, "", image) => {
+            copy_image(settings, host, image, archives)?;
+            PreloadAction::Loaded
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    // a pull that succeeded for another platform still leaves nothing to run
+    if !is_present(settings, host, image) {
+        return Err(format!("{} is still missing after the {}", image, action));
+    }
+    Ok(action)
+}
+
+impl ChallengeRepository {
+    // pull every image onto every configured host before the event, see CdmSettings::hosts
+    pub fn preload(&self) -> Result<Vec<ImagePreload>, String> {
+        let hosts = settings::global()?.hosts.clone();
+        if hosts.is_empty() {
+            return self.preload_to(&[String::new()]);
+        }
+        self.preload_to(&hosts)
+    }
+
+    pub fn preload_to(&self, hosts: &[String]) -> Result<Vec<ImagePreload>, String> {
+        let settings = settings::global()?;
+        let mut images = Vec::new();
+        for challenge in self.challenges.iter().filter(|c| c.config.is_dockerd) {
+            let cdm = ChallengeDockerManager::new(challenge.path.clone(), InstanceId::new(0))?;
+            let challenge_images = ComposeBackend::images(&cdm).map_err(|e| {
+                format!(
+                    "Failed to list the images of {}: {}",
+                    challenge.config.name, e
+                )
+            })?;
+            images.extend(
+                challenge_images
+                    .into_iter()
+                    .map(|image| (challenge.config.name.clone(), image)),
+            );
+        }
+
+        let mut archives = HashMap::new();
+        let mut results = Vec::new();
+        for host in hosts {
+            for (challenge, image) in &images {
+                let started = Instant::now();
+                let result = preload_image(&settings, host, image, &mut archives);
+                if let Ok(PreloadAction::Pulled | PreloadAction::Loaded) = result {
+                    timing::global().record(
+                        challenge,
+                        SpawnStage::Pull,
+                        started.elapsed().as_millis() as u64,
+                    );
+                }
+                results.push(ImagePreload {
+                    challenge: challenge.clone(),
+                    image: image.clone(),
+                    host: host.clone(),
+                    action: *result.as_ref().unwrap_or(&PreloadAction::Failed),
+                    error: result.err(),
+                });
+            }
+        }
+        for archive in archives.values() {
+            let _ = std::fs::remove_file(archive);
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod test_preload {
+    use super::*;
+
+    #[test]
+    fn check_docker_on() {
+        assert!(docker_on("", &["pull", "nginx"]).env.is_empty());
+        assert_eq!(
+            docker_on("ssh://deploy@node1", &["pull", "nginx"]).to_string(),
+            "DOCKER_HOST=ssh://deploy@node1 docker pull nginx"
+        );
+        assert!(
+            archive_path("registry:5000/web:latest")
+                .ends_with("preload/registry_5000_web_latest.tar")
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use crate::ChallengeDockerManager;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+// what differs between e.g. dev, staging and prod, unset fields leave the challenge as it is
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    // put on every service of the instance
+    pub mem_limit: Option<String>,
+    pub cpus: Option<String>,
+    pub pids_limit: Option<u64>,
+    // an existing network every service joins besides its own
+    pub network: Option<String>,
+    // every instance gets this flag instead of its own, for authors testing locally
+    pub static_flag: Option<String>,
+    // print the lifecycle events of every instance to stderr
+    pub verbose: Option<bool>,
+}
+
+impl Profile {
+    // the fields set in the override win
+    pub fn merge(&self, over: &Profile) -> Profile {
+        Profile {
+            mem_limit: over.mem_limit.clone().or_else(|| self.mem_limit.clone()),
+            cpus: over.cpus.clone().or_else(|| self.cpus.clone()),
+            pids_limit: over.pids_limit.or(self.pids_limit),
+            network: over.network.clone().or_else(|| self.network.clone()),
+            static_flag: over
+                .static_flag
+                .clone()
+                .or_else(|| self.static_flag.clone()),
+            verbose: over.verbose.or(self.verbose),
+        }
+    }
+
+    pub fn is_verbose(&self) -> bool {
+        self.verbose.unwrap_or(false)
+    }
+
+    // the limits and the network as a compose override of the services, None when there is nothing to put
+    pub(crate) fn compose_override(&self, services: &[&String]) -> Option<Value> {
+        let mut service = json!({});
+        if let Some(mem_limit) = &self.mem_limit {
+            service["mem_limit"] = json!(mem_limit);
+        }
+        if let Some(cpus) = &self.cpus {
+            service["cpus"] = json!(cpus);
+        }
+        if let Some(pids_limit) = self.pids_limit {
+            service["pids_limit"] = json!(pids_limit);
+        }
+        if let Some(network) = &self.network {
+            // naming networks drops the implicit default one
+            service["networks"] = json!(["default", network]);
+        }
+        if service
+            .as_object()
+            .is_some_and(|service| service.is_empty())
+        {
+            return None;
+        }
+
+        let services: serde_json::Map<String, Value> = services
+            .iter()
+            .map(|name| (name.to_string(), service.clone()))
+            .collect();
+        let mut content = json!({ "services": services });
+        if let Some(network) = &self.network {
+            let mut networks = serde_json::Map::new();
+            networks.insert(network.clone(), json!({ "external": true }));
+            content["networks"] = Value::Object(networks);
+        }
+        Some(content)
+    }
+}
+
+impl ChallengeDockerManager {
+    // the template of the category, the settings profile over it, then the one of the same name in the FloatCTF.toml
+    pub fn profile(&self) -> Profile {
+        let template = self
+            .template()
+            .map(|template| template.profile())
+            .unwrap_or_default();
+        let Some(name) = self.profile_name() else {
+            return template;
+        };
+        let base = match self.settings().profiles.get(name) {
+            Some(profile) => template.merge(profile),
+            None => template,
+        };
+        match self.challenge_docker_config.profiles.get(name) {
+            Some(over) => base.merge(over),
+            None => base,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_profile {
+    use super::*;
+    use crate::backend::{MockBackend, MockOperation};
+    use crate::flag::Flag;
+    use crate::settings::CdmSettings;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_profiles() {
+        let mut settings = CdmSettings::default();
+        settings.profiles.insert(
+            "prod".to_string(),
+            Profile {
+                mem_limit: Some("256m".to_string()),
+                pids_limit: Some(128),
+                network: Some("ctf-egress".to_string()),
+                ..Default::default()
+            },
+        );
+        settings.profiles.insert(
+            "dev".to_string(),
+            Profile {
+                static_flag: Some("flag{dev}".to_string()),
+                verbose: Some(true),
+                ..Default::default()
+            },
+        );
+        let mock = Arc::new(MockBackend::new());
+        let mut cdm = ChallengeDockerManager::test_manager("comment", 1)
+            .with_backend(mock.clone())
+            .with_settings(Arc::new(settings))
+            .with_profile("prod");
+        cdm.config_mut().profiles.insert(
+            "prod".to_string(),
+            Profile {
+                mem_limit: Some("1g".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let profile = cdm.profile();
+        assert_eq!(profile.mem_limit.as_deref(), Some("1g"));
+        assert_eq!(profile.pids_limit, Some(128));
+        let web = "web".to_string();
+        let content = profile.compose_override(&[&web]).unwrap();
+        assert_eq!(content["services"]["web"]["mem_limit"], "1g");
+        assert_eq!(
+            content["services"]["web"]["networks"],
+            json!(["default", "ctf-egress"])
+        );
+        assert_eq!(content["networks"]["ctf-egress"]["external"], true);
+        assert!(Profile::default().compose_override(&[&web]).is_none());
+
+        let dev = cdm.with_profile("dev");
+        assert!(dev.profile().is_verbose());
+        dev.up(&"flag{generated}".into()).unwrap();
+        assert_eq!(
+            mock.calls_of(MockOperation::Up)[0].flag().map(Flag::expose),
+            Some("flag{dev}")
+        );
+        assert_eq!(dev.with_profile("staging").profile(), Profile::default());
+    }
+}
@@ -0,0 +1,252 @@
+use crate::ChallengeDockerManager;
+use crate::error::CdmError;
+use crate::id::TeamId;
+use crate::limits;
+use crate::schedule;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, try_from = "RawRateLimit")]
+pub struct RateLimit {
+    // spawns a team may do within the period
+    pub max_spawns: usize,
+    pub period: u64,
+    // seconds after taking an instance down before the team may spawn again
+    pub cooldown: u64,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            max_spawns: 5,
+            period: 600,
+            cooldown: 0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct RawRateLimit {
+    max_spawns: usize,
+    period: u64,
+    cooldown: u64,
+}
+
+impl Default for RawRateLimit {
+    fn default() -> Self {
+        let limit = RateLimit::default();
+        RawRateLimit {
+            max_spawns: limit.max_spawns,
+            period: limit.period,
+            cooldown: limit.cooldown,
+        }
+    }
+}
+
+impl TryFrom<RawRateLimit> for RateLimit {
+    type Error = String;
+
+    fn try_from(raw: RawRateLimit) -> Result<Self, Self::Error> {
+        let limit = RateLimit {
+            max_spawns: raw.max_spawns,
+            period: raw.period,
+            cooldown: raw.cooldown,
+        };
+        limits::check_rate_limit(&limit)?;
+        Ok(limit)
+    }
+}
+
+#[derive(Debug, Default)]
+struct TeamSpawns {
+    spawned_at: VecDeque<u64>,
+    destroyed_at: Option<u64>,
+}
+
+// spawns per team over a sliding period, so teams spamming spawn and destroy do not thrash the host
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    teams: Mutex<HashMap<TeamId, TeamSpawns>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            teams: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    // the seconds to wait when the team may not spawn now
+    pub fn retry_after(&self, team: TeamId, now: u64) -> Option<u64> {
+        // no spawn is ever allowed, a period is as good a wait as any
+        if self.limit.max_spawns == 0 {
+            return Some(self.limit.period.max(1));
+        }
+        let mut teams = self.teams.lock().unwrap_or_else(|e| e.into_inner());
+        let spawns = teams.get_mut(&team)?;
+        let since = now.saturating_sub(self.limit.period);
+        while spawns.spawned_at.front().is_some_and(|at| *at < since) {
+            spawns.spawned_at.pop_front();
+        }
+
+        let cooldown = spawns
+            .destroyed_at
+            .map(|at| at.saturating_add(self.limit.cooldown).saturating_sub(now))
+            .unwrap_or(0);
+        // the oldest spawn leaving the period frees a slot
+        let period = match spawns.spawned_at.len() >= self.limit.max_spawns {
+            true => spawns
+                .spawned_at
+                .iter()
+                .rev()
+                .nth(self.limit.max_spawns.saturating_sub(1))
+                .map(|at| {
+                    at.saturating_add(self.limit.period)
+                        .saturating_add(1)
+                        .saturating_sub(now)
+                })
+                .unwrap_or(1),
+            false => 0,
+        };
+        match cooldown.max(period) {
+            0 => None,
+            wait => Some(wait),
+        }
+    }
+
+    pub fn record_spawn(&self, team: TeamId, now: u64) {
+        self.teams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(team)
+            .or_default()
+            .spawned_at
+            .push_back(now);
+    }
+
+    pub fn record_destroy(&self, team: TeamId, now: u64) {
+        self.teams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(team)
+            .or_default()
+            .destroyed_at = Some(now);
+    }
+}
+
+impl ChallengeDockerManager {
+    // instances without a team, and admins, are not limited
+    pub(crate) fn check_rate_limit(&self, admin: bool) -> Result<(), CdmError> {
+        let (Some(limiter), Some(team)) = (self.rate_limiter(), self.team) else {
+            return Ok(());
+        };
+        if admin {
+            return Ok(());
+        }
+        match limiter.retry_after(team, schedule::now()) {
+            Some(retry_after) => Err(CdmError::RateLimited { team, retry_after }),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn record_spawn(&self, spawned: bool) {
+        let (Some(limiter), Some(team)) = (self.rate_limiter(), self.team) else {
+            return;
+        };
+        if spawned {
+            limiter.record_spawn(team, schedule::now());
+        } else {
+            limiter.record_destroy(team, schedule::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_ratelimit {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::lifecycle::UpOptions;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_rate_limiter() {
+        let limiter = RateLimiter::new(RateLimit {
+            max_spawns: 2,
+            period: 600,
+            cooldown: 60,
+        });
+        let (red, blue) = (TeamId::new(1), TeamId::new(2));
+        assert_eq!(limiter.retry_after(red, 0), None);
+        limiter.record_spawn(red, 0);
+        limiter.record_spawn(red, 100);
+        assert_eq!(limiter.retry_after(red, 200), Some(401));
+        assert_eq!(limiter.retry_after(red, 601), None);
+        assert_eq!(limiter.retry_after(blue, 200), None);
+
+        limiter.record_destroy(blue, 200);
+        assert_eq!(limiter.retry_after(blue, 230), Some(30));
+        assert_eq!(limiter.retry_after(blue, 260), None);
+
+        // not even the first spawn of a team gets through
+        let closed = RateLimiter::new(RateLimit {
+            max_spawns: 0,
+            period: 600,
+            cooldown: 0,
+        });
+        assert_eq!(closed.retry_after(red, 0), Some(600));
+
+        // the limiter itself does not trust the limit to be bounded
+        let unbounded = RateLimiter::new(RateLimit {
+            max_spawns: 1,
+            period: u64::MAX,
+            cooldown: u64::MAX,
+        });
+        unbounded.record_spawn(red, u64::MAX - 1);
+        unbounded.record_destroy(red, u64::MAX - 1);
+        assert_eq!(unbounded.retry_after(red, u64::MAX - 1), Some(1));
+
+        let limit: RateLimit = toml::from_str("max_spawns = 3").unwrap();
+        assert_eq!(limit.period, RateLimit::default().period);
+        for hostile in [
+            "period = 0",
+            "period = 18446744073709551615",
+            "cooldown = 18446744073709551615",
+        ] {
+            assert!(toml::from_str::<RateLimit>(hostile).is_err(), "{}", hostile);
+        }
+    }
+
+    #[test]
+    fn check_rate_limited() {
+        let mock = Arc::new(MockBackend::new());
+        let limiter = Arc::new(RateLimiter::new(RateLimit {
+            max_spawns: 1,
+            period: 600,
+            cooldown: 0,
+        }));
+        let team = TeamId::new(7);
+        let cdm = ChallengeDockerManager::test_manager("comment", 1)
+            .with_backend(mock.clone())
+            .with_team(team)
+            .with_rate_limiter(limiter.clone());
+        cdm.up(&"flag{rate}".into()).unwrap();
+        cdm.down().unwrap();
+
+        assert!(matches!(
+            cdm.up_with(&"flag{rate}".into(), &UpOptions::default()),
+            Err(CdmError::RateLimited { team: limited, retry_after }) if limited == team && retry_after > 0
+        ));
+        assert!(mock.running().is_empty());
+        cdm.up_with(&"flag{rate}".into(), &UpOptions::default().admin(true))
+            .unwrap();
+    }
+}
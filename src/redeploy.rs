@@ -0,0 +1,98 @@
+use crate::ChallengeDockerManager;
+use crate::flag::Flag;
+use crate::id::InstanceId;
+
+#[derive(Debug, Clone)]
+pub struct Redeployed {
+    // the instance now serving, under the standby id
+    pub manager: ChallengeDockerManager,
+    pub port: u64,
+    pub retired: InstanceId,
+    // the new instance is live even when the old one could not be taken down
+    pub retire_error: Option<String>,
+}
+
+impl ChallengeDockerManager {
+    // bring the new version up next to the running one and only retire the old one once the route points at the new port
+    pub fn redeploy(
+        &self,
+        flag: &Flag,
+        standby_id: InstanceId,
+        switch_route: impl FnOnce(u64) -> Result<(), String>,
+    ) -> Result<Redeployed, String> {
+        if standby_id == self.id {
+            return Err(format!(
+                "The standby of {} needs another id than {}",
+                self.challenge_docker_config.name, self.id
+            ));
+        }
+        self.build()?;
+
+        let standby = self.instance(standby_id);
+        let port = standby.up(flag)?;
+        let switched = standby
+            .wait_ready(port, self.settings().readiness_timeout)
+            .and_then(|_| switch_route(port));
+        if let Err(e) = switched {
+            // the old instance still serves, only the standby goes
+            let _ = standby.down();
+            return Err(format!(
+                "Failed to redeploy {}, instance {} keeps serving: {}",
+                self.challenge_docker_config.name, self.id, e
+            ));
+        }
+
+        Ok(Redeployed {
+            retire_error: self.down().err(),
+            manager: standby,
+            port,
+            retired: self.id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_redeploy {
+    use super::*;
+    use crate::backend::{MockBackend, MockOperation};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_redeploy() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port() as u64;
+        let mock = Arc::new(MockBackend::new());
+        let cdm = ChallengeDockerManager::new(root.clone(), InstanceId::new(1))
+            .unwrap()
+            .with_backend(mock.clone());
+        cdm.up(&"flag{shared}".into()).unwrap();
+        mock.respond_up(port);
+
+        let result = cdm.redeploy(&"flag{shared}".into(), InstanceId::new(2), |_| {
+            Err("proxy unreachable".to_string())
+        });
+        assert!(result.unwrap_err().contains("proxy unreachable"));
+        assert_eq!(mock.running(), [cdm.docker_compose_project_name.as_str()]);
+
+        mock.respond_up(port);
+        let mut routed = 0;
+        let redeployed = cdm
+            .redeploy(&"flag{shared}".into(), InstanceId::new(2), |port| {
+                routed = port;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(routed, port);
+        assert_eq!(redeployed.port, port);
+        assert_eq!(redeployed.retired, cdm.id);
+        assert!(redeployed.retire_error.is_none());
+        assert_eq!(
+            mock.running(),
+            [redeployed.manager.docker_compose_project_name.as_str()]
+        );
+        assert_eq!(mock.calls_of(MockOperation::Build).len(), 2);
+    }
+}
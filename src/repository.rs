@@ -0,0 +1,412 @@
+use crate::backend::Backend;
+use crate::build_cache::BuildCache;
+use crate::capabilities::DockerCapabilities;
+use crate::config_cache;
+use crate::id::InstanceId;
+use crate::plan::{Operation, Plan};
+use crate::schedule::Schedule;
+use crate::settings;
+use crate::{ChallengeDockerConfig, ChallengeDockerManager};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Challenge {
+    pub path: PathBuf,
+    pub config: Arc<ChallengeDockerConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitSource {
+    pub url: String,
+    pub rev: String,
+    pub subdir: Option<String>,
+    pub checkout: PathBuf,
+    // the deployed commit
+    pub commit: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepositoryUpdate {
+    pub from: String,
+    pub to: String,
+    // added or modified, these need a rebuild
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChallengeRepository {
+    pub root: PathBuf,
+    pub challenges: Vec<Challenge>,
+    pub git: Option<GitSource>,
+    #[serde(default)]
+    pub build_cache: Option<BuildCache>,
+    #[serde(default)]
+    pub schedule: Schedule,
+    // handed to every manager, see ChallengeDockerManager::with_capabilities
+    #[serde(skip)]
+    pub capabilities: Option<DockerCapabilities>,
+}
+
+fn git(checkout: &Path, args: &[&str]) -> Result<String, String> {
+    let settings = settings::global()?;
+    let mut full_args = vec!["-C".to_string(), checkout.to_string_lossy().to_string()];
+    full_args.extend(args.iter().map(|arg| arg.to_string()));
+    let full_args: Vec<&str> = full_args.iter().map(String::as_str).collect();
+
+    let output = ChallengeDockerManager::run_command(&settings, "git", &full_args, None)?;
+    let s = String::from_utf8(output).map_err(|e| format!("Invalid UTF-8 in output: {}", e))?;
+    Ok(s.trim().to_string())
+}
+
+// every directory with a FloatCTF.toml is a challenge, challenges are not nested
+fn find_challenges(dir: &Path, challenges: &mut Vec<PathBuf>) -> Result<(), String> {
+    if dir.join("FloatCTF.toml").is_file() {
+        challenges.push(dir.to_path_buf());
+        return Ok(());
+    }
+
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .filter(|path| {
+            !path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'))
+        })
+        .collect();
+    dirs.sort();
+
+    for dir in dirs {
+        find_challenges(&dir, challenges)?;
+    }
+    Ok(())
+}
+
+impl ChallengeRepository {
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self, String> {
+        let root = root.into();
+        let mut paths = Vec::new();
+        find_challenges(&root, &mut paths)?;
+
+        let mut challenges = Vec::new();
+        for path in paths {
+            let config = config_cache::global()
+                .get(&path)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            if challenges
+                .iter()
+                .any(|c: &Challenge| c.config.name == config.name)
+            {
+                return Err(format!("Duplicate challenge name: {}", config.name));
+            }
+            crate::slug::global().register(&config.name)?;
+            challenges.push(Challenge { path, config });
+        }
+
+        let schedule = match root.join("schedule.toml") {
+            path if path.is_file() => Schedule::load(&path)?,
+            _ => Schedule::default(),
+        };
+        Ok(ChallengeRepository {
+            root,
+            challenges,
+            git: None,
+            build_cache: None,
+            schedule,
+            capabilities: None,
+        })
+    }
+
+    pub fn from_git(url: &str, rev: &str, checkout: impl Into<PathBuf>) -> Result<Self, String> {
+        ChallengeRepository::clone_git(url, rev, None, checkout.into())
+    }
+
+    // only check out a single subdirectory of the repository
+    pub fn from_git_subdir(
+        url: &str,
+        rev: &str,
+        subdir: &str,
+        checkout: impl Into<PathBuf>,
+    ) -> Result<Self, String> {
+        ChallengeRepository::clone_git(url, rev, Some(subdir.to_string()), checkout.into())
+    }
+
+    fn clone_git(
+        url: &str,
+        rev: &str,
+        subdir: Option<String>,
+        checkout: PathBuf,
+    ) -> Result<Self, String> {
+        std::fs::create_dir_all(&checkout)
+            .map_err(|e| format!("Failed to create {}: {}", checkout.display(), e))?;
+
+        if !checkout.join(".git").exists() {
+            git(&checkout, &["init", "--quiet"])?;
+            git(&checkout, &["remote", "add", "origin", url])?;
+        }
+        if let Some(subdir) = &subdir {
+            git(&checkout, &["sparse-checkout", "set", subdir])?;
+        }
+        // fetching the rev directly works for branches, tags and commits alike
+        git(
+            &checkout,
+            &["fetch", "--quiet", "--depth", "1", "origin", rev],
+        )?;
+        git(&checkout, &["checkout", "--quiet", "--force", "FETCH_HEAD"])?;
+        let commit = git(&checkout, &["rev-parse", "HEAD"])?;
+
+        let root = match &subdir {
+            Some(subdir) => checkout.join(subdir),
+            None => checkout.clone(),
+        };
+        let mut repository = ChallengeRepository::open(root)?;
+        repository.git = Some(GitSource {
+            url: url.to_string(),
+            rev: rev.to_string(),
+            subdir,
+            checkout,
+            commit,
+        });
+        Ok(repository)
+    }
+
+    // pull the tracked rev and report the challenges that need a rebuild
+    pub fn update(&mut self) -> Result<RepositoryUpdate, String> {
+        let source = self
+            .git
+            .clone()
+            .ok_or("The repository is not tracking a git source")?;
+
+        git(
+            &source.checkout,
+            &["fetch", "--quiet", "--depth", "1", "origin", &source.rev],
+        )?;
+        let commit = git(&source.checkout, &["rev-parse", "FETCH_HEAD"])?;
+        if commit == source.commit {
+            return Ok(RepositoryUpdate {
+                from: source.commit.clone(),
+                to: commit,
+                ..Default::default()
+            });
+        }
+
+        let diff = git(
+            &source.checkout,
+            &["diff", "--name-only", &source.commit, &commit],
+        )?;
+        git(
+            &source.checkout,
+            &["checkout", "--quiet", "--force", &commit],
+        )?;
+
+        let before = self.challenges.clone();
+        let mut updated = ChallengeRepository::open(&self.root)?;
+
+        let changed_paths: Vec<PathBuf> = diff
+            .lines()
+            .map(|line| source.checkout.join(line))
+            .collect();
+        let before_names: BTreeSet<&str> = before.iter().map(|c| c.config.name.as_str()).collect();
+        let after_names: BTreeSet<&str> = updated
+            .challenges
+            .iter()
+            .map(|c| c.config.name.as_str())
+            .collect();
+
+        let changed = updated
+            .challenges
+            .iter()
+            .filter(|c| {
+                !before_names.contains(c.config.name.as_str())
+                    || changed_paths.iter().any(|path| path.starts_with(&c.path))
+            })
+            .map(|c| c.config.name.clone())
+            .collect();
+        let removed = before_names
+            .difference(&after_names)
+            .map(|name| name.to_string())
+            .collect();
+
+        updated.git = Some(GitSource {
+            commit: commit.clone(),
+            ..source.clone()
+        });
+        updated.build_cache = self.build_cache.clone();
+        updated.capabilities = self.capabilities.clone();
+        // one set with with_schedule outlives updates that leave schedule.toml alone
+        if !changed_paths.contains(&self.root.join("schedule.toml")) {
+            updated.schedule = self.schedule.clone();
+        }
+        *self = updated;
+
+        Ok(RepositoryUpdate {
+            from: source.commit,
+            to: commit,
+            changed,
+            removed,
+        })
+    }
+
+    pub fn with_build_cache(mut self, build_cache: BuildCache) -> Self {
+        self.build_cache = Some(build_cache);
+        self
+    }
+
+    // e.g. what check_docker_env found
+    pub fn with_capabilities(mut self, capabilities: DockerCapabilities) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    pub fn challenge(&self, name: &str) -> Option<&Challenge> {
+        self.challenges.iter().find(|c| c.config.name == name)
+    }
+
+    pub fn manager(&self, name: &str, id: InstanceId) -> Result<ChallengeDockerManager, String> {
+        let challenge = self
+            .challenge(name)
+            .ok_or(format!("No such challenge: {}", name))?;
+        let cdm = ChallengeDockerManager::new(challenge.path.clone(), id)?
+            .with_build_cache(self.build_cache.clone())
+            .with_window(self.window(name));
+        match &self.capabilities {
+            Some(capabilities) => cdm.with_capabilities(capabilities.clone()),
+            None => Ok(cdm),
+        }
+    }
+
+    // the plans are returned either way, a dry run only prints them
+    pub fn build_all(&self, backend: Arc<dyn Backend>, dry_run: bool) -> Result<Vec<Plan>, String> {
+        let mut plans = Vec::new();
+        for challenge in &self.challenges {
+            let cdm = ChallengeDockerManager::new(challenge.path.clone(), InstanceId::new(0))?
+                .with_backend(backend.clone())
+                .with_build_cache(self.build_cache.clone())
+                .with_dry_run(dry_run);
+            plans.push(cdm.plan(Operation::Build, "")?);
+            cdm.build()
+                .map_err(|e| format!("{}: {}", challenge.config.name, e))?;
+        }
+        Ok(plans)
+    }
+}
+
+#[cfg(test)]
+mod test_repository {
+    use super::*;
+    use crate::schedule::ChallengeWindow;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn check_open() {
+        let dir = crate::testing::TestDir::new("repository").unwrap();
+        let root = dir.path.clone();
+        crate::testing::write_challenge(&root.join("web/comment"), "comment").unwrap();
+        crate::testing::write_challenge(&root.join("pwn/stack"), "stack").unwrap();
+        std::fs::create_dir_all(root.join(".git/objects")).unwrap();
+
+        let repository = ChallengeRepository::open(&root).unwrap();
+        let names: Vec<_> = repository
+            .challenges
+            .iter()
+            .map(|c| c.config.name.as_str())
+            .collect();
+        assert_eq!(names, ["stack", "comment"]);
+        assert!(repository.challenge("comment").is_some());
+
+        let plans = repository
+            .build_all(crate::backend::default_backend(), true)
+            .unwrap();
+        assert_eq!(plans.len(), 2);
+        assert!(plans[0].commands[1].to_string().ends_with(" build"));
+
+        let repository = repository.with_build_cache(BuildCache::Local {
+            dir: root.join(".cache"),
+        });
+        let plans = repository
+            .build_all(crate::backend::default_backend(), true)
+            .unwrap();
+        let build = plans[0].commands[1].to_string();
+        assert!(build.starts_with("COMPOSE_DOCKER_CLI_BUILD=1 DOCKER_BUILDKIT=1 "));
+        assert!(build.contains("build-cache.yml build"));
+        assert!(plans[0].files[0].content.contains("stack-web"));
+    }
+
+    #[test]
+    fn check_update() {
+        let dir = crate::testing::TestDir::new("git").unwrap();
+        let root = dir.path.clone();
+        let origin = root.join("origin");
+        crate::testing::write_challenge(&origin.join("challenges/web/comment"), "comment").unwrap();
+        crate::testing::write_challenge(&origin.join("challenges/pwn/stack"), "stack").unwrap();
+        let commit = |message: &str| {
+            git(&origin, &["add", "--all"]).unwrap();
+            git(
+                &origin,
+                &[
+                    "-c",
+                    "user.name=cdm",
+                    "-c",
+                    "user.email=cdm@floatctf.org",
+                    "commit",
+                    "--quiet",
+                    "--message",
+                    message,
+                ],
+            )
+            .unwrap()
+        };
+        git(&origin, &["init", "--quiet"]).unwrap();
+        commit("two challenges");
+
+        let url = format!("file://{}", origin.display());
+        let mut full = ChallengeRepository::from_git(&url, "HEAD", root.join("full")).unwrap();
+        assert_eq!(full.challenges.len(), 2);
+        let mut web =
+            ChallengeRepository::from_git_subdir(&url, "HEAD", "challenges/web", root.join("web"))
+                .unwrap();
+        assert_eq!(web.challenges.len(), 1);
+        assert_eq!(web.root, root.join("web/challenges/web"));
+        assert!(!root.join("web/challenges/pwn").exists());
+        assert_eq!(full.update().unwrap().changed, Vec::<String>::new());
+        let window = ChallengeWindow {
+            opens_at: Some(100),
+            closes_at: None,
+        };
+        full = full.with_schedule(Schedule {
+            challenges: BTreeMap::from([("comment".to_string(), window)]),
+        });
+
+        std::fs::write(
+            origin.join("challenges/web/comment/Dockerfile"),
+            "FROM busybox:1.37\n",
+        )
+        .unwrap();
+        std::fs::remove_dir_all(origin.join("challenges/pwn/stack")).unwrap();
+        commit("update comment, drop stack");
+
+        let update = full.update().unwrap();
+        assert_ne!(update.from, update.to);
+        assert_eq!(update.changed, ["comment"]);
+        assert_eq!(update.removed, ["stack"]);
+        assert_eq!(full.git.as_ref().unwrap().commit, update.to);
+        let names: Vec<_> = full
+            .challenges
+            .iter()
+            .map(|c| c.config.name.as_str())
+            .collect();
+        assert_eq!(names, ["comment"]);
+        assert_eq!(full.window("comment"), Some(window));
+
+        let update = web.update().unwrap();
+        assert_eq!(update.changed, ["comment"]);
+        assert!(update.removed.is_empty());
+    }
+}
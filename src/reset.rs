@@ -0,0 +1,124 @@
+use crate::ChallengeDockerManager;
+use crate::backend::Backend;
+use crate::error::CdmError;
+use crate::events::EventKind;
+use crate::flag::Flag;
+use crate::id::{InstanceId, TeamId};
+use crate::labels;
+use crate::lifecycle::{DownOptions, IfRunning, UpOptions};
+use crate::repository::ChallengeRepository;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use strum::{AsRefStr, Display};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, AsRefStr, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum PortPolicy {
+    // the team keeps the address it already shared with its teammates
+    #[default]
+    Preserve,
+    Reallocate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResetReport {
+    pub challenge: String,
+    pub instance_id: InstanceId,
+    pub team: Option<TeamId>,
+    // None when nothing was running
+    pub previous_port: Option<u64>,
+    pub port: u64,
+}
+
+impl ChallengeDockerManager {
+    // tear the instance down whatever state it is in and bring it up again with the new flag
+    pub fn force_reset(&self, flag: &Flag, ports: PortPolicy) -> Result<ResetReport, CdmError> {
+        let previous_port = match self.backend.is_running(self)? {
+            true => Some(self.get_port()?),
+            false => None,
+        };
+        self.down_with(&DownOptions::force())?;
+
+        let fixed_port = match ports {
+            PortPolicy::Preserve => previous_port.and_then(|port| u16::try_from(port).ok()),
+            PortPolicy::Reallocate => None,
+        };
+        let cdm = self.clone().with_port(fixed_port.or(self.fixed_port()));
+        // support staff resets challenges in maintenance too
+        let options = UpOptions::default()
+            .if_running(IfRunning::Error)
+            .admin(true);
+        let port = cdm.up_with(flag, &options)?;
+
+        self.emit(EventKind::Reset {
+            previous_port,
+            port,
+        });
+        Ok(ResetReport {
+            challenge: self.challenge_docker_config.name.clone(),
+            instance_id: self.id,
+            team: self.team,
+            previous_port,
+            port,
+        })
+    }
+}
+
+impl ChallengeRepository {
+    // the instance of the team is found by its labels, so this works without any registry
+    pub fn force_reset(
+        &self,
+        team: TeamId,
+        challenge: &str,
+        flag: &Flag,
+        ports: PortPolicy,
+        backend: Arc<dyn Backend>,
+    ) -> Result<ResetReport, CdmError> {
+        let instance_id = labels::discover(Some(challenge), None)?
+            .into_iter()
+            .find(|container| container.team == Some(team))
+            .and_then(|container| container.instance_id)
+            .ok_or_else(|| format!("Team {} has no instance of {}", team, challenge))?;
+        self.manager(challenge, instance_id)?
+            .with_backend(backend)
+            .with_team(team)
+            .force_reset(flag, ports)
+    }
+}
+
+#[cfg(test)]
+mod test_reset {
+    use super::*;
+    use crate::backend::{MockBackend, MockOperation};
+    use crate::events::{EventLog, EventSink};
+
+    #[test]
+    fn check_force_reset() {
+        let mock = Arc::new(MockBackend::new());
+        let log = Arc::new(EventLog::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 42)
+            .with_backend(mock.clone())
+            .with_team(TeamId::new(42))
+            .with_event_sink(log.clone() as Arc<dyn EventSink>);
+        let port = cdm.up(&"flag{old}".into()).unwrap();
+
+        let report = cdm
+            .force_reset(&"flag{new}".into(), PortPolicy::Preserve)
+            .unwrap();
+        assert_eq!(report.previous_port, Some(port));
+        assert_eq!(report.port, port);
+        let ups = mock.calls_of(MockOperation::Up);
+        assert_eq!(ups.len(), 2);
+        assert_eq!(ups[1].flag().map(Flag::expose), Some("flag{new}"));
+        assert!(matches!(
+            log.events().last().map(|event| &event.kind),
+            Some(EventKind::Reset { previous_port: Some(previous), .. }) if *previous == port
+        ));
+
+        let report = cdm
+            .force_reset(&"flag{newer}".into(), PortPolicy::Reallocate)
+            .unwrap();
+        assert_ne!(report.port, port);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::backend::Backend;
+use crate::events::EventSink;
+use crate::flag::Flag;
+use crate::id::{InstanceId, TeamId};
+use crate::labels;
+use crate::lifecycle::{DownOptions, UpOptions};
+use crate::repository::ChallengeRepository;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RollingStrategy {
+    // instances replaced before the next batch starts
+    pub batch_size: usize,
+    // seconds between batches
+    pub pause: u64,
+    // the update stops once more instances than this failed, the rest keep the old version
+    pub max_failures: usize,
+    // wait until every replaced instance accepts connections before the next batch
+    pub wait_ready: bool,
+}
+
+impl Default for RollingStrategy {
+    fn default() -> Self {
+        RollingStrategy {
+            batch_size: 1,
+            pause: 0,
+            max_failures: 0,
+            wait_ready: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RollingReport {
+    pub updated: Vec<InstanceId>,
+    pub failed: Vec<(InstanceId, String)>,
+    // never touched because the update stopped
+    pub skipped: Vec<InstanceId>,
+}
+
+impl ChallengeRepository {
+    // rebuild, then recreate the running instances batch by batch with the flag, port and team they had
+    pub fn rolling_update(
+        &self,
+        challenge: &str,
+        backend: Arc<dyn Backend>,
+        sinks: &[Arc<dyn EventSink>],
+        strategy: &RollingStrategy,
+    ) -> Result<RollingReport, String> {
+        let mut instances: BTreeMap<InstanceId, Option<TeamId>> = BTreeMap::new();
+        for container in labels::discover(Some(challenge), None)? {
+            if let Some(id) = container.instance_id {
+                let team = instances.entry(id).or_default();
+                *team = team.or(container.team);
+            }
+        }
+        self.roll(challenge, backend, sinks, strategy, instances)
+    }
+
+    fn roll(
+        &self,
+        challenge: &str,
+        backend: Arc<dyn Backend>,
+        sinks: &[Arc<dyn EventSink>],
+        strategy: &RollingStrategy,
+        instances: BTreeMap<InstanceId, Option<TeamId>>,
+    ) -> Result<RollingReport, String> {
+        self.manager(challenge, InstanceId::new(0))?
+            .with_backend(backend.clone())
+            .build()?;
+
+        let mut report = RollingReport::default();
+        let instances: Vec<(InstanceId, Option<TeamId>)> = instances.into_iter().collect();
+        let batches: Vec<_> = instances.chunks(strategy.batch_size.max(1)).collect();
+        for (index, batch) in batches.iter().enumerate() {
+            if report.failed.len() > strategy.max_failures {
+                report.skipped.extend(batch.iter().map(|(id, _)| *id));
+                continue;
+            }
+            if index > 0 && strategy.pause > 0 {
+                std::thread::sleep(Duration::from_secs(strategy.pause));
+            }
+            for (id, team) in batch.iter() {
+                match self.replace(challenge, *id, *team, backend.clone(), sinks, strategy) {
+                    Ok(()) => report.updated.push(*id),
+                    Err(e) => report.failed.push((*id, e)),
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    fn replace(
+        &self,
+        challenge: &str,
+        id: InstanceId,
+        team: Option<TeamId>,
+        backend: Arc<dyn Backend>,
+        sinks: &[Arc<dyn EventSink>],
+        strategy: &RollingStrategy,
+    ) -> Result<(), String> {
+        let mut cdm = self.manager(challenge, id)?.with_backend(backend.clone());
+        for sink in sinks {
+            cdm = cdm.with_event_sink(sink.clone());
+        }
+        if let Some(team) = team {
+            cdm = cdm.with_team(team);
+        }
+        // a new flag would invalidate what the team already found
+        let flag = match backend.deployed_flag(&cdm)? {
+            Some(flag) => flag,
+            None if !cdm.challenge_docker_config.is_dynamic_flag => Flag::from(""),
+            None => {
+                return Err(format!(
+                    "The flag of {} #{} is unknown, it is left on the old version",
+                    challenge, id
+                ));
+            }
+        };
+        let port = cdm
+            .get_port()
+            .ok()
+            .and_then(|port| u16::try_from(port).ok())
+            .filter(|port| *port != 0)
+            .or(cdm.fixed_port());
+        let cdm = cdm.with_port(port);
+
+        cdm.down_with(&DownOptions::force())?;
+        let port = cdm.up_with(&flag, &UpOptions::default().admin(true))?;
+        if strategy.wait_ready {
+            cdm.wait_ready(port, cdm.settings().readiness_timeout)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_rolling {
+    use super::*;
+    use crate::backend::{MockBackend, MockOperation};
+    use crate::events::EventLog;
+
+    #[test]
+    fn check_rolling_update() {
+        let dir = crate::testing::TestDir::new("rolling").unwrap();
+        let root = dir.path.clone();
+        crate::testing::write_challenge(&root.join("web/comment"), "comment").unwrap();
+        let repository = ChallengeRepository::open(&root).unwrap();
+        let mock = Arc::new(MockBackend::new());
+        let mut instances = BTreeMap::new();
+        for (id, team) in [(1, Some(TeamId::new(7))), (2, None), (3, None)] {
+            let cdm = repository
+                .manager("comment", InstanceId::new(id))
+                .unwrap()
+                .with_backend(mock.clone());
+            if id != 3 {
+                mock.respond_up(31000 + id);
+                cdm.up(&format!("flag{{team_{}}}", id).as_str().into())
+                    .unwrap();
+            }
+            instances.insert(InstanceId::new(id), team);
+        }
+        mock.fail_next(MockOperation::Up, "no space left on device");
+
+        let log = Arc::new(EventLog::new());
+        let strategy = RollingStrategy {
+            wait_ready: false,
+            ..Default::default()
+        };
+        let report = repository
+            .roll(
+                "comment",
+                mock.clone(),
+                &[log.clone() as Arc<dyn EventSink>],
+                &strategy,
+                instances,
+            )
+            .unwrap();
+        assert!(report.updated.is_empty());
+        assert_eq!(report.failed[0].0, InstanceId::new(1));
+        assert_eq!(report.skipped, [InstanceId::new(2), InstanceId::new(3)]);
+
+        let mut instances = BTreeMap::new();
+        instances.insert(InstanceId::new(1), Some(TeamId::new(7)));
+        instances.insert(InstanceId::new(2), None);
+        mock.respond_up(31001);
+        repository
+            .manager("comment", InstanceId::new(1))
+            .unwrap()
+            .with_backend(mock.clone())
+            .up(&"flag{team_1}".into())
+            .unwrap();
+        let strategy = RollingStrategy {
+            batch_size: 2,
+            ..strategy
+        };
+        let report = repository
+            .roll("comment", mock.clone(), &[], &strategy, instances)
+            .unwrap();
+        assert_eq!(report.updated, [InstanceId::new(1), InstanceId::new(2)]);
+        assert_eq!(mock.calls_of(MockOperation::Build).len(), 2);
+        let ups = mock.calls_of(MockOperation::Up);
+        assert_eq!(
+            ups[ups.len() - 2].flag().map(Flag::expose),
+            Some("flag{team_1}")
+        );
+        assert_eq!(
+            ups[ups.len() - 1].flag().map(Flag::expose),
+            Some("flag{team_2}")
+        );
+        assert_eq!(mock.running().len(), 2);
+        let port = |id| {
+            repository
+                .manager("comment", InstanceId::new(id))
+                .unwrap()
+                .with_backend(mock.clone())
+                .get_port()
+                .unwrap()
+        };
+        assert_eq!((port(1), port(2)), (31001, 31002));
+        assert!(!log.events().is_empty());
+    }
+}
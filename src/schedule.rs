@@ -0,0 +1,329 @@
+use crate::ChallengeDockerManager;
+use crate::backend::Backend;
+use crate::error::CdmError;
+use crate::events::{Event, EventKind, EventSink};
+use crate::id::InstanceId;
+use crate::lifecycle::DownOptions;
+use crate::limits;
+use crate::repository::ChallengeRepository;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use strum::{AsRefStr, Display};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, AsRefStr, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum WindowState {
+    Pending,
+    Open,
+    Closed,
+}
+
+// unix timestamps, a missing one leaves that side open
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChallengeWindow {
+    #[serde(default)]
+    pub opens_at: Option<u64>,
+    #[serde(default)]
+    pub closes_at: Option<u64>,
+}
+
+impl ChallengeWindow {
+    pub fn state(&self, now: u64) -> WindowState {
+        if self.opens_at.is_some_and(|opens_at| now < opens_at) {
+            WindowState::Pending
+        } else if self.closes_at.is_some_and(|closes_at| now >= closes_at) {
+            WindowState::Closed
+        } else {
+            WindowState::Open
+        }
+    }
+}
+
+// schedule.toml at the root of the repository, it wins over the [schedule] of a FloatCTF.toml
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Schedule {
+    #[serde(default)]
+    pub challenges: BTreeMap<String, ChallengeWindow>,
+}
+
+impl Schedule {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = limits::read_limited(path, limits::MAX_CONFIG_SIZE)?;
+        toml::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl ChallengeDockerManager {
+    pub fn window(&self) -> Option<ChallengeWindow> {
+        self.window.or(self.challenge_docker_config.schedule)
+    }
+
+    pub(crate) fn check_window(&self, now: u64) -> Result<(), CdmError> {
+        let Some(window) = self.window() else {
+            return Ok(());
+        };
+        let challenge = self.challenge_docker_config.name.clone();
+        match window.state(now) {
+            WindowState::Open => Ok(()),
+            WindowState::Pending => Err(CdmError::NotOpen {
+                challenge,
+                opens_at: window.opens_at.unwrap_or_default(),
+            }),
+            WindowState::Closed => Err(CdmError::Closed {
+                challenge,
+                closed_at: window.closes_at.unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+impl ChallengeRepository {
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    pub fn window(&self, name: &str) -> Option<ChallengeWindow> {
+        self.schedule
+            .challenges
+            .get(name)
+            .copied()
+            .or_else(|| self.challenge(name)?.config.schedule)
+    }
+
+    // take down every instance of the backend of the challenge, the sinks hear them reaped, one
+    // failing down does not keep the others up
+    pub fn close_instances(
+        &self,
+        name: &str,
+        backend: Arc<dyn Backend>,
+        sinks: &[Arc<dyn EventSink>],
+    ) -> Result<usize, String> {
+        let probe = self.manager(name, InstanceId::new(0))?;
+        let ids = backend.instances(&probe)?;
+        let mut errors = Vec::new();
+        for id in &ids {
+            let closed = self.manager(name, *id).and_then(|cdm| {
+                let cdm = sinks
+                    .iter()
+                    .fold(cdm.with_backend(backend.clone()), |cdm, sink| {
+                        cdm.with_event_sink(sink.clone())
+                    });
+                Ok(cdm.down_with(&DownOptions::reaped("the challenge closed"))?)
+            });
+            if let Err(e) = closed {
+                errors.push(format!("{} #{}: {}", name, id, e));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("; "));
+        }
+        Ok(ids.len())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleTransition {
+    pub challenge: String,
+    pub from: WindowState,
+    pub to: WindowState,
+    pub at: u64,
+}
+
+// opens and closes the scheduled challenges of a repository, call poll or enforce periodically
+#[derive(Debug, Default)]
+pub struct ScheduleWatcher {
+    states: HashMap<String, WindowState>,
+    event_sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl ScheduleWatcher {
+    pub fn new() -> Self {
+        ScheduleWatcher::default()
+    }
+
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.event_sinks.push(sink);
+        self
+    }
+
+    // the challenges whose window changed since the last poll, a scheduled challenge starts out pending
+    pub fn poll(&mut self, repository: &ChallengeRepository, now: u64) -> Vec<ScheduleTransition> {
+        let mut transitions = Vec::new();
+        for challenge in &repository.challenges {
+            let name = &challenge.config.name;
+            let Some(window) = repository.window(name) else {
+                continue;
+            };
+            let to = window.state(now);
+            let from = self
+                .states
+                .insert(name.clone(), to)
+                .unwrap_or(WindowState::Pending);
+            if from == to {
+                continue;
+            }
+            let kind = match to {
+                WindowState::Open => EventKind::Opened,
+                WindowState::Closed => EventKind::Closed,
+                WindowState::Pending => continue,
+            };
+            let event = Event::new(kind, name, InstanceId::new(0));
+            for sink in &self.event_sinks {
+                let _ = sink.emit(&event);
+            }
+            transitions.push(ScheduleTransition {
+                challenge: name.clone(),
+                from,
+                to,
+                at: now,
+            });
+        }
+        transitions
+    }
+
+    // poll and take down the instances of the challenges that just closed
+    pub fn enforce(
+        &mut self,
+        repository: &ChallengeRepository,
+        backend: Arc<dyn Backend>,
+        now: u64,
+    ) -> Result<Vec<ScheduleTransition>, String> {
+        let transitions = self.poll(repository, now);
+        for transition in &transitions {
+            if transition.to == WindowState::Closed {
+                repository.close_instances(
+                    &transition.challenge,
+                    backend.clone(),
+                    &self.event_sinks,
+                )?;
+            }
+        }
+        Ok(transitions)
+    }
+}
+
+#[cfg(test)]
+mod test_schedule {
+    use super::*;
+    use crate::backend::{MockBackend, MockOperation};
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct Recorder(Mutex<Vec<Event>>);
+
+    impl EventSink for Recorder {
+        fn emit(&self, event: &Event) -> Result<(), String> {
+            self.0.lock().unwrap().push(event.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn check_window() {
+        let window = ChallengeWindow {
+            opens_at: Some(100),
+            closes_at: Some(200),
+        };
+        assert_eq!(window.state(99), WindowState::Pending);
+        assert_eq!(window.state(100), WindowState::Open);
+        assert_eq!(window.state(200), WindowState::Closed);
+        assert_eq!(ChallengeWindow::default().state(0), WindowState::Open);
+
+        let mock = Arc::new(MockBackend::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 1)
+            .with_backend(mock.clone())
+            .with_window(Some(ChallengeWindow {
+                opens_at: Some(now() + 3600),
+                closes_at: None,
+            }));
+        assert!(matches!(
+            cdm.up(&"flag{early}".into()),
+            Err(e) if e.contains("not open")
+        ));
+        let cdm = cdm.with_window(Some(ChallengeWindow {
+            opens_at: None,
+            closes_at: Some(1),
+        }));
+        assert!(cdm.up(&"flag{late}".into()).is_err());
+        assert!(mock.running().is_empty());
+        cdm.with_window(None).up(&"flag{open}".into()).unwrap();
+    }
+
+    #[test]
+    fn check_poll() {
+        let dir = crate::testing::TestDir::new("schedule").unwrap();
+        let root = dir.path.clone();
+        crate::testing::write_challenge(&root.join("web/comment"), "comment").unwrap();
+        crate::testing::write_challenge(&root.join("pwn/stack"), "stack").unwrap();
+        std::fs::write(
+            root.join("schedule.toml"),
+            "[challenges.comment]\nopens_at = 100\ncloses_at = 200\n",
+        )
+        .unwrap();
+        let repository = ChallengeRepository::open(&root).unwrap();
+        assert!(repository.window("stack").is_none());
+        let cdm = repository.manager("comment", InstanceId::new(1)).unwrap();
+        assert_eq!(cdm.window(), repository.window("comment"));
+
+        let recorder = Arc::new(Recorder::default());
+        let mut watcher = ScheduleWatcher::new().with_event_sink(recorder.clone());
+        assert!(watcher.poll(&repository, 50).is_empty());
+        let opened = watcher.poll(&repository, 150);
+        assert_eq!(opened.len(), 1);
+        assert_eq!(opened[0].to, WindowState::Open);
+        assert!(watcher.poll(&repository, 160).is_empty());
+        let closed = watcher.poll(&repository, 250);
+        assert_eq!(closed[0].from, WindowState::Open);
+        assert_eq!(closed[0].to, WindowState::Closed);
+
+        let events = recorder.0.lock().unwrap();
+        let kinds: Vec<&EventKind> = events.iter().map(|event| &event.kind).collect();
+        assert_eq!(kinds, [&EventKind::Opened, &EventKind::Closed]);
+    }
+
+    #[test]
+    fn check_close_instances() {
+        let dir = crate::testing::TestDir::new("schedule-close").unwrap();
+        let root = dir.path.clone();
+        crate::testing::write_challenge(&root.join("web/comment"), "comment").unwrap();
+        crate::testing::write_challenge(&root.join("pwn/stack"), "stack").unwrap();
+        let repository = ChallengeRepository::open(&root).unwrap();
+        let mock = Arc::new(MockBackend::new());
+        for (name, id) in [("comment", 1), ("comment", 2), ("comment", 3), ("stack", 1)] {
+            repository
+                .manager(name, InstanceId::new(id))
+                .unwrap()
+                .with_backend(mock.clone())
+                .up(&"flag{closing}".into())
+                .unwrap();
+        }
+
+        mock.fail_next(MockOperation::Down, "daemon went away");
+        let recorder: Arc<dyn EventSink> = Arc::new(Recorder::default());
+        let error = repository
+            .close_instances("comment", mock.clone(), &[recorder])
+            .unwrap_err();
+        assert!(error.contains("comment #1") && error.contains("daemon went away"));
+        // the failure did not stop the others from going down
+        assert_eq!(mock.calls_of(MockOperation::Down).len(), 3);
+        assert_eq!(mock.running().len(), 2);
+
+        assert_eq!(
+            repository.close_instances("comment", mock.clone(), &[]),
+            Ok(1)
+        );
+        assert_eq!(mock.running().len(), 1);
+    }
+}
@@ -0,0 +1,228 @@
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tiny_http::{Header, Request, Response, Server};
+
+// serves the packaged attachments of a directory behind expiring signed urls
+#[derive(Clone)]
+pub struct AttachmentServer {
+    pub root: PathBuf,
+    pub bind: String,
+    // the url players see, e.g. https://files.ctf.local
+    pub public_url: String,
+    secret: String,
+    downloads: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl fmt::Debug for AttachmentServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AttachmentServer")
+            .field("root", &self.root)
+            .field("bind", &self.bind)
+            .field("public_url", &self.public_url)
+            .field("secret", &"<redacted>")
+            .finish_non_exhaustive()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl AttachmentServer {
+    pub fn new(root: impl Into<PathBuf>, bind: &str, public_url: &str, secret: &str) -> Self {
+        AttachmentServer {
+            root: root.into(),
+            bind: bind.to_string(),
+            public_url: public_url.trim_end_matches('/').to_string(),
+            secret: secret.to_string(),
+            downloads: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn mac(&self, name: &str, expires: u64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(format!("{}\n{}", name, expires).as_bytes());
+        mac
+    }
+
+    pub fn token(&self, name: &str, expires: u64) -> String {
+        hex::encode(self.mac(name, expires).finalize().into_bytes())
+    }
+
+    pub fn issue_url(&self, name: &str, ttl: u64) -> String {
+        let expires = now().saturating_add(ttl);
+        format!(
+            "{}/files/{}?expires={}&token={}",
+            self.public_url,
+            name,
+            expires,
+            self.token(name, expires)
+        )
+    }
+
+    pub fn verify(&self, name: &str, expires: u64, token: &str) -> bool {
+        if expires < now() {
+            return false;
+        }
+        let Ok(token) = hex::decode(token) else {
+            return false;
+        };
+        self.mac(name, expires).verify_slice(&token).is_ok()
+    }
+
+    pub fn downloads(&self) -> HashMap<String, u64> {
+        self.downloads
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    // files are only looked up directly below the root
+    fn resolve(&self, url: &str) -> Option<(String, u64, String)> {
+        let (path, query) = url.split_once('?')?;
+        let name = path.strip_prefix("/files/")?;
+        if name.is_empty() || name.contains('/') || name.contains('\\') || name.starts_with('.') {
+            return None;
+        }
+
+        let params: HashMap<&str, &str> = query
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .collect();
+        let expires = params.get("expires")?.parse().ok()?;
+        let token = params.get("token")?;
+        Some((name.to_string(), expires, token.to_string()))
+    }
+
+    fn handle(&self, request: Request) {
+        let response = match self.resolve(request.url()) {
+            Some((name, expires, token)) if self.verify(&name, expires, &token) => {
+                match File::open(self.root.join(&name)) {
+                    Ok(file) => {
+                        *self
+                            .downloads
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .entry(name.clone())
+                            .or_default() += 1;
+                        let disposition =
+                            format!("attachment; filename=\"{}\"", name.replace('"', ""));
+                        match Header::from_bytes("Content-Disposition", disposition) {
+                            Ok(header) => Response::from_file(file).with_header(header).boxed(),
+                            Err(_) => Response::from_file(file).boxed(),
+                        }
+                    }
+                    Err(_) => Response::from_string("Not Found")
+                        .with_status_code(404)
+                        .boxed(),
+                }
+            }
+            Some(_) => Response::from_string("Forbidden")
+                .with_status_code(403)
+                .boxed(),
+            None => Response::from_string("Not Found")
+                .with_status_code(404)
+                .boxed(),
+        };
+        let _ = request.respond(response);
+    }
+
+    pub fn serve(&self) -> Result<(), String> {
+        let server =
+            Server::http(&self.bind).map_err(|e| format!("Failed to bind {}: {}", self.bind, e))?;
+        for request in server.incoming_requests() {
+            self.handle(request);
+        }
+        Ok(())
+    }
+
+    pub fn spawn(&self) -> Result<JoinHandle<()>, String> {
+        let server =
+            Server::http(&self.bind).map_err(|e| format!("Failed to bind {}: {}", self.bind, e))?;
+        let this = self.clone();
+        std::thread::Builder::new()
+            .name("cdm-attachment-server".to_string())
+            .spawn(move || {
+                for request in server.incoming_requests() {
+                    this.handle(request);
+                }
+            })
+            .map_err(|e| format!("Failed to spawn server thread: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod test_server {
+    use super::*;
+
+    #[test]
+    fn check_token() {
+        let server = AttachmentServer::new("/tmp", "127.0.0.1:0", "http://files/", "secret");
+        let url = server.issue_url("chall-v1.zip", 60);
+        let (name, expires, token) = server
+            .resolve(url.strip_prefix("http://files").unwrap())
+            .unwrap();
+
+        assert!(server.verify(&name, expires, &token));
+        assert!(!server.verify("other.zip", expires, &token));
+        assert!(!server.verify(&name, expires + 1, &token));
+        assert!(!server.verify(&name, 1, &server.token(&name, 1)));
+
+        let url = server.issue_url("chall-v1.zip", u64::MAX);
+        assert!(url.contains(&format!("expires={}&", u64::MAX)));
+        let debug = format!("{:?}", server);
+        assert!(debug.contains("<redacted>") && !debug.contains("\"secret\""));
+    }
+
+    #[test]
+    fn check_resolve_rejects_traversal() {
+        let server = AttachmentServer::new("/tmp", "127.0.0.1:0", "http://files", "secret");
+        assert!(
+            server
+                .resolve("/files/../etc/passwd?expires=1&token=00")
+                .is_none()
+        );
+        assert!(server.resolve("/files/.env?expires=1&token=00").is_none());
+    }
+
+    #[test]
+    fn check_download() {
+        let dir = crate::testing::TestDir::new("server").unwrap();
+        let root = dir.path.clone();
+        std::fs::write(root.join("chall.zip"), b"PK").unwrap();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let bind = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let server = AttachmentServer::new(&root, &bind, &format!("http://{}", bind), "secret");
+        server.spawn().unwrap();
+
+        let body = ureq::get(&server.issue_url("chall.zip", 60))
+            .call()
+            .unwrap()
+            .body_mut()
+            .read_to_vec()
+            .unwrap();
+        assert_eq!(body, b"PK");
+        assert_eq!(server.downloads()["chall.zip"], 1);
+        assert!(
+            ureq::get(&format!(
+                "http://{}/files/chall.zip?expires=1&token=00",
+                bind
+            ))
+            .call()
+            .is_err()
+        );
+    }
+}
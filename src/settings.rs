@@ -0,0 +1,498 @@
+use crate::Category;
+use crate::limits;
+use crate::mounts::MountPolicy;
+use crate::profile::Profile;
+use crate::shell::ShellPolicy;
+use crate::sysctls;
+use crate::template::ChallengeTemplate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::TcpListener;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// where ${PORT} of a compose file is picked from, both ends included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawPortRange")]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+#[derive(Deserialize)]
+struct RawPortRange {
+    start: u16,
+    end: u16,
+}
+
+impl TryFrom<RawPortRange> for PortRange {
+    type Error = String;
+
+    fn try_from(raw: RawPortRange) -> Result<Self, Self::Error> {
+        PortRange::new(raw.start, raw.end)
+    }
+}
+
+// operator settings, defaults < the file in CDM_CONFIG < CDM_* environment variables
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CdmSettings {
+    pub docker: String,
+    // e.g. ["docker", "compose"] for compose v2
+    pub compose: Vec<String>,
+    // the address players reach published ports on
+    pub bind_address: String,
+    // what players are told to connect to, the bind address unless set
+    pub public_host: Option<String>,
+    // {domain} of the connection info templates
+    pub domain: Option<String>,
+    // templates by category, see ChallengeDockerManager::connection_template
+    pub connection_info: BTreeMap<String, String>,
+    pub port_range: Option<PortRange>,
+    // by category, e.g. pwn = { start = 32000, end = 33999 }, port_range is for the others
+    pub port_ranges: BTreeMap<String, PortRange>,
+    // seconds compose down waits before killing
+    pub down_timeout: u64,
+    pub readiness_timeout: u64,
+    // the daemon every docker and compose call targets, a docker context name or a DOCKER_HOST
+    pub context: Option<String>,
+    // the daemons images are preloaded onto, e.g. ssh://deploy@node1 or the name of a context, empty for the local one
+    pub hosts: Vec<String>,
+    // the profile managers use unless they are given one, e.g. dev on an author's laptop
+    pub profile: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+    // checked by validate and before every compose up
+    pub mounts: MountPolicy,
+    // the sysctls challenges may set, names or prefixes ending in *
+    pub allowed_sysctls: Vec<String>,
+    // for open_shell and attach
+    pub shell: ShellPolicy,
+    // host variables compose sees besides HOST_ENV, e.g. HTTP_PROXY, names or prefixes ending in *
+    pub env_passthrough: Vec<String>,
+    // defaults by category merged under every FloatCTF.toml, and what scaffold writes
+    pub templates: BTreeMap<String, ChallengeTemplate>,
+    // hooks without a service run on the host with the flag, only trusted repositories get them
+    pub allow_host_hooks: bool,
+}
+
+// what docker-compose needs to find docker and its own config, the rest of the host env is stripped
+const HOST_ENV: [&str; 10] = [
+    "PATH",
+    "HOME",
+    "USER",
+    "TMPDIR",
+    "XDG_RUNTIME_DIR",
+    "SSH_AUTH_SOCK",
+    "DOCKER_HOST",
+    "DOCKER_CONTEXT",
+    "DOCKER_CONFIG",
+    "DOCKER_CERT_PATH",
+];
+
+impl Default for CdmSettings {
+    fn default() -> Self {
+        CdmSettings {
+            docker: "docker".to_string(),
+            compose: vec!["docker-compose".to_string()],
+            bind_address: "127.0.0.1".to_string(),
+            public_host: None,
+            domain: None,
+            connection_info: BTreeMap::new(),
+            port_range: None,
+            port_ranges: BTreeMap::new(),
+            down_timeout: 1,
+            readiness_timeout: 60,
+            context: None,
+            hosts: Vec::new(),
+            profile: None,
+            profiles: BTreeMap::new(),
+            mounts: MountPolicy::default(),
+            allowed_sysctls: sysctls::default_allowlist(),
+            shell: ShellPolicy::default(),
+            env_passthrough: Vec::new(),
+            templates: BTreeMap::new(),
+            allow_host_hooks: false,
+        }
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or(format!("Invalid port range {}, expected start-end", s))?;
+        let parse = |port: &str| {
+            port.trim()
+                .parse::<u16>()
+                .map_err(|e| format!("Invalid port range {}: {}", s, e))
+        };
+        PortRange::new(parse(start)?, parse(end)?)
+    }
+}
+
+impl PortRange {
+    pub fn new(start: u16, end: u16) -> Result<Self, String> {
+        if start == 0 || start > end {
+            return Err(format!("Invalid port range {}-{}", start, end));
+        }
+        Ok(PortRange { start, end })
+    }
+
+    pub fn contains(&self, port: u64) -> bool {
+        (self.start as u64..=self.end as u64).contains(&port)
+    }
+
+    // a port nobody listens on yet, instances start looking at different offsets so they rarely race
+    pub fn free_port(&self, host: &str, offset: u64) -> Option<u16> {
+        self.find_port(host, offset, true)
+    }
+
+    // what free_port would hand out without reserving it, for plans
+    pub fn peek_port(&self, host: &str, offset: u64) -> Option<u16> {
+        self.find_port(host, offset, false)
+    }
+
+    fn find_port(&self, host: &str, offset: u64, reserve: bool) -> Option<u16> {
+        // built by hand the range may still be inverted
+        let len = self.end.checked_sub(self.start)? as u64 + 1;
+        let mut reserved = RESERVED_PORTS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        reserved.retain(|_, at| at.elapsed() < PORT_RESERVATION);
+        let port = (0..len)
+            .map(|i| self.start + ((offset % len + i) % len) as u16)
+            .find(|port| {
+                !reserved.contains_key(&(host.to_string(), *port))
+                    && TcpListener::bind((host, *port)).is_ok()
+            })?;
+        if reserve {
+            reserved.insert((host.to_string(), port), Instant::now());
+        }
+        Some(port)
+    }
+}
+
+// handed out but maybe not listened on yet, compose takes a while to publish the port
+static RESERVED_PORTS: OnceLock<Mutex<HashMap<(String, u16), Instant>>> = OnceLock::new();
+const PORT_RESERVATION: Duration = Duration::from_secs(60);
+
+// ssh://deploy@node1 and unix:///run/docker.sock are hosts, anything else names a docker context
+pub fn target_env(target: &str) -> (&'static str, &str) {
+    if target.contains("://") {
+        ("DOCKER_HOST", target)
+    } else {
+        ("DOCKER_CONTEXT", target)
+    }
+}
+
+fn parse_env<T: FromStr>(key: &str, value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("Invalid {} {}: {}", key, value, e))
+}
+
+impl CdmSettings {
+    // of docker calls against the context or host, the one of the settings unless given
+    pub fn docker_env<'a>(&'a self, target: Option<&'a str>) -> Option<HashMap<&'a str, &'a str>> {
+        target
+            .or(self.context.as_deref())
+            .map(|target| HashMap::from([target_env(target)]))
+    }
+
+    // CDM_CONFIG names the file, if any
+    pub fn load() -> Result<Self, String> {
+        let mut settings = match std::env::var("CDM_CONFIG") {
+            Ok(path) => CdmSettings::from_file(Path::new(&path))?,
+            Err(_) => CdmSettings::default(),
+        };
+        settings.apply_env(std::env::vars())?;
+        Ok(settings)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = limits::read_limited(path, limits::MAX_SETTINGS_SIZE)?;
+        let settings: CdmSettings = toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        for category in settings.port_ranges.keys() {
+            Category::try_from(category.clone())
+                .map_err(|e| format!("Invalid port_ranges in {}: {}", path.display(), e))?;
+        }
+        for category in settings.connection_info.keys() {
+            Category::try_from(category.clone())
+                .map_err(|e| format!("Invalid connection_info in {}: {}", path.display(), e))?;
+        }
+        for category in settings.templates.keys() {
+            Category::try_from(category.clone())
+                .map_err(|e| format!("Invalid templates in {}: {}", path.display(), e))?;
+        }
+        Ok(settings)
+    }
+
+    pub fn apply_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), String> {
+        for (key, value) in vars {
+            match key.as_str() {
+                "CDM_DOCKER" => self.docker = value,
+                "CDM_COMPOSE" => {
+                    self.compose = value.split_whitespace().map(str::to_string).collect()
+                }
+                "CDM_BIND_ADDRESS" => self.bind_address = value,
+                "CDM_PUBLIC_HOST" => self.public_host = Some(value),
+                "CDM_DOMAIN" => self.domain = Some(value),
+                "CDM_PORT_RANGE" => self.port_range = Some(parse_env(&key, &value)?),
+                // web=30000-31999,pwn=32000-33999
+                "CDM_PORT_RANGES" => {
+                    for entry in value.split(',').filter(|entry| !entry.trim().is_empty()) {
+                        let (category, range) = entry.split_once('=').ok_or(format!(
+                            "Invalid {} {}, expected category=start-end",
+                            key, value
+                        ))?;
+                        let category = Category::try_from(category.to_string())?;
+                        self.port_ranges
+                            .insert(category.to_string(), parse_env(&key, range)?);
+                    }
+                }
+                "CDM_DOWN_TIMEOUT" => self.down_timeout = parse_env(&key, &value)?,
+                "CDM_READINESS_TIMEOUT" => self.readiness_timeout = parse_env(&key, &value)?,
+                "CDM_CONTEXT" => self.context = Some(value),
+                "CDM_HOSTS" => {
+                    self.hosts = value
+                        .split([',', ' '])
+                        .filter(|host| !host.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                "CDM_PROFILE" => self.profile = Some(value),
+                "CDM_ALLOW_HOST_HOOKS" => self.allow_host_hooks = parse_env(&key, &value)?,
+                "CDM_ENV_PASSTHROUGH" => {
+                    self.env_passthrough = value
+                        .split([',', ' '])
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                _ => {}
+            }
+        }
+        if self.compose.is_empty() {
+            return Err("CDM_COMPOSE is empty".to_string());
+        }
+        Ok(())
+    }
+
+    // where the published ports of a category go, names in port_ranges are spelled like in FloatCTF.toml
+    pub fn port_range_of(&self, category: Category) -> Option<PortRange> {
+        self.port_ranges
+            .iter()
+            .find(|(name, _)| Category::try_from(name.to_string()).ok() == Some(category))
+            .map(|(_, range)| *range)
+            .or(self.port_range)
+    }
+
+    pub fn template_of(&self, category: Category) -> Option<ChallengeTemplate> {
+        self.templates
+            .iter()
+            .find(|(name, _)| Category::try_from(name.to_string()).ok() == Some(category))
+            .map(|(_, template)| template.clone())
+    }
+
+    // the part of the host env compose invocations get, the variables cdm sets come on top
+    pub(crate) fn compose_env(
+        &self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Vec<(String, String)> {
+        let passed = |key: &str| {
+            HOST_ENV.contains(&key)
+                || self
+                    .env_passthrough
+                    .iter()
+                    .any(|pattern| match pattern.strip_suffix('*') {
+                        Some(prefix) => key.starts_with(prefix),
+                        None => pattern == key,
+                    })
+        };
+        vars.into_iter().filter(|(key, _)| passed(key)).collect()
+    }
+
+    // the program and leading arguments cdm runs in place of docker and docker-compose
+    pub(crate) fn resolve(&self, program: &str) -> (String, Vec<String>) {
+        match program {
+            "docker" => (self.docker.clone(), Vec::new()),
+            "docker-compose" => match self.compose.split_first() {
+                Some((program, args)) => (program.clone(), args.to_vec()),
+                None => (program.to_string(), Vec::new()),
+            },
+            _ => (program.to_string(), Vec::new()),
+        }
+    }
+}
+
+// loaded once per process, broken settings fail every caller instead of running with the defaults
+pub fn global() -> Result<Arc<CdmSettings>, String> {
+    static SETTINGS: OnceLock<Result<Arc<CdmSettings>, String>> = OnceLock::new();
+    SETTINGS
+        .get_or_init(|| CdmSettings::load().map(Arc::new))
+        .clone()
+}
+
+// what a manager read back from a state file has until restore hands it the loaded ones
+pub(crate) fn unloaded() -> Arc<CdmSettings> {
+    Arc::new(CdmSettings::default())
+}
+
+#[cfg(test)]
+mod test_settings {
+    use super::*;
+
+    #[test]
+    fn check_layers() {
+        let tmp = crate::testing::TestDir::new("settings").unwrap();
+        let dir = tmp.path.clone();
+        let path = dir.join("cdm.toml");
+        std::fs::write(
+            &path,
+            "compose = [\"docker\", \"compose\"]\ndown_timeout = 10\n",
+        )
+        .unwrap();
+
+        let mut settings = CdmSettings::from_file(&path).unwrap();
+        assert_eq!(settings.down_timeout, 10);
+        assert_eq!(settings.readiness_timeout, 60);
+
+        let env = [
+            ("CDM_DOWN_TIMEOUT", "5"),
+            ("CDM_PORT_RANGE", "30000-30100"),
+            ("CDM_HOSTS", "ssh://node1, ssh://node2"),
+            ("HOME", "/root"),
+        ];
+        settings
+            .apply_env(env.map(|(k, v)| (k.to_string(), v.to_string())))
+            .unwrap();
+        assert_eq!(settings.down_timeout, 5);
+        assert_eq!(settings.hosts, ["ssh://node1", "ssh://node2"]);
+        assert_eq!(
+            target_env(&settings.hosts[0]),
+            ("DOCKER_HOST", "ssh://node1")
+        );
+        assert_eq!(target_env("pwn-box"), ("DOCKER_CONTEXT", "pwn-box"));
+        assert_eq!(settings.docker_env(None), None);
+        settings.context = Some("pwn-box".to_string());
+        assert_eq!(
+            settings.docker_env(None),
+            Some(HashMap::from([("DOCKER_CONTEXT", "pwn-box")]))
+        );
+        assert_eq!(
+            settings.docker_env(Some("ssh://node1")),
+            Some(HashMap::from([("DOCKER_HOST", "ssh://node1")]))
+        );
+        settings.context = None;
+        assert_eq!(
+            settings.port_range,
+            Some(PortRange {
+                start: 30000,
+                end: 30100
+            })
+        );
+        assert_eq!(
+            settings.resolve("docker-compose"),
+            ("docker".to_string(), vec!["compose".to_string()])
+        );
+
+        let bad = [("CDM_DOWN_TIMEOUT".to_string(), "soon".to_string())];
+        assert!(settings.apply_env(bad).is_err());
+        assert!("2000-1000".parse::<PortRange>().is_err());
+    }
+
+    #[test]
+    fn check_port_ranges() {
+        let mut settings: CdmSettings = toml::from_str(
+            "port_range = { start = 40000, end = 40999 }\n[port_ranges]\nweb = { start = 30000, end = 31999 }\n",
+        )
+        .unwrap();
+        let env = [("CDM_PORT_RANGES", "pwnable=32000-33999")];
+        settings
+            .apply_env(env.map(|(k, v)| (k.to_string(), v.to_string())))
+            .unwrap();
+        assert_eq!(settings.port_range_of(Category::Web).unwrap().start, 30000);
+        assert_eq!(settings.port_range_of(Category::Pwn).unwrap().start, 32000);
+        assert_eq!(settings.port_range_of(Category::Misc).unwrap().start, 40000);
+
+        let bad = [("CDM_PORT_RANGES".to_string(), "forensics=1-2".to_string())];
+        assert!(settings.apply_env(bad).is_err());
+        assert!(
+            toml::from_str::<CdmSettings>("port_range = { start = 40000, end = 30000 }").is_err()
+        );
+        assert!(toml::from_str::<CdmSettings>("port_range = { start = 0, end = 30000 }").is_err());
+    }
+
+    #[test]
+    fn check_compose_env() {
+        let mut settings = CdmSettings::default();
+        let env = [("CDM_ENV_PASSTHROUGH", "HTTP_PROXY, REGISTRY_*")];
+        settings
+            .apply_env(env.map(|(k, v)| (k.to_string(), v.to_string())))
+            .unwrap();
+        let host = [
+            ("PATH", "/usr/bin"),
+            ("HTTP_PROXY", "http://proxy:3128"),
+            ("REGISTRY_MIRROR", "mirror.floatctf.org"),
+            ("AWS_SECRET_ACCESS_KEY", "hunter2"),
+            ("HTTPS_PROXY", "http://proxy:3128"),
+        ];
+        let keys: Vec<String> = settings
+            .compose_env(host.map(|(k, v)| (k.to_string(), v.to_string())))
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, ["PATH", "HTTP_PROXY", "REGISTRY_MIRROR"]);
+    }
+
+    #[test]
+    fn check_free_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let taken = listener.local_addr().unwrap().port();
+        let range = PortRange {
+            start: taken,
+            end: taken.saturating_add(1),
+        };
+        assert_ne!(range.free_port("127.0.0.1", 0), Some(taken));
+
+        // a port is only handed out once, even before anything listens on it
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let range = PortRange {
+            start: port,
+            end: port,
+        };
+        assert_eq!(range.peek_port("127.0.0.1", 0), Some(port));
+        assert_eq!(range.free_port("127.0.0.1", 0), Some(port));
+        assert_eq!(range.free_port("127.0.0.1", 0), None);
+        assert_eq!(range.peek_port("127.0.0.1", 0), None);
+
+        let inverted = PortRange {
+            start: 40000,
+            end: 30000,
+        };
+        assert_eq!(inverted.free_port("127.0.0.1", 0), None);
+
+        // instance ids are any u64
+        let wide = PortRange {
+            start: 40000,
+            end: 40999,
+        };
+        assert!(wide.peek_port("127.0.0.1", u64::MAX).is_some());
+    }
+}
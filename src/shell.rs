@@ -0,0 +1,174 @@
+use crate::ChallengeDockerManager;
+use crate::backend::InstanceStatus;
+use crate::error::CdmError;
+use crate::events::EventKind;
+use crate::plan::PlannedCommand;
+use serde::{Deserialize, Serialize};
+
+fn default_shell() -> String {
+    "/bin/sh".to_string()
+}
+
+// who gets a shell in a running instance, nobody unless enabled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShellPolicy {
+    pub enabled: bool,
+    // the authors allowed in, empty lets in whoever runs cdm
+    pub users: Vec<String>,
+    // the container user to exec as, the one of the image when unset
+    pub container_user: Option<String>,
+    pub shell: String,
+}
+
+impl Default for ShellPolicy {
+    fn default() -> Self {
+        ShellPolicy {
+            enabled: false,
+            users: Vec::new(),
+            container_user: None,
+            shell: default_shell(),
+        }
+    }
+}
+
+impl ShellPolicy {
+    pub fn allows(&self, user: &str) -> bool {
+        self.enabled && (self.users.is_empty() || self.users.iter().any(|u| u == user))
+    }
+}
+
+impl ChallengeDockerManager {
+    // checked and written to the event log before the terminal is handed over
+    fn shell_command(&self, user: &str, attach: bool) -> Result<PlannedCommand, CdmError> {
+        let policy = &self.settings().shell;
+        if !policy.allows(user) {
+            return Err(CdmError::ShellDenied {
+                challenge: self.challenge_docker_config.name.clone(),
+                user: user.to_string(),
+            });
+        }
+        let status = self.status()?;
+        if status != InstanceStatus::Running {
+            return Err(CdmError::NotRunning {
+                challenge: self.challenge_docker_config.name.clone(),
+                instance_id: self.id,
+                status,
+            });
+        }
+
+        let command = if attach {
+            // detaching must not stop the challenge, ctrl-c goes nowhere
+            PlannedCommand::new(
+                "docker",
+                &[
+                    "attach",
+                    "--sig-proxy=false",
+                    "--detach-keys",
+                    "ctrl-p,ctrl-q",
+                    &self.main_container_name,
+                ],
+            )
+        } else {
+            let mut command = PlannedCommand::new("docker", &["exec", "--interactive", "--tty"]);
+            if let Some(container_user) = &policy.container_user {
+                command = command.arg("--user").arg(container_user);
+            }
+            command.arg(&self.main_container_name).arg(&policy.shell)
+        }
+        .on(self.docker_context());
+        self.emit(EventKind::ShellOpened {
+            user: user.to_string(),
+            command: command.args.first().cloned().unwrap_or_default(),
+        });
+        Ok(command)
+    }
+
+    fn run_interactive(&self, command: &PlannedCommand) -> Result<i32, CdmError> {
+        let status = command
+            .process(self.settings())
+            .status()
+            .map_err(|e| format!("Failed to execute {}: {}", command.program, e))?;
+        Ok(status.code().unwrap_or(-1))
+    }
+
+    // a shell in the main container on this terminal, returns its exit code
+    pub fn open_shell(&self, user: &str) -> Result<i32, CdmError> {
+        self.run_interactive(&self.shell_command(user, false)?)
+    }
+
+    // the stdio of the main process itself, for challenges that talk on it
+    pub fn attach(&self, user: &str) -> Result<i32, CdmError> {
+        self.run_interactive(&self.shell_command(user, true)?)
+    }
+}
+
+#[cfg(test)]
+mod test_shell {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::events::EventLog;
+    use crate::settings::CdmSettings;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_shell_policy() {
+        let log = Arc::new(EventLog::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 1)
+            .with_backend(Arc::new(MockBackend::new()))
+            .with_event_sink(log.clone());
+        assert!(matches!(
+            cdm.shell_command("alice", false),
+            Err(CdmError::ShellDenied { .. })
+        ));
+
+        let settings = CdmSettings {
+            shell: ShellPolicy {
+                enabled: true,
+                users: vec!["alice".to_string()],
+                container_user: Some("root".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let cdm = cdm.with_settings(Arc::new(settings));
+        assert!(matches!(
+            cdm.shell_command("alice", false),
+            Err(CdmError::NotRunning { .. })
+        ));
+        cdm.up(&"flag{shell}".into()).unwrap();
+        assert!(matches!(
+            cdm.shell_command("mallory", false),
+            Err(CdmError::ShellDenied { .. })
+        ));
+        let command = cdm.shell_command("alice", false).unwrap();
+        assert_eq!(
+            command.args,
+            [
+                "exec",
+                "--interactive",
+                "--tty",
+                "--user",
+                "root",
+                "challenge-comment-1",
+                "/bin/sh"
+            ]
+        );
+        assert_eq!(cdm.shell_command("alice", true).unwrap().args[0], "attach");
+
+        let opened: Vec<EventKind> = log
+            .events()
+            .into_iter()
+            .map(|event| event.kind)
+            .filter(|kind| matches!(kind, EventKind::ShellOpened { .. }))
+            .collect();
+        assert_eq!(
+            opened[0],
+            EventKind::ShellOpened {
+                user: "alice".to_string(),
+                command: "exec".to_string(),
+            }
+        );
+        assert_eq!(opened.len(), 2);
+    }
+}
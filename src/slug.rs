@@ -0,0 +1,135 @@
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+// compose project names take [a-z0-9_-] and have to start with a letter or digit
+fn is_slug(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+// names docker takes stay as they are, lowercased like compose does, the others get a hash of
+// the name so "Web 1" and "web-1" differ
+pub fn slugify(name: &str) -> String {
+    let lowercase = name.to_lowercase();
+    if is_slug(&lowercase) {
+        return lowercase;
+    }
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let hash = hex::encode(Sha256::digest(name.as_bytes()));
+    match slug.trim_end_matches('-') {
+        // all CJK or emoji
+        "" => format!("c-{}", &hash[..8]),
+        slug => format!("{}-{}", slug, &hash[..8]),
+    }
+}
+
+// container names may keep their case and dots, compose files already name containers after them
+pub fn container_slug(name: &str) -> String {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        name.to_string()
+    } else {
+        slugify(name)
+    }
+}
+
+// the display name of every slug handed out in this process
+#[derive(Debug, Default)]
+pub struct SlugRegistry {
+    names: Mutex<BTreeMap<String, String>>,
+}
+
+impl SlugRegistry {
+    pub fn new() -> Self {
+        SlugRegistry::default()
+    }
+
+    // two names sharing a slug would share their containers, the second one is refused
+    pub fn register(&self, name: &str) -> Result<String, String> {
+        let slug = slugify(name);
+        let mut names = self.names.lock().unwrap_or_else(|e| e.into_inner());
+        match names.get(&slug) {
+            Some(other) if other != name => Err(format!(
+                "The challenge names {} and {} both map to {}, rename one of them",
+                other, name, slug
+            )),
+            Some(_) => Ok(slug),
+            None => {
+                names.insert(slug.clone(), name.to_string());
+                Ok(slug)
+            }
+        }
+    }
+
+    pub fn display_name(&self, slug: &str) -> Option<String> {
+        let names = self.names.lock().unwrap_or_else(|e| e.into_inner());
+        names.get(slug).cloned()
+    }
+}
+
+pub fn global() -> Arc<SlugRegistry> {
+    static REGISTRY: OnceLock<Arc<SlugRegistry>> = OnceLock::new();
+    REGISTRY
+        .get_or_init(|| Arc::new(SlugRegistry::new()))
+        .clone()
+}
+
+#[cfg(test)]
+mod test_slug {
+    use super::*;
+
+    #[test]
+    fn check_slugify() {
+        assert_eq!(slugify("comment"), "comment");
+        assert_eq!(slugify("baby_heap-2"), "baby_heap-2");
+        assert_eq!(slugify("Comment"), "comment");
+        assert_eq!(container_slug("Comment"), "Comment");
+        assert_eq!(container_slug("Baby Heap!"), slugify("Baby Heap!"));
+        let slug = slugify("Baby Heap!");
+        assert!(slug.starts_with("baby-heap-"));
+        assert_ne!(slug, slugify("baby heap"));
+        assert!(slugify("签到").starts_with("c-"));
+        assert!(slugify("🚩 Flag Shop").starts_with("flag-shop-"));
+        for name in ["Baby Heap!", "签到", "🚩 Flag Shop", "-x"] {
+            assert!(is_slug(&slugify(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn check_registry() {
+        let registry = SlugRegistry::new();
+        let slug = registry.register("Baby Heap").unwrap();
+        assert_eq!(registry.register("Baby Heap").unwrap(), slug);
+        assert_eq!(registry.display_name(&slug).as_deref(), Some("Baby Heap"));
+        registry
+            .names
+            .lock()
+            .unwrap()
+            .insert(slugify("web"), "Web".to_string());
+        assert!(registry.register("web").is_err());
+        assert!(registry.display_name("nobody").is_none());
+
+        let cdm = crate::ChallengeDockerManager::test_manager("Baby Heap", 1);
+        assert_eq!(
+            cdm.main_container_name,
+            format!("challenge-{}-1", container_slug("Baby Heap"))
+        );
+        assert_eq!(
+            cdm.docker_compose_project_name,
+            format!("challenge-project-1-{}", cdm.slug())
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use crate::ChallengeDockerManager;
+use crate::backend::Backend;
+use crate::id::InstanceId;
+use crate::repository::{Challenge, ChallengeRepository};
+use crate::timing::SpawnStage;
+use serde::{Deserialize, Serialize};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use strum::{AsRefStr, Display};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmokeOptions {
+    pub concurrency: usize,
+    // the readiness_timeout of the settings unless given
+    pub readiness_timeout: Option<u64>,
+    // also run the solver of the challenges that have a solve/ directory
+    pub solve: bool,
+    // smoke tests run before the event, so there are no team instances to collide with
+    pub instance_id: InstanceId,
+}
+
+impl Default for SmokeOptions {
+    fn default() -> Self {
+        SmokeOptions {
+            concurrency: 4,
+            readiness_timeout: None,
+            solve: true,
+            instance_id: InstanceId::new(0),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, AsRefStr, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SmokeStage {
+    Build,
+    Up,
+    Ready,
+    Solve,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageTiming {
+    pub stage: SmokeStage,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SmokeResult {
+    pub challenge: String,
+    pub passed: bool,
+    // the stage that failed
+    pub failed_stage: Option<SmokeStage>,
+    pub error: Option<String>,
+    pub port: Option<u64>,
+    pub timings: Vec<StageTiming>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SmokeReport {
+    pub results: Vec<SmokeResult>,
+    pub duration_ms: u64,
+}
+
+impl SmokeReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|result| result.passed)
+    }
+
+    pub fn failed(&self) -> Vec<&SmokeResult> {
+        self.results
+            .iter()
+            .filter(|result| !result.passed)
+            .collect()
+    }
+}
+
+fn elapsed_ms(started: Instant) -> u64 {
+    started.elapsed().as_millis() as u64
+}
+
+impl ChallengeDockerManager {
+    // wait until the mapped port accepts connections
+    pub fn wait_ready(&self, port: u64, timeout: u64) -> Result<(), String> {
+        let port = u16::try_from(port).map_err(|e| format!("Invalid port {}: {}", port, e))?;
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        let started = Instant::now();
+        let deadline = started + Duration::from_secs(timeout);
+        loop {
+            match TcpStream::connect_timeout(&addr, Duration::from_secs(1)) {
+                Ok(_) => {
+                    self.timings().record(
+                        &self.challenge_docker_config.name,
+                        SpawnStage::Ready,
+                        elapsed_ms(started),
+                    );
+                    return Ok(());
+                }
+                Err(e) if Instant::now() > deadline => {
+                    return Err(format!(
+                        "The {} is not ready on port {} after {}s: {}",
+                        self.challenge_docker_config.name, port, timeout, e
+                    ));
+                }
+                Err(_) => std::thread::sleep(Duration::from_millis(500)),
+            }
+        }
+    }
+
+    fn smoke_stages(
+        &self,
+        options: &SmokeOptions,
+        result: &mut SmokeResult,
+    ) -> Result<(), (SmokeStage, String)> {
+        let mut stage = |stage: SmokeStage, f: &mut dyn FnMut() -> Result<(), String>| {
+            let started = Instant::now();
+            let outcome = f();
+            result.timings.push(StageTiming {
+                stage,
+                duration_ms: elapsed_ms(started),
+            });
+            outcome.map_err(|e| (stage, e))
+        };
+
+        stage(SmokeStage::Build, &mut || self.build())?;
+
+        let flag = self.check_flag().map_err(|e| (SmokeStage::Up, e))?;
+        let mut port = 0;
+        stage(SmokeStage::Up, &mut || {
+            port = self.up(&flag)?;
+            Ok(())
+        })?;
+        result.port = Some(port);
+
+        // the stages after up must not leave the instance behind
+        let mut checks = || -> Result<(), (SmokeStage, String)> {
+            if self.challenge_docker_config.is_dockerd {
+                stage(SmokeStage::Ready, &mut || {
+                    self.wait_ready(
+                        port,
+                        options
+                            .readiness_timeout
+                            .unwrap_or(self.settings().readiness_timeout),
+                    )
+                })?;
+            }
+            if options.solve && self.solve_dir().is_dir() {
+                stage(SmokeStage::Solve, &mut || {
+                    let report = self.solve_instance(port, &flag)?;
+                    if !report.solved {
+                        return Err(format!(
+                            "The solver did not print the flag: {}",
+                            report.output
+                        ));
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(())
+        };
+        let checked = checks();
+        let down = stage(SmokeStage::Down, &mut || self.down());
+        checked.and(down)
+    }
+
+    pub fn smoke_test(&self, options: &SmokeOptions) -> SmokeResult {
+        let started = Instant::now();
+        let mut result = SmokeResult {
+            challenge: self.challenge_docker_config.name.clone(),
+            passed: false,
+            failed_stage: None,
+            error: None,
+            port: None,
+            timings: Vec::new(),
+            duration_ms: 0,
+        };
+        match self.smoke_stages(options, &mut result) {
+            Ok(()) => result.passed = true,
+            Err((stage, error)) => {
+                result.failed_stage = Some(stage);
+                result.error = Some(error);
+            }
+        }
+        result.duration_ms = elapsed_ms(started);
+        result
+    }
+}
+
+impl ChallengeRepository {
+    // build, up, probe, solve and down every challenge, running `concurrency` of them at once
+    pub fn smoke_test(&self, backend: Arc<dyn Backend>, options: &SmokeOptions) -> SmokeReport {
+        let started = Instant::now();
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<(usize, SmokeResult)>> = Mutex::new(Vec::new());
+
+        let smoke = |challenge: &Challenge| -> SmokeResult {
+            match ChallengeDockerManager::new(challenge.path.clone(), options.instance_id) {
+                Ok(cdm) => cdm.with_backend(backend.clone()).smoke_test(options),
+                Err(e) => SmokeResult {
+                    challenge: challenge.config.name.clone(),
+                    passed: false,
+                    failed_stage: Some(SmokeStage::Build),
+                    error: Some(e),
+                    port: None,
+                    timings: Vec::new(),
+                    duration_ms: 0,
+                },
+            }
+        };
+
+        std::thread::scope(|scope| {
+            for _ in 0..options.concurrency.clamp(1, self.challenges.len().max(1)) {
+                scope.spawn(|| {
+                    loop {
+                        let index = next.fetch_add(1, Ordering::SeqCst);
+                        let Some(challenge) = self.challenges.get(index) else {
+                            break;
+                        };
+                        let result = smoke(challenge);
+                        results
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .push((index, result));
+                    }
+                });
+            }
+        });
+
+        // keep the order of the repository regardless of which finished first
+        let mut results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+        results.sort_by_key(|(index, _)| *index);
+        SmokeReport {
+            results: results.into_iter().map(|(_, result)| result).collect(),
+            duration_ms: elapsed_ms(started),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_smoke {
+    use super::*;
+    use crate::backend::{MockBackend, MockOperation};
+    use std::net::TcpListener;
+
+    #[test]
+    fn check_smoke_test() {
+        let dir = crate::testing::TestDir::new("smoke").unwrap();
+        let root = dir.path.clone();
+        crate::testing::write_challenge(&root.join("web/comment"), "comment").unwrap();
+        crate::testing::write_challenge(&root.join("pwn/stack"), "stack").unwrap();
+        let repository = ChallengeRepository::open(&root).unwrap();
+
+        // something has to listen on the port the mock hands out
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port() as u64;
+        let mock = Arc::new(MockBackend::new());
+        mock.respond_up(port);
+        mock.fail_next(MockOperation::Build, "no space left on device");
+
+        let options = SmokeOptions {
+            concurrency: 1,
+            readiness_timeout: Some(1),
+            ..Default::default()
+        };
+        let report = repository.smoke_test(mock.clone(), &options);
+        assert!(!report.passed());
+        assert_eq!(report.results[0].challenge, "stack");
+        assert_eq!(report.results[0].failed_stage, Some(SmokeStage::Build));
+        assert!(report.results[1].passed);
+        assert_eq!(report.results[1].port, Some(port));
+        assert!(mock.running().is_empty());
+    }
+}
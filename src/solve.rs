@@ -0,0 +1,255 @@
+use crate::ChallengeDockerManager;
+use crate::flag::Flag;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// the [solve] table of FloatCTF.toml, without it the script in solve/ is run on the host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolveConfig {
+    // run the solver in this image with solve/ mounted at /solve
+    pub image: Option<String>,
+    #[serde(default)]
+    pub command: Vec<String>,
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+impl Default for SolveConfig {
+    fn default() -> Self {
+        SolveConfig {
+            image: None,
+            command: Vec::new(),
+            timeout: default_timeout(),
+        }
+    }
+}
+
+fn default_timeout() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolveReport {
+    pub challenge: String,
+    pub port: u64,
+    pub solved: bool,
+    pub duration_ms: u64,
+    // the tail of the solver output
+    pub output: String,
+}
+
+fn generate_flag(challenge: &str) -> Flag {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let seed = format!("{}-{}-{}", challenge, std::process::id(), nanos);
+    Flag::new(format!(
+        "flag{{cdm_solve_{}}}",
+        &hex::encode(Sha256::digest(seed))[..16]
+    ))
+}
+
+fn default_command(solve_dir: &Path) -> Result<Vec<String>, String> {
+    for (script, interpreter) in [("solve.py", "python3"), ("solve.sh", "sh")] {
+        if solve_dir.join(script).is_file() {
+            return Ok(vec![interpreter.to_string(), script.to_string()]);
+        }
+    }
+    if solve_dir.join("solve").is_file() {
+        return Ok(vec!["./solve".to_string()]);
+    }
+    Err(format!(
+        "{} has no solve.py, solve.sh or solve",
+        solve_dir.display()
+    ))
+}
+
+fn tail(output: &str) -> String {
+    let lines: Vec<&str> = output.lines().collect();
+    lines[lines.len().saturating_sub(20)..].join("\n")
+}
+
+fn run_solver(mut cmd: Command, timeout: u64) -> Result<String, String> {
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to execute the solver: {}", e))?;
+
+    // drain the pipes while waiting, a chatty solver would block on a full pipe
+    let mut stdout = child.stdout.take().ok_or("The solver has no stdout")?;
+    let mut stderr = child.stderr.take().ok_or("The solver has no stderr")?;
+    let stdout = std::thread::spawn(move || {
+        let mut buffer = String::new();
+        let _ = stdout.read_to_string(&mut buffer);
+        buffer
+    });
+    let stderr = std::thread::spawn(move || {
+        let mut buffer = String::new();
+        let _ = stderr.read_to_string(&mut buffer);
+        buffer
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(timeout);
+    let timed_out = loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break false,
+            Ok(None) if Instant::now() > deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break true;
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(100)),
+            Err(e) => return Err(format!("Failed to wait for the solver: {}", e)),
+        }
+    };
+
+    let mut output = stdout.join().unwrap_or_default();
+    output.push_str(&stderr.join().unwrap_or_default());
+    if timed_out {
+        return Err(format!(
+            "The solver timed out after {}s: {}",
+            timeout,
+            tail(&output)
+        ));
+    }
+    Ok(output)
+}
+
+impl ChallengeDockerManager {
+    pub fn solve_dir(&self) -> PathBuf {
+        self.challenge_path.join("solve")
+    }
+
+    // a fresh flag for dynamic challenges, so a solver printing a hardcoded one does not pass
+    pub(crate) fn check_flag(&self) -> Result<Flag, String> {
+        if self.challenge_docker_config.is_dynamic_flag {
+            Ok(generate_flag(&self.challenge_docker_config.name))
+        } else {
+            self.get_static_flag()
+        }
+    }
+
+    // spin up an instance, run the solver against it and check it prints the flag
+    pub fn check_solvable(&self) -> Result<SolveReport, String> {
+        let flag = self.check_flag()?;
+        let port = self.up(&flag)?;
+        let result = self.solve_instance(port, &flag);
+        // always tear down, the result of the solver is more interesting than this one
+        let down = self.down();
+        let report = result?;
+        down?;
+        Ok(report)
+    }
+
+    // run the solver against an instance that is already up with the flag
+    pub fn solve_instance(&self, port: u64, flag: &Flag) -> Result<SolveReport, String> {
+        let config = self
+            .challenge_docker_config
+            .solve
+            .clone()
+            .unwrap_or_default();
+        let solve_dir = self.solve_dir();
+        if !solve_dir.is_dir() {
+            return Err(format!(
+                "The {} has no solve directory",
+                self.challenge_docker_config.name
+            ));
+        }
+        let command = if config.command.is_empty() {
+            default_command(&solve_dir)?
+        } else {
+            config.command.clone()
+        };
+
+        let started = Instant::now();
+        let output = self.run_solve(&config, &command, &solve_dir, port)?;
+        Ok(SolveReport {
+            challenge: self.challenge_docker_config.name.clone(),
+            port,
+            solved: output.contains(flag.expose()),
+            duration_ms: started.elapsed().as_millis() as u64,
+            output: tail(&output),
+        })
+    }
+
+    // the solver gets HOST and PORT from the environment and as arguments
+    fn run_solve(
+        &self,
+        config: &SolveConfig,
+        command: &[String],
+        solve_dir: &Path,
+        port: u64,
+    ) -> Result<String, String> {
+        let host = "127.0.0.1";
+        let port = port.to_string();
+
+        let cmd = match &config.image {
+            Some(image) => {
+                // docker only bind mounts absolute paths
+                let solve_dir = solve_dir
+                    .canonicalize()
+                    .map_err(|e| format!("Failed to resolve {}: {}", solve_dir.display(), e))?;
+                let mut cmd = Command::new("docker");
+                cmd.args(["run", "--rm", "--network", "host"])
+                    .args(crate::labels::label_args(&self.labels()))
+                    .args(["--env", &format!("HOST={}", host)])
+                    .args(["--env", &format!("PORT={}", port)])
+                    .args(["--volume", &format!("{}:/solve:ro", solve_dir.display())])
+                    .args(["--workdir", "/solve", image])
+                    .args(command)
+                    .args([host, &port]);
+                cmd
+            }
+            None => {
+                let mut cmd = Command::new(&command[0]);
+                cmd.args(&command[1..])
+                    .args([host, &port])
+                    .current_dir(solve_dir)
+                    .env("HOST", host)
+                    .env("PORT", &port);
+                cmd
+            }
+        };
+        run_solver(cmd, config.timeout)
+    }
+}
+
+#[cfg(test)]
+mod test_solve {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::id::InstanceId;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_solvable_on_host() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+        std::fs::create_dir_all(root.join("solve")).unwrap();
+        std::fs::write(root.join(".env"), "FLAG=flag{static}\n").unwrap();
+
+        let mut cdm = ChallengeDockerManager::new(root.clone(), InstanceId::new(1))
+            .unwrap()
+            .with_backend(Arc::new(MockBackend::new()));
+        cdm.config_mut().is_dynamic_flag = false;
+
+        std::fs::write(
+            root.join("solve/solve.sh"),
+            "echo \"$1:$2\"; echo flag{static}\n",
+        )
+        .unwrap();
+        let report = cdm.check_solvable().unwrap();
+        assert!(report.solved);
+        assert!(report.output.starts_with("127.0.0.1:30000"));
+
+        std::fs::write(root.join("solve/solve.sh"), "echo flag{wrong}\n").unwrap();
+        assert!(!cdm.check_solvable().unwrap().solved);
+    }
+}
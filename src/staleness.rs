@@ -0,0 +1,188 @@
+use crate::ChallengeDockerManager;
+use crate::attachments::sha256_file;
+use crate::validate::ValidationReport;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// what the last successful build() saw, kept in <challenge>/.cdm/build.json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildRecord {
+    pub context_hash: String,
+    pub built_at: u64,
+    pub attachments: BTreeMap<String, String>,
+}
+
+fn context_files(dir: &Path, root: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+            .path();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        // the attachments are the output of the build, not its input
+        if relative == Path::new("attachments")
+            || relative == Path::new("FloatCTF.toml")
+            || relative.to_string_lossy().starts_with('.')
+        {
+            continue;
+        }
+        if path.is_dir() {
+            context_files(&path, root, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+impl ChallengeDockerManager {
+    fn build_context(&self) -> Result<Vec<PathBuf>, String> {
+        let mut files = Vec::new();
+        context_files(&self.challenge_path, &self.challenge_path, &mut files)?;
+        files.sort();
+        Ok(files)
+    }
+
+    pub fn build_context_hash(&self) -> Result<String, String> {
+        let mut hasher = Sha256::new();
+        for path in self.build_context()? {
+            let relative = path.strip_prefix(&self.challenge_path).unwrap_or(&path);
+            hasher.update(relative.to_string_lossy().as_bytes());
+            hasher.update(b"\0");
+            hasher.update(sha256_file(&path)?.as_bytes());
+            hasher.update(b"\n");
+        }
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    fn build_record_path(&self) -> PathBuf {
+        self.challenge_path.join(".cdm").join("build.json")
+    }
+
+    pub fn build_record(&self) -> Option<BuildRecord> {
+        let content = std::fs::read(self.build_record_path()).ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    pub(crate) fn record_build(&self) -> Result<(), String> {
+        let mut attachments = BTreeMap::new();
+        for attachment in &self.attachment_files {
+            if attachment.path.is_file() {
+                attachments.insert(attachment.name.clone(), sha256_file(&attachment.path)?);
+            }
+        }
+        let record = BuildRecord {
+            context_hash: self.build_context_hash()?,
+            built_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            attachments,
+        };
+
+        let path = self.build_record_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_vec_pretty(&record)
+            .map_err(|e| format!("Failed to serialize build record: {}", e))?;
+        std::fs::write(&path, json)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    // only flags probable mismatches, so everything ends up as a warning
+    pub(crate) fn validate_staleness(&self, report: &mut ValidationReport) {
+        let attachments_dir = self.attachments_dir();
+        let distributed: Vec<_> = self
+            .attachment_files
+            .iter()
+            .filter(|a| a.path.starts_with(&attachments_dir) && a.path.is_file())
+            .collect();
+        if distributed.is_empty() {
+            return;
+        }
+
+        if let Some(record) = self.build_record() {
+            match self.build_context_hash() {
+                Ok(hash) if hash != record.context_hash => report.warnings.push(
+                    "the build context changed since the last build, attachments may be stale"
+                        .to_string(),
+                ),
+                Ok(_) => {}
+                Err(e) => report.warnings.push(e),
+            }
+            for attachment in &distributed {
+                let Some(built) = record.attachments.get(&attachment.name) else {
+                    continue;
+                };
+                if sha256_file(&attachment.path).is_ok_and(|hash| &hash != built) {
+                    report.warnings.push(format!(
+                        "attachment {} differs from the one produced by the last build",
+                        attachment.name
+                    ));
+                }
+            }
+            return;
+        }
+
+        // never built here, fall back to comparing modification times
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let Ok(context) = self.build_context() else {
+            return;
+        };
+        let Some((newest, newest_source)) = context
+            .iter()
+            .filter_map(|path| Some((modified(path)?, path)))
+            .max()
+        else {
+            return;
+        };
+        for attachment in distributed {
+            if modified(&attachment.path).is_some_and(|time| time < newest) {
+                let source = newest_source
+                    .strip_prefix(&self.challenge_path)
+                    .unwrap_or(newest_source);
+                report.warnings.push(format!(
+                    "attachment {} is older than {}, it was probably not regenerated",
+                    attachment.name,
+                    source.display()
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_staleness {
+    use super::*;
+    use crate::id::InstanceId;
+    use crate::validate::ValidationPolicy;
+
+    #[test]
+    fn check_build_record() {
+        let challenge = crate::testing::TestChallenge::new("staleness").unwrap();
+        let dir = challenge.path.clone();
+        std::fs::write(dir.join("Dockerfile"), "FROM alpine").unwrap();
+        std::fs::write(dir.join("attachments/chall"), b"\x7fELF").unwrap();
+
+        let mut cdm = ChallengeDockerManager::new(dir.clone(), InstanceId::new(1)).unwrap();
+        cdm.config_mut().attachments = vec!["chall".into()];
+        cdm.refresh_attachments().unwrap();
+        cdm.record_build().unwrap();
+        assert!(
+            cdm.validate(&ValidationPolicy::default())
+                .warnings
+                .is_empty()
+        );
+
+        std::fs::write(dir.join("Dockerfile"), "FROM ubuntu").unwrap();
+        std::fs::write(dir.join("attachments/chall"), b"\x7fELF2").unwrap();
+        let warnings = cdm.validate(&ValidationPolicy::default()).warnings;
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::ChallengeDockerManager;
+use crate::backend::{Backend, InstanceStatus, PortMapping};
+use crate::dto::DTO_VERSION;
+use crate::flag::Flag;
+use crate::settings;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn dto_version() -> u32 {
+    DTO_VERSION
+}
+
+// everything needed to pick an instance up again after the platform restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceState {
+    #[serde(default = "dto_version")]
+    pub version: u32,
+    pub manager: ChallengeDockerManager,
+    pub status: InstanceStatus,
+    #[serde(default)]
+    pub ports: Vec<PortMapping>,
+    #[serde(default)]
+    pub created_at: Option<u64>,
+    // sha256 of the injected flag, the flag itself is never written down
+    #[serde(default)]
+    pub flag_sha256: Option<String>,
+}
+
+pub fn flag_sha256(flag: &str) -> String {
+    hex::encode(Sha256::digest(flag.as_bytes()))
+}
+
+impl InstanceState {
+    pub fn with_created_at(mut self, created_at: u64) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    pub fn with_flag(mut self, flag: &Flag) -> Self {
+        self.flag_sha256 = Some(flag_sha256(flag.expose()));
+        self
+    }
+
+    pub fn is_flag(&self, flag: &str) -> bool {
+        self.flag_sha256
+            .as_deref()
+            .is_some_and(|hash| hash == flag_sha256(flag))
+    }
+
+    // the backend and the settings are not serialized, the restored manager gets the one it is
+    // handed and the loaded settings
+    pub fn restore(&self, backend: Arc<dyn Backend>) -> Result<ChallengeDockerManager, String> {
+        Ok(self
+            .manager
+            .clone()
+            .with_backend(backend)
+            .with_settings(settings::global()?))
+    }
+}
+
+impl ChallengeDockerManager {
+    // ask the backend what the instance looks like right now
+    pub fn state(&self) -> Result<InstanceState, String> {
+        let status = self.status()?;
+        let ports = match status {
+            InstanceStatus::Running | InstanceStatus::Paused
+                if self.challenge_docker_config.is_dockerd =>
+            {
+                self.backend().ports(self)?
+            }
+            _ => Vec::new(),
+        };
+        Ok(InstanceState {
+            version: DTO_VERSION,
+            manager: self.clone(),
+            status,
+            ports,
+            created_at: None,
+            flag_sha256: None,
+        })
+    }
+
+    // the state right after up, stamped with the current time
+    pub fn up_state(&self, flag: &Flag) -> Result<InstanceState, String> {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Ok(self.state()?.with_created_at(created_at).with_flag(flag))
+    }
+}
+
+#[cfg(test)]
+mod test_state {
+    use super::*;
+    use crate::backend::MockBackend;
+
+    #[test]
+    fn check_round_trip() {
+        let mock = Arc::new(MockBackend::new());
+        let cdm = ChallengeDockerManager::test_manager("comment", 4).with_backend(mock.clone());
+        let flag = Flag::from("flag{state}");
+        let port = cdm.up(&flag).unwrap();
+        let state = cdm.up_state(&flag).unwrap();
+        assert_eq!(state.status, InstanceStatus::Running);
+        assert_eq!(state.ports[0].host_port, port);
+        assert!(state.created_at.is_some());
+
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(!json.contains("flag{state}"));
+        let back: InstanceState = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+        assert!(back.is_flag("flag{state}"));
+        assert!(!back.is_flag("flag{other}"));
+
+        // the restored manager drives the same instance
+        let restored = back.restore(mock.clone()).unwrap();
+        assert_eq!(
+            restored.docker_compose_project_name,
+            cdm.docker_compose_project_name
+        );
+        restored.down().unwrap();
+        assert!(mock.running().is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+mod local;
+mod s3;
+mod webdav;
+
+pub use local::LocalStore;
+pub use s3::S3Store;
+pub use webdav::WebDavStore;
+
+use crate::ChallengeDockerManager;
+use crate::attachments::{AttachmentPackage, sha256_file};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::path::Path;
+
+// files at least this large are uploaded in resumable chunks
+pub const CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishedAttachment {
+    pub filename: String,
+    pub key: String,
+    pub sha256: String,
+    pub url: String,
+}
+
+// wherever the event keeps its downloads
+pub trait AttachmentStore: Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn prefix(&self) -> &str;
+
+    fn exists(&self, key: &str) -> Result<bool, String>;
+
+    // large files are uploaded in chunks and an interrupted upload picks up where it stopped
+    fn put(&self, key: &str, path: &Path) -> Result<(), String>;
+
+    fn url(&self, key: &str) -> Result<String, String>;
+
+    // content addressed, so an unchanged file always maps to the same key
+    fn key(&self, filename: &str, sha256: &str) -> String {
+        let prefix = self.prefix().trim_matches('/');
+        if prefix.is_empty() {
+            format!("{}/{}", sha256, filename)
+        } else {
+            format!("{}/{}/{}", prefix, sha256, filename)
+        }
+    }
+
+    fn publish(&self, path: &Path) -> Result<PublishedAttachment, String> {
+        let filename = path
+            .file_name()
+            .ok_or(format!("{} is not a file", path.display()))?
+            .to_string_lossy()
+            .to_string();
+        let sha256 = sha256_file(path)?;
+        let key = self.key(&filename, &sha256);
+
+        if !self.exists(&key)? {
+            self.put(&key, path)?;
+        }
+
+        Ok(PublishedAttachment {
+            url: self.url(&key)?,
+            filename,
+            key,
+            sha256,
+        })
+    }
+}
+
+impl ChallengeDockerManager {
+    // package the attachments and publish the archive and its manifest
+    pub fn publish_attachments(
+        &self,
+        store: &dyn AttachmentStore,
+        out_dir: &Path,
+    ) -> Result<Option<(AttachmentPackage, PublishedAttachment)>, String> {
+        let Some(package) = self.package_attachments(out_dir)? else {
+            return Ok(None);
+        };
+        let published = store.publish(&package.archive)?;
+        store.publish(&package.archive.with_extension("json"))?;
+        Ok(Some((package, published)))
+    }
+}
@@ -0,0 +1,114 @@
+use super::{AttachmentStore, CHUNK_SIZE};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// a directory served by something else, e.g. nginx or the attachment server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalStore {
+    pub root: PathBuf,
+    pub prefix: String,
+    pub public_base_url: String,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>, public_base_url: &str) -> Self {
+        LocalStore {
+            root: root.into(),
+            prefix: String::new(),
+            public_base_url: public_base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl AttachmentStore for LocalStore {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, String> {
+        Ok(self.root.join(key).is_file())
+    }
+
+    fn put(&self, key: &str, path: &Path) -> Result<(), String> {
+        let target = self.root.join(key);
+        if let Some(dir) = target.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+
+        // keys are content addressed, so a leftover partial file holds a prefix of the same content
+        let partial = target.with_extension("partial");
+        let mut output = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&partial)
+            .map_err(|e| format!("Failed to open {}: {}", partial.display(), e))?;
+        let done = output
+            .metadata()
+            .map_err(|e| format!("Failed to read {}: {}", partial.display(), e))?
+            .len();
+
+        let mut input =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        input
+            .seek(SeekFrom::Start(done))
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        let mut buffer = vec![0; (CHUNK_SIZE as usize).min(8 * 1024 * 1024)];
+        loop {
+            let n = input
+                .read(&mut buffer)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            if n == 0 {
+                break;
+            }
+            output
+                .write_all(&buffer[..n])
+                .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+        }
+        output
+            .sync_all()
+            .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+
+        std::fs::rename(&partial, &target)
+            .map_err(|e| format!("Failed to write {}: {}", target.display(), e))
+    }
+
+    fn url(&self, key: &str) -> Result<String, String> {
+        Ok(format!("{}/{}", self.public_base_url, key))
+    }
+}
+
+#[cfg(test)]
+mod test_local {
+    use super::*;
+
+    #[test]
+    fn check_resume() {
+        let dir = crate::testing::TestDir::new("store").unwrap();
+        let root = dir.path.clone();
+        let source = root.join("image.dd");
+        std::fs::write(&source, b"0123456789").unwrap();
+
+        let store = LocalStore::new(root.join("public"), "https://files.ctf.local/");
+        let published = store.publish(&source).unwrap();
+        assert_eq!(
+            published.url,
+            format!("https://files.ctf.local/{}/image.dd", published.sha256)
+        );
+
+        // an interrupted upload leaves a partial file behind that is completed later
+        let key = store.key("image.dd", &published.sha256);
+        let target = root.join("public").join(&key);
+        std::fs::remove_file(&target).unwrap();
+        std::fs::write(target.with_extension("partial"), b"01234").unwrap();
+        store.put(&key, &source).unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"0123456789");
+    }
+}
@@ -0,0 +1,260 @@
+use super::{AttachmentStore, CHUNK_SIZE};
+use crate::ChallengeDockerManager;
+use crate::settings;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3Store {
+    pub bucket: String,
+    pub prefix: String,
+    // set for MinIO and other S3 compatible storage
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    // public objects are linked directly, otherwise a presigned url is returned
+    pub public_base_url: Option<String>,
+    pub presign_expiry: u64,
+}
+
+impl S3Store {
+    pub fn new(bucket: &str) -> Self {
+        S3Store {
+            bucket: bucket.to_string(),
+            prefix: "attachments".to_string(),
+            endpoint: None,
+            region: None,
+            public_base_url: None,
+            presign_expiry: 7 * 24 * 3600,
+        }
+    }
+
+    fn aws(&self, args: &[&str]) -> Result<Vec<u8>, String> {
+        let settings = settings::global()?;
+        let mut full_args = args.to_vec();
+        if let Some(endpoint) = &self.endpoint {
+            full_args.extend(["--endpoint-url", endpoint]);
+        }
+        if let Some(region) = &self.region {
+            full_args.extend(["--region", region]);
+        }
+        Ok(ChallengeDockerManager::run_command(
+            &settings, "aws", &full_args, None,
+        )?)
+    }
+
+    fn aws_json(&self, args: &[&str]) -> Result<Value, String> {
+        let output = self.aws(&[args, &["--output", "json"]].concat())?;
+        if output.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Value::Null);
+        }
+        serde_json::from_slice(&output).map_err(|e| format!("Failed to parse aws output: {}", e))
+    }
+
+    // resumes the pending multipart upload of the key if there is one
+    fn put_multipart(&self, key: &str, path: &Path) -> Result<(), String> {
+        let uploads = self.aws_json(&[
+            "s3api",
+            "list-multipart-uploads",
+            "--bucket",
+            &self.bucket,
+            "--prefix",
+            key,
+        ])?;
+        let pending = uploads["Uploads"].as_array().and_then(|uploads| {
+            uploads
+                .iter()
+                .find(|upload| upload["Key"] == key)
+                .and_then(|upload| upload["UploadId"].as_str().map(str::to_string))
+        });
+        let upload_id = match pending {
+            Some(upload_id) => upload_id,
+            None => self.aws_json(&[
+                "s3api",
+                "create-multipart-upload",
+                "--bucket",
+                &self.bucket,
+                "--key",
+                key,
+            ])?["UploadId"]
+                .as_str()
+                .ok_or("create-multipart-upload returned no UploadId")?
+                .to_string(),
+        };
+
+        let listed = self.aws_json(&[
+            "s3api",
+            "list-parts",
+            "--bucket",
+            &self.bucket,
+            "--key",
+            key,
+            "--upload-id",
+            &upload_id,
+        ])?;
+        let mut parts: Vec<(u64, String)> = listed["Parts"]
+            .as_array()
+            .map(|parts| {
+                parts
+                    .iter()
+                    .filter_map(|part| {
+                        Some((
+                            part["PartNumber"].as_u64()?,
+                            part["ETag"].as_str()?.to_string(),
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let size = std::fs::metadata(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+            .len();
+        let mut input =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let chunk_file = std::env::temp_dir().join(format!(
+            "cdm-s3-{}-{}.part",
+            std::process::id(),
+            key.replace('/', "_")
+        ));
+
+        let count = size.div_ceil(CHUNK_SIZE);
+        for number in 1..=count {
+            if parts.iter().any(|(n, _)| *n == number) {
+                continue;
+            }
+            let mut chunk = Vec::new();
+            input
+                .seek(SeekFrom::Start((number - 1) * CHUNK_SIZE))
+                .and_then(|_| (&mut input).take(CHUNK_SIZE).read_to_end(&mut chunk))
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            std::fs::write(&chunk_file, &chunk)
+                .map_err(|e| format!("Failed to write {}: {}", chunk_file.display(), e))?;
+
+            let uploaded = self.aws_json(&[
+                "s3api",
+                "upload-part",
+                "--bucket",
+                &self.bucket,
+                "--key",
+                key,
+                "--upload-id",
+                &upload_id,
+                "--part-number",
+                &number.to_string(),
+                "--body",
+                &chunk_file.to_string_lossy(),
+            ]);
+            let _ = std::fs::remove_file(&chunk_file);
+            let etag = uploaded?["ETag"]
+                .as_str()
+                .ok_or("upload-part returned no ETag")?
+                .to_string();
+            parts.push((number, etag));
+        }
+
+        parts.sort();
+        let manifest = json!({
+            "Parts": parts
+                .iter()
+                .map(|(number, etag)| json!({"PartNumber": number, "ETag": etag}))
+                .collect::<Vec<_>>(),
+        });
+        self.aws(&[
+            "s3api",
+            "complete-multipart-upload",
+            "--bucket",
+            &self.bucket,
+            "--key",
+            key,
+            "--upload-id",
+            &upload_id,
+            "--multipart-upload",
+            &manifest.to_string(),
+        ])?;
+        Ok(())
+    }
+}
+
+impl AttachmentStore for S3Store {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, String> {
+        match self.aws(&[
+            "s3api",
+            "head-object",
+            "--bucket",
+            &self.bucket,
+            "--key",
+            key,
+        ]) {
+            Ok(_) => Ok(true),
+            Err(e) if e.contains("404") || e.contains("Not Found") => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn put(&self, key: &str, path: &Path) -> Result<(), String> {
+        let size = std::fs::metadata(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+            .len();
+        if size >= CHUNK_SIZE {
+            return self.put_multipart(key, path);
+        }
+        self.aws(&[
+            "s3",
+            "cp",
+            &path.to_string_lossy(),
+            &format!("s3://{}/{}", self.bucket, key),
+            "--only-show-errors",
+        ])?;
+        Ok(())
+    }
+
+    fn url(&self, key: &str) -> Result<String, String> {
+        if let Some(base) = &self.public_base_url {
+            return Ok(format!("{}/{}", base.trim_end_matches('/'), key));
+        }
+        let output = self.aws(&[
+            "s3",
+            "presign",
+            &format!("s3://{}/{}", self.bucket, key),
+            "--expires-in",
+            &self.presign_expiry.to_string(),
+        ])?;
+        let url =
+            String::from_utf8(output).map_err(|e| format!("Invalid UTF-8 in output: {}", e))?;
+        Ok(url.trim().to_string())
+    }
+}
+
+#[cfg(test)]
+mod test_s3 {
+    use super::*;
+
+    #[test]
+    fn check_key() {
+        let mut store = S3Store::new("ctf");
+        assert_eq!(store.key("chall.zip", "abcd"), "attachments/abcd/chall.zip");
+        store.prefix = String::new();
+        assert_eq!(store.key("chall.zip", "abcd"), "abcd/chall.zip");
+    }
+
+    #[test]
+    fn check_public_url() {
+        let mut store = S3Store::new("ctf");
+        store.public_base_url = Some("https://files.ctf.local/".to_string());
+        assert_eq!(
+            store.url("attachments/abcd/chall.zip").unwrap(),
+            "https://files.ctf.local/attachments/abcd/chall.zip"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use super::AttachmentStore;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebDavStore {
+    // collection the keys are created below, e.g. https://dav.ctf.local/remote.php/dav/files/ctf
+    pub url: String,
+    pub prefix: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // defaults to the WebDAV url itself
+    pub public_base_url: Option<String>,
+    pub max_retries: u32,
+}
+
+impl WebDavStore {
+    pub fn new(url: &str) -> Self {
+        WebDavStore {
+            url: url.trim_end_matches('/').to_string(),
+            prefix: "attachments".to_string(),
+            username: None,
+            password: None,
+            public_base_url: None,
+            max_retries: 3,
+        }
+    }
+
+    fn authorization(&self) -> Option<String> {
+        let username = self.username.as_ref()?;
+        let credentials = format!("{}:{}", username, self.password.as_deref().unwrap_or(""));
+        Some(format!("Basic {}", STANDARD.encode(credentials)))
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::http::request::Builder {
+        let mut builder = ureq::http::Request::builder().method(method).uri(url);
+        if let Some(authorization) = self.authorization() {
+            builder = builder.header("Authorization", authorization);
+        }
+        builder
+    }
+
+    // PUT needs every parent collection to exist
+    fn create_collections(&self, key: &str) -> Result<(), String> {
+        let mut collection = self.url.clone();
+        let parents: Vec<&str> = key.split('/').collect();
+        for parent in &parents[..parents.len().saturating_sub(1)] {
+            collection = format!("{}/{}", collection, parent);
+            let request = self
+                .request("MKCOL", &collection)
+                .body(())
+                .map_err(|e| e.to_string())?;
+            match ureq::run(request) {
+                // 405 means the collection already exists
+                Ok(_) | Err(ureq::Error::StatusCode(405)) => {}
+                Err(e) => return Err(format!("Failed to create {}: {}", collection, e)),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AttachmentStore for WebDavStore {
+    fn name(&self) -> &'static str {
+        "webdav"
+    }
+
+    fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, String> {
+        let url = format!("{}/{}", self.url, key);
+        let request = self
+            .request("HEAD", &url)
+            .body(())
+            .map_err(|e| e.to_string())?;
+        match ureq::run(request) {
+            Ok(_) => Ok(true),
+            Err(ureq::Error::StatusCode(404)) => Ok(false),
+            Err(e) => Err(format!("Failed to check {}: {}", url, e)),
+        }
+    }
+
+    // the file is streamed rather than buffered, a failed upload is retried from the start
+    fn put(&self, key: &str, path: &Path) -> Result<(), String> {
+        self.create_collections(key)?;
+        let url = format!("{}/{}", self.url, key);
+
+        let mut attempt = 0;
+        loop {
+            let file = File::open(path)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+            let request = self
+                .request("PUT", &url)
+                .body(file)
+                .map_err(|e| e.to_string())?;
+            match ureq::run(request) {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt >= self.max_retries => {
+                    return Err(format!("Failed to upload {}: {}", url, e));
+                }
+                Err(_) => attempt += 1,
+            }
+        }
+    }
+
+    fn url(&self, key: &str) -> Result<String, String> {
+        let base = self.public_base_url.as_deref().unwrap_or(&self.url);
+        Ok(format!("{}/{}", base.trim_end_matches('/'), key))
+    }
+}
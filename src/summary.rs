@@ -0,0 +1,222 @@
+use crate::ChallengeDockerManager;
+use crate::backend::InstanceStatus;
+use crate::compose::ComposeFile;
+use crate::dto::InstanceRecord;
+use crate::id::InstanceId;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceSummary {
+    pub challenge: String,
+    pub category: String,
+    pub instance_id: InstanceId,
+    pub status: InstanceStatus,
+    pub host: String,
+    pub port: Option<u64>,
+    pub dynamic_flag: bool,
+    pub created_at: Option<u64>,
+    pub expires_at: Option<u64>,
+    // as written in the compose file, e.g. mem 256m
+    pub limits: Vec<String>,
+    pub maintenance: Option<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// 1h 5m, 5m 12s or 12s
+pub fn human_duration(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, _) => format!("{}m {}s", minutes, seconds % 60),
+        (0, _, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+fn yaml_scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+impl InstanceSummary {
+    pub fn with_host(mut self, host: &str) -> Self {
+        self.host = host.to_string();
+        self
+    }
+
+    // take the times the platform keeps
+    pub fn with_record(mut self, record: &InstanceRecord) -> Self {
+        self.created_at = record.created_at;
+        self.expires_at = record.expires_at;
+        self
+    }
+}
+
+impl fmt::Display for InstanceSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let now = now();
+        writeln!(
+            f,
+            "{} #{} ({})",
+            self.challenge, self.instance_id, self.category
+        )?;
+        writeln!(f, "  status   {}", self.status)?;
+        match self.port {
+            Some(port) => writeln!(f, "  address  {}:{}", self.host, port)?,
+            None => writeln!(f, "  address  -")?,
+        }
+        let flag = if self.dynamic_flag {
+            "dynamic"
+        } else {
+            "static"
+        };
+        writeln!(f, "  flag     {}", flag)?;
+        if let Some(created_at) = self.created_at {
+            writeln!(
+                f,
+                "  age      {}",
+                human_duration(now.saturating_sub(created_at))
+            )?;
+        }
+        if let Some(expires_at) = self.expires_at {
+            if expires_at > now {
+                writeln!(f, "  expires  in {}", human_duration(expires_at - now))?;
+            } else {
+                writeln!(f, "  expires  {} ago", human_duration(now - expires_at))?;
+            }
+        }
+        if !self.limits.is_empty() {
+            writeln!(f, "  limits   {}", self.limits.join(", "))?;
+        }
+        if let Some(reason) = &self.maintenance {
+            writeln!(f, "  notice   under maintenance, {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+// one line, without asking docker anything
+impl fmt::Display for ChallengeDockerManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let config = &self.challenge_docker_config;
+        let kind = match (config.is_dockerd, config.is_dynamic_flag) {
+            (false, _) => "static files",
+            (true, true) => "dynamic flag",
+            (true, false) => "static flag",
+        };
+        write!(
+            f,
+            "{} #{} ({}, {} points, {}",
+            config.name, self.id, config.category, config.points, kind
+        )?;
+        if let Some(info) = self.maintenance() {
+            write!(f, ", maintenance: {}", info.reason)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl ChallengeDockerManager {
+    // the resource limits of the main service
+    fn limits(&self) -> Vec<String> {
+        let Ok(compose) = ComposeFile::load(&self.docker_compose_yml) else {
+            return Vec::new();
+        };
+        let Some((_, service)) = compose.main_service(&self.main_container_name, self.id) else {
+            return Vec::new();
+        };
+        [
+            ("mem", &service.mem_limit),
+            ("cpus", &service.cpus),
+            ("pids", &service.pids_limit),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some(format!("{} {}", name, yaml_scalar(value.as_ref()?)?)))
+        .collect()
+    }
+
+    pub fn summary(&self) -> Result<InstanceSummary, String> {
+        let status = self.status()?;
+        let port = match status {
+            InstanceStatus::Running | InstanceStatus::Paused
+                if self.challenge_docker_config.is_dockerd =>
+            {
+                self.backend()
+                    .ports(self)?
+                    .first()
+                    .map(|mapping| mapping.host_port)
+            }
+            _ => None,
+        };
+        Ok(InstanceSummary {
+            challenge: self.challenge_docker_config.name.clone(),
+            category: self.challenge_docker_config.category.to_string(),
+            instance_id: self.id,
+            status,
+            host: self.settings().bind_address.clone(),
+            port,
+            dynamic_flag: self.challenge_docker_config.is_dynamic_flag,
+            created_at: None,
+            expires_at: None,
+            limits: self.limits(),
+            maintenance: self.maintenance().map(|info| info.reason),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_summary {
+    use super::*;
+    use crate::backend::MockBackend;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_human_duration() {
+        assert_eq!(human_duration(12), "12s");
+        assert_eq!(human_duration(312), "5m 12s");
+        assert_eq!(human_duration(3900), "1h 5m");
+        assert_eq!(human_duration(90000), "1d 1h");
+    }
+
+    #[test]
+    fn check_summary() {
+        let challenge = crate::testing::TestChallenge::new("comment").unwrap();
+        let root = challenge.path.clone();
+        let compose = root.join("docker-compose.yml");
+        let content = std::fs::read_to_string(&compose).unwrap();
+        std::fs::write(&compose, format!("{}    mem_limit: 256m\n", content)).unwrap();
+
+        let cdm = ChallengeDockerManager::new(root.clone(), InstanceId::new(3))
+            .unwrap()
+            .with_backend(Arc::new(MockBackend::new()));
+        assert_eq!(
+            cdm.to_string(),
+            "comment #3 (Misc, 100 points, dynamic flag)"
+        );
+
+        let port = cdm.up(&"flag{summary}".into()).unwrap();
+        let record = InstanceRecord::new(&cdm, InstanceStatus::Running, Some(port))
+            .with_created_at(now() - 312);
+        let summary = cdm
+            .summary()
+            .unwrap()
+            .with_host("ctf.local")
+            .with_record(&record);
+        let printed = summary.to_string();
+        assert!(printed.starts_with("comment #3 (Misc)\n  status   Running\n"));
+        assert!(printed.contains(&format!("  address  ctf.local:{}\n", port)));
+        assert!(printed.contains("  age      5m 1"));
+        assert!(printed.contains("  limits   mem 256m\n"));
+    }
+}
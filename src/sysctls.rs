@@ -0,0 +1,115 @@
+use crate::ChallengeDockerManager;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+// what the kernel keeps per network or ipc namespace, everything else would change the host
+const NAMESPACED: [&str; 5] = [
+    "net.",
+    "kernel.shm",
+    "kernel.msg",
+    "kernel.sem",
+    "fs.mqueue.",
+];
+
+// safe to hand any challenge, a trailing * allows the whole subtree
+pub fn default_allowlist() -> Vec<String> {
+    [
+        "kernel.shm_rmid_forced",
+        "kernel.msgmax",
+        "kernel.msgmnb",
+        "kernel.sem",
+        "fs.mqueue.*",
+        "net.ipv4.ip_local_port_range",
+        "net.ipv4.ip_unprivileged_port_start",
+        "net.ipv4.ping_group_range",
+        "net.ipv4.tcp_syncookies",
+        "net.ipv4.tcp_fin_timeout",
+        "net.ipv4.tcp_keepalive_*",
+        "net.ipv4.conf.all.route_localnet",
+        "net.ipv6.conf.all.disable_ipv6",
+    ]
+    .iter()
+    .map(|sysctl| sysctl.to_string())
+    .collect()
+}
+
+fn allowed(allowlist: &[String], sysctl: &str) -> bool {
+    allowlist
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => sysctl.starts_with(prefix),
+            None => pattern == sysctl,
+        })
+}
+
+// one message per sysctl docker cannot or the operator will not set
+pub fn violations(sysctls: &BTreeMap<String, String>, allowlist: &[String]) -> Vec<String> {
+    sysctls
+        .keys()
+        .filter_map(|sysctl| {
+            if !NAMESPACED.iter().any(|prefix| sysctl.starts_with(prefix)) {
+                Some(format!(
+                    "sysctl {} is not namespaced, docker cannot set it for one container",
+                    sysctl
+                ))
+            } else if !allowed(allowlist, sysctl) {
+                Some(format!("sysctl {} is not in the allowlist", sysctl))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+impl ChallengeDockerManager {
+    pub(crate) fn sysctl_violations(&self) -> Vec<String> {
+        violations(
+            &self.challenge_docker_config.sysctls,
+            &self.settings().allowed_sysctls,
+        )
+    }
+
+    // the sysctls of the main service, None when the FloatCTF.toml sets none
+    pub(crate) fn sysctls_override(&self, service: &str) -> Result<Option<Value>, String> {
+        let sysctls = &self.challenge_docker_config.sysctls;
+        if sysctls.is_empty() {
+            return Ok(None);
+        }
+        let violations = self.sysctl_violations();
+        if !violations.is_empty() {
+            return Err(format!(
+                "The {} sets sysctls it may not: {}",
+                self.challenge_docker_config.name,
+                violations.join("; ")
+            ));
+        }
+        let mut services = serde_json::Map::new();
+        services.insert(service.to_string(), json!({ "sysctls": sysctls }));
+        Ok(Some(json!({ "services": services })))
+    }
+}
+
+#[cfg(test)]
+mod test_sysctls {
+    use super::*;
+
+    #[test]
+    fn check_violations() {
+        let sysctls: BTreeMap<String, String> = [
+            ("kernel.yama.ptrace_scope", "0"),
+            ("kernel.randomize_va_space", "0"),
+            ("net.ipv4.tcp_keepalive_time", "60"),
+            ("net.ipv4.ip_forward", "1"),
+            ("fs.mqueue.msg_max", "64"),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+        let violations = violations(&sysctls, &default_allowlist());
+        assert_eq!(violations.len(), 3, "{:?}", violations);
+        assert!(violations[0].contains("kernel.randomize_va_space is not namespaced"));
+        assert!(violations[1].contains("kernel.yama.ptrace_scope is not namespaced"));
+        assert!(violations[2].contains("net.ipv4.ip_forward is not in the allowlist"));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::ChallengeDockerManager;
+use crate::id::TeamId;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// the [team_attachments] table of FloatCTF.toml
+// a generator built by the challenge's compose file needs an `image:` tag on its service
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TeamAttachmentGenerator {
+    pub image: String,
+    #[serde(default)]
+    pub command: Vec<String>,
+    // the generator writes its files here
+    #[serde(default = "default_output")]
+    pub output: String,
+}
+
+fn default_output() -> String {
+    "/out".to_string()
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+            .path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+impl ChallengeDockerManager {
+    pub fn team_attachments_dir(&self, out_root: &Path, team: TeamId) -> PathBuf {
+        out_root
+            .join(&self.challenge_docker_config.name)
+            .join(team.to_string())
+    }
+
+    // run the generator with TEAM_ID and FLAG, its output lands in <out_root>/<challenge>/<team>
+    pub fn generate_team_attachments(
+        &self,
+        team: TeamId,
+        flag: &str,
+        out_root: &Path,
+    ) -> Result<Vec<PathBuf>, String> {
+        let generator = self
+            .challenge_docker_config
+            .team_attachments
+            .as_ref()
+            .ok_or(format!(
+                "The {} has no team attachment generator",
+                self.challenge_docker_config.name
+            ))?;
+
+        let dir = self.team_attachments_dir(out_root, team);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir)
+                .map_err(|e| format!("Failed to remove {}: {}", dir.display(), e))?;
+        }
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        // docker only bind mounts absolute paths
+        let dir = dir
+            .canonicalize()
+            .map_err(|e| format!("Failed to resolve {}: {}", dir.display(), e))?;
+
+        let volume = format!("{}:{}", dir.display(), generator.output);
+        let mut labels = self.labels();
+        labels.insert(crate::labels::TEAM.to_string(), team.to_string());
+        let labels = crate::labels::label_args(&labels);
+        let mut args = vec!["run", "--rm"];
+        args.extend(labels.iter().map(String::as_str));
+        args.extend([
+            "--network",
+            "none",
+            // the values come from the environment so the flag stays out of the process list
+            "--env",
+            "TEAM_ID",
+            "--env",
+            "FLAG",
+            "--volume",
+            &volume,
+            &generator.image,
+        ]);
+        args.extend(generator.command.iter().map(String::as_str));
+
+        let team_id = team.to_string();
+        let mut env_vars = self.docker_env();
+        env_vars.insert("TEAM_ID", team_id.as_str());
+        env_vars.insert("FLAG", flag);
+        ChallengeDockerManager::run_command(self.settings(), "docker", &args, Some(env_vars))?;
+
+        let mut files = Vec::new();
+        collect_files(&dir, &mut files)?;
+        if files.is_empty() {
+            return Err(format!(
+                "The generator of {} produced no attachment for team {}",
+                self.challenge_docker_config.name, team
+            ));
+        }
+        files.sort();
+        Ok(files)
+    }
+}
@@ -0,0 +1,191 @@
+use crate::flag::FlagDelivery;
+use crate::healthcheck::HealthcheckConfig;
+use crate::profile::Profile;
+use crate::repository::ChallengeRepository;
+use crate::slug;
+use crate::{Category, ChallengeDockerManager};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// defaults of a category in the settings, whatever the FloatCTF.toml sets wins
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChallengeTemplate {
+    // put on every service, below the profiles
+    pub mem_limit: Option<String>,
+    pub cpus: Option<String>,
+    pub pids_limit: Option<u64>,
+    // an existing network every service joins besides its own
+    pub network: Option<String>,
+    pub healthcheck: Option<HealthcheckConfig>,
+    pub flag_delivery: Option<FlagDelivery>,
+}
+
+impl ChallengeTemplate {
+    pub fn profile(&self) -> Profile {
+        Profile {
+            mem_limit: self.mem_limit.clone(),
+            cpus: self.cpus.clone(),
+            pids_limit: self.pids_limit,
+            network: self.network.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+const DOCKERFILE: &str = r#"FROM ubuntu:24.04
+# TODO: install the challenge and make it listen on the port in docker-compose.yml
+EXPOSE {port}
+CMD ["sleep", "infinity"]
+"#;
+
+// a challenge in dir that validates and builds, for its author to fill in
+pub fn scaffold(
+    dir: &Path,
+    name: &str,
+    category: Category,
+    template: &ChallengeTemplate,
+) -> Result<(), String> {
+    if dir.exists() {
+        return Err(format!("{} already exists", dir.display()));
+    }
+    let flag_delivery = template.flag_delivery.unwrap_or_default();
+    let port = template
+        .healthcheck
+        .as_ref()
+        .and_then(|healthcheck| healthcheck.tcp.or(healthcheck.port))
+        .unwrap_or(match category {
+            Category::Web => 80,
+            _ => 1337,
+        });
+
+    let mut config = format!(
+        r#"name = "{}"
+author = ""
+category = "{}"
+tags = []
+description = ""
+attachments = []
+is_dynamic_flag = true
+is_dockerd = true
+points = 100
+"#,
+        name, category
+    );
+    // the file has to say so, the template only applies while the settings have it
+    if flag_delivery != FlagDelivery::Env {
+        config.push_str(&format!("flag_delivery = \"{}\"\n", flag_delivery));
+    }
+    let mut compose = format!(
+        r#"services:
+  {}:
+    build: .
+    container_name: challenge-{}-${{ID}}
+    ports:
+      - "{}"
+"#,
+        category.as_ref().to_lowercase(),
+        slug::container_slug(name),
+        port
+    );
+    if flag_delivery == FlagDelivery::Env {
+        compose.push_str("    environment:\n      - FLAG=${FLAG}\n");
+    }
+
+    std::fs::create_dir_all(dir.join("attachments"))
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let files = [
+        ("FloatCTF.toml", config),
+        ("docker-compose.yml", compose),
+        (
+            "Dockerfile",
+            DOCKERFILE.replace("{port}", &port.to_string()),
+        ),
+    ];
+    for (file, content) in files {
+        let path = dir.join(file);
+        std::fs::write(&path, content)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+impl ChallengeRepository {
+    // under <root>/<category>/<name>, with the template of the category in the global settings
+    pub fn scaffold(&self, name: &str, category: Category) -> Result<PathBuf, String> {
+        if self.challenges.iter().any(|c| c.config.name == name) {
+            return Err(format!("Duplicate challenge name: {}", name));
+        }
+        let dir = self
+            .root
+            .join(category.as_ref().to_lowercase())
+            .join(slug::slugify(name));
+        let template = crate::settings::global()?
+            .template_of(category)
+            .unwrap_or_default();
+        scaffold(&dir, name, category, &template)?;
+        Ok(dir)
+    }
+}
+
+impl ChallengeDockerManager {
+    pub fn template(&self) -> Option<ChallengeTemplate> {
+        self.settings()
+            .template_of(self.challenge_docker_config.category)
+    }
+
+    pub fn flag_delivery(&self) -> FlagDelivery {
+        self.challenge_docker_config
+            .flag_delivery
+            .or_else(|| self.template().and_then(|template| template.flag_delivery))
+            .unwrap_or_default()
+    }
+
+    pub fn healthcheck(&self) -> Option<HealthcheckConfig> {
+        self.challenge_docker_config
+            .healthcheck
+            .clone()
+            .or_else(|| self.template().and_then(|template| template.healthcheck))
+    }
+}
+
+#[cfg(test)]
+mod test_template {
+    use super::*;
+    use crate::ChallengeDockerConfig;
+    use crate::settings::CdmSettings;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_template() {
+        let settings: CdmSettings = toml::from_str(
+            "[templates.pwnable]\nmem_limit = \"128m\"\npids_limit = 32\nflag_delivery = \"secret\"\nhealthcheck = { tcp = 9001 }\n",
+        )
+        .unwrap();
+        let template = settings.template_of(Category::Pwn).unwrap();
+        let mut cdm = ChallengeDockerManager::test_manager("heap", 1)
+            .with_settings(Arc::new(settings.clone()));
+        assert_eq!(cdm.flag_delivery(), FlagDelivery::Env);
+        assert!(cdm.profile().mem_limit.is_none());
+
+        cdm.config_mut().category = Category::Pwn;
+        assert_eq!(cdm.flag_delivery(), FlagDelivery::Secret);
+        assert_eq!(cdm.healthcheck().unwrap().tcp, Some(9001));
+        assert_eq!(cdm.profile().pids_limit, Some(32));
+        cdm.config_mut().flag_delivery = Some(FlagDelivery::Env);
+        assert_eq!(cdm.flag_delivery(), FlagDelivery::Env);
+
+        let tmp = crate::testing::TestDir::new("scaffold").unwrap();
+        let dir = tmp.path.join("heap");
+        scaffold(&dir, "heap", Category::Pwn, &template).unwrap();
+        assert!(dir.join("attachments").is_dir());
+        let config = ChallengeDockerConfig::load(&dir).unwrap();
+        assert_eq!(config.category, Category::Pwn);
+        assert_eq!(config.flag_delivery, Some(FlagDelivery::Secret));
+        let compose = std::fs::read_to_string(dir.join("docker-compose.yml")).unwrap();
+        assert!(compose.contains("container_name: challenge-heap-${ID}"));
+        assert!(compose.contains("\"9001\""));
+        assert!(!compose.contains("FLAG=${FLAG}"));
+        assert!(scaffold(&dir, "heap", Category::Pwn, &template).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use crate::ChallengeDockerManager;
+use crate::flag::Flag;
+use crate::id::InstanceId;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// listens on 1337 and hands out the flag to every connection
+const DOCKERFILE: &str = r#"FROM busybox:1.36
+EXPOSE 1337
+CMD ["sh", "-c", "while true; do echo \"$FLAG\" | nc -l -p 1337; done"]
+"#;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// the pid keeps parallel test runs apart, the counter the ids of one run
+pub fn unique_id() -> InstanceId {
+    let pid = u64::from(std::process::id());
+    InstanceId::new((pid << 32) + NEXT_ID.fetch_add(1, Ordering::SeqCst))
+}
+
+// the minimal challenge cdm tests itself with
+pub fn write_challenge(dir: &Path, name: &str) -> Result<(), String> {
+    std::fs::create_dir_all(dir.join("attachments"))
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let files = [
+        (
+            "FloatCTF.toml",
+            format!(
+                r#"
+name = "{}"
+author = "FloatCTF"
+category = "Misc"
+tags = []
+description = ""
+attachments = []
+is_dynamic_flag = true
+is_dockerd = true
+points = 100
+"#,
+                name
+            ),
+        ),
+        (
+            "docker-compose.yml",
+            format!(
+                r#"
+services:
+  web:
+    build: .
+    container_name: challenge-{}-${{ID}}
+    environment:
+      - FLAG=${{FLAG}}
+    ports:
+      - "1337"
+"#,
+                name
+            ),
+        ),
+        ("Dockerfile", DOCKERFILE.to_string()),
+    ];
+    for (file, content) in files {
+        let path = dir.join(file);
+        std::fs::write(&path, content)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+// a temporary copy of the bundled challenge, removed on drop
+#[derive(Debug)]
+pub struct TestChallenge {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl TestChallenge {
+    pub fn new(name: &str) -> Result<Self, String> {
+        let path = std::env::temp_dir().join(format!("cdm-test-{}-{}", name, unique_id()));
+        let _ = std::fs::remove_dir_all(&path);
+        write_challenge(&path, name)?;
+        Ok(TestChallenge {
+            name: name.to_string(),
+            path,
+        })
+    }
+
+    // the flag comes from .env instead of the platform, compose interpolates it from there
+    pub fn with_static_flag(name: &str, flag: &str) -> Result<Self, String> {
+        let challenge = TestChallenge::new(name)?;
+        let config = challenge.path.join("FloatCTF.toml");
+        let content = std::fs::read_to_string(&config)
+            .map_err(|e| format!("Failed to read {}: {}", config.display(), e))?
+            .replace("is_dynamic_flag = true", "is_dynamic_flag = false");
+        std::fs::write(&config, content)
+            .map_err(|e| format!("Failed to write {}: {}", config.display(), e))?;
+
+        let dot_env = challenge.path.join(".env");
+        std::fs::write(&dot_env, format!("FLAG={}\n", flag))
+            .map_err(|e| format!("Failed to write {}: {}", dot_env.display(), e))?;
+        Ok(challenge)
+    }
+
+    pub fn manager(&self) -> Result<ChallengeDockerManager, String> {
+        ChallengeDockerManager::new(self.path.clone(), unique_id())
+    }
+}
+
+impl Drop for TestChallenge {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+// a temporary directory for fixtures that are not a single challenge, removed on drop
+#[derive(Debug)]
+pub struct TestDir {
+    pub path: PathBuf,
+}
+
+impl TestDir {
+    pub fn new(name: &str) -> Result<Self, String> {
+        let path = std::env::temp_dir().join(format!("cdm-{}-{}", name, unique_id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        Ok(TestDir { path })
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+// an instance that is torn down on drop, even when the test panics, brought up by cdm itself
+// rather than testcontainers, which starts single containers and never goes through compose
+#[derive(Debug)]
+pub struct InstanceGuard {
+    cdm: ChallengeDockerManager,
+    pub port: u64,
+}
+
+impl InstanceGuard {
+    pub fn up(cdm: ChallengeDockerManager, flag: &Flag) -> Result<Self, String> {
+        // a failed up may leave half the project behind, the guard cleans that up too
+        let mut guard = InstanceGuard { cdm, port: 0 };
+        guard.port = guard.cdm.up(flag)?;
+        Ok(guard)
+    }
+}
+
+impl Deref for InstanceGuard {
+    type Target = ChallengeDockerManager;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cdm
+    }
+}
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        let _ = self.cdm.down();
+    }
+}
+
+#[cfg(test)]
+mod test_testing {
+    use super::*;
+    use crate::backend::MockBackend;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_guards() {
+        let challenge = TestChallenge::with_static_flag("fixture", "flag{fixture}").unwrap();
+        let path = challenge.path.clone();
+        let mock = Arc::new(MockBackend::new());
+        let cdm = challenge.manager().unwrap().with_backend(mock.clone());
+        assert_eq!(cdm.get_static_flag().unwrap().expose(), "flag{fixture}");
+        assert_ne!(cdm.id, challenge.manager().unwrap().id);
+        assert_eq!(cdm.id.get() >> 32, u64::from(std::process::id()));
+
+        let instance = InstanceGuard::up(cdm, &"flag{fixture}".into()).unwrap();
+        assert_eq!(mock.running().len(), 1);
+        drop(instance);
+        assert!(mock.running().is_empty());
+
+        drop(challenge);
+        assert!(!path.exists());
+    }
+}
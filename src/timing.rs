@@ -0,0 +1,222 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+use strum::{AsRefStr, Display};
+
+#[derive(
+    Debug,
+    Deserialize,
+    Serialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    AsRefStr,
+    Display,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SpawnStage {
+    Build,
+    // preloading the images onto the hosts, see ChallengeRepository::preload
+    Pull,
+    Start,
+    // until the mapped port accepts connections, see wait_ready
+    Ready,
+}
+
+impl SpawnStage {
+    pub const ALL: [SpawnStage; 4] = [
+        SpawnStage::Build,
+        SpawnStage::Pull,
+        SpawnStage::Start,
+        SpawnStage::Ready,
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageStats {
+    pub stage: SpawnStage,
+    pub count: u64,
+    pub total_ms: u64,
+    pub max_ms: u64,
+}
+
+impl StageStats {
+    fn new(stage: SpawnStage) -> Self {
+        StageStats {
+            stage,
+            count: 0,
+            total_ms: 0,
+            max_ms: 0,
+        }
+    }
+
+    fn add(&mut self, duration_ms: u64) {
+        self.count += 1;
+        self.total_ms += duration_ms;
+        self.max_ms = self.max_ms.max(duration_ms);
+    }
+
+    pub fn mean_ms(&self) -> u64 {
+        self.total_ms.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChallengeTiming {
+    pub challenge: String,
+    // in the order of SpawnStage, only the stages that were measured
+    pub stages: Vec<StageStats>,
+    // what a spawn costs on average, the sum of the stage means
+    pub spawn_ms: u64,
+    pub slowest_stage: Option<SpawnStage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimingReport {
+    // slowest first
+    pub challenges: Vec<ChallengeTiming>,
+    // every challenge together, to tell whether building or starting dominates
+    pub stages: Vec<StageStats>,
+}
+
+// build and spawn durations by challenge, so slow challenges are found before the event
+#[derive(Debug, Default)]
+pub struct TimingLog {
+    stats: Mutex<HashMap<(String, SpawnStage), StageStats>>,
+}
+
+impl TimingLog {
+    pub fn new() -> Self {
+        TimingLog::default()
+    }
+
+    pub fn record(&self, challenge: &str, stage: SpawnStage, duration_ms: u64) {
+        self.stats
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry((challenge.to_string(), stage))
+            .or_insert_with(|| StageStats::new(stage))
+            .add(duration_ms);
+    }
+
+    // run f and record how long it took when it succeeded, failures would only skew the means
+    pub fn measure<T, E>(
+        &self,
+        challenge: &str,
+        stage: SpawnStage,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let started = Instant::now();
+        let result = f();
+        if result.is_ok() {
+            self.record(challenge, stage, started.elapsed().as_millis() as u64);
+        }
+        result
+    }
+
+    pub fn clear(&self) {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    // the `limit` slowest challenges
+    pub fn report(&self, limit: usize) -> TimingReport {
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner()).clone();
+
+        let mut by_challenge: HashMap<String, Vec<StageStats>> = HashMap::new();
+        let mut totals: HashMap<SpawnStage, StageStats> = HashMap::new();
+        for ((challenge, stage), stage_stats) in stats {
+            let total = totals
+                .entry(stage)
+                .or_insert_with(|| StageStats::new(stage));
+            total.count += stage_stats.count;
+            total.total_ms += stage_stats.total_ms;
+            total.max_ms = total.max_ms.max(stage_stats.max_ms);
+            by_challenge.entry(challenge).or_default().push(stage_stats);
+        }
+
+        let mut challenges: Vec<ChallengeTiming> = by_challenge
+            .into_iter()
+            .map(|(challenge, mut stages)| {
+                stages.sort_by_key(|stats| stats.stage);
+                ChallengeTiming {
+                    challenge,
+                    spawn_ms: stages.iter().map(StageStats::mean_ms).sum(),
+                    slowest_stage: stages
+                        .iter()
+                        .max_by_key(|stats| stats.mean_ms())
+                        .map(|stats| stats.stage),
+                    stages,
+                }
+            })
+            .collect();
+        challenges.sort_by(|a, b| {
+            b.spawn_ms
+                .cmp(&a.spawn_ms)
+                .then_with(|| a.challenge.cmp(&b.challenge))
+        });
+        challenges.truncate(limit);
+
+        TimingReport {
+            challenges,
+            stages: SpawnStage::ALL
+                .iter()
+                .filter_map(|stage| totals.remove(stage))
+                .collect(),
+        }
+    }
+}
+
+// the log ChallengeDockerManager records to unless given another one
+pub fn global() -> Arc<TimingLog> {
+    static LOG: OnceLock<Arc<TimingLog>> = OnceLock::new();
+    LOG.get_or_init(|| Arc::new(TimingLog::new())).clone()
+}
+
+pub fn timing_report(limit: usize) -> TimingReport {
+    global().report(limit)
+}
+
+#[cfg(test)]
+mod test_timing {
+    use super::*;
+
+    #[test]
+    fn check_report() {
+        let log = TimingLog::new();
+        log.record("comment", SpawnStage::Start, 2_000);
+        log.record("comment", SpawnStage::Start, 4_000);
+        log.record("comment", SpawnStage::Build, 1_000);
+        log.record("pwn", SpawnStage::Build, 90_000);
+        log.record("pwn", SpawnStage::Ready, 500);
+        log.record("crypto", SpawnStage::Pull, 100);
+        let failed: Result<(), String> =
+            log.measure("crypto", SpawnStage::Start, || Err("no".to_string()));
+        assert!(failed.is_err());
+
+        let report = log.report(2);
+        assert_eq!(report.challenges.len(), 2);
+        assert_eq!(report.challenges[0].challenge, "pwn");
+        assert_eq!(report.challenges[0].spawn_ms, 90_500);
+        assert_eq!(report.challenges[0].slowest_stage, Some(SpawnStage::Build));
+
+        let comment = &report.challenges[1];
+        assert_eq!(comment.spawn_ms, 4_000);
+        assert_eq!(comment.slowest_stage, Some(SpawnStage::Start));
+        assert_eq!(comment.stages[1].mean_ms(), 3_000);
+        assert_eq!(comment.stages[1].max_ms, 4_000);
+
+        let stages: Vec<SpawnStage> = report.stages.iter().map(|stats| stats.stage).collect();
+        assert_eq!(stages, SpawnStage::ALL);
+        assert_eq!(report.stages[0].total_ms, 91_000);
+        assert_eq!(report.stages[2].count, 2);
+
+        log.clear();
+        assert!(log.report(10).challenges.is_empty());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::ChallengeDockerManager;
+use crate::error::CdmError;
+use crate::events::EventKind;
+use crate::id::{InstanceId, TeamId};
+use crate::schedule;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TeamUsage {
+    // of the instances already taken down
+    pub finished_seconds: u64,
+    // when each of the running instances started, by challenge#instance
+    pub running: BTreeMap<String, u64>,
+}
+
+impl TeamUsage {
+    pub fn seconds(&self, now: u64) -> u64 {
+        self.finished_seconds
+            + self
+                .running
+                .values()
+                .map(|started| now.saturating_sub(*started))
+                .sum::<u64>()
+    }
+}
+
+fn running_key(challenge: &str, id: InstanceId) -> String {
+    format!("{}#{}", challenge, id)
+}
+
+// cumulative instance time per team, optionally capped
+#[derive(Debug, Default)]
+pub struct UsageLedger {
+    teams: Mutex<HashMap<TeamId, TeamUsage>>,
+    // seconds a team may use in total, spawns are denied past it
+    budget: Option<u64>,
+}
+
+impl UsageLedger {
+    pub fn new() -> Self {
+        UsageLedger::default()
+    }
+
+    pub fn with_budget_hours(mut self, hours: u64) -> Self {
+        self.budget = Some(hours * 3600);
+        self
+    }
+
+    pub fn budget_seconds(&self) -> Option<u64> {
+        self.budget
+    }
+
+    pub fn start(&self, team: TeamId, challenge: &str, id: InstanceId, now: u64) {
+        self.teams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(team)
+            .or_default()
+            .running
+            .entry(running_key(challenge, id))
+            .or_insert(now);
+    }
+
+    pub fn stop(&self, team: TeamId, challenge: &str, id: InstanceId, now: u64) {
+        let mut teams = self.teams.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(usage) = teams.get_mut(&team) else {
+            return;
+        };
+        if let Some(started) = usage.running.remove(&running_key(challenge, id)) {
+            usage.finished_seconds += now.saturating_sub(started);
+        }
+    }
+
+    pub fn seconds(&self, team: TeamId, now: u64) -> u64 {
+        self.teams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&team)
+            .map(|usage| usage.seconds(now))
+            .unwrap_or(0)
+    }
+
+    pub fn hours(&self, team: TeamId, now: u64) -> f64 {
+        self.seconds(team, now) as f64 / 3600.0
+    }
+
+    // seconds left of the budget, None without one
+    pub fn remaining(&self, team: TeamId, now: u64) -> Option<u64> {
+        self.budget
+            .map(|budget| budget.saturating_sub(self.seconds(team, now)))
+    }
+
+    pub fn teams(&self) -> BTreeMap<TeamId, TeamUsage> {
+        self.teams
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(team, usage)| (*team, usage.clone()))
+            .collect()
+    }
+
+    // the teams by seconds used, most first
+    pub fn leaderboard(&self, now: u64) -> Vec<(TeamId, u64)> {
+        let mut teams: Vec<(TeamId, u64)> = self
+            .teams()
+            .into_iter()
+            .map(|(team, usage)| (team, usage.seconds(now)))
+            .collect();
+        teams.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        teams
+    }
+}
+
+impl ChallengeDockerManager {
+    // instances without a team are not accounted
+    pub(crate) fn check_budget(&self) -> Result<(), CdmError> {
+        let (Some(usage), Some(team)) = (self.usage(), self.team) else {
+            return Ok(());
+        };
+        let Some(budget) = usage.budget_seconds() else {
+            return Ok(());
+        };
+        let used = usage.seconds(team, schedule::now());
+        if used < budget {
+            return Ok(());
+        }
+        self.emit(EventKind::QuotaExhausted {
+            scope: format!("team {} instance hours", team),
+            limit: budget / 3600,
+        });
+        Err(CdmError::BudgetExhausted {
+            team,
+            used_seconds: used,
+            budget_seconds: budget,
+        })
+    }
+
+    pub(crate) fn record_usage(&self, running: bool) {
+        let (Some(usage), Some(team)) = (self.usage(), self.team) else {
+            return;
+        };
+        let name = &self.challenge_docker_config.name;
+        if running {
+            usage.start(team, name, self.id, schedule::now());
+        } else {
+            usage.stop(team, name, self.id, schedule::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_usage {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::lifecycle::UpOptions;
+    use std::sync::Arc;
+
+    #[test]
+    fn check_ledger() {
+        let ledger = UsageLedger::new().with_budget_hours(2);
+        let (red, blue) = (TeamId::new(1), TeamId::new(2));
+        ledger.start(red, "comment", InstanceId::new(1), 0);
+        ledger.start(red, "stack", InstanceId::new(2), 1800);
+        ledger.stop(red, "comment", InstanceId::new(1), 3600);
+        ledger.start(blue, "comment", InstanceId::new(3), 3600);
+
+        assert_eq!(ledger.seconds(red, 5400), 3600 + 3600);
+        assert_eq!(ledger.hours(red, 5400), 2.0);
+        assert_eq!(ledger.remaining(red, 5400), Some(0));
+        assert_eq!(ledger.remaining(blue, 5400), Some(7200 - 1800));
+        assert_eq!(ledger.leaderboard(5400), [(red, 7200), (blue, 1800)]);
+        // stopping twice does not count twice
+        ledger.stop(red, "comment", InstanceId::new(1), 9000);
+        assert_eq!(ledger.teams()[&red].finished_seconds, 3600);
+    }
+
+    #[test]
+    fn check_budget() {
+        let mock = Arc::new(MockBackend::new());
+        let ledger = Arc::new(UsageLedger::new().with_budget_hours(1));
+        let team = TeamId::new(7);
+        let cdm = ChallengeDockerManager::test_manager("comment", 1)
+            .with_backend(mock.clone())
+            .with_team(team)
+            .with_usage(ledger.clone());
+        cdm.up(&"flag{usage}".into()).unwrap();
+        assert_eq!(ledger.teams()[&team].running.len(), 1);
+        cdm.down().unwrap();
+        assert!(ledger.teams()[&team].running.is_empty());
+
+        ledger.start(team, "stack", InstanceId::new(9), schedule::now() - 3600);
+        assert!(matches!(
+            cdm.up_with(&"flag{usage}".into(), &UpOptions::default()),
+            Err(CdmError::BudgetExhausted {
+                team: denied,
+                used_seconds,
+                budget_seconds: 3600,
+            }) if denied == team && used_seconds >= 3600
+        ));
+        assert!(mock.running().is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+use crate::ChallengeDockerManager;
+use crate::backend::ComposeBackend;
+use crate::compose::ComposeFile;
+use crate::id::InstanceId;
+use crate::repository::ChallengeRepository;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationPolicy {
+    // bytes
+    pub max_attachment_size: u64,
+    pub max_total_attachment_size: u64,
+    // lowercase, without the dot
+    pub forbidden_extensions: Vec<String>,
+    pub check_staleness: bool,
+    // runs docker-compose config, so it is off unless docker is around
+    pub check_compose: bool,
+}
+
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        ValidationPolicy {
+            max_attachment_size: 100 * 1024 * 1024,
+            max_total_attachment_size: 200 * 1024 * 1024,
+            forbidden_extensions: ["vmdk", "vdi", "qcow2", "ova", "vhd", "vhdx"]
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            check_staleness: true,
+            check_compose: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub challenge: String,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<ValidationReport, String> {
+        if self.is_ok() {
+            return Ok(self);
+        }
+        Err(format!(
+            "The {} failed validation: {}",
+            self.challenge,
+            self.errors.join("; ")
+        ))
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn size_of(path: &Path) -> Result<u64, String> {
+    let metadata =
+        std::fs::metadata(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut total = 0;
+    let entries =
+        std::fs::read_dir(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        total += size_of(&entry.path())?;
+    }
+    Ok(total)
+}
+
+impl ChallengeDockerManager {
+    pub fn validate(&self, policy: &ValidationPolicy) -> ValidationReport {
+        let mut report = ValidationReport {
+            challenge: self.challenge_docker_config.name.clone(),
+            ..Default::default()
+        };
+        self.validate_attachments(policy, &mut report);
+        self.validate_mounts(&mut report);
+        self.validate_capabilities(&mut report);
+        report.errors.extend(self.sysctl_violations());
+        if policy.check_staleness {
+            self.validate_staleness(&mut report);
+        }
+        if policy.check_compose
+            && let Err(e) = ComposeBackend::config(self)
+        {
+            report.errors.push(e);
+        }
+        report
+    }
+
+    fn validate_mounts(&self, report: &mut ValidationReport) {
+        match ComposeFile::load(&self.docker_compose_yml) {
+            Ok(compose) => report.errors.extend(
+                self.settings()
+                    .mounts
+                    .violations(&compose, &self.challenge_path),
+            ),
+            Err(e) => report.errors.push(e),
+        }
+    }
+
+    fn validate_capabilities(&self, report: &mut ValidationReport) {
+        match self.capability_warnings() {
+            Ok(warnings) => report.warnings.extend(warnings),
+            Err(e) => report.errors.push(e),
+        }
+        if let (Some(capabilities), Some(range)) =
+            (self.capabilities(), self.challenge_docker_config.port_range)
+            && let Err(e) = capabilities.adapt_range(range)
+        {
+            report.errors.push(e);
+        }
+    }
+
+    fn validate_attachments(&self, policy: &ValidationPolicy, report: &mut ValidationReport) {
+        let built: Vec<&str> = self
+            .challenge_docker_config
+            .built_attachments
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+
+        let mut total = 0;
+        for attachment in &self.attachment_files {
+            let (name, path) = (&attachment.name, &attachment.path);
+            let extension = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase());
+            if let Some(extension) = extension
+                && policy.forbidden_extensions.contains(&extension)
+            {
+                report.errors.push(format!(
+                    "attachment {} has a forbidden extension: .{}",
+                    name, extension
+                ));
+            }
+
+            if !path.exists() && built.contains(&name.as_str()) {
+                report
+                    .warnings
+                    .push(format!("attachment {} has not been built yet", name));
+                continue;
+            }
+            let size = match size_of(path) {
+                Ok(size) => size,
+                Err(e) => {
+                    report.errors.push(e);
+                    continue;
+                }
+            };
+            if size > policy.max_attachment_size {
+                report.errors.push(format!(
+                    "attachment {} is {}, the limit is {}",
+                    name,
+                    human_size(size),
+                    human_size(policy.max_attachment_size)
+                ));
+            }
+            total += size;
+        }
+
+        if total > policy.max_total_attachment_size {
+            report.errors.push(format!(
+                "attachments are {} in total, the limit is {}",
+                human_size(total),
+                human_size(policy.max_total_attachment_size)
+            ));
+        }
+    }
+}
+
+impl ChallengeRepository {
+    pub fn validate(&self, policy: &ValidationPolicy) -> Vec<ValidationReport> {
+        self.challenges
+            .iter()
+            .map(|challenge| {
+                match ChallengeDockerManager::new(challenge.path.clone(), InstanceId::new(0)) {
+                    Ok(cdm) => cdm.validate(policy),
+                    Err(e) => ValidationReport {
+                        challenge: challenge.config.name.clone(),
+                        errors: vec![e],
+                        warnings: Vec::new(),
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test_validate {
+    use super::*;
+
+    #[test]
+    fn check_human_size() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(2 * 1024 * 1024 * 1024), "2.0 GiB");
+    }
+
+    #[test]
+    fn check_attachment_limits() {
+        let challenge = crate::testing::TestChallenge::new("validate").unwrap();
+        let dir = challenge.path.clone();
+        std::fs::write(dir.join("attachments/disk.VMDK"), b"vm").unwrap();
+        std::fs::write(dir.join("attachments/big.bin"), vec![0; 2048]).unwrap();
+
+        let mut cdm = ChallengeDockerManager::new(dir.clone(), InstanceId::new(1)).unwrap();
+        cdm.config_mut().attachments = vec!["disk.VMDK".into(), "big.bin".into()];
+        cdm.refresh_attachments().unwrap();
+        let policy = ValidationPolicy {
+            max_attachment_size: 1024,
+            max_total_attachment_size: 1024,
+            ..Default::default()
+        };
+
+        let report = cdm.validate(&policy);
+        assert_eq!(report.errors.len(), 3, "{:?}", report.errors);
+        assert!(report.errors[0].contains(".vmdk"));
+        assert!(report.into_result().is_err());
+        assert!(cdm.validate(&ValidationPolicy::default()).errors.len() == 1);
+    }
+}
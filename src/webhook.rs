@@ -0,0 +1,226 @@
+use crate::events::{Event, EventSink};
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+use std::fmt;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// per attempt, a receiver that hangs must not hold up the events queued behind it
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(10);
+// events waiting for delivery, the ones beyond are dropped rather than block the lifecycle
+pub(crate) const QUEUE_SIZE: usize = 256;
+
+pub(crate) fn agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .into()
+}
+
+// a single delivery thread and its bounded queue, started by the first event
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Outbox(Arc<Mutex<Option<SyncSender<Event>>>>);
+
+impl Outbox {
+    pub(crate) fn push(
+        &self,
+        name: &str,
+        event: &Event,
+        deliver: impl Fn(&Event) + Send + 'static,
+    ) -> Result<(), String> {
+        let mut sender = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if sender.is_none() {
+            let (tx, rx) = mpsc::sync_channel::<Event>(QUEUE_SIZE);
+            std::thread::Builder::new()
+                .name(name.to_string())
+                .spawn(move || rx.iter().for_each(|event| deliver(&event)))
+                .map_err(|e| format!("Failed to spawn {} thread: {}", name, e))?;
+            *sender = Some(tx);
+        }
+        let Some(tx) = sender.as_ref() else {
+            return Ok(());
+        };
+        match tx.try_send(event.clone()) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(format!(
+                "The {} queue is full, the {} event is dropped",
+                name,
+                event.kind.name()
+            )),
+            // the thread died, the next event starts another
+            Err(TrySendError::Disconnected(_)) => {
+                *sender = None;
+                Err(format!(
+                    "The {} thread is gone, the {} event is dropped",
+                    name,
+                    event.kind.name()
+                ))
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    pub url: String,
+    pub secret: String,
+    pub max_retries: u32,
+    pub backoff: Duration,
+    agent: ureq::Agent,
+    outbox: Outbox,
+}
+
+impl fmt::Debug for WebhookDispatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookDispatcher")
+            .field("url", &self.url)
+            .field("secret", &"<redacted>")
+            .field("max_retries", &self.max_retries)
+            .field("backoff", &self.backoff)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WebhookDispatcher {
+    pub fn new(url: &str, secret: &str) -> Self {
+        WebhookDispatcher {
+            url: url.to_string(),
+            secret: secret.to_string(),
+            max_retries: 3,
+            backoff: Duration::from_millis(500),
+            agent: agent(),
+            outbox: Outbox::default(),
+        }
+    }
+
+    // hex encoded HMAC-SHA256 of the body, sent as `X-Cdm-Signature: sha256=<hex>`
+    pub fn sign(&self, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    // post the event, retrying with exponential backoff
+    pub fn deliver(&self, event: &Event) -> Result<(), String> {
+        let body =
+            serde_json::to_vec(event).map_err(|e| format!("Failed to serialize event: {}", e))?;
+        let signature = format!("sha256={}", self.sign(&body));
+
+        let mut delay = self.backoff;
+        let mut attempt = 0;
+        loop {
+            let result = self
+                .agent
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .header("X-Cdm-Signature", &signature)
+                .send(&body[..]);
+
+            let error = match result {
+                Ok(_) => return Ok(()),
+                // the receiver rejected the event, retrying will not help
+                Err(ureq::Error::StatusCode(code)) if (400..500).contains(&code) => {
+                    return Err(format!("Webhook {} rejected the event: {}", self.url, code));
+                }
+                Err(e) => e,
+            };
+
+            attempt += 1;
+            if attempt > self.max_retries {
+                return Err(format!(
+                    "Webhook {} failed after {} attempts: {}",
+                    self.url, attempt, error
+                ));
+            }
+            std::thread::sleep(delay);
+            delay *= 2;
+        }
+    }
+}
+
+impl EventSink for WebhookDispatcher {
+    // delivered in the background so lifecycle operations never wait on the receiver
+    fn emit(&self, event: &Event) -> Result<(), String> {
+        // without the outbox, the thread would keep itself alive
+        let dispatcher = WebhookDispatcher {
+            outbox: Outbox::default(),
+            ..self.clone()
+        };
+        self.outbox.push("cdm-webhook", event, move |event| {
+            let _ = dispatcher.deliver(event);
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_webhook {
+    use super::*;
+    use crate::events::EventKind;
+    use crate::id::InstanceId;
+
+    #[test]
+    fn check_sign() {
+        let dispatcher = WebhookDispatcher::new("http://127.0.0.1:1/hook", "key");
+        // the well known HMAC-SHA256 test vector
+        assert_eq!(
+            dispatcher.sign(b"The quick brown fox jumps over the lazy dog"),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn check_debug() {
+        let dispatcher = WebhookDispatcher::new("http://127.0.0.1:1/hook", "hunter2");
+        assert!(!format!("{:?}", dispatcher).contains("hunter2"));
+    }
+
+    #[test]
+    fn check_outbox() {
+        let event = Event::new(EventKind::Down, "comment", InstanceId::new(1));
+        let (delivered, received) = mpsc::channel();
+        let outbox = Outbox::default();
+        for _ in 0..3 {
+            let delivered = delivered.clone();
+            outbox
+                .push("cdm-test", &event, move |event| {
+                    delivered.send(event.clone()).unwrap();
+                })
+                .unwrap();
+        }
+        // one thread delivers everything
+        for _ in 0..3 {
+            assert_eq!(received.recv_timeout(TIMEOUT).unwrap(), event);
+        }
+
+        let (release, gate) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate);
+        let blocked = Outbox::default();
+        let deliver = move |_: &Event| {
+            let _ = gate.lock().unwrap().recv();
+        };
+        blocked.push("cdm-test", &event, deliver).unwrap();
+        let results: Vec<_> = (0..QUEUE_SIZE + 1)
+            .map(|_| blocked.push("cdm-test", &event, |_| {}))
+            .collect();
+        assert!(
+            results
+                .last()
+                .unwrap()
+                .as_ref()
+                .unwrap_err()
+                .contains("full")
+        );
+        drop(release);
+    }
+
+    #[test]
+    fn check_event_json() {
+        let event = Event::new(EventKind::Up { port: 31337 }, "comment", InstanceId::new(1));
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "up");
+        assert_eq!(json["port"], 31337);
+        assert_eq!(json["challenge"], "comment");
+    }
+}